@@ -11,6 +11,9 @@ pub const MONEROD_DEFAULT_NETWORK: &str = "monero_network";
 /// this doesn't matter.
 pub const RPC_PORT: u16 = 18081;
 
+/// The port monerod publishes block and transaction pool notifications on.
+pub const ZMQ_PUB_PORT: u16 = 18083;
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Monerod;
 
@@ -29,6 +32,10 @@ impl Image for Monerod {
         vec![WaitFor::message_on_stdout("RPC server started ok")]
     }
 
+    fn expose_ports(&self) -> Vec<u16> {
+        vec![ZMQ_PUB_PORT]
+    }
+
     fn entrypoint(&self) -> Option<String> {
         Some("".to_owned()) // an empty entrypoint disables the entrypoint
                             // script and gives us full control
@@ -77,6 +84,7 @@ pub struct MonerodArgs {
     pub rpc_bind_ip: String,
     pub fixed_difficulty: u32,
     pub data_dir: String,
+    pub zmq_pub: bool,
 }
 
 impl Default for MonerodArgs {
@@ -91,6 +99,7 @@ impl Default for MonerodArgs {
             rpc_bind_ip: "0.0.0.0".to_string(),
             fixed_difficulty: 1,
             data_dir: "/monero".to_string(),
+            zmq_pub: true,
         }
     }
 }
@@ -142,6 +151,10 @@ impl IntoIterator for MonerodArgs {
             args.push(format!("--fixed-difficulty={}", self.fixed_difficulty));
         }
 
+        if self.zmq_pub {
+            args.push(format!("--zmq-pub=tcp://0.0.0.0:{}", ZMQ_PUB_PORT));
+        }
+
         args.into_iter()
     }
 }
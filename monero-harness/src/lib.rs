@@ -31,7 +31,9 @@ use monero_rpc::monerod;
 use monero_rpc::monerod::MonerodRpc as _;
 use monero_rpc::wallet::{self, GetAddress, MoneroWalletRpc as _, Refreshed, Transfer};
 
-use crate::image::{MONEROD_DAEMON_CONTAINER_NAME, MONEROD_DEFAULT_NETWORK, RPC_PORT};
+use crate::image::{
+    MONEROD_DAEMON_CONTAINER_NAME, MONEROD_DEFAULT_NETWORK, RPC_PORT, ZMQ_PUB_PORT,
+};
 
 pub mod image;
 
@@ -188,6 +190,19 @@ impl<'c> Monero {
         Ok(())
     }
 
+    /// Mines the given number of blocks immediately, in addition to the blocks
+    /// mined by the background miner.
+    pub async fn generate_blocks(&self, amount_of_blocks: u32) -> Result<()> {
+        let miner_address = self.wallet("miner")?.address().await?.address;
+
+        self.monerod
+            .client()
+            .generateblocks(amount_of_blocks, miner_address)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn init_and_start_miner(&self) -> Result<()> {
         self.init_miner().await?;
         self.start_miner().await?;
@@ -210,6 +225,7 @@ pub struct Monerod {
     name: String,
     network: String,
     client: monerod::Client,
+    zmq_pub_endpoint: String,
 }
 
 #[derive(Clone, Debug)]
@@ -232,12 +248,14 @@ impl<'c> Monerod {
 
         let container = cli.run(image);
         let monerod_rpc_port = container.get_host_port_ipv4(RPC_PORT);
+        let zmq_pub_port = container.get_host_port_ipv4(ZMQ_PUB_PORT);
 
         Ok((
             Self {
                 name,
                 network,
                 client: monerod::Client::localhost(monerod_rpc_port)?,
+                zmq_pub_endpoint: format!("tcp://127.0.0.1:{}", zmq_pub_port),
             },
             container,
         ))
@@ -247,6 +265,11 @@ impl<'c> Monerod {
         &self.client
     }
 
+    /// The endpoint to subscribe to monerod's ZMQ notifications on.
+    pub fn zmq_pub_endpoint(&self) -> &str {
+        &self.zmq_pub_endpoint
+    }
+
     /// Spawns a task to mine blocks in a regular interval to the provided
     /// address
     pub async fn start_miner(&self, miner_wallet_address: &str) -> Result<()> {
@@ -1,5 +1,9 @@
+use futures::TryStreamExt;
+use monero::cryptonote::hash::Hashable;
+use monero::TxIn;
 use monero_harness::Monero;
 use monero_rpc::monerod::MonerodRpc as _;
+use monero_rpc::zmq::BlockSubscription;
 use std::time::Duration;
 use testcontainers::clients::Cli;
 use tokio::time;
@@ -29,3 +33,125 @@ async fn init_miner_and_mine_to_miner_address() {
 
     assert!(block_height > 70);
 }
+
+#[tokio::test]
+async fn get_transactions_reports_unknown_transactions_as_missing() {
+    let _guard = tracing_subscriber::fmt()
+        .with_env_filter("warn,test=debug,monero_harness=debug,monero_rpc=debug")
+        .set_default();
+
+    let tc = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) = Monero::new(&tc, vec![]).await.unwrap();
+
+    monero.init_and_start_miner().await.unwrap();
+
+    let client = monero.monerod().client();
+    let block = client.get_block(10).await.unwrap().blob;
+    let known = block.miner_tx.hash();
+    let mut unknown = known;
+    unknown.0[0] ^= 0xff;
+
+    let transactions = client.get_transactions(&[known, unknown]).await.unwrap();
+
+    assert_eq!(transactions.len(), 2);
+    let (txid, transaction) = &transactions[0];
+    assert_eq!(*txid, known);
+    let transaction = transaction.as_ref().expect("miner transaction to be found");
+    assert!(!transaction.in_pool);
+    assert_eq!(transaction.block_height, Some(10));
+    assert_eq!(transactions[1].0, unknown);
+    assert!(transactions[1].1.is_none());
+}
+
+#[tokio::test]
+async fn stream_blocks_returns_generated_blocks_in_order() {
+    let _guard = tracing_subscriber::fmt()
+        .with_env_filter("warn,test=debug,monero_harness=debug,monero_rpc=debug")
+        .set_default();
+
+    let tc = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) = Monero::new(&tc, vec![]).await.unwrap();
+
+    monero.init_miner().await.unwrap();
+    let client = monero.monerod().client();
+    let start = u64::from(client.get_block_count().await.unwrap().count);
+    monero.generate_blocks(30).await.unwrap();
+
+    let blocks = client
+        .stream_blocks(start..start + 30)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+    let heights = blocks
+        .iter()
+        .map(|block| match &block.miner_tx.prefix.inputs[0] {
+            TxIn::Gen { height } => height.0,
+            _ => panic!("miner transaction must spend a coinbase input"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(heights, (start..start + 30).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn wait_for_block_above_detects_generated_block_within_one_poll_interval() {
+    let _guard = tracing_subscriber::fmt()
+        .with_env_filter("warn,test=debug,monero_harness=debug,monero_rpc=debug")
+        .set_default();
+
+    let tc = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) = Monero::new(&tc, vec![]).await.unwrap();
+
+    monero.init_miner().await.unwrap();
+    let client = monero.monerod().client();
+    let height = u64::from(client.get_block_count().await.unwrap().count) - 1;
+    let poll_interval = Duration::from_secs(1);
+
+    let waiting = tokio::spawn({
+        let client = client.clone();
+        async move { client.wait_for_block_above(height, poll_interval).await }
+    });
+    monero.generate_blocks(1).await.unwrap();
+
+    let new_height = time::timeout(poll_interval * 2, waiting)
+        .await
+        .expect("block to be detected within one poll interval")
+        .unwrap()
+        .unwrap();
+    assert_eq!(new_height, height + 1);
+}
+
+#[tokio::test]
+async fn block_subscription_announces_generated_block() {
+    let _guard = tracing_subscriber::fmt()
+        .with_env_filter("warn,test=debug,monero_harness=debug,monero_rpc=debug")
+        .set_default();
+
+    let tc = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) = Monero::new(&tc, vec![]).await.unwrap();
+
+    monero.init_miner().await.unwrap();
+    let height = u64::from(
+        monero
+            .monerod()
+            .client()
+            .get_block_count()
+            .await
+            .unwrap()
+            .count,
+    ) - 1;
+    let subscription = BlockSubscription::connect(monero.monerod().zmq_pub_endpoint())
+        .await
+        .unwrap();
+    let mut heights = subscription.heights();
+    // ZMQ drops messages published before the subscription reached monerod.
+    time::sleep(Duration::from_millis(500)).await;
+
+    monero.generate_blocks(1).await.unwrap();
+
+    time::timeout(Duration::from_secs(1), heights.changed())
+        .await
+        .expect("block to be announced within one poll interval")
+        .unwrap();
+    assert_eq!(*heights.borrow(), height + 1);
+}
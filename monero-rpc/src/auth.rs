@@ -0,0 +1,514 @@
+//! HTTP transport shared by the monerod and monero-wallet-rpc clients.
+//!
+//! Both daemons protect their RPC interface with HTTP digest authentication
+//! when started with `--rpc-login user:pass`. Requests are sent without
+//! credentials first; a 401 challenge is answered once.
+//!
+//! Read-only requests are retried on timeouts, connection errors and 5xx
+//! responses according to the client's [`RetryPolicy`].
+
+use digest_auth::{AuthContext, HttpMethod};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+/// Username and password the daemon was started with through `--rpc-login`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+/// How often read-only requests are retried and how long to wait in between.
+///
+/// The wait starts at `backoff` and doubles with every retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    credentials: Option<Credentials>,
+    retry_policy: RetryPolicy,
+}
+
+impl Client {
+    pub fn new(inner: reqwest::Client, credentials: Option<Credentials>) -> Self {
+        Self {
+            inner,
+            credentials,
+            retry_policy: RetryPolicy::NONE,
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub async fn get(&self, url: Url) -> Result<Response, Error> {
+        self.send_with_retries(Method::GET, url, None).await
+    }
+
+    /// Sends a request that changes state on the server, so it is sent once.
+    pub async fn post(
+        &self,
+        url: Url,
+        content_type: &'static str,
+        body: Vec<u8>,
+    ) -> Result<Response, Error> {
+        self.send(Method::POST, url, Some((content_type, body)))
+            .await
+    }
+
+    /// Sends a read-only request, retrying it according to the retry policy.
+    pub async fn post_idempotent(
+        &self,
+        url: Url,
+        content_type: &'static str,
+        body: Vec<u8>,
+    ) -> Result<Response, Error> {
+        self.send_with_retries(Method::POST, url, Some((content_type, body)))
+            .await
+    }
+
+    async fn send_with_retries(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<(&'static str, Vec<u8>)>,
+    ) -> Result<Response, Error> {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            let result = self.send(method.clone(), url.clone(), body.clone()).await;
+
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(Error::Http(e)) => e.is_timeout() || e.is_connect(),
+                Err(_) => false,
+            };
+            if !retryable || self.retry_policy.max_retries == 0 {
+                return result;
+            }
+            if attempts > self.retry_policy.max_retries {
+                let source = match result {
+                    Ok(response) => Error::Status(response.status()),
+                    Err(e) => e,
+                };
+
+                return Err(Error::GaveUp {
+                    url,
+                    attempts,
+                    source: Box::new(source),
+                });
+            }
+
+            let delay = self.retry_policy.delay(attempts - 1);
+            tracing::debug!(%url, attempts, ?delay, "Request failed, retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<(&'static str, Vec<u8>)>,
+    ) -> Result<Response, Error> {
+        let response = self
+            .request(method.clone(), url.clone(), body.clone())
+            .send()
+            .await?;
+
+        let credentials = match &self.credentials {
+            Some(credentials) if response.status() == StatusCode::UNAUTHORIZED => credentials,
+            _ => return check_authorized(response),
+        };
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|challenge| challenge.to_str().ok())
+            .ok_or(Error::Unauthorized)?;
+        let authorization = authorization(
+            credentials,
+            challenge,
+            &method,
+            &url,
+            body.as_ref().map(|(_, body)| body.as_slice()),
+        )?;
+
+        let response = self
+            .request(method, url, body)
+            .header(AUTHORIZATION, authorization)
+            .send()
+            .await?;
+
+        check_authorized(response)
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<(&'static str, Vec<u8>)>,
+    ) -> RequestBuilder {
+        let request = self.inner.request(method, url);
+
+        match body {
+            Some((content_type, body)) => request.header(CONTENT_TYPE, content_type).body(body),
+            None => request,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl jsonrpc_client::SendRequest for Client {
+    type Error = Error;
+
+    async fn send_request<P>(
+        &self,
+        endpoint: Url,
+        body: String,
+    ) -> Result<jsonrpc_client::Response<P>, Self::Error>
+    where
+        P: DeserializeOwned,
+    {
+        let response = if is_read_only(&body) {
+            self.post_idempotent(endpoint, "application/json", body.into_bytes())
+                .await?
+        } else {
+            self.post(endpoint, "application/json", body.into_bytes())
+                .await?
+        };
+
+        Ok(response.json().await?)
+    }
+}
+
+/// The JSON-RPC methods of monerod and monero-wallet-rpc that only read
+/// state are all named `get_*`.
+fn is_read_only(body: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Request {
+        method: String,
+    }
+
+    serde_json::from_str::<Request>(body).is_ok_and(|request| request.method.starts_with("get_"))
+}
+
+/// Answers the digest `challenge` the daemon sent for a request.
+fn authorization(
+    credentials: &Credentials,
+    challenge: &str,
+    method: &Method,
+    url: &Url,
+    body: Option<&[u8]>,
+) -> Result<String, Error> {
+    let uri = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+    let context = AuthContext::new_with_method(
+        credentials.username.as_str(),
+        credentials.password.as_str(),
+        uri,
+        body,
+        HttpMethod::from(method.as_str()),
+    );
+
+    let mut prompt = digest_auth::parse(challenge)?;
+
+    Ok(prompt.respond(&context)?.to_header_string())
+}
+
+fn check_authorized(response: Response) -> Result<Response, Error> {
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err(Error::Unauthorized);
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    Challenge(digest_auth::Error),
+    Unauthorized,
+    Status(StatusCode),
+    GaveUp {
+        url: Url,
+        attempts: u32,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Whether the request failed because no connection to the server could
+    /// be established, e.g. because it is not running.
+    pub fn is_connect(&self) -> bool {
+        match self {
+            Error::Http(e) => e.is_connect(),
+            Error::GaveUp { source, .. } => source.is_connect(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "{}", e),
+            Error::Challenge(e) => write!(f, "Failed to answer authentication challenge: {}", e),
+            Error::Unauthorized => write!(
+                f,
+                "Daemon requires authentication, check the configured RPC username and password"
+            ),
+            Error::Status(status) => write!(f, "Request failed with status code {}", status),
+            Error::GaveUp {
+                url,
+                attempts,
+                source,
+            } => write!(
+                f,
+                "Request to {} failed after {} attempts: {}",
+                url, attempts, source
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Challenge(e) => Some(e),
+            Error::Unauthorized | Error::Status(_) => None,
+            Error::GaveUp { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<digest_auth::Error> for Error {
+    fn from(e: digest_auth::Error) -> Self {
+        Error::Challenge(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const CHALLENGE: &str = r#"Digest qop="auth",algorithm=MD5,realm="monero-rpc",nonce="kF8YjPAq1Fo5vpJEvXvUPA==",stale=false"#;
+
+    /// Serves one HTTP response per connection and returns the request heads
+    /// it received.
+    async fn serve(listener: TcpListener, responses: Vec<String>) -> Vec<String> {
+        let mut requests = Vec::new();
+
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let read = stream.read(&mut buffer).await.unwrap();
+            requests.push(String::from_utf8_lossy(&buffer[..read]).into_owned());
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+
+        requests
+    }
+
+    fn response(status: &str, extra_header: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\n{}Connection: close\r\nContent-Length: 2\r\n\r\nok",
+            status, extra_header
+        )
+    }
+
+    #[tokio::test]
+    async fn answers_digest_challenge_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/json_rpc", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = tokio::spawn(serve(
+            listener,
+            vec![
+                response(
+                    "401 Unauthorized",
+                    &format!("WWW-Authenticate: {}\r\n", CHALLENGE),
+                ),
+                response("200 OK", ""),
+            ],
+        ));
+        let client = Client::new(
+            reqwest::Client::new(),
+            Some(Credentials::new("alice", "secret")),
+        );
+
+        let response = client
+            .post(url, "application/json", b"{}".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let requests = server.await.unwrap();
+        assert!(!requests[0].to_lowercase().contains("authorization:"));
+        assert!(requests[1].contains(r#"Digest username="alice", realm="monero-rpc""#));
+        assert!(requests[1].contains(r#"uri="/json_rpc""#));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_of_read_only_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/get_outs.bin", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = tokio::spawn(serve(
+            listener,
+            vec![
+                response("502 Bad Gateway", ""),
+                response("503 Service Unavailable", ""),
+                response("200 OK", ""),
+            ],
+        ));
+        let client = Client::new(reqwest::Client::new(), None).with_retry_policy(RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+        });
+
+        let response = client
+            .post_idempotent(url, "application/octet-stream", vec![])
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries_with_url_and_attempts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/get_info", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = tokio::spawn(serve(
+            listener,
+            vec![
+                response("502 Bad Gateway", ""),
+                response("502 Bad Gateway", ""),
+            ],
+        ));
+        let client = Client::new(reqwest::Client::new(), None).with_retry_policy(RetryPolicy {
+            max_retries: 1,
+            backoff: Duration::from_millis(1),
+        });
+
+        let error = client.get(url.clone()).await.unwrap_err();
+
+        assert!(matches!(error, Error::GaveUp { attempts: 2, .. }));
+        assert!(error.to_string().contains(url.as_str()));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_requests_that_change_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!(
+            "http://{}/send_raw_transaction",
+            listener.local_addr().unwrap()
+        )
+        .parse()
+        .unwrap();
+        let server = tokio::spawn(serve(listener, vec![response("502 Bad Gateway", "")]));
+        let client = Client::new(reqwest::Client::new(), None).with_retry_policy(RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_millis(1),
+        });
+
+        let response = client
+            .post(url, "application/json", b"{}".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn only_get_methods_are_read_only() {
+        assert!(is_read_only(
+            r#"{"id":"1","jsonrpc":"2.0","method":"get_block_count","params":[]}"#
+        ));
+        assert!(!is_read_only(
+            r#"{"id":"1","jsonrpc":"2.0","method":"generateblocks","params":[]}"#
+        ));
+        assert!(!is_read_only(
+            r#"{"id":"1","jsonrpc":"2.0","method":"transfer","params":{}}"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_credentials() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/get_info", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = tokio::spawn(serve(
+            listener,
+            vec![response(
+                "401 Unauthorized",
+                &format!("WWW-Authenticate: {}\r\n", CHALLENGE),
+            )],
+        ));
+        let client = Client::new(reqwest::Client::new(), None);
+
+        let result = client.get(url).await;
+
+        assert!(matches!(result, Err(Error::Unauthorized)));
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+//! Decoy selection for ring signatures, following the gamma picker of the
+//! reference wallet (`wallet2`). Picking decoys any other way makes our
+//! transactions stand out on chain.
+
+use crate::monerod::{Client, GetOutputsOut, MonerodRpc as _};
+use anyhow::{anyhow, bail, Context, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Gamma};
+use std::collections::BTreeSet;
+
+/// Parameters of the distribution of spend ages, in log-seconds, measured by
+/// Möser et al.
+const GAMMA_SHAPE: f64 = 19.28;
+const GAMMA_SCALE: f64 = 1.0 / 1.61;
+
+const DIFFICULTY_TARGET_SECS: u64 = 120;
+/// Outputs can only be spent this many blocks after they were created.
+const SPENDABLE_AGE: usize = 10;
+const DEFAULT_UNLOCK_TIME_SECS: f64 = 1200.0;
+const RECENT_SPEND_WINDOW_SECS: u64 = 1800;
+const BLOCKS_IN_A_YEAR: usize = 86400 * 365 / 120;
+
+/// Gives up on rings that cannot be filled, e.g. on a fresh regtest chain.
+const MAX_PICKS_PER_DECOY: usize = 100;
+
+/// Picks `ring_size - 1` unlocked decoys for the output with global index
+/// `real_global_index`, considering outputs created up to `unlock_height`.
+///
+/// The decoys are returned sorted by global index and never include the real
+/// output.
+pub async fn pick_decoys(
+    client: &Client,
+    real_global_index: u64,
+    ring_size: usize,
+    unlock_height: u64,
+) -> Result<Vec<GetOutputsOut>> {
+    let distribution = client
+        .get_output_distribution(vec![0], true, 0, unlock_height, false)
+        .await?
+        .distributions
+        .into_iter()
+        .find(|distribution| distribution.amount == 0)
+        .context("Daemon did not return the RingCT output distribution")?;
+    let picker = GammaPicker::new(distribution.distribution)?;
+
+    let needed = ring_size
+        .checked_sub(1)
+        .context("Ring size must be at least 1")?;
+    let mut rng = StdRng::from_entropy();
+    let mut tried = BTreeSet::from([real_global_index]);
+    let mut decoys = Vec::with_capacity(needed);
+
+    while decoys.len() < needed {
+        let candidates = sample_indices(&picker, &tried, needed - decoys.len(), &mut rng)?;
+        tried.extend(candidates.iter().copied());
+
+        let outputs = candidates
+            .iter()
+            .map(|index| GetOutputsOut {
+                amount: 0,
+                index: *index,
+            })
+            .collect::<Vec<_>>();
+        let keys = client.get_outs(outputs.clone()).await?;
+
+        decoys.extend(
+            outputs
+                .into_iter()
+                .zip(keys.outs)
+                .filter(|(_, key)| key.unlocked)
+                .map(|(output, _)| output),
+        );
+    }
+
+    decoys.sort_by_key(|output| output.index);
+
+    Ok(decoys)
+}
+
+/// Draws `count` distinct output indices from `picker` that are not in
+/// `exclude`.
+pub fn sample_indices<R: Rng>(
+    picker: &GammaPicker,
+    exclude: &BTreeSet<u64>,
+    count: usize,
+    rng: &mut R,
+) -> Result<BTreeSet<u64>> {
+    let mut picked = BTreeSet::new();
+
+    for _ in 0..count * MAX_PICKS_PER_DECOY {
+        if picked.len() == count {
+            return Ok(picked);
+        }
+
+        if let Some(index) = picker.pick(rng) {
+            if !exclude.contains(&index) {
+                picked.insert(index);
+            }
+        }
+    }
+
+    if picked.len() < count {
+        bail!(
+            "Could only find {} of {} decoys among {} spendable outputs",
+            picked.len(),
+            count,
+            picker.num_spendable_outputs
+        )
+    }
+
+    Ok(picked)
+}
+
+#[derive(Debug)]
+pub struct GammaPicker {
+    /// Cumulative number of outputs at the end of each block.
+    rct_offsets: Vec<u64>,
+    /// Blocks whose outputs are old enough to be spent.
+    num_spendable_blocks: usize,
+    num_spendable_outputs: u64,
+    average_output_time: f64,
+    gamma: Gamma<f64>,
+}
+
+impl GammaPicker {
+    /// Builds the picker from the cumulative RingCT output distribution.
+    pub fn new(rct_offsets: Vec<u64>) -> Result<Self> {
+        let num_spendable_blocks = rct_offsets
+            .len()
+            .checked_sub(SPENDABLE_AGE)
+            .filter(|blocks| *blocks > 0)
+            .context("Not enough blocks to pick decoys from")?;
+        let num_spendable_outputs = rct_offsets[num_spendable_blocks - 1];
+
+        let blocks_to_consider = rct_offsets.len().min(BLOCKS_IN_A_YEAR);
+        let outputs_before = match rct_offsets.len() - blocks_to_consider {
+            0 => 0,
+            first => rct_offsets[first - 1],
+        };
+        let outputs_to_consider = rct_offsets[rct_offsets.len() - 1] - outputs_before;
+        if outputs_to_consider == 0 {
+            bail!("No outputs to pick decoys from")
+        }
+
+        let average_output_time = average_output_time(blocks_to_consider, outputs_to_consider);
+        let gamma = Gamma::new(GAMMA_SHAPE, GAMMA_SCALE)
+            .map_err(|e| anyhow!("Invalid gamma distribution: {:?}", e))?;
+
+        Ok(Self {
+            rct_offsets,
+            num_spendable_blocks,
+            num_spendable_outputs,
+            average_output_time,
+            gamma,
+        })
+    }
+
+    /// Picks one output index, or `None` if the sample falls outside the
+    /// chain or into a block without outputs; callers simply pick again.
+    // The sampled ages are positive and far below 2^53 seconds, so the
+    // conversions between f64 and u64 neither truncate nor lose precision.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn pick<R: Rng>(&self, rng: &mut R) -> Option<u64> {
+        let mut age = self.gamma.sample(rng).exp();
+        if age > DEFAULT_UNLOCK_TIME_SECS {
+            age -= DEFAULT_UNLOCK_TIME_SECS;
+        } else {
+            age = rng.gen_range(0, RECENT_SPEND_WINDOW_SECS) as f64;
+        }
+
+        let outputs_ago = (age / self.average_output_time) as u64;
+        if outputs_ago >= self.num_spendable_outputs {
+            return None;
+        }
+        let output_index = self.num_spendable_outputs - 1 - outputs_ago;
+
+        let spendable = &self.rct_offsets[..self.num_spendable_blocks];
+        let block = spendable.partition_point(|outputs| *outputs < output_index);
+        let first_output = match block {
+            0 => 0,
+            block => spendable[block - 1],
+        };
+        let outputs_in_block = spendable[block] - first_output;
+        if outputs_in_block == 0 {
+            return None;
+        }
+
+        Some(first_output + rng.gen_range(0, outputs_in_block))
+    }
+}
+
+// Block and output counts stay far below 2^53, so converting them to f64 is
+// lossless.
+#[allow(clippy::cast_precision_loss)]
+fn average_output_time(blocks: usize, outputs: u64) -> f64 {
+    (DIFFICULTY_TARGET_SECS as f64) * (blocks as f64) / (outputs as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3000 blocks with a varying number of outputs, including empty blocks.
+    fn fixture_distribution() -> Vec<u64> {
+        (0..3000u64)
+            .scan(0, |total, height| {
+                *total += height % 7;
+                Some(*total)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picked_indices_are_spendable_unique_and_exclude_the_real_output() {
+        let offsets = fixture_distribution();
+        let num_spendable_outputs = offsets[offsets.len() - SPENDABLE_AGE - 1];
+        let picker = GammaPicker::new(offsets).unwrap();
+        let real = num_spendable_outputs - 5;
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let picked = sample_indices(&picker, &BTreeSet::from([real]), 15, &mut rng).unwrap();
+
+        assert_eq!(picked.len(), 15);
+        assert!(!picked.contains(&real));
+        assert!(picked.iter().all(|index| *index < num_spendable_outputs));
+    }
+
+    #[test]
+    fn picks_are_deterministic_for_a_seeded_rng() {
+        let picker = GammaPicker::new(fixture_distribution()).unwrap();
+
+        let first =
+            sample_indices(&picker, &BTreeSet::new(), 15, &mut StdRng::seed_from_u64(7)).unwrap();
+        let second =
+            sample_indices(&picker, &BTreeSet::new(), 15, &mut StdRng::seed_from_u64(7)).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn picks_favour_recent_outputs() {
+        let offsets = fixture_distribution();
+        let num_spendable_outputs = offsets[offsets.len() - SPENDABLE_AGE - 1];
+        let picker = GammaPicker::new(offsets).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let picked = sample_indices(&picker, &BTreeSet::new(), 200, &mut rng).unwrap();
+        let recent = picked
+            .iter()
+            .filter(|index| **index >= num_spendable_outputs / 2)
+            .count();
+
+        assert!(recent > picked.len() / 2);
+    }
+
+    #[test]
+    fn too_few_outputs_are_an_error() {
+        let picker = GammaPicker::new(vec![0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2]).unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+
+        assert!(sample_indices(&picker, &BTreeSet::new(), 15, &mut rng).is_err());
+    }
+
+    #[test]
+    fn chain_shorter_than_the_spendable_age_is_rejected() {
+        assert!(GammaPicker::new(vec![1; SPENDABLE_AGE]).is_err());
+    }
+}
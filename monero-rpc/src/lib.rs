@@ -12,7 +12,13 @@
 )]
 #![forbid(unsafe_code)]
 
+pub mod auth;
+pub mod decoys;
 pub mod monerod;
 pub mod wallet;
+#[cfg(feature = "zmq")]
+pub mod zmq;
+
+pub use auth::Credentials;
 
 pub use jsonrpc_client as jsonrpc;
@@ -1,49 +1,185 @@
-use anyhow::{Context, Result};
+use crate::auth;
+use crate::auth::RetryPolicy;
+use crate::Credentials;
+use anyhow::{anyhow, bail, Context, Result};
+use futures::Stream;
+use monero::consensus::Decodable;
 use monero::cryptonote::hash::Hash;
 use monero::util::ringct;
 use monero::PublicKey;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::Cursor;
+use std::ops::Range;
+use std::time::Duration;
+
+/// monerod rejects `get_transactions` requests for more hashes than this
+/// from untrusted clients, so larger requests are split up.
+const GET_TRANSACTIONS_CHUNK_SIZE: usize = 100;
 
 #[jsonrpc_client::api(version = "2.0")]
 pub trait MonerodRpc {
     async fn generateblocks(&self, amount_of_blocks: u32, wallet_address: String)
         -> GenerateBlocks;
-    async fn get_block_header_by_height(&self, height: u32) -> BlockHeader;
+    async fn get_block_header_by_height(&self, height: u64) -> BlockHeaderResponse;
+    async fn get_block_headers_range(&self, start_height: u64, end_height: u64) -> BlockHeaders;
     async fn get_block_count(&self) -> BlockCount;
     async fn get_block(&self, height: u32) -> GetBlockResponse;
+    async fn get_fee_estimate(&self) -> FeeEstimate;
+    async fn get_info(&self) -> GetInfo;
+    async fn get_output_distribution(
+        &self,
+        amounts: Vec<u64>,
+        cumulative: bool,
+        from_height: u64,
+        to_height: u64,
+        binary: bool,
+    ) -> GetOutputDistribution;
 }
 
 #[jsonrpc_client::implement(MonerodRpc)]
 #[derive(Debug, Clone)]
 pub struct Client {
-    inner: reqwest::Client,
+    inner: auth::Client,
     base_url: reqwest::Url,
     get_o_indexes_bin_url: reqwest::Url,
     get_outs_bin_url: reqwest::Url,
+    get_blocks_bin_url: reqwest::Url,
+    get_transactions_url: reqwest::Url,
+    send_raw_transaction_url: reqwest::Url,
+}
+
+/// Configures how a [`Client`] talks to monerod.
+///
+/// Read-only calls that time out or hit a 5xx response are retried up to
+/// `max_retries` times, waiting `backoff` before the first retry and twice
+/// as long before every further one. `send_raw_transaction` is never retried.
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    base_url: reqwest::Url,
+    credentials: Option<Credentials>,
+    timeout: Duration,
+    max_retries: u32,
+    backoff: Duration,
+    accept_invalid_certs: bool,
+}
+
+impl ClientBuilder {
+    /// `base_url` is where the daemon's RPC is served, e.g.
+    /// `https://node.example:443` or `https://example.com/monero/` behind a
+    /// reverse proxy. The endpoints are resolved relative to it.
+    pub fn new(base_url: reqwest::Url) -> Self {
+        Self {
+            base_url,
+            credentials: None,
+            timeout: Duration::from_secs(30),
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// Logs in to a daemon started with `--rpc-login`.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Accepts any TLS certificate, e.g. a self-signed one of a node you run
+    /// yourself. Only use this if you trust the network path to the node.
+    pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let Self {
+            base_url,
+            credentials,
+            timeout,
+            max_retries,
+            backoff,
+            accept_invalid_certs,
+        } = self;
+
+        Ok(Client {
+            inner: auth::Client::new(
+                reqwest::ClientBuilder::new()
+                    .connection_verbose(true)
+                    .timeout(timeout)
+                    .danger_accept_invalid_certs(accept_invalid_certs)
+                    .build()?,
+                credentials,
+            )
+            .with_retry_policy(RetryPolicy {
+                max_retries,
+                backoff,
+            }),
+            base_url: endpoint(&base_url, "json_rpc")?,
+            get_o_indexes_bin_url: endpoint(&base_url, "get_o_indexes.bin")?,
+            get_outs_bin_url: endpoint(&base_url, "get_outs.bin")?,
+            get_blocks_bin_url: endpoint(&base_url, "getblocks.bin")?,
+            get_transactions_url: endpoint(&base_url, "get_transactions")?,
+            send_raw_transaction_url: endpoint(&base_url, "send_raw_transaction")?,
+        })
+    }
+}
+
+/// Resolves `name` relative to the daemon's `base_url`, keeping any path
+/// prefix whether or not it ends with a slash.
+pub fn endpoint(base_url: &reqwest::Url, name: &str) -> Result<reqwest::Url> {
+    let mut base_url = base_url.clone();
+    if !base_url.path().ends_with('/') {
+        let path = format!("{}/", base_url.path());
+        base_url.set_path(&path);
+    }
+
+    base_url
+        .join(name)
+        .with_context(|| format!("Failed to build {} url from {}", name, base_url))
 }
 
 impl Client {
-    /// New local host monerod RPC client.
-    pub fn localhost(port: u16) -> Result<Self> {
-        Self::new("127.0.0.1".to_owned(), port)
+    /// New monerod RPC client with the default settings of [`ClientBuilder`].
+    pub fn new(base_url: reqwest::Url) -> Result<Self> {
+        ClientBuilder::new(base_url).build()
     }
 
-    fn new(host: String, port: u16) -> Result<Self> {
-        Ok(Self {
-            inner: reqwest::ClientBuilder::new()
-                .connection_verbose(true)
-                .build()?,
-            base_url: format!("http://{}:{}/json_rpc", host, port)
-                .parse()
-                .context("url is well formed")?,
-            get_o_indexes_bin_url: format!("http://{}:{}/get_o_indexes.bin", host, port)
-                .parse()
-                .context("url is well formed")?,
-            get_outs_bin_url: format!("http://{}:{}/get_outs.bin", host, port)
+    /// New local host monerod RPC client.
+    pub fn localhost(port: u16) -> Result<Self> {
+        Self::new(
+            format!("http://127.0.0.1:{}", port)
                 .parse()
                 .context("url is well formed")?,
-        })
+        )
+    }
+
+    /// New monerod RPC client for a daemon started with `--rpc-login`.
+    pub fn new_with_auth(
+        base_url: reqwest::Url,
+        username: String,
+        password: String,
+    ) -> Result<Self> {
+        ClientBuilder::new(base_url)
+            .credentials(Credentials::new(username, password))
+            .build()
     }
 
     pub async fn get_o_indexes(&self, txid: Hash) -> Result<GetOIndexesResponse> {
@@ -59,6 +195,186 @@ impl Client {
             .await
     }
 
+    /// Fetches the blocks from `start_height` on, as many as the daemon is
+    /// willing to return in one response.
+    pub async fn get_blocks_bin(&self, start_height: u64) -> Result<GetBlocksResponse> {
+        // The daemon needs at least one known block id to find the chain
+        // we are following; the genesis block is always known.
+        let genesis = self.get_block_header_by_height(0).await?.block_header;
+        let block_ids = hex::decode(&genesis.hash).context("Genesis block hash is not hex")?;
+
+        let response: GetBlocksResponse = self
+            .binary_request(
+                self.get_blocks_bin_url.clone(),
+                GetBlocksPayload {
+                    requested_info: 0,
+                    block_ids,
+                    start_height,
+                    prune: false,
+                    no_miner_tx: false,
+                    pool_info_since: 0,
+                },
+            )
+            .await?;
+
+        if response.base.status != Status::Ok {
+            bail!(
+                "Daemon failed to return blocks from height {}",
+                start_height
+            )
+        }
+
+        Ok(response)
+    }
+
+    /// Yields the blocks at `heights` in order, requesting further pages from
+    /// the daemon until the range is covered.
+    pub fn stream_blocks(
+        &self,
+        heights: Range<u64>,
+    ) -> impl Stream<Item = Result<monero::Block>> + '_ {
+        let end = heights.end;
+
+        futures::stream::try_unfold(
+            (heights.start, VecDeque::new()),
+            move |(mut next_height, mut pending)| async move {
+                if pending.is_empty() && next_height < end {
+                    pending = self.blocks_page(next_height, end).await?;
+                    next_height += u64::try_from(pending.len())?;
+                }
+
+                Ok::<_, anyhow::Error>(
+                    pending
+                        .pop_front()
+                        .map(|block| (block, (next_height, pending))),
+                )
+            },
+        )
+    }
+
+    /// Decodes the blocks the daemon returns in one page, starting at `from`
+    /// and stopping before `end`. Fails rather than returning an empty page.
+    async fn blocks_page(&self, from: u64, end: u64) -> Result<VecDeque<monero::Block>> {
+        let response = self.get_blocks_bin(from).await?;
+        if response.start_height > from {
+            bail!(
+                "Daemon returned blocks from height {} instead of {}",
+                response.start_height,
+                from
+            )
+        }
+
+        let blocks = (response.start_height..)
+            .zip(response.blocks)
+            .skip_while(|(height, _)| *height < from)
+            .take_while(|(height, _)| *height < end)
+            .map(|(_, entry)| entry.decode_block())
+            .collect::<Result<VecDeque<_>>>()?;
+
+        if blocks.is_empty() {
+            bail!(
+                "Daemon at height {} returned no blocks from height {}",
+                response.current_height,
+                from
+            )
+        }
+
+        Ok(blocks)
+    }
+
+    /// Looks up the transactions with the given ids, in the same order.
+    ///
+    /// Transactions the daemon does not know about, e.g. because they were
+    /// evicted from the mempool, are returned as `None`.
+    pub async fn get_transactions(
+        &self,
+        txids: &[Hash],
+    ) -> Result<Vec<(Hash, Option<TransactionInfo>)>> {
+        let mut transactions = Vec::with_capacity(txids.len());
+
+        for chunk in txids.chunks(GET_TRANSACTIONS_CHUNK_SIZE) {
+            let payload = GetTransactionsPayload {
+                txs_hashes: chunk
+                    .iter()
+                    .map(|txid| hex::encode(txid.as_bytes()))
+                    .collect(),
+                decode_as_json: false,
+            };
+            let response = self
+                .json_request(self.get_transactions_url.clone(), payload)
+                .await?;
+
+            transactions.extend(match_transactions(chunk, response)?);
+        }
+
+        Ok(transactions)
+    }
+
+    /// Submits `transaction` to the daemon.
+    ///
+    /// With `do_not_relay` the daemon only validates the transaction and
+    /// keeps it out of the network. If the daemon rejects the transaction
+    /// the error is a [`SendRawTransactionError`].
+    pub async fn send_raw_transaction(
+        &self,
+        transaction: &monero::Transaction,
+        do_not_relay: bool,
+    ) -> Result<()> {
+        let payload = SendRawTransactionPayload {
+            tx_as_hex: hex::encode(monero::consensus::encode::serialize(transaction)),
+            do_not_relay,
+        };
+        // Not retried: the daemon may have accepted the transaction even if
+        // we never saw the response.
+        let response = self
+            .inner
+            .post(
+                self.send_raw_transaction_url.clone(),
+                "application/json",
+                serde_json::to_vec(&payload)?,
+            )
+            .await?;
+        let response: SendRawTransactionResponse = json_response(response).await?;
+
+        if let Some(error) = response.error() {
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails with the number of remaining blocks if the daemon has not
+    /// caught up with the network yet.
+    pub async fn assert_synced(&self) -> Result<()> {
+        let info = self.get_info().await?;
+
+        if let Some(remaining) = info.remaining_blocks() {
+            bail!(
+                "Monero daemon is not synchronized, it is at height {} with {} blocks remaining",
+                info.height,
+                remaining
+            )
+        }
+
+        Ok(())
+    }
+
+    /// Polls the daemon every `poll_interval` until the top block is above
+    /// `height` and returns the height of the new top block.
+    pub async fn wait_for_block_above(&self, height: u64, poll_interval: Duration) -> Result<u64> {
+        loop {
+            let block_count = self.get_block_count().await?.count;
+            let top_height = u64::from(block_count).saturating_sub(1);
+
+            if top_height > height {
+                return Ok(top_height);
+            }
+
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Calls a read-only epee endpoint, retrying failed attempts.
     async fn binary_request<Req, Res>(&self, url: reqwest::Url, request: Req) -> Result<Res>
     where
         Req: Serialize,
@@ -66,9 +382,11 @@ impl Client {
     {
         let response = self
             .inner
-            .post(url)
-            .body(monero_epee_bin_serde::to_bytes(&request)?)
-            .send()
+            .post_idempotent(
+                url,
+                "application/octet-stream",
+                monero_epee_bin_serde::to_bytes(&request)?,
+            )
             .await?;
 
         if !response.status().is_success() {
@@ -79,6 +397,63 @@ impl Client {
 
         Ok(monero_epee_bin_serde::from_bytes(body)?)
     }
+
+    /// Calls a read-only JSON endpoint, retrying failed attempts.
+    async fn json_request<Req, Res>(&self, url: reqwest::Url, request: Req) -> Result<Res>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let response = self
+            .inner
+            .post_idempotent(url, "application/json", serde_json::to_vec(&request)?)
+            .await?;
+
+        json_response(response).await
+    }
+}
+
+async fn json_response<Res>(response: reqwest::Response) -> Result<Res>
+where
+    Res: DeserializeOwned,
+{
+    if !response.status().is_success() {
+        anyhow::bail!("Request failed with status code {}", response.status())
+    }
+
+    Ok(response.json().await?)
+}
+
+/// Pairs each requested txid with the transaction the daemon returned for it.
+fn match_transactions(
+    txids: &[Hash],
+    response: GetTransactionsResponse,
+) -> Result<Vec<(Hash, Option<TransactionInfo>)>> {
+    if response.base.status != Status::Ok {
+        bail!("Daemon failed to look up transactions")
+    }
+
+    let mut found = response
+        .txs
+        .into_iter()
+        .map(|entry| (entry.tx_hash.clone(), entry))
+        .collect::<HashMap<_, _>>();
+
+    txids
+        .iter()
+        .map(|txid| {
+            let tx_hash = hex::encode(txid.as_bytes());
+
+            match found.remove(&tx_hash) {
+                Some(entry) => Ok((*txid, Some(entry.into_transaction_info()?))),
+                None if response.missed_tx.contains(&tx_hash) => Ok((*txid, None)),
+                None => bail!(
+                    "Daemon neither returned transaction {} nor reported it as missed",
+                    tx_hash
+                ),
+            }
+        })
+        .collect()
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -92,25 +467,316 @@ pub struct BlockCount {
     pub count: u32,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockHeaderResponse {
+    pub block_header: BlockHeader,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockHeaders {
+    pub headers: Vec<BlockHeader>,
+}
+
 // We should be able to use monero-rs for this but it does not include all
 // the fields.
+//
+// `difficulty` and `cumulative_difficulty` only hold the low 64 bits; the
+// full values are in the hex-encoded `wide_` fields.
 #[derive(Clone, Debug, Deserialize)]
 pub struct BlockHeader {
-    pub block_size: u32,
-    pub depth: u32,
-    pub difficulty: u32,
+    pub block_size: u64,
+    pub block_weight: u64,
+    pub cumulative_difficulty: u64,
+    pub wide_cumulative_difficulty: String,
+    pub depth: u64,
+    pub difficulty: u64,
+    pub wide_difficulty: String,
     pub hash: String,
-    pub height: u32,
-    pub major_version: u32,
-    pub minor_version: u32,
+    pub height: u64,
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub miner_tx_hash: String,
     pub nonce: u32,
-    pub num_txes: u32,
+    pub num_txes: u64,
     pub orphan_status: bool,
+    /// Only filled in when requested with `fill_pow_hash`.
+    #[serde(default)]
+    pub pow_hash: String,
     pub prev_hash: String,
     pub reward: u64,
-    pub timestamp: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct FeeEstimate {
+    /// Fee per byte in piconero.
+    pub fee: u64,
+    /// Fees should be rounded up to a multiple of this.
+    pub quantization_mask: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct GetInfo {
+    pub status: String,
+    pub height: u64,
+    /// Height of the best chain announced by our peers, 0 if none of them is
+    /// ahead of us.
+    pub target_height: u64,
+    pub synchronized: bool,
+    pub busy_syncing: bool,
+    pub offline: bool,
+    pub nettype: String,
+    pub mainnet: bool,
+    pub stagenet: bool,
+    pub testnet: bool,
+    pub top_block_hash: String,
+}
+
+impl GetInfo {
+    /// Number of blocks the daemon still has to download, `None` once it is
+    /// synchronized.
+    pub fn remaining_blocks(&self) -> Option<u64> {
+        if self.synchronized {
+            return None;
+        }
+
+        Some(self.target_height.saturating_sub(self.height))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct GetOutputDistribution {
+    pub distributions: Vec<OutputDistribution>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct OutputDistribution {
+    pub amount: u64,
+    /// Number of outputs created before `start_height`.
+    pub base: u64,
+    /// Number of outputs created per block from `start_height` on, or the
+    /// running total if requested as cumulative.
+    pub distribution: Vec<u64>,
+    pub start_height: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionInfo {
+    pub transaction: monero::Transaction,
+    pub in_pool: bool,
+    /// Height of the block that includes the transaction, `None` while it is
+    /// in the mempool.
+    pub block_height: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct GetTransactionsPayload {
+    txs_hashes: Vec<String>,
+    decode_as_json: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GetTransactionsResponse {
+    #[serde(flatten)]
+    base: BaseResponse,
+    #[serde(default)]
+    txs: Vec<GetTransactionsEntry>,
+    #[serde(default)]
+    missed_tx: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GetTransactionsEntry {
+    tx_hash: String,
+    as_hex: String,
+    in_pool: bool,
+    #[serde(default)]
+    block_height: u64,
+}
+
+impl GetTransactionsEntry {
+    fn into_transaction_info(self) -> Result<TransactionInfo> {
+        let bytes = hex::decode(&self.as_hex)
+            .with_context(|| format!("Transaction {} is not valid hex", self.tx_hash))?;
+        let transaction = monero::Transaction::consensus_decode(&mut Cursor::new(bytes))
+            .map_err(|e| anyhow!("Failed to decode transaction {}: {}", self.tx_hash, e))?;
+
+        Ok(TransactionInfo {
+            transaction,
+            in_pool: self.in_pool,
+            block_height: (!self.in_pool).then_some(self.block_height),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct GetBlocksPayload {
+    requested_info: u8,
+    #[serde(serialize_with = "byte_array::serialize")]
+    block_ids: Vec<u8>,
+    start_height: u64,
+    prune: bool,
+    no_miner_tx: bool,
+    pool_info_since: u64,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct GetBlocksResponse {
+    #[serde(flatten)]
+    pub base: BaseResponse,
+    #[serde(default)]
+    pub blocks: Vec<BlockCompleteEntry>,
+    /// Height of the first entry in `blocks`.
+    pub start_height: u64,
+    /// Height of the daemon's chain.
+    pub current_height: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockCompleteEntry {
+    pub block: Blob,
+    #[serde(default)]
+    pub block_weight: u64,
+    /// The blobs of the block's transactions, without the miner transaction.
+    #[serde(default)]
+    pub txs: Vec<Blob>,
+}
+
+impl BlockCompleteEntry {
+    pub fn decode_block(&self) -> Result<monero::Block> {
+        monero::Block::consensus_decode(&mut Cursor::new(&self.block.0))
+            .map_err(|e| anyhow!("Failed to decode block: {}", e))
+    }
+}
+
+/// Binary string as returned by the epee endpoints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blob(pub Vec<u8>);
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Blob;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a byte buffer")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(Blob(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(Blob(v))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(Visitor)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SendRawTransactionPayload {
+    tx_as_hex: String,
+    do_not_relay: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct SendRawTransactionResponse {
+    status: String,
+    reason: String,
+    double_spend: bool,
+    fee_too_low: bool,
+    invalid_input: bool,
+    invalid_output: bool,
+    low_mixin: bool,
+    overspend: bool,
+    too_big: bool,
+    not_relayed: bool,
+}
+
+impl SendRawTransactionResponse {
+    fn error(&self) -> Option<SendRawTransactionError> {
+        if self.status == "OK" {
+            return None;
+        }
+
+        let rejection = if self.double_spend {
+            Rejection::DoubleSpend
+        } else if self.fee_too_low {
+            Rejection::FeeTooLow
+        } else if self.invalid_input {
+            Rejection::InvalidInput
+        } else if self.invalid_output {
+            Rejection::InvalidOutput
+        } else if self.low_mixin {
+            Rejection::LowMixin
+        } else if self.overspend {
+            Rejection::Overspend
+        } else if self.too_big {
+            Rejection::TooBig
+        } else if self.not_relayed {
+            Rejection::NotRelayed
+        } else {
+            Rejection::Other
+        };
+
+        Some(SendRawTransactionError {
+            rejection,
+            reason: self.reason.clone(),
+        })
+    }
+}
+
+/// Why monerod refused a transaction submitted through
+/// [`Client::send_raw_transaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    DoubleSpend,
+    FeeTooLow,
+    InvalidInput,
+    InvalidOutput,
+    LowMixin,
+    Overspend,
+    TooBig,
+    NotRelayed,
+    /// The daemon failed without setting any of the flags above.
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendRawTransactionError {
+    pub rejection: Rejection,
+    /// Free-form explanation from the daemon, often empty.
+    pub reason: String,
+}
+
+impl fmt::Display for SendRawTransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Daemon rejected transaction: {:?}", self.rejection)?;
+
+        if !self.reason.is_empty() {
+            write!(f, " ({})", self.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for SendRawTransactionError {}
+
 #[derive(Debug, Deserialize)]
 pub struct GetBlockResponse {
     #[serde(with = "monero_serde_hex_block")]
@@ -258,3 +924,418 @@ mod byte_array {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_client::Response;
+
+    #[test]
+    fn can_deserialize_fee_estimate() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+            "credits": 0,
+            "fee": 20000,
+            "fees": [20000, 80000, 320000, 4000000],
+            "quantization_mask": 10000,
+            "status": "OK",
+            "top_hash": "",
+            "untrusted": false
+          }
+        }"#;
+
+        let _: Response<FeeEstimate> = serde_json::from_str(response).unwrap();
+    }
+
+    #[test]
+    fn can_deserialize_mainnet_get_info() {
+        let info = get_info(
+            r#"{
+              "id": "0",
+              "jsonrpc": "2.0",
+              "result": {
+                "adjusted_time": 1729256317,
+                "alt_blocks_count": 0,
+                "block_size_limit": 600000,
+                "block_size_median": 300000,
+                "block_weight_limit": 600000,
+                "block_weight_median": 300000,
+                "bootstrap_daemon_address": "",
+                "busy_syncing": false,
+                "credits": 0,
+                "cumulative_difficulty": 0,
+                "cumulative_difficulty_top64": 0,
+                "database_size": 236223201280,
+                "difficulty": 294937424578,
+                "difficulty_top64": 0,
+                "free_space": 1073741824000,
+                "grey_peerlist_size": 4998,
+                "height": 3257430,
+                "height_without_bootstrap": 3257430,
+                "incoming_connections_count": 12,
+                "mainnet": true,
+                "nettype": "mainnet",
+                "offline": false,
+                "outgoing_connections_count": 16,
+                "restricted": false,
+                "rpc_connections_count": 1,
+                "stagenet": false,
+                "start_time": 1729150000,
+                "status": "OK",
+                "synchronized": true,
+                "target": 120,
+                "target_height": 0,
+                "testnet": false,
+                "top_block_hash": "4f1d2d6bc0d5a2fca1d0b8af6c8b32d9a3e1b4ab1f9b0d5b56d3c7e2a1f0e9d8",
+                "top_hash": "",
+                "tx_count": 48125632,
+                "tx_pool_size": 23,
+                "untrusted": false,
+                "update_available": false,
+                "version": "0.18.3.4-release",
+                "was_bootstrap_ever_used": false,
+                "white_peerlist_size": 1000,
+                "wide_cumulative_difficulty": "0x5f3c1a2b3c4d5e6f7a",
+                "wide_difficulty": "0x44ab3c12c2"
+              }
+            }"#,
+        );
+
+        assert!(info.mainnet);
+        assert_eq!(info.nettype, "mainnet");
+        assert_eq!(info.height, 3257430);
+        assert_eq!(info.remaining_blocks(), None);
+    }
+
+    #[test]
+    fn can_deserialize_syncing_stagenet_get_info() {
+        let info = get_info(
+            r#"{
+              "id": "0",
+              "jsonrpc": "2.0",
+              "result": {
+                "adjusted_time": 1729256317,
+                "alt_blocks_count": 0,
+                "block_size_limit": 600000,
+                "block_size_median": 300000,
+                "block_weight_limit": 600000,
+                "block_weight_median": 300000,
+                "bootstrap_daemon_address": "",
+                "busy_syncing": true,
+                "credits": 0,
+                "cumulative_difficulty": 0,
+                "cumulative_difficulty_top64": 0,
+                "database_size": 10737418240,
+                "difficulty": 276713,
+                "difficulty_top64": 0,
+                "free_space": 1073741824000,
+                "grey_peerlist_size": 312,
+                "height": 1700000,
+                "height_without_bootstrap": 1700000,
+                "incoming_connections_count": 0,
+                "mainnet": false,
+                "nettype": "stagenet",
+                "offline": false,
+                "outgoing_connections_count": 8,
+                "restricted": false,
+                "rpc_connections_count": 1,
+                "stagenet": true,
+                "start_time": 1729250000,
+                "status": "OK",
+                "synchronized": false,
+                "target": 120,
+                "target_height": 1720500,
+                "testnet": false,
+                "top_block_hash": "a3c9b0f7e2d1c4b5a6978877665544332211ffeeddccbbaa0099887766554433",
+                "top_hash": "",
+                "tx_count": 1204391,
+                "tx_pool_size": 0,
+                "untrusted": false,
+                "update_available": false,
+                "version": "0.18.3.4-release",
+                "was_bootstrap_ever_used": false,
+                "white_peerlist_size": 95,
+                "wide_cumulative_difficulty": "0x1d2c3b4a59",
+                "wide_difficulty": "0x438e9"
+              }
+            }"#,
+        );
+
+        assert!(info.stagenet);
+        assert_eq!(info.nettype, "stagenet");
+        assert_eq!(info.remaining_blocks(), Some(20500));
+    }
+
+    #[test]
+    fn can_deserialize_mainnet_block_header() {
+        let header = result::<BlockHeaderResponse>(
+            r#"{
+              "id": "0",
+              "jsonrpc": "2.0",
+              "result": {
+                "block_header": {
+                  "block_size": 65793,
+                  "block_weight": 65793,
+                  "cumulative_difficulty": 6271842390731234567,
+                  "cumulative_difficulty_top64": 23,
+                  "depth": 12,
+                  "difficulty": 286312044918,
+                  "difficulty_top64": 0,
+                  "hash": "7a8d2b3cd4c9f55a0b0f2e3cf07e4b0de5fe3b1f0c7a5e8d6f4b3a2c1d0e9f8a",
+                  "height": 3250000,
+                  "long_term_weight": 65793,
+                  "major_version": 16,
+                  "miner_tx_hash": "c5a1e4a8f9d0b7c6e3f2a1b0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0",
+                  "minor_version": 16,
+                  "nonce": 3489724716,
+                  "num_txes": 31,
+                  "orphan_status": false,
+                  "pow_hash": "",
+                  "prev_hash": "0e6d1b9b5e1c2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a",
+                  "reward": 600000000000,
+                  "timestamp": 1727353052,
+                  "wide_cumulative_difficulty": "0x17570a3b1c8e6a9f07",
+                  "wide_difficulty": "0x42a9b3c576"
+                },
+                "credits": 0,
+                "status": "OK",
+                "top_hash": "",
+                "untrusted": false
+              }
+            }"#,
+        )
+        .block_header;
+
+        assert_eq!(header.height, 3250000);
+        assert_eq!(header.difficulty, 286312044918);
+        assert_eq!(header.cumulative_difficulty, 6271842390731234567);
+        assert_eq!(header.wide_difficulty, "0x42a9b3c576");
+        assert_eq!(header.nonce, 3489724716);
+    }
+
+    #[test]
+    fn can_deserialize_block_headers_range() {
+        let header = |height: u64, depth: u64| {
+            format!(
+                r#"{{
+                  "block_size": 4221,
+                  "block_weight": 4221,
+                  "cumulative_difficulty": 6271842390731234567,
+                  "depth": {},
+                  "difficulty": 286312044918,
+                  "hash": "{:064x}",
+                  "height": {},
+                  "major_version": 16,
+                  "miner_tx_hash": "{:064x}",
+                  "minor_version": 16,
+                  "nonce": 1,
+                  "num_txes": 2,
+                  "orphan_status": false,
+                  "prev_hash": "{:064x}",
+                  "reward": 600000000000,
+                  "timestamp": 1727353052,
+                  "wide_cumulative_difficulty": "0x570a3b1c8e6a9f07",
+                  "wide_difficulty": "0x42a9b3c576"
+                }}"#,
+                depth,
+                height,
+                height,
+                height,
+                height - 1
+            )
+        };
+        let headers = result::<BlockHeaders>(&format!(
+            r#"{{
+              "id": "0",
+              "jsonrpc": "2.0",
+              "result": {{
+                "credits": 0,
+                "headers": [{}, {}],
+                "status": "OK",
+                "top_hash": "",
+                "untrusted": false
+              }}
+            }}"#,
+            header(3250000, 1),
+            header(3250001, 0)
+        ))
+        .headers;
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].height, 3250000);
+        assert_eq!(headers[1].depth, 0);
+        assert_eq!(headers[1].pow_hash, "");
+    }
+
+    #[test]
+    fn unknown_transactions_are_reported_as_missing() {
+        let known = Hash::from([1u8; 32]);
+        let unknown = Hash::from([2u8; 32]);
+        let response: GetTransactionsResponse = serde_json::from_str(&format!(
+            r#"{{
+              "credits": 0,
+              "missed_tx": ["{}", "{}"],
+              "status": "OK",
+              "top_hash": "",
+              "untrusted": false
+            }}"#,
+            hex::encode(known.as_bytes()),
+            hex::encode(unknown.as_bytes())
+        ))
+        .unwrap();
+
+        let transactions = match_transactions(&[known, unknown], response).unwrap();
+
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions.iter().all(|(_, tx)| tx.is_none()));
+        assert_eq!(transactions[0].0, known);
+        assert_eq!(transactions[1].0, unknown);
+    }
+
+    #[test]
+    fn transactions_missing_from_the_response_are_an_error() {
+        let response: GetTransactionsResponse = serde_json::from_str(
+            r#"{
+              "credits": 0,
+              "status": "OK",
+              "top_hash": "",
+              "untrusted": false
+            }"#,
+        )
+        .unwrap();
+
+        assert!(match_transactions(&[Hash::from([1u8; 32])], response).is_err());
+    }
+
+    #[test]
+    fn accepted_transaction_is_not_an_error() {
+        let response: SendRawTransactionResponse = serde_json::from_str(
+            r#"{
+              "credits": 0,
+              "double_spend": false,
+              "fee_too_low": false,
+              "invalid_input": false,
+              "invalid_output": false,
+              "low_mixin": false,
+              "not_relayed": true,
+              "overspend": false,
+              "reason": "",
+              "sanity_check_failed": false,
+              "status": "OK",
+              "too_big": false,
+              "too_few_outputs": false,
+              "top_hash": "",
+              "untrusted": false
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.error(), None);
+    }
+
+    #[test]
+    fn rejected_transaction_reports_the_flag_set_by_the_daemon() {
+        let cases = [
+            ("double_spend", Rejection::DoubleSpend),
+            ("fee_too_low", Rejection::FeeTooLow),
+            ("invalid_input", Rejection::InvalidInput),
+            ("invalid_output", Rejection::InvalidOutput),
+            ("low_mixin", Rejection::LowMixin),
+            ("overspend", Rejection::Overspend),
+            ("too_big", Rejection::TooBig),
+            ("not_relayed", Rejection::NotRelayed),
+        ];
+
+        for (flag, rejection) in cases {
+            let response: SendRawTransactionResponse = serde_json::from_str(&format!(
+                r#"{{
+                  "credits": 0,
+                  "{}": true,
+                  "reason": "tx rejected",
+                  "status": "Failed",
+                  "top_hash": "",
+                  "untrusted": false
+                }}"#,
+                flag
+            ))
+            .unwrap();
+
+            assert_eq!(
+                response.error(),
+                Some(SendRawTransactionError {
+                    rejection,
+                    reason: "tx rejected".to_owned()
+                }),
+                "{}",
+                flag
+            );
+        }
+    }
+
+    #[test]
+    fn failure_without_flags_is_reported_as_other() {
+        let response: SendRawTransactionResponse = serde_json::from_str(
+            r#"{
+              "credits": 0,
+              "reason": "",
+              "status": "Failed",
+              "top_hash": "",
+              "untrusted": false
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.error().unwrap().rejection, Rejection::Other);
+    }
+
+    #[test]
+    fn endpoints_are_derived_from_the_base_url() {
+        let cases = [
+            ("http://127.0.0.1:18081", "http://127.0.0.1:18081/json_rpc"),
+            ("http://127.0.0.1:18081/", "http://127.0.0.1:18081/json_rpc"),
+            ("https://node.example:443", "https://node.example/json_rpc"),
+            (
+                "https://example.com/monero",
+                "https://example.com/monero/json_rpc",
+            ),
+            (
+                "https://example.com/monero/",
+                "https://example.com/monero/json_rpc",
+            ),
+        ];
+
+        for (base_url, expected) in cases {
+            assert_eq!(
+                endpoint(&base_url.parse().unwrap(), "json_rpc")
+                    .unwrap()
+                    .as_str(),
+                expected,
+                "{}",
+                base_url
+            );
+        }
+    }
+
+    #[test]
+    fn binary_endpoints_keep_the_path_prefix() {
+        let base_url = "https://example.com/monero".parse().unwrap();
+
+        assert_eq!(
+            endpoint(&base_url, "get_outs.bin").unwrap().as_str(),
+            "https://example.com/monero/get_outs.bin"
+        );
+    }
+
+    fn get_info(response: &str) -> GetInfo {
+        result(response)
+    }
+
+    fn result<T: DeserializeOwned>(response: &str) -> T {
+        let _: Response<T> = serde_json::from_str(response).unwrap();
+        let response: serde_json::Value = serde_json::from_str(response).unwrap();
+
+        serde_json::from_value(response["result"].clone()).unwrap()
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::auth;
+use crate::Credentials;
 use anyhow::{Context, Result};
 use rust_decimal::Decimal;
 use serde::de::Error;
@@ -34,14 +36,15 @@ pub trait MoneroWalletRpc {
         autosave_current: bool,
     ) -> GenerateFromKeys;
     async fn refresh(&self) -> Refreshed;
-    async fn sweep_all(&self, address: String) -> SweepAll;
+    async fn sweep_all(&self, address: String, priority: TransferPriority) -> SweepAll;
+    async fn get_transfer_by_txid(&self, txid: String) -> GetTransferByTxid;
     async fn get_version(&self) -> Version;
 }
 
 #[jsonrpc_client::implement(MoneroWalletRpc)]
 #[derive(Debug, Clone)]
 pub struct Client {
-    inner: reqwest::Client,
+    inner: auth::Client,
     base_url: reqwest::Url,
 }
 
@@ -57,10 +60,23 @@ impl Client {
 
     /// Constructs a monero-wallet-rpc client with `url` endpoint.
     pub fn new(url: reqwest::Url) -> Result<Self> {
+        Self::with_credentials(url, None)
+    }
+
+    /// Constructs a monero-wallet-rpc client for a wallet RPC started with
+    /// `--rpc-login`.
+    pub fn new_with_auth(url: reqwest::Url, username: String, password: String) -> Result<Self> {
+        Self::with_credentials(url, Some(Credentials::new(username, password)))
+    }
+
+    fn with_credentials(url: reqwest::Url, credentials: Option<Credentials>) -> Result<Self> {
         Ok(Self {
-            inner: reqwest::ClientBuilder::new()
-                .connection_verbose(true)
-                .build()?,
+            inner: auth::Client::new(
+                reqwest::ClientBuilder::new()
+                    .connection_verbose(true)
+                    .build()?,
+                credentials,
+            ),
             base_url: url,
         })
     }
@@ -214,6 +230,50 @@ pub struct Refreshed {
 #[derive(Debug, Clone, Deserialize)]
 pub struct SweepAll {
     pub tx_hash_list: Vec<String>,
+    #[serde(default)]
+    pub amount_list: Vec<u64>,
+    #[serde(default)]
+    pub fee_list: Vec<u64>,
+}
+
+/// Fee level of a transaction, the wallet multiplies the base fee by a
+/// factor that grows with the priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u32")]
+pub enum TransferPriority {
+    /// Lets the wallet choose, it only pays more than `Unimportant` when
+    /// blocks are full.
+    Default,
+    Unimportant,
+    Normal,
+    Elevated,
+    Priority,
+}
+
+impl From<TransferPriority> for u32 {
+    fn from(priority: TransferPriority) -> Self {
+        match priority {
+            TransferPriority::Default => 0,
+            TransferPriority::Unimportant => 1,
+            TransferPriority::Normal => 2,
+            TransferPriority::Elevated => 3,
+            TransferPriority::Priority => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetTransferByTxid {
+    pub transfer: TransferEntry,
+}
+
+/// A transaction from the point of view of the loaded wallet.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferEntry {
+    pub txid: String,
+    /// Amount received or sent, excluding the fee.
+    pub amount: u64,
+    pub fee: u64,
 }
 
 #[derive(Debug, Copy, Clone, Deserialize)]
@@ -269,6 +329,18 @@ mod tests {
         }"#;
 
         let _: Response<SweepAll> = serde_json::from_str(response).unwrap();
+
+        let response: serde_json::Value = serde_json::from_str(response).unwrap();
+        let sweep_all: SweepAll = serde_json::from_value(response["result"].clone()).unwrap();
+        assert_eq!(sweep_all.amount_list, vec![29921410000]);
+        assert_eq!(sweep_all.fee_list, vec![78590000]);
+    }
+
+    #[test]
+    fn transfer_priority_is_sent_as_number() {
+        let priority = serde_json::to_value(TransferPriority::Elevated).unwrap();
+
+        assert_eq!(priority, serde_json::json!(3));
     }
 
     #[test]
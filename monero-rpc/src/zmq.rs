@@ -0,0 +1,109 @@
+//! New-block notifications from monerod's ZMQ publisher, enabled on the
+//! daemon with `--zmq-pub tcp://127.0.0.1:18083`.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::sync::watch;
+use zeromq::{Socket, SocketRecv, SubSocket};
+
+const CHAIN_MAIN_TOPIC: &str = "json-minimal-chain_main";
+
+/// Height of the top block of monerod's main chain, updated whenever the
+/// daemon announces new blocks.
+///
+/// The channel starts at 0 and is closed if the connection to the daemon
+/// fails; callers are expected to fall back to polling in that case.
+#[derive(Clone, Debug)]
+pub struct BlockSubscription {
+    heights: watch::Receiver<u64>,
+}
+
+impl BlockSubscription {
+    /// Subscribes to the zmq-pub endpoint of monerod, e.g.
+    /// `tcp://127.0.0.1:18083`.
+    pub async fn connect(endpoint: &str) -> Result<Self> {
+        let mut socket = SubSocket::new();
+        socket
+            .connect(endpoint)
+            .await
+            .with_context(|| format!("Failed to connect to monerod ZMQ endpoint {}", endpoint))?;
+        socket
+            .subscribe(CHAIN_MAIN_TOPIC)
+            .await
+            .context("Failed to subscribe to new blocks")?;
+
+        let (sender, heights) = watch::channel(0);
+
+        tokio::spawn(async move {
+            loop {
+                let message = match socket.recv().await {
+                    Ok(message) => message,
+                    Err(error) => {
+                        tracing::warn!(%error, "Lost connection to monerod ZMQ endpoint");
+                        return;
+                    }
+                };
+
+                let height = match message.get(0).map(|frame| top_height(frame)) {
+                    Some(Ok(height)) => height,
+                    Some(Err(error)) => {
+                        tracing::debug!("Ignoring malformed block notification: {:#}", error);
+                        continue;
+                    }
+                    None => continue,
+                };
+
+                if sender.send(height).is_err() {
+                    // Nobody is listening anymore.
+                    return;
+                }
+            }
+        });
+
+        Ok(Self { heights })
+    }
+
+    /// Returns a receiver for the height of the top block.
+    pub fn heights(&self) -> watch::Receiver<u64> {
+        self.heights.clone()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MinimalChainMain {
+    first_height: u64,
+    ids: Vec<String>,
+}
+
+/// Extracts the height of the newest block from a `json-minimal-chain_main`
+/// message, which is the topic followed by `:` and a JSON body.
+fn top_height(frame: &[u8]) -> Result<u64> {
+    let body = frame
+        .strip_prefix(CHAIN_MAIN_TOPIC.as_bytes())
+        .and_then(|rest| rest.strip_prefix(b":"))
+        .context("Not a chain_main notification")?;
+    let chain: MinimalChainMain = serde_json::from_slice(body)?;
+    let new_blocks = u64::try_from(chain.ids.len())?;
+
+    Ok(chain.first_height + new_blocks.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_height_is_the_last_announced_block() {
+        let frame = br#"json-minimal-chain_main:{"first_height":3250000,"first_prev_id":"0e6d1b9b5e1c2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a","ids":["7a8d2b3cd4c9f55a0b0f2e3cf07e4b0de5fe3b1f0c7a5e8d6f4b3a2c1d0e9f8a","c5a1e4a8f9d0b7c6e3f2a1b0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0"]}"#;
+
+        assert_eq!(top_height(frame).unwrap(), 3250001);
+    }
+
+    #[test]
+    fn other_topics_are_rejected() {
+        let frame =
+            br#"json-minimal-txpool_add:[{"id":"00","blob_size":1500,"weight":1500,"fee":30000}]"#;
+
+        assert!(top_height(frame).is_err());
+    }
+}
@@ -0,0 +1,5 @@
+CREATE TABLE if NOT EXISTS swap_summaries
+(
+    swap_id     TEXT    PRIMARY KEY NOT NULL,
+    summary     TEXT                NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE if NOT EXISTS broadcast_transactions
+(
+    swap_id     TEXT    NOT NULL,
+    txid        TEXT    NOT NULL,
+    tx          TEXT    NOT NULL,
+    PRIMARY KEY (swap_id, txid)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE if NOT EXISTS state_format
+(
+    version     INTEGER NOT NULL
+);
+
+-- States written before this table existed are stored without an envelope
+INSERT INTO state_format (version) VALUES (0);
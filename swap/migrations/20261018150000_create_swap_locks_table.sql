@@ -0,0 +1,6 @@
+CREATE TABLE if NOT EXISTS swap_locks
+(
+    swap_id      TEXT    PRIMARY KEY NOT NULL,
+    pid          INTEGER NOT NULL,
+    refreshed_at INTEGER NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE peer_addresses ADD COLUMN last_connected_at INTEGER;
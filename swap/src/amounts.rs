@@ -0,0 +1,218 @@
+//! Conversions between BTC and XMR amounts at a quoted rate.
+//!
+//! Rates are integer satoshis per XMR and every conversion uses integer
+//! arithmetic with an explicit rounding direction. Given the same rate and
+//! amount, Alice and Bob therefore compute bit-identical amounts.
+
+use crate::monero::PICONERO_OFFSET;
+use crate::{bitcoin, monero};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The price of 1 XMR in satoshis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Rate {
+    sats_per_xmr: u64,
+}
+
+/// Which way a conversion rounds if the result is not a whole satoshi or
+/// piconero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ConversionError {
+    #[error("Cannot convert at a rate of zero")]
+    ZeroRate,
+    #[error("Conversion result does not fit into a u64")]
+    Overflow,
+}
+
+impl Rate {
+    pub const fn from_sats_per_xmr(sats_per_xmr: u64) -> Self {
+        Self { sats_per_xmr }
+    }
+
+    /// The rate for a price of 1 XMR, e.g. [`BidQuote::price`].
+    ///
+    /// [`BidQuote::price`]: crate::network::quote::BidQuote::price
+    pub fn from_price(price: bitcoin::Amount) -> Self {
+        Self::from_sats_per_xmr(price.to_sat())
+    }
+
+    pub fn to_price(self) -> bitcoin::Amount {
+        bitcoin::Amount::from_sat(self.sats_per_xmr)
+    }
+
+    /// The XMR bought with `btc` at this rate.
+    pub fn btc_to_xmr(
+        self,
+        btc: bitcoin::Amount,
+        rounding: Rounding,
+    ) -> Result<monero::Amount, ConversionError> {
+        if self.sats_per_xmr == 0 {
+            return Err(ConversionError::ZeroRate);
+        }
+
+        let piconero = mul_div(btc.to_sat(), PICONERO_OFFSET, self.sats_per_xmr, rounding)?;
+
+        Ok(monero::Amount::from_piconero(piconero))
+    }
+
+    /// The BTC it takes to buy `xmr` at this rate.
+    pub fn xmr_to_btc(
+        self,
+        xmr: monero::Amount,
+        rounding: Rounding,
+    ) -> Result<bitcoin::Amount, ConversionError> {
+        let sats = mul_div(
+            xmr.as_piconero(),
+            self.sats_per_xmr,
+            PICONERO_OFFSET,
+            rounding,
+        )?;
+
+        Ok(bitcoin::Amount::from_sat(sats))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/XMR", self.to_price())
+    }
+}
+
+/// Computes `a * b / divisor` without intermediate overflow.
+fn mul_div(a: u64, b: u64, divisor: u64, rounding: Rounding) -> Result<u64, ConversionError> {
+    // The product of two u64 always fits into a u128
+    let product = u128::from(a) * u128::from(b);
+    let divisor = u128::from(divisor);
+
+    let quotient = match rounding {
+        Rounding::Down => product / divisor,
+        Rounding::Up => (product + divisor - 1) / divisor,
+    };
+
+    u64::try_from(quotient).map_err(|_| ConversionError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asb::Rate as AskRate;
+    use crate::monero::MONERO_FEE;
+    use proptest::prelude::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn converts_at_whole_rates() {
+        let rate = Rate::from_price(bitcoin::Amount::from_btc(0.0025).unwrap());
+
+        let xmr = rate
+            .btc_to_xmr(bitcoin::Amount::from_btc(2.5).unwrap(), Rounding::Down)
+            .unwrap();
+        let btc = rate.xmr_to_btc(xmr, Rounding::Down).unwrap();
+
+        assert_eq!(xmr, monero::Amount::parse_monero("1000").unwrap());
+        assert_eq!(btc, bitcoin::Amount::from_btc(2.5).unwrap());
+    }
+
+    #[test]
+    fn rounds_in_the_requested_direction() {
+        let rate = Rate::from_sats_per_xmr(3);
+        let btc = bitcoin::Amount::from_sat(1);
+
+        let down = rate.btc_to_xmr(btc, Rounding::Down).unwrap();
+        let up = rate.btc_to_xmr(btc, Rounding::Up).unwrap();
+
+        assert_eq!(down.as_piconero(), 333_333_333_333);
+        assert_eq!(up.as_piconero(), 333_333_333_334);
+    }
+
+    #[test]
+    fn zero_rate_is_rejected() {
+        let result = Rate::from_sats_per_xmr(0).btc_to_xmr(bitcoin::Amount::ONE_SAT, Rounding::Up);
+
+        assert_eq!(result, Err(ConversionError::ZeroRate));
+    }
+
+    #[test]
+    fn overflow_is_reported() {
+        let result = Rate::from_sats_per_xmr(1)
+            .btc_to_xmr(bitcoin::Amount::from_sat(u64::MAX), Rounding::Down);
+
+        assert_eq!(result, Err(ConversionError::Overflow));
+    }
+
+    fn rate() -> impl Strategy<Value = Rate> {
+        (1u64..=bitcoin::Amount::MAX_MONEY.to_sat()).prop_map(Rate::from_sats_per_xmr)
+    }
+
+    fn btc() -> impl Strategy<Value = bitcoin::Amount> {
+        (0u64..=bitcoin::Amount::MAX_MONEY.to_sat()).prop_map(bitcoin::Amount::from_sat)
+    }
+
+    fn xmr() -> impl Strategy<Value = monero::Amount> {
+        any::<u64>().prop_map(monero::Amount::from_piconero)
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_never_gains_and_loses_at_most_one_piconero_worth(rate in rate(), btc in btc()) {
+            if let Ok(xmr) = rate.btc_to_xmr(btc, Rounding::Down) {
+                let back = rate.xmr_to_btc(xmr, Rounding::Down).unwrap();
+
+                // What one piconero is worth, rounded up, plus one satoshi for
+                // rounding down the second conversion
+                let max_loss = rate.xmr_to_btc(monero::Amount::from_piconero(1), Rounding::Up).unwrap()
+                    + bitcoin::Amount::ONE_SAT;
+
+                prop_assert!(back <= btc);
+                prop_assert!(btc - back <= max_loss);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rounding_up_exceeds_rounding_down_by_at_most_one_unit(rate in rate(), btc in btc(), xmr in xmr()) {
+            if let (Ok(down), Ok(up)) = (rate.btc_to_xmr(btc, Rounding::Down), rate.btc_to_xmr(btc, Rounding::Up)) {
+                prop_assert!(up.as_piconero() - down.as_piconero() <= 1);
+            }
+
+            if let (Ok(down), Ok(up)) = (rate.xmr_to_btc(xmr, Rounding::Down), rate.xmr_to_btc(xmr, Rounding::Up)) {
+                prop_assert!(up.to_sat() - down.to_sat() <= 1);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn taker_computes_the_makers_sell_quote(rate in rate(), btc in btc()) {
+            let maker = AskRate::new(rate.to_price(), Decimal::ZERO).sell_quote(btc);
+            let taker = rate.btc_to_xmr(btc, Rounding::Down);
+
+            match (maker, taker) {
+                (Ok(maker), Ok(taker)) => prop_assert_eq!(maker, taker),
+                (Err(_), Err(_)) => {}
+                (maker, taker) => prop_assert!(false, "maker {:?} but taker {:?}", maker, taker),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn max_bitcoin_for_balance_never_sells_more_than_the_balance(rate in rate(), balance in xmr()) {
+            let max_btc = balance.max_bitcoin_for_price(rate.to_price());
+
+            if let Some(max_btc) = max_btc {
+                let sold = rate.btc_to_xmr(max_btc, Rounding::Down).unwrap();
+
+                prop_assert!(sold.as_piconero() + MONERO_FEE.as_piconero() <= balance.as_piconero().max(MONERO_FEE.as_piconero()));
+            }
+        }
+    }
+}
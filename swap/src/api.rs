@@ -4,16 +4,20 @@ use crate::database::open_db;
 use crate::env::{Config as EnvConfig, GetConfig, Mainnet, Testnet};
 use crate::fs::system_data_dir;
 use crate::network::rendezvous::XmrBtcNamespace;
-use crate::protocol::Database;
+use crate::protocol::{Database, SwapLocked};
 use crate::seed::Seed;
 use crate::{bitcoin, cli, monero};
 use anyhow::{bail, Context as AnyContext, Error, Result};
 use futures::future::try_join_all;
+use monero_rpc::wallet::BlockHeight;
+pub use request::{buy_xmr, cancel_and_refund, history, resume, BuyXmrArgs, SwapHandle};
+use std::collections::HashMap;
 use std::fmt;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{Arc, Once};
+use std::time::Duration;
 use tokio::sync::{broadcast, broadcast::Sender, Mutex, RwLock};
 use tokio::task::JoinHandle;
 use url::Url;
@@ -64,53 +68,98 @@ impl PendingTaskList {
     }
 }
 
+/// A lock held by another process is taken over if it was not refreshed for
+/// this long, e.g. because the process was killed.
+const PROCESS_LOCK_STALE_AFTER: Duration = Duration::from_secs(120);
+const PROCESS_LOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Tracks the swaps running in a [`Context`].
+///
+/// Any number of swaps can run at the same time, but each swap at most once.
 pub struct SwapLock {
-    current_swap: RwLock<Option<Uuid>>,
-    suspension_trigger: Sender<()>,
+    running_swaps: RwLock<Vec<Uuid>>,
+    /// Suspends the given swap, or all swaps if none is given.
+    suspension_trigger: Sender<Option<Uuid>>,
+    process_lock: Option<ProcessLock>,
+}
+
+/// Keeps other processes using the same database from handling the swaps
+/// that run in this process.
+struct ProcessLock {
+    db: Arc<dyn Database + Send + Sync>,
+    /// Take over swaps from other processes that seem to handle them.
+    force: bool,
+    refresh_tasks: Mutex<HashMap<Uuid, JoinHandle<()>>>,
 }
 
 impl SwapLock {
     pub fn new() -> Self {
         let (suspension_trigger, _) = broadcast::channel(10);
         SwapLock {
-            current_swap: RwLock::new(None),
+            running_swaps: RwLock::new(Vec::new()),
             suspension_trigger,
+            process_lock: None,
         }
     }
 
-    pub async fn listen_for_swap_force_suspension(&self) -> Result<(), Error> {
+    /// Also locks the swaps in the database, so that a swap is never handled
+    /// by two processes at the same time.
+    pub fn with_process_lock(mut self, db: Arc<dyn Database + Send + Sync>, force: bool) -> Self {
+        self.process_lock = Some(ProcessLock {
+            db,
+            force,
+            refresh_tasks: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// Completes once all swaps or the given swap are to be suspended.
+    pub async fn listen_for_swap_force_suspension(&self, swap_id: Uuid) -> Result<(), Error> {
         let mut listener = self.suspension_trigger.subscribe();
-        let event = listener.recv().await;
-        match event {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                tracing::error!("Error receiving swap suspension signal: {}", e);
-                bail!(e)
+        loop {
+            match listener.recv().await {
+                Ok(Some(suspended)) if suspended != swap_id => continue,
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    tracing::error!("Error receiving swap suspension signal: {}", e);
+                    bail!(e)
+                }
             }
         }
     }
 
     pub async fn acquire_swap_lock(&self, swap_id: Uuid) -> Result<(), Error> {
-        let mut current_swap = self.current_swap.write().await;
-        if current_swap.is_some() {
-            bail!("There already exists an active swap lock");
+        let mut running_swaps = self.running_swaps.write().await;
+        if running_swaps.contains(&swap_id) {
+            bail!("Swap {} is already running", swap_id);
+        }
+
+        if let Some(process_lock) = &self.process_lock {
+            process_lock
+                .acquire(swap_id, self.suspension_trigger.clone())
+                .await?;
         }
 
         tracing::debug!(swap_id = %swap_id, "Acquiring swap lock");
-        *current_swap = Some(swap_id);
+        running_swaps.push(swap_id);
         Ok(())
     }
 
+    /// Returns the swap that has been running the longest.
     pub async fn get_current_swap_id(&self) -> Option<Uuid> {
-        *self.current_swap.read().await
+        self.running_swaps.read().await.first().copied()
+    }
+
+    pub async fn get_current_swap_ids(&self) -> Vec<Uuid> {
+        self.running_swaps.read().await.clone()
     }
 
     /// Sends a signal to suspend all ongoing swap processes.
     ///
     /// This function performs the following steps:
-    /// 1. Triggers the suspension by sending a unit `()` signal to all listeners via `self.suspension_trigger`.
-    /// 2. Polls the `current_swap` state every 50 milliseconds to check if it has been set to `None`, indicating that the swap processes have been suspended and the lock released.
-    /// 3. If the lock is not released within 10 seconds, the function returns an error.
+    /// 1. Triggers the suspension by sending a `None` signal to all listeners via `self.suspension_trigger`.
+    /// 2. Polls the `running_swaps` state every 50 milliseconds to check if it is empty, indicating that the swap processes have been suspended and their locks released.
+    /// 3. If the locks are not released within 10 seconds, the function returns an error.
     ///
     /// If we send a suspend signal while no swap is in progress, the function will not fail, but will return immediately.
     ///
@@ -124,10 +173,10 @@ impl SwapLock {
         const TIMEOUT: u64 = 10_000;
         const INTERVAL: u64 = 50;
 
-        let _ = self.suspension_trigger.send(())?;
+        let _ = self.suspension_trigger.send(None)?;
 
         for _ in 0..(TIMEOUT / INTERVAL) {
-            if self.get_current_swap_id().await.is_none() {
+            if self.running_swaps.read().await.is_empty() {
                 return Ok(());
             }
             tokio::time::sleep(tokio::time::Duration::from_millis(INTERVAL)).await;
@@ -136,53 +185,232 @@ impl SwapLock {
         bail!("Timed out waiting for swap lock to be released");
     }
 
-    pub async fn release_swap_lock(&self) -> Result<Uuid, Error> {
-        let mut current_swap = self.current_swap.write().await;
-        if let Some(swap_id) = current_swap.as_ref() {
-            tracing::debug!(swap_id = %swap_id, "Releasing swap lock");
+    pub async fn release_swap_lock(&self, swap_id: Uuid) -> Result<(), Error> {
+        let mut running_swaps = self.running_swaps.write().await;
+        match running_swaps.iter().position(|running| *running == swap_id) {
+            Some(index) => {
+                tracing::debug!(swap_id = %swap_id, "Releasing swap lock");
+                running_swaps.remove(index);
+                if let Some(process_lock) = &self.process_lock {
+                    process_lock.release(swap_id).await;
+                }
+                Ok(())
+            }
+            None => bail!("There is no swap lock to release for swap {}", swap_id),
+        }
+    }
+}
+
+impl ProcessLock {
+    async fn acquire(&self, swap_id: Uuid, suspension_trigger: Sender<Option<Uuid>>) -> Result<()> {
+        let pid = std::process::id();
+
+        if let Err(error) = self
+            .db
+            .acquire_swap_lock(swap_id, pid, PROCESS_LOCK_STALE_AFTER, self.force)
+            .await
+        {
+            match error.downcast_ref::<SwapLocked>() {
+                // The lock was not released because the process holding it was
+                // killed, possibly an earlier process with the same PID
+                Some(locked) if locked.pid == pid || !is_running(locked.pid) => {
+                    tracing::debug!(%swap_id, pid = locked.pid, "Taking over the swap from a process that no longer runs");
+                    self.db
+                        .acquire_swap_lock(swap_id, pid, PROCESS_LOCK_STALE_AFTER, true)
+                        .await?;
+                }
+                _ => return Err(error),
+            }
+        }
+
+        let refresh = tokio::spawn(refresh_process_lock(
+            self.db.clone(),
+            swap_id,
+            suspension_trigger,
+        ));
+        self.refresh_tasks.lock().await.insert(swap_id, refresh);
+
+        Ok(())
+    }
+
+    async fn release(&self, swap_id: Uuid) {
+        if let Some(refresh) = self.refresh_tasks.lock().await.remove(&swap_id) {
+            refresh.abort();
+        }
 
-            let prev_swap_id = *swap_id;
-            *current_swap = None;
-            drop(current_swap);
-            Ok(prev_swap_id)
-        } else {
-            bail!("There is no current swap lock to release");
+        // Another process can take over the swap once the lock is stale, so
+        // failing to release it is not fatal
+        if let Err(error) = self.db.release_swap_lock(swap_id, std::process::id()).await {
+            tracing::warn!(%swap_id, "Failed to release the swap lock in the database: {:#}", error);
+        }
+    }
+}
+
+async fn refresh_process_lock(
+    db: Arc<dyn Database + Send + Sync>,
+    swap_id: Uuid,
+    suspension_trigger: Sender<Option<Uuid>>,
+) {
+    loop {
+        tokio::time::sleep(PROCESS_LOCK_REFRESH_INTERVAL).await;
+
+        match db.refresh_swap_lock(swap_id, std::process::id()).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::error!(%swap_id, "Another process took over the swap, suspending it");
+                // only this swap, the others still hold their locks
+                let _ = suspension_trigger.send(Some(swap_id));
+                return;
+            }
+            Err(error) => {
+                tracing::warn!(%swap_id, "Failed to refresh the swap lock in the database: {:#}", error)
+            }
         }
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn is_running(pid: u32) -> bool {
+    use nix::errno::Errno;
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    match i32::try_from(pid) {
+        // Sending no signal only checks whether the process exists
+        Ok(pid) => kill(Pid::from_raw(pid), None::<Signal>) != Err(Errno::ESRCH),
+        Err(_) => true,
+    }
+}
+
+// Only stale locks are taken over on Windows
+#[cfg(target_os = "windows")]
+fn is_running(_: u32) -> bool {
+    true
+}
+
 impl Default for SwapLock {
     fn default() -> Self {
         Self::new()
     }
 }
 
-// workaround for warning over monero_rpc_process which we must own but not read
-#[allow(dead_code)]
 pub struct Context {
     pub db: Arc<dyn Database + Send + Sync>,
     bitcoin_wallet: Option<Arc<bitcoin::Wallet>>,
     monero_wallet: Option<Arc<monero::Wallet>>,
-    monero_rpc_process: Option<monero::WalletRpcProcess>,
     pub swap_lock: Arc<SwapLock>,
     pub config: Config,
     pub tasks: Arc<PendingTaskList>,
 }
 
-#[allow(clippy::too_many_arguments)]
+/// Configures the wallets, database and network settings of a [`Context`].
+///
+/// Only the configured wallets are started when building the context, e.g. a
+/// context without Monero settings can list the swap history but can't buy
+/// XMR.
+#[derive(Debug)]
+pub struct ContextBuilder {
+    bitcoin: Option<Bitcoin>,
+    monero: Option<Monero>,
+    monero_restore_height: Option<BlockHeight>,
+    tor: Option<Tor>,
+    data: Option<PathBuf>,
+    is_testnet: bool,
+    debug: bool,
+    json: bool,
+    server_address: Option<SocketAddr>,
+    force_swap_lock: bool,
+}
+
 impl Context {
-    pub async fn build(
-        bitcoin: Option<Bitcoin>,
-        monero: Option<Monero>,
-        tor: Option<Tor>,
-        data: Option<PathBuf>,
-        is_testnet: bool,
-        debug: bool,
-        json: bool,
-        server_address: Option<SocketAddr>,
-    ) -> Result<Context> {
+    pub fn builder(is_testnet: bool) -> ContextBuilder {
+        ContextBuilder {
+            bitcoin: None,
+            monero: None,
+            monero_restore_height: None,
+            tor: None,
+            data: None,
+            is_testnet,
+            debug: false,
+            json: false,
+            server_address: None,
+            force_swap_lock: false,
+        }
+    }
+}
+
+impl ContextBuilder {
+    pub fn with_bitcoin(mut self, bitcoin: Bitcoin) -> Self {
+        self.bitcoin = Some(bitcoin);
+        self
+    }
+
+    pub fn with_monero(mut self, monero: Monero) -> Self {
+        self.monero = Some(monero);
+        self
+    }
+
+    /// Height from which a newly created Monero wallet scans the chain,
+    /// instead of the daemon's current height.
+    pub fn with_monero_restore_height(
+        mut self,
+        restore_height: impl Into<Option<BlockHeight>>,
+    ) -> Self {
+        self.monero_restore_height = restore_height.into();
+        self
+    }
+
+    pub fn with_tor(mut self, tor: Tor) -> Self {
+        self.tor = Some(tor);
+        self
+    }
+
+    /// Uses the given data directory instead of the OS default.
+    pub fn with_data_dir(mut self, data: impl Into<Option<PathBuf>>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn with_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    pub fn with_server_address(mut self, server_address: impl Into<Option<SocketAddr>>) -> Self {
+        self.server_address = server_address.into();
+        self
+    }
+
+    /// Takes over swaps even if another process seems to be handling them.
+    pub fn with_force_swap_lock(mut self, force: bool) -> Self {
+        self.force_swap_lock = force;
+        self
+    }
+
+    pub async fn build(self) -> Result<Context> {
+        let ContextBuilder {
+            bitcoin,
+            monero,
+            monero_restore_height,
+            tor,
+            data,
+            is_testnet,
+            debug,
+            json,
+            server_address,
+            force_swap_lock,
+        } = self;
+
         let data_dir = data::data_dir_from(data, is_testnet)?;
-        let env_config = env_config_from(is_testnet);
+        let env_config = match &bitcoin {
+            Some(bitcoin) => bitcoin.apply_fee_rate_limits(env_config_from(is_testnet))?,
+            None => env_config_from(is_testnet),
+        };
 
         START.call_once(|| {
             let _ = cli::tracing::init(debug, json, data_dir.join("logs"));
@@ -193,8 +421,10 @@ impl Context {
 
         let bitcoin_wallet = {
             if let Some(bitcoin) = bitcoin {
+                let socks5_proxy = bitcoin.bitcoin_socks5_proxy;
                 let (bitcoin_electrum_rpc_url, bitcoin_target_block) =
                     bitcoin.apply_defaults(is_testnet)?;
+                cli::preflight::check_electrum(&bitcoin_electrum_rpc_url, socks5_proxy).await?;
                 Some(Arc::new(
                     init_bitcoin_wallet(
                         bitcoin_electrum_rpc_url,
@@ -202,6 +432,7 @@ impl Context {
                         data_dir.clone(),
                         env_config,
                         bitcoin_target_block,
+                        socks5_proxy,
                     )
                     .await?,
                 ))
@@ -210,24 +441,44 @@ impl Context {
             }
         };
 
-        let (monero_wallet, monero_rpc_process) = {
+        let monero_wallet = {
             if let Some(monero) = monero {
+                let monero_wallet_rpc_binary = monero.monero_wallet_rpc_binary.clone();
+                let socks5_proxy = monero.monero_socks5_proxy;
+                let daemon_credentials = monero.daemon_credentials();
                 let monero_daemon_address = monero.apply_defaults(is_testnet);
-                let (wlt, prc) =
-                    init_monero_wallet(data_dir.clone(), monero_daemon_address, env_config).await?;
-                (Some(Arc::new(wlt)), Some(prc))
+                let daemon_height = cli::preflight::check_monero_daemon(
+                    &monero_daemon_address,
+                    env_config.monero_network,
+                    socks5_proxy,
+                    daemon_credentials.clone(),
+                )
+                .await?;
+                let wallet = init_monero_wallet(
+                    data_dir.clone(),
+                    monero_daemon_address,
+                    monero_wallet_rpc_binary,
+                    env_config,
+                    socks5_proxy,
+                    daemon_credentials,
+                    monero_restore_height.unwrap_or(daemon_height),
+                )
+                .await?;
+                Some(Arc::new(wallet))
             } else {
-                (None, None)
+                None
             }
         };
 
         let tor_socks5_port = tor.map_or(9050, |tor| tor.tor_socks5_port);
 
+        let db = open_db(data_dir.join("sqlite")).await?;
+
         let context = Context {
-            db: open_db(data_dir.join("sqlite")).await?,
+            swap_lock: Arc::new(SwapLock::new().with_process_lock(db.clone(), force_swap_lock)),
+            db,
             bitcoin_wallet,
             monero_wallet,
-            monero_rpc_process,
             config: Config {
                 tor_socks5_port,
                 namespace: XmrBtcNamespace::from_is_testnet(is_testnet),
@@ -239,13 +490,14 @@ impl Context {
                 is_testnet,
                 data_dir,
             },
-            swap_lock: Arc::new(SwapLock::new()),
             tasks: Arc::new(PendingTaskList::default()),
         };
 
         Ok(context)
     }
+}
 
+impl Context {
     pub async fn for_harness(
         seed: Seed,
         env_config: EnvConfig,
@@ -262,7 +514,6 @@ impl Context {
             db: open_db(db_path)
                 .await
                 .expect("Could not open sqlite database"),
-            monero_rpc_process: None,
             swap_lock: Arc::new(SwapLock::new()),
             tasks: Arc::new(PendingTaskList::default()),
         }
@@ -281,6 +532,7 @@ async fn init_bitcoin_wallet(
     data_dir: PathBuf,
     env_config: EnvConfig,
     bitcoin_target_block: usize,
+    socks5_proxy: Option<SocketAddr>,
 ) -> Result<bitcoin::Wallet> {
     let wallet_dir = data_dir.join("wallet");
 
@@ -290,6 +542,7 @@ async fn init_bitcoin_wallet(
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
         bitcoin_target_block,
+        socks5_proxy,
     )
     .await
     .context("Failed to initialize Bitcoin wallet")?;
@@ -302,26 +555,40 @@ async fn init_bitcoin_wallet(
 async fn init_monero_wallet(
     data_dir: PathBuf,
     monero_daemon_address: String,
+    monero_wallet_rpc_binary: Option<PathBuf>,
     env_config: EnvConfig,
-) -> Result<(monero::Wallet, monero::WalletRpcProcess)> {
+    socks5_proxy: Option<SocketAddr>,
+    daemon_credentials: Option<monero_rpc::Credentials>,
+    restore_height: BlockHeight,
+) -> Result<monero::Wallet> {
     let network = env_config.monero_network;
 
     const MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-monitoring-wallet";
 
-    let monero_wallet_rpc = monero::WalletRpc::new(data_dir.join("monero")).await?;
+    let monero_wallet_rpc = match monero_wallet_rpc_binary {
+        Some(binary) => monero::WalletRpc::with_binary(data_dir.join("monero"), binary).await?,
+        None => monero::WalletRpc::new(data_dir.join("monero")).await?,
+    };
 
     let monero_wallet_rpc_process = monero_wallet_rpc
-        .run(network, Some(monero_daemon_address))
+        .run(
+            network,
+            Some(monero_daemon_address),
+            socks5_proxy,
+            daemon_credentials,
+        )
         .await?;
 
     let monero_wallet = monero::Wallet::open_or_create(
         monero_wallet_rpc_process.endpoint(),
         MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
         env_config,
+        Some(restore_height),
     )
-    .await?;
+    .await?
+    .with_rpc_process(monero_wallet_rpc_process);
 
-    Ok((monero_wallet, monero_wallet_rpc_process))
+    Ok(monero_wallet)
 }
 
 mod data {
@@ -369,6 +636,35 @@ impl Config {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn suspending_one_swap_leaves_the_others_running() {
+        let swap_lock = SwapLock::new();
+        let (suspended, running) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut suspended_listener =
+            Box::pin(swap_lock.listen_for_swap_force_suspension(suspended));
+        let mut running_listener = Box::pin(swap_lock.listen_for_swap_force_suspension(running));
+        assert!((&mut suspended_listener).now_or_never().is_none());
+        assert!((&mut running_listener).now_or_never().is_none());
+
+        swap_lock.suspension_trigger.send(Some(suspended)).unwrap();
+
+        assert!(matches!(
+            (&mut suspended_listener).now_or_never(),
+            Some(Ok(()))
+        ));
+        assert!((&mut running_listener).now_or_never().is_none());
+
+        swap_lock.send_suspend_signal().await.unwrap();
+
+        assert!(matches!(running_listener.now_or_never(), Some(Ok(()))));
+    }
+}
+
 #[cfg(test)]
 pub mod api_test {
     use super::*;
@@ -432,10 +728,11 @@ pub mod api_test {
             };
 
             Request::new(Method::BuyXmr {
-                seller,
+                seller_addresses: vec![seller],
                 bitcoin_change_address,
                 monero_receive_address,
                 swap_id: Uuid::new_v4(),
+                btc_amount: None,
             })
         }
 
@@ -1,24 +1,33 @@
+use crate::amounts::{self, Rounding};
 use crate::api::Context;
+use crate::bitcoin::wallet::DUST_AMOUNT;
 use crate::bitcoin::{Amount, ExpiredTimelocks, TxLock};
+use crate::cli::output::Output;
 use crate::cli::{list_sellers, EventLoop, SellerStatus};
-use crate::libp2p_ext::MultiAddrExt;
-use crate::network::quote::{BidQuote, ZeroQuoteReceived};
+use crate::database::SwapBackup;
+use crate::libp2p_ext::{peer_id_of, MultiAddrExt};
+use crate::network::quote::{BidQuote, QuoteBelowMinimumSwapAmount, ZeroQuoteReceived};
 use crate::network::swarm;
-use crate::protocol::bob::{BobState, Swap};
-use crate::protocol::{bob, State};
+use crate::protocol::bob::{BobState, Swap, SwapProgress, SwapSummary};
+use crate::protocol::{bob, State, SwapAlreadyExists};
 use crate::{bitcoin, cli, monero, rpc};
-use anyhow::{bail, Context as AnyContext, Result};
+use anyhow::{anyhow, bail, Context as AnyContext, Result};
 use libp2p::core::Multiaddr;
+use libp2p::PeerId;
 use qrcode::render::unicode;
 use qrcode::QrCode;
 use serde_json::json;
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::convert::TryInto;
 use std::future::Future;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{debug_span, field, Instrument, Span};
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::oneshot;
+use tracing::{debug_span, field, info_span, Instrument, Span};
 use uuid::Uuid;
 
 #[derive(PartialEq, Debug)]
@@ -30,10 +39,12 @@ pub struct Request {
 #[derive(Debug, PartialEq)]
 pub enum Method {
     BuyXmr {
-        seller: Multiaddr,
+        /// The addresses of a single seller, which are dialled at once.
+        seller_addresses: Vec<Multiaddr>,
         bitcoin_change_address: bitcoin::Address,
         monero_receive_address: monero::Address,
         swap_id: Uuid,
+        btc_amount: Option<Amount>,
     },
     Resume {
         swap_id: Uuid,
@@ -41,10 +52,42 @@ pub enum Method {
     CancelAndRefund {
         swap_id: Uuid,
     },
+    /// Speeds up the unconfirmed lock transaction of a swap, uses the current
+    /// fee rate estimate if no fee rate is given.
+    BumpFee {
+        swap_id: Uuid,
+        fee_rate_sat_vb: Option<u64>,
+    },
     MoneroRecovery {
         swap_id: Uuid,
     },
-    History,
+    /// Writes a backup of the swap, encrypted with a key derived from the
+    /// seed, to `file`.
+    ExportSwap {
+        swap_id: Uuid,
+        file: PathBuf,
+    },
+    /// Restores a swap from a backup written by [`Method::ExportSwap`].
+    ImportSwap {
+        file: PathBuf,
+        force: bool,
+    },
+    History {
+        only_unfinished: bool,
+    },
+    /// Removes swaps that finished more than `older_than` ago from the
+    /// database together with their Monero wallet files.
+    Cleanup {
+        older_than: Duration,
+        dry_run: bool,
+    },
+    /// Prints every state the swap went through.
+    Log {
+        swap_id: Uuid,
+    },
+    Show {
+        swap_id: Uuid,
+    },
     Config,
     WithdrawBtc {
         amount: Option<Amount>,
@@ -56,6 +99,10 @@ pub enum Method {
     ListSellers {
         rendezvous_point: Multiaddr,
     },
+    Quote {
+        seller: Multiaddr,
+        timeout: Duration,
+    },
     ExportBitcoinWallet,
     SuspendCurrentSwap,
     StartDaemon {
@@ -66,6 +113,31 @@ pub enum Method {
         swap_id: Uuid,
     },
     GetRawStates,
+    /// Requests quotes from all sellers and swaps with the one that has the
+    /// best price.
+    BuyXmrFromBestSeller {
+        /// The addresses of each seller.
+        sellers: Vec<Vec<Multiaddr>>,
+        quote_timeout: Duration,
+        bitcoin_change_address: bitcoin::Address,
+        monero_receive_address: monero::Address,
+        swap_id: Uuid,
+        btc_amount: Option<Amount>,
+    },
+    /// Discovers sellers at a rendezvous point and swaps with the one that
+    /// has the best price, or with the given seller.
+    BuyXmrFromRendezvousPoint {
+        rendezvous_point: Multiaddr,
+        seller_peer_id: Option<PeerId>,
+        bitcoin_change_address: bitcoin::Address,
+        monero_receive_address: monero::Address,
+        swap_id: Uuid,
+        btc_amount: Option<Amount>,
+    },
+    /// Starts several swaps and waits until all of them finished.
+    BuyXmrConcurrently {
+        swaps: Vec<BuyXmrArgs>,
+    },
 }
 
 impl Method {
@@ -81,12 +153,29 @@ impl Method {
             Method::BuyXmr { swap_id, .. } => {
                 debug_span!("method", method_name="BuyXmr", swap_id=%swap_id, log_reference_id=field::Empty)
             }
+            Method::BuyXmrFromBestSeller { swap_id, .. } => {
+                debug_span!("method", method_name="BuyXmrFromBestSeller", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
+            Method::BuyXmrFromRendezvousPoint { swap_id, .. } => {
+                debug_span!("method", method_name="BuyXmrFromRendezvousPoint", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
+            Method::BuyXmrConcurrently { swaps } => {
+                debug_span!(
+                    "method",
+                    method_name = "BuyXmrConcurrently",
+                    count = swaps.len(),
+                    log_reference_id = field::Empty
+                )
+            }
             Method::CancelAndRefund { swap_id } => {
                 debug_span!("method", method_name="CancelAndRefund", swap_id=%swap_id, log_reference_id=field::Empty)
             }
             Method::Resume { swap_id } => {
                 debug_span!("method", method_name="Resume", swap_id=%swap_id, log_reference_id=field::Empty)
             }
+            Method::BumpFee { swap_id, .. } => {
+                debug_span!("method", method_name="BumpFee", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
             Method::Config => {
                 debug_span!(
                     "method",
@@ -115,13 +204,26 @@ impl Method {
                     log_reference_id = field::Empty
                 )
             }
-            Method::History => {
+            Method::History { .. } => {
                 debug_span!(
                     "method",
                     method_name = "History",
                     log_reference_id = field::Empty
                 )
             }
+            Method::Cleanup { .. } => {
+                debug_span!(
+                    "method",
+                    method_name = "Cleanup",
+                    log_reference_id = field::Empty
+                )
+            }
+            Method::Log { swap_id } => {
+                debug_span!("method", method_name="Log", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
+            Method::Show { swap_id } => {
+                debug_span!("method", method_name="Show", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
             Method::ListSellers { .. } => {
                 debug_span!(
                     "method",
@@ -129,6 +231,9 @@ impl Method {
                     log_reference_id = field::Empty
                 )
             }
+            Method::Quote { seller, .. } => {
+                debug_span!("method", method_name="Quote", seller=%seller, log_reference_id=field::Empty)
+            }
             Method::MoneroRecovery { .. } => {
                 debug_span!(
                     "method",
@@ -136,6 +241,16 @@ impl Method {
                     log_reference_id = field::Empty
                 )
             }
+            Method::ExportSwap { swap_id, .. } => {
+                debug_span!("method", method_name="ExportSwap", swap_id=%swap_id, log_reference_id=field::Empty)
+            }
+            Method::ImportSwap { .. } => {
+                debug_span!(
+                    "method",
+                    method_name = "ImportSwap",
+                    log_reference_id = field::Empty
+                )
+            }
             Method::GetRawStates => debug_span!(
                 "method",
                 method_name = "RawHistory",
@@ -315,318 +430,293 @@ impl Request {
                 }))
             }
             Method::BuyXmr {
-                seller,
+                seller_addresses,
                 bitcoin_change_address,
                 monero_receive_address,
                 swap_id,
+                btc_amount,
             } => {
-                let bitcoin_wallet = Arc::clone(
-                    context
-                        .bitcoin_wallet
-                        .as_ref()
-                        .expect("Could not find Bitcoin wallet"),
-                );
-                let monero_wallet = Arc::clone(
-                    context
-                        .monero_wallet
-                        .as_ref()
-                        .context("Could not get Monero wallet")?,
-                );
-                let env_config = context.config.env_config;
-                let seed = context.config.seed.clone().context("Could not get seed")?;
+                let swap = buy_xmr(
+                    BuyXmrArgs {
+                        seller_addresses,
+                        bitcoin_change_address,
+                        monero_receive_address,
+                        swap_id,
+                        btc_amount,
+                    },
+                    context,
+                )
+                .await?;
 
-                let seller_peer_id = seller
-                    .extract_peer_id()
-                    .context("Seller address must contain peer ID")?;
-                context
-                    .db
-                    .insert_address(seller_peer_id, seller.clone())
-                    .await?;
+                Ok(json!({
+                    "swapId": swap.swap_id.to_string(),
+                    "quote": swap.quote,
+                }))
+            }
+            Method::BuyXmrFromBestSeller {
+                sellers,
+                quote_timeout,
+                bitcoin_change_address,
+                monero_receive_address,
+                swap_id,
+                btc_amount,
+            } => {
+                let identity = context
+                    .config
+                    .seed
+                    .as_ref()
+                    .context("Cannot extract seed")?
+                    .derive_libp2p_identity(context.config.namespace);
 
-                let behaviour = cli::Behaviour::new(
-                    seller_peer_id,
-                    env_config,
-                    bitcoin_wallet.clone(),
-                    (seed.derive_libp2p_identity(), context.config.namespace),
-                );
-                let mut swarm = swarm::cli(
-                    seed.derive_libp2p_identity(),
+                let (seller_addresses, quote) = cli::best_quote(
+                    sellers,
                     context.config.tor_socks5_port,
-                    behaviour,
+                    identity,
+                    quote_timeout,
                 )
                 .await?;
-
-                swarm.behaviour_mut().add_address(seller_peer_id, seller);
-
-                context
-                    .db
-                    .insert_monero_address(swap_id, monero_receive_address)
-                    .await?;
-
-                tracing::debug!(peer_id = %swarm.local_peer_id(), "Network layer initialized");
-
-                context.swap_lock.acquire_swap_lock(swap_id).await?;
-
-                let initialize_swap = tokio::select! {
-                    biased;
-                    _ = context.swap_lock.listen_for_swap_force_suspension() => {
-                        tracing::debug!("Shutdown signal received, exiting");
-                        context.swap_lock.release_swap_lock().await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
-                        bail!("Shutdown signal received");
-                    },
-                    result = async {
-                        let (event_loop, mut event_loop_handle) =
-                            EventLoop::new(swap_id, swarm, seller_peer_id)?;
-                        let event_loop = tokio::spawn(event_loop.run().in_current_span());
-
-                        let bid_quote = event_loop_handle.request_quote().await?;
-
-                        Ok::<_, anyhow::Error>((event_loop, event_loop_handle, bid_quote))
-                    } => {
-                        result
+                let seller = peer_id_of(&seller_addresses)?;
+                tracing::info!(%seller, price = %quote.price, "Swapping with the seller with the best price");
+
+                let swap = buy_xmr(
+                    BuyXmrArgs {
+                        seller_addresses,
+                        bitcoin_change_address,
+                        monero_receive_address,
+                        swap_id,
+                        btc_amount,
                     },
-                };
-
-                let (event_loop, event_loop_handle, bid_quote) = match initialize_swap {
-                    Ok(result) => result,
-                    Err(error) => {
-                        tracing::error!(%swap_id, "Swap initialization failed: {:#}", error);
-                        context
-                            .swap_lock
-                            .release_swap_lock()
-                            .await
-                            .expect("Could not release swap lock");
-                        bail!(error);
-                    }
-                };
-
-                context.tasks.clone().spawn(async move {
-                    tokio::select! {
-                        biased;
-                        _ = context.swap_lock.listen_for_swap_force_suspension() => {
-                            tracing::debug!("Shutdown signal received, exiting");
-                            context.swap_lock.release_swap_lock().await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
-                            bail!("Shutdown signal received");
-                        },
-                        event_loop_result = event_loop => {
-                            match event_loop_result {
-                                Ok(_) => {
-                                    tracing::debug!(%swap_id, "EventLoop completed")
-                                }
-                                Err(error) => {
-                                    tracing::error!(%swap_id, "EventLoop failed: {:#}", error)
-                                }
-                            }
-                        },
-                        swap_result = async {
-                            let max_givable = || bitcoin_wallet.max_giveable(TxLock::script_size());
-                            let estimate_fee = |amount| bitcoin_wallet.estimate_fee(TxLock::weight(), amount);
-
-                            let determine_amount = determine_btc_to_swap(
-                                context.config.json,
-                                bid_quote,
-                                bitcoin_wallet.new_address(),
-                                || bitcoin_wallet.balance(),
-                                max_givable,
-                                || bitcoin_wallet.sync(),
-                                estimate_fee,
-                            );
-
-                            let (amount, fees) = match determine_amount.await {
-                                Ok(val) => val,
-                                Err(error) => match error.downcast::<ZeroQuoteReceived>() {
-                                    Ok(_) => {
-                                        bail!("Seller's XMR balance is currently too low to initiate a swap, please try again later")
-                                    }
-                                    Err(other) => bail!(other),
-                                },
-                            };
-
-                            tracing::info!(%amount, %fees,  "Determined swap amount");
-
-                            context.db.insert_peer_id(swap_id, seller_peer_id).await?;
-
-                            let swap = Swap::new(
-                                Arc::clone(&context.db),
-                                swap_id,
-                                Arc::clone(&bitcoin_wallet),
-                                monero_wallet,
-                                env_config,
-                                event_loop_handle,
-                                monero_receive_address,
-                                bitcoin_change_address,
-                                amount,
-                            );
-
-                            bob::run(swap).await
-                        } => {
-                            match swap_result {
-                                Ok(state) => {
-                                    tracing::debug!(%swap_id, state=%state, "Swap completed")
-                                }
-                                Err(error) => {
-                                    tracing::error!(%swap_id, "Failed to complete swap: {:#}", error)
-                                }
-                            }
-                        },
-                    };
-                    tracing::debug!(%swap_id, "Swap completed");
-
-                    context
-                        .swap_lock
-                        .release_swap_lock()
-                        .await
-                        .expect("Could not release swap lock");
-                    Ok::<_, anyhow::Error>(())
-                }.in_current_span()).await;
+                    context,
+                )
+                .await?;
 
                 Ok(json!({
-                    "swapId": swap_id.to_string(),
-                    "quote": bid_quote,
+                    "swapId": swap.swap_id.to_string(),
+                    "seller": seller.to_string(),
+                    "quote": swap.quote,
                 }))
             }
-            Method::Resume { swap_id } => {
-                context.swap_lock.acquire_swap_lock(swap_id).await?;
-
-                let seller_peer_id = context.db.get_peer_id(swap_id).await?;
-                let seller_addresses = context.db.get_addresses(seller_peer_id).await?;
+            Method::BuyXmrFromRendezvousPoint {
+                rendezvous_point,
+                seller_peer_id,
+                bitcoin_change_address,
+                monero_receive_address,
+                swap_id,
+                btc_amount,
+            } => {
+                let rendezvous_node_peer_id = rendezvous_point
+                    .extract_peer_id()
+                    .context("Rendezvous node address must contain peer ID")?;
 
-                let seed = context
+                let identity = context
                     .config
                     .seed
                     .as_ref()
-                    .context("Could not get seed")?
-                    .derive_libp2p_identity();
-
-                let behaviour = cli::Behaviour::new(
-                    seller_peer_id,
-                    context.config.env_config,
-                    Arc::clone(
-                        context
-                            .bitcoin_wallet
-                            .as_ref()
-                            .context("Could not get Bitcoin wallet")?,
-                    ),
-                    (seed.clone(), context.config.namespace),
-                );
-                let mut swarm =
-                    swarm::cli(seed.clone(), context.config.tor_socks5_port, behaviour).await?;
-                let our_peer_id = swarm.local_peer_id();
-
-                tracing::debug!(peer_id = %our_peer_id, "Network layer initialized");
+                    .context("Cannot extract seed")?
+                    .derive_libp2p_identity(context.config.namespace);
 
-                for seller_address in seller_addresses {
-                    swarm
-                        .behaviour_mut()
-                        .add_address(seller_peer_id, seller_address);
-                }
+                let sellers = list_sellers(
+                    rendezvous_node_peer_id,
+                    rendezvous_point,
+                    context.config.namespace,
+                    context.config.tor_socks5_port,
+                    identity,
+                )
+                .await?;
+                tracing::info!(
+                    number_of_sellers = sellers.len(),
+                    "Discovered sellers at the rendezvous point"
+                );
 
-                let (event_loop, event_loop_handle) =
-                    EventLoop::new(swap_id, swarm, seller_peer_id)?;
-                let monero_receive_address = context.db.get_monero_address(swap_id).await?;
-                let swap = Swap::from_db(
-                    Arc::clone(&context.db),
-                    swap_id,
-                    Arc::clone(
-                        context
-                            .bitcoin_wallet
-                            .as_ref()
-                            .context("Could not get Bitcoin wallet")?,
-                    ),
-                    Arc::clone(
-                        context
-                            .monero_wallet
-                            .as_ref()
-                            .context("Could not get Monero wallet")?,
-                    ),
-                    context.config.env_config,
-                    event_loop_handle,
-                    monero_receive_address,
+                let (seller_address, quote) = cli::best_seller(sellers, seller_peer_id)?;
+                let seller = seller_address
+                    .extract_peer_id()
+                    .context("Seller address must contain peer ID")?;
+                tracing::info!(%seller, price = %quote.price, "Swapping with the seller with the best price");
+
+                let swap = buy_xmr(
+                    BuyXmrArgs {
+                        seller_addresses: vec![seller_address],
+                        bitcoin_change_address,
+                        monero_receive_address,
+                        swap_id,
+                        btc_amount,
+                    },
+                    context,
                 )
                 .await?;
 
-                context.tasks.clone().spawn(
-                    async move {
-                        let handle = tokio::spawn(event_loop.run().in_current_span());
-                        tokio::select! {
-                            biased;
-                            _ = context.swap_lock.listen_for_swap_force_suspension() => {
-                                 tracing::debug!("Shutdown signal received, exiting");
-                                context.swap_lock.release_swap_lock().await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
-                                bail!("Shutdown signal received");
-                            },
-
-                            event_loop_result = handle => {
-                                match event_loop_result {
-                                    Ok(_) => {
-                                        tracing::debug!(%swap_id, "EventLoop completed during swap resume")
-                                    }
-                                    Err(error) => {
-                                        tracing::error!(%swap_id, "EventLoop failed during swap resume: {:#}", error)
-                                    }
-                                }
-                            },
-                            swap_result = bob::run(swap) => {
-                                match swap_result {
-                                    Ok(state) => {
-                                        tracing::debug!(%swap_id, state=%state, "Swap completed after resuming")
-                                    }
-                                    Err(error) => {
-                                        tracing::error!(%swap_id, "Failed to resume swap: {:#}", error)
-                                    }
-                                }
+                Ok(json!({
+                    "swapId": swap.swap_id.to_string(),
+                    "seller": seller.to_string(),
+                    "quote": swap.quote,
+                }))
+            }
+            Method::BuyXmrConcurrently { swaps } => {
+                // A swap only starts once the previous one reserved its funds,
+                // so every swap determines its amount from the balance that is
+                // left, waiting for its own deposit if there is none
+                let mut started = Vec::new();
+                for args in swaps {
+                    let swap_id = args.swap_id;
+                    let mut handle = buy_xmr(args, Arc::clone(&context))
+                        .instrument(info_span!("swap", id = %swap_id))
+                        .await;
+
+                    match &mut handle {
+                        Ok(handle) => handle.setup_completed().await,
+                        Err(error) => {
+                            tracing::error!(%swap_id, "Failed to start swap: {:#}", error)
+                        }
+                    }
+                    started.push((swap_id, handle));
+                }
+
+                // One failing swap must not stop the others, so every swap is
+                // awaited before reporting
+                let outcomes = futures::future::join_all(started.into_iter().map(
+                    |(swap_id, handle)| async move {
+                        let outcome = match handle {
+                            Ok(handle) => handle.finished().await,
+                            Err(error) => Err(error),
+                        };
+                        (swap_id, outcome)
+                    },
+                ))
+                .await;
 
-                            }
+                let mut summary = Vec::new();
+                for (swap_id, outcome) in outcomes {
+                    match outcome {
+                        Ok(state) => {
+                            tracing::info!(%swap_id, %state, "Swap finished");
+                            summary.push(json!({ "swapId": swap_id, "state": state.to_string() }));
+                        }
+                        Err(error) => {
+                            tracing::error!(%swap_id, "Swap failed: {:#}", error);
+                            summary.push(
+                                json!({ "swapId": swap_id, "error": format!("{:#}", error) }),
+                            );
                         }
-                        context
-                            .swap_lock
-                            .release_swap_lock()
-                            .await
-                            .expect("Could not release swap lock");
-                        Ok::<(), anyhow::Error>(())
                     }
-                    .in_current_span(),
-                ).await;
+                }
+
+                Ok(json!({ "swaps": summary }))
+            }
+            Method::Resume { swap_id } => {
+                resume(swap_id, context).await?;
+
                 Ok(json!({
                     "result": "ok",
                 }))
             }
             Method::CancelAndRefund { swap_id } => {
+                let state = cancel_and_refund(swap_id, &context).await?;
+
+                Ok(json!({
+                    "result": state,
+                }))
+            }
+            Method::BumpFee {
+                swap_id,
+                fee_rate_sat_vb,
+            } => {
                 let bitcoin_wallet = context
                     .bitcoin_wallet
                     .as_ref()
                     .context("Could not get Bitcoin wallet")?;
 
-                context.swap_lock.acquire_swap_lock(swap_id).await?;
-
-                let state = cli::cancel_and_refund(
+                let txid = cli::bump_lock_fee(
                     swap_id,
+                    fee_rate_sat_vb,
                     Arc::clone(bitcoin_wallet),
                     Arc::clone(&context.db),
                 )
-                .await;
+                .await?;
 
-                context
-                    .swap_lock
-                    .release_swap_lock()
-                    .await
-                    .expect("Could not release swap lock");
+                Ok(json!({
+                    "txid": txid,
+                }))
+            }
+            Method::History { only_unfinished } => {
+                let mut swaps = history(&context).await?;
+                if only_unfinished {
+                    swaps.retain(|(_, state)| state.status().progress == SwapProgress::InProgress);
+                }
 
-                state.map(|state| {
-                    json!({
-                        "result": state,
-                    })
-                })
+                if context.config.json {
+                    Output::history(&swaps).print();
+                } else {
+                    for (swap_id, state) in &swaps {
+                        tracing::info!(%swap_id, %state, status = %state.status(), "Found swap in database");
+                    }
+                }
+
+                let swaps: Vec<(Uuid, String)> = swaps
+                    .into_iter()
+                    .map(|(swap_id, state)| (swap_id, state.to_string()))
+                    .collect();
+
+                Ok(json!({ "swaps": swaps }))
             }
-            Method::History => {
-                let swaps = context.db.all().await?;
-                let mut vec: Vec<(Uuid, String)> = Vec::new();
-                for (swap_id, state) in swaps {
-                    let state: BobState = state.try_into()?;
-                    vec.push((swap_id, state.to_string()));
+            Method::Cleanup {
+                older_than,
+                dry_run,
+            } => {
+                let cleanup = cli::cleanup(
+                    Arc::clone(&context.db),
+                    &context.config.data_dir,
+                    older_than,
+                    dry_run,
+                )
+                .await?;
+
+                tracing::info!(
+                    swaps = cleanup.swaps.len(),
+                    reclaimed_bytes = cleanup.reclaimed_bytes,
+                    dry_run,
+                    "Cleaned up finished swaps"
+                );
+
+                Ok(json!({
+                    "swaps": cleanup.swaps,
+                    "reclaimed_bytes": cleanup.reclaimed_bytes,
+                    "dry_run": dry_run,
+                }))
+            }
+            Method::Log { swap_id } => {
+                let history = context
+                    .db
+                    .history(swap_id)
+                    .await?
+                    .into_iter()
+                    .map(|(entered_at, state)| Ok((entered_at, state.try_into()?)))
+                    .collect::<Result<Vec<(OffsetDateTime, BobState)>>>()?;
+                if history.is_empty() {
+                    bail!("No state in database for swap: {}", swap_id)
+                }
+
+                let output = Output::swap_log(swap_id, &history)?;
+                if context.config.json {
+                    output.print();
+                } else {
+                    for (entered_at, state) in &history {
+                        tracing::info!(%swap_id, %entered_at, %state, "Swap state");
+                    }
                 }
 
-                Ok(json!({ "swaps": vec }))
+                Ok(json!({ "log": output }))
+            }
+            Method::Show { swap_id } => {
+                let summary = context
+                    .db
+                    .get_swap_summary(swap_id)
+                    .await?
+                    .with_context(|| format!("No summary was recorded for swap {}", swap_id))?;
+
+                print_swap_summary(context.config.json, swap_id, &summary);
+
+                Ok(json!({ "summary": summary }))
             }
             Method::GetRawStates => {
                 let raw_history = context.db.raw_all().await?;
@@ -656,22 +746,38 @@ impl Request {
                     .as_ref()
                     .context("Could not get Bitcoin wallet")?;
 
+                let max_giveable = bitcoin_wallet
+                    .max_giveable(address.script_pubkey().len())
+                    .await?;
+                if max_giveable == Amount::ZERO {
+                    bail!("There are no funds to withdraw after paying network fees");
+                }
+
                 let amount = match amount {
+                    Some(amount) if amount > max_giveable => bail!(
+                        "Cannot withdraw {}, at most {} can be withdrawn after paying network fees",
+                        amount,
+                        max_giveable
+                    ),
                     Some(amount) => amount,
-                    None => {
-                        bitcoin_wallet
-                            .max_giveable(address.script_pubkey().len())
-                            .await?
-                    }
+                    None => max_giveable,
                 };
                 let psbt = bitcoin_wallet
-                    .send_to_address(address, amount, None)
+                    .send_to_address(address.clone(), amount, None)
                     .await?;
                 let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
 
-                bitcoin_wallet
+                if let Err(error) = bitcoin_wallet
                     .broadcast(signed_tx.clone(), "withdraw")
-                    .await?;
+                    .await
+                {
+                    bitcoin_wallet
+                        .release_utxos(signed_tx.input.iter().map(|input| input.previous_output))
+                        .await;
+                    return Err(error);
+                }
+
+                tracing::info!(%amount, %address, txid = %signed_tx.txid(), "Withdrew Bitcoin");
 
                 Ok(json!({
                     "signed_tx": signed_tx,
@@ -707,8 +813,14 @@ impl Request {
                 let bitcoin_balance = bitcoin_wallet.balance().await?;
 
                 if force_refresh {
+                    let by_confirmation = bitcoin_wallet.balance_by_confirmation().await?;
+                    let deposit_address = bitcoin_wallet.deposit_address().await?;
+
                     tracing::info!(
                         balance = %bitcoin_balance,
+                        confirmed = %by_confirmation.confirmed,
+                        pending = %by_confirmation.pending,
+                        %deposit_address,
                         "Checked Bitcoin balance",
                     );
                 } else {
@@ -732,7 +844,7 @@ impl Request {
                     .seed
                     .as_ref()
                     .context("Cannot extract seed")?
-                    .derive_libp2p_identity();
+                    .derive_libp2p_identity(context.config.namespace);
 
                 let sellers = list_sellers(
                     rendezvous_node_peer_id,
@@ -767,6 +879,35 @@ impl Request {
 
                 Ok(json!({ "sellers": sellers }))
             }
+            Method::Quote { seller, timeout } => {
+                let identity = context
+                    .config
+                    .seed
+                    .as_ref()
+                    .context("Cannot extract seed")?
+                    .derive_libp2p_identity(context.config.namespace);
+
+                let quote = cli::request_quote(
+                    vec![seller],
+                    context.config.tor_socks5_port,
+                    identity,
+                    timeout,
+                )
+                .await?;
+
+                if context.config.json {
+                    Output::Quote { quote }.print();
+                } else {
+                    tracing::info!(
+                        price = %quote.price,
+                        min_quantity = %quote.min_quantity,
+                        max_quantity = %quote.max_quantity,
+                        "Received quote"
+                    );
+                }
+
+                Ok(json!({ "quote": quote }))
+            }
             Method::ExportBitcoinWallet => {
                 let bitcoin_wallet = context
                     .bitcoin_wallet
@@ -780,9 +921,20 @@ impl Request {
                 }))
             }
             Method::MoneroRecovery { swap_id } => {
-                let swap_state: BobState = context.db.get_state(swap_id).await?.try_into()?;
-
-                if let BobState::BtcRedeemed(state5) = swap_state {
+                // The keys are also needed if the Monero was swept but did not arrive,
+                // so we look for them in every state the swap went through
+                let btc_redeemed = context
+                    .db
+                    .get_states(swap_id)
+                    .await?
+                    .into_iter()
+                    .rev()
+                    .find_map(|state| match state {
+                        State::Bob(BobState::BtcRedeemed(state5)) => Some(state5),
+                        _ => None,
+                    });
+
+                if let Some(state5) = btc_redeemed {
                     let (spend_key, view_key) = state5.xmr_keys();
                     let restore_height = state5.monero_wallet_restore_blockheight.height;
 
@@ -801,12 +953,64 @@ impl Request {
                         "restore_height": state5.monero_wallet_restore_blockheight.height,
                     }))
                 } else {
+                    let swap_state: BobState = context.db.get_state(swap_id).await?.try_into()?;
+
                     bail!(
-                        "Cannot print monero recovery information in state {}, only possible for BtcRedeemed",
+                        "Cannot print monero recovery information in state {}, only possible once the swap reached BtcRedeemed",
                         swap_state
                     )
                 }
             }
+            Method::ExportSwap { swap_id, file } => {
+                let seed = context.config.seed.as_ref().context("Could not get seed")?;
+
+                let backup = context.db.export_swap(swap_id).await?;
+                let contents = backup.encrypt(seed)?;
+
+                // Never clobber an existing file, it might be the only backup of another swap
+                let mut output = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&file)
+                    .await
+                    .with_context(|| format!("Failed to create {}", file.display()))?;
+                output.write_all(&contents).await?;
+                output.sync_all().await?;
+
+                tracing::info!(%swap_id, file = %file.display(), "Exported swap");
+
+                Ok(json!({
+                    "swap_id": swap_id,
+                    "file": file,
+                }))
+            }
+            Method::ImportSwap { file, force } => {
+                let seed = context.config.seed.as_ref().context("Could not get seed")?;
+
+                let contents = tokio::fs::read(&file)
+                    .await
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                let backup = SwapBackup::decrypt(&contents, seed)?;
+                let swap_id = backup.swap_id;
+
+                context
+                    .db
+                    .import_swap(backup, force)
+                    .await
+                    .map_err(|error| match error.downcast_ref::<SwapAlreadyExists>() {
+                        Some(_) => error.context(format!(
+                            "Refusing to import swap {}, pass --force to replace it",
+                            swap_id
+                        )),
+                        None => error,
+                    })?;
+
+                tracing::info!(%swap_id, replaced = force, "Imported swap");
+
+                Ok(json!({
+                    "swap_id": swap_id,
+                }))
+            }
             Method::GetCurrentSwap => Ok(json!({
                 "swap_id": context.swap_lock.get_current_swap_id().await
             })),
@@ -828,6 +1032,489 @@ impl Request {
     }
 }
 
+/// The arguments for starting a new swap with [`buy_xmr`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuyXmrArgs {
+    /// The addresses of a single seller, which are dialled at once.
+    pub seller_addresses: Vec<Multiaddr>,
+    pub bitcoin_change_address: bitcoin::Address,
+    pub monero_receive_address: monero::Address,
+    pub swap_id: Uuid,
+    /// Caps the amount of Bitcoin to swap. Without it the swap uses as much of
+    /// the wallet's balance as the seller accepts.
+    pub btc_amount: Option<Amount>,
+}
+
+/// A swap running in the background of a [`Context`].
+///
+/// The swap keeps running if the handle is dropped, the context's
+/// [`PendingTaskList`](crate::api::PendingTaskList) tracks it either way.
+#[derive(Debug)]
+pub struct SwapHandle {
+    pub swap_id: Uuid,
+    /// The quote the swap amount was determined from. Only known for swaps
+    /// started with [`buy_xmr`].
+    pub quote: Option<BidQuote>,
+    setup_completed: oneshot::Receiver<()>,
+    finished: oneshot::Receiver<Result<BobState>>,
+}
+
+impl SwapHandle {
+    /// Waits until the swap reserved the Bitcoin it locks, from then on other
+    /// swaps can't spend those funds. Returns early if the swap stopped before.
+    pub async fn setup_completed(&mut self) {
+        let _ = (&mut self.setup_completed).await;
+    }
+
+    /// Waits until the swap reached a final state, failed or was suspended.
+    pub async fn finished(self) -> Result<BobState> {
+        self.finished
+            .await
+            .context("Swap stopped without reporting its result")?
+    }
+}
+
+/// Requests a quote from the seller and starts a swap in the background once
+/// enough Bitcoin has been deposited.
+///
+/// Returns as soon as the quote has been received, the handle can be used to
+/// wait for the outcome of the swap.
+pub async fn buy_xmr(args: BuyXmrArgs, context: Arc<Context>) -> Result<SwapHandle> {
+    let BuyXmrArgs {
+        seller_addresses,
+        bitcoin_change_address,
+        monero_receive_address,
+        swap_id,
+        btc_amount,
+    } = args;
+
+    let bitcoin_wallet = Arc::clone(
+        context
+            .bitcoin_wallet
+            .as_ref()
+            .context("Could not get Bitcoin wallet")?,
+    );
+    let monero_wallet = Arc::clone(
+        context
+            .monero_wallet
+            .as_ref()
+            .context("Could not get Monero wallet")?,
+    );
+    let env_config = context.config.env_config;
+    let seed = context.config.seed.clone().context("Could not get seed")?;
+
+    reserve_funds_of_unfinished_swaps(&context, &bitcoin_wallet).await?;
+
+    let seller_peer_id = peer_id_of(&seller_addresses).context("Invalid seller address")?;
+    for address in &seller_addresses {
+        context
+            .db
+            .insert_address(seller_peer_id, address.clone())
+            .await?;
+    }
+
+    let behaviour = cli::Behaviour::new(
+        seller_peer_id,
+        env_config,
+        bitcoin_wallet.clone(),
+        (
+            seed.derive_libp2p_identity(context.config.namespace),
+            context.config.namespace,
+        ),
+    );
+    let mut swarm = swarm::cli(
+        seed.derive_libp2p_identity(context.config.namespace),
+        context.config.tor_socks5_port,
+        behaviour,
+    )
+    .await?;
+
+    for address in seller_addresses {
+        swarm.behaviour_mut().add_address(seller_peer_id, address);
+    }
+
+    context
+        .db
+        .insert_monero_address(swap_id, monero_receive_address)
+        .await?;
+
+    tracing::debug!(peer_id = %swarm.local_peer_id(), "Network layer initialized");
+
+    context.swap_lock.acquire_swap_lock(swap_id).await?;
+
+    let initialize_swap = tokio::select! {
+        biased;
+        _ = context.swap_lock.listen_for_swap_force_suspension(swap_id) => {
+            tracing::debug!("Shutdown signal received, exiting");
+            context.swap_lock.release_swap_lock(swap_id).await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
+            bail!("Shutdown signal received");
+        },
+        result = async {
+            let (event_loop, mut event_loop_handle) = EventLoop::new(
+                swap_id,
+                swarm,
+                seller_peer_id,
+                Arc::clone(&context.db),
+                env_config,
+            )?;
+            let event_loop = tokio::spawn(event_loop.run().in_current_span());
+
+            let bid_quote = event_loop_handle.request_quote().await?;
+
+            Ok::<_, anyhow::Error>((event_loop, event_loop_handle, bid_quote))
+        } => {
+            result
+        },
+    };
+
+    let (event_loop, event_loop_handle, bid_quote) = match initialize_swap {
+        Ok(result) => result,
+        Err(error) => {
+            tracing::error!(%swap_id, "Swap initialization failed: {:#}", error);
+            context
+                .swap_lock
+                .release_swap_lock(swap_id)
+                .await
+                .expect("Could not release swap lock");
+            bail!(error);
+        }
+    };
+
+    let json = context.config.json;
+    let (setup_completed_sender, setup_completed) = oneshot::channel();
+    let (finished_sender, finished) = oneshot::channel();
+
+    context.tasks.clone().spawn(async move {
+        tokio::select! {
+            biased;
+            _ = context.swap_lock.listen_for_swap_force_suspension(swap_id) => {
+                tracing::debug!("Shutdown signal received, exiting");
+                let _ = finished_sender.send(Err(anyhow!("Shutdown signal received")));
+                context.swap_lock.release_swap_lock(swap_id).await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
+                bail!("Shutdown signal received");
+            },
+            event_loop_result = event_loop => {
+                let error = match event_loop_result {
+                    Ok(_) => {
+                        tracing::debug!(%swap_id, "EventLoop completed");
+                        anyhow!("EventLoop completed before the swap")
+                    }
+                    Err(error) => {
+                        tracing::error!(%swap_id, "EventLoop failed: {:#}", error);
+                        anyhow::Error::new(error).context("EventLoop failed")
+                    }
+                };
+                let _ = finished_sender.send(Err(error));
+            },
+            swap_result = async {
+                let max_givable = || bitcoin_wallet.max_giveable(TxLock::script_size());
+                let estimate_fee = |amount| bitcoin_wallet.estimate_fee(TxLock::weight(), amount);
+
+                let determine_amount = determine_btc_to_swap(
+                    context.config.json,
+                    bid_quote,
+                    btc_amount,
+                    bitcoin_wallet.new_address(),
+                    || bitcoin_wallet.balance(),
+                    max_givable,
+                    || bitcoin_wallet.sync(),
+                    estimate_fee,
+                );
+
+                let (amount, fees) = match determine_amount.await {
+                    Ok(val) => val,
+                    Err(error) => match error.downcast::<ZeroQuoteReceived>() {
+                        Ok(_) => {
+                            bail!("Seller's XMR balance is currently too low to initiate a swap, please try again later")
+                        }
+                        Err(other) => bail!(other),
+                    },
+                };
+
+                tracing::info!(%amount, %fees,  "Determined swap amount");
+
+                context.db.insert_peer_id(swap_id, seller_peer_id).await?;
+
+                let swap = Swap::new(
+                    Arc::clone(&context.db),
+                    swap_id,
+                    Arc::clone(&bitcoin_wallet),
+                    monero_wallet,
+                    env_config,
+                    event_loop_handle,
+                    monero_receive_address,
+                    bitcoin_change_address,
+                    amount,
+                );
+
+                let mut setup_completed_sender = Some(setup_completed_sender);
+                bob::run_and_observe(swap, |state| {
+                    if json {
+                        Output::swap_state(swap_id, state).print();
+                    }
+                    if !matches!(state, BobState::Started { .. }) {
+                        if let Some(sender) = setup_completed_sender.take() {
+                            let _ = sender.send(());
+                        }
+                    }
+                })
+                .await
+            } => {
+                match &swap_result {
+                    Ok(state) => {
+                        tracing::debug!(%swap_id, state=%state, "Swap completed")
+                    }
+                    Err(error) => {
+                        tracing::error!(%swap_id, "Failed to complete swap: {:#}", error)
+                    }
+                }
+                if json {
+                    Output::swap_result(swap_id, &swap_result).print();
+                }
+                print_stored_swap_summary(&context, swap_id).await;
+                let _ = finished_sender.send(swap_result);
+            },
+        };
+        tracing::debug!(%swap_id, "Swap completed");
+
+        context
+            .swap_lock
+            .release_swap_lock(swap_id)
+            .await
+            .expect("Could not release swap lock");
+        Ok::<_, anyhow::Error>(())
+    }.in_current_span()).await;
+
+    Ok(SwapHandle {
+        swap_id,
+        quote: Some(bid_quote),
+        setup_completed,
+        finished,
+    })
+}
+
+/// Resumes an unfinished swap from the database in the background.
+pub async fn resume(swap_id: Uuid, context: Arc<Context>) -> Result<SwapHandle> {
+    context.swap_lock.acquire_swap_lock(swap_id).await?;
+
+    let seller_peer_id = context.db.get_peer_id(swap_id).await?;
+    let seller_addresses = context.db.get_addresses(seller_peer_id).await?;
+
+    let seed = context
+        .config
+        .seed
+        .as_ref()
+        .context("Could not get seed")?
+        .derive_libp2p_identity(context.config.namespace);
+
+    let behaviour = cli::Behaviour::new(
+        seller_peer_id,
+        context.config.env_config,
+        Arc::clone(
+            context
+                .bitcoin_wallet
+                .as_ref()
+                .context("Could not get Bitcoin wallet")?,
+        ),
+        (seed.clone(), context.config.namespace),
+    );
+    let mut swarm = swarm::cli(seed.clone(), context.config.tor_socks5_port, behaviour).await?;
+    let our_peer_id = swarm.local_peer_id();
+
+    tracing::debug!(peer_id = %our_peer_id, "Network layer initialized");
+
+    for seller_address in seller_addresses {
+        swarm
+            .behaviour_mut()
+            .add_address(seller_peer_id, seller_address);
+    }
+
+    let bitcoin_wallet = Arc::clone(
+        context
+            .bitcoin_wallet
+            .as_ref()
+            .context("Could not get Bitcoin wallet")?,
+    );
+    reserve_funds_of_unfinished_swaps(&context, &bitcoin_wallet).await?;
+
+    let (event_loop, event_loop_handle) = EventLoop::new(
+        swap_id,
+        swarm,
+        seller_peer_id,
+        Arc::clone(&context.db),
+        context.config.env_config,
+    )?;
+    let monero_receive_address = context.db.get_monero_address(swap_id).await?;
+    let swap = Swap::from_db(
+        Arc::clone(&context.db),
+        swap_id,
+        bitcoin_wallet,
+        Arc::clone(
+            context
+                .monero_wallet
+                .as_ref()
+                .context("Could not get Monero wallet")?,
+        ),
+        context.config.env_config,
+        event_loop_handle,
+        monero_receive_address,
+    )
+    .await?;
+
+    let json = context.config.json;
+    let (finished_sender, finished) = oneshot::channel();
+
+    context.tasks.clone().spawn(
+        async move {
+            let handle = tokio::spawn(event_loop.run().in_current_span());
+            tokio::select! {
+                biased;
+                _ = context.swap_lock.listen_for_swap_force_suspension(swap_id) => {
+                    tracing::debug!("Shutdown signal received, exiting");
+                    let _ = finished_sender.send(Err(anyhow!("Shutdown signal received")));
+                    context.swap_lock.release_swap_lock(swap_id).await.expect("Shutdown signal received but failed to release swap lock. The swap process has been terminated but the swap lock is still active.");
+                    bail!("Shutdown signal received");
+                },
+
+                event_loop_result = handle => {
+                    let error = match event_loop_result {
+                        Ok(_) => {
+                            tracing::debug!(%swap_id, "EventLoop completed during swap resume");
+                            anyhow!("EventLoop completed before the swap")
+                        }
+                        Err(error) => {
+                            tracing::error!(%swap_id, "EventLoop failed during swap resume: {:#}", error);
+                            anyhow::Error::new(error).context("EventLoop failed")
+                        }
+                    };
+                    let _ = finished_sender.send(Err(error));
+                },
+                swap_result = bob::run_and_observe(swap, |state| {
+                    if json {
+                        Output::swap_state(swap_id, state).print();
+                    }
+                }) => {
+                    match &swap_result {
+                        Ok(state) => {
+                            tracing::debug!(%swap_id, state=%state, "Swap completed after resuming")
+                        }
+                        Err(error) => {
+                            tracing::error!(%swap_id, "Failed to resume swap: {:#}", error)
+                        }
+                    }
+                    if json {
+                        Output::swap_result(swap_id, &swap_result).print();
+                    }
+                    print_stored_swap_summary(&context, swap_id).await;
+                    let _ = finished_sender.send(swap_result);
+                }
+            }
+            context
+                .swap_lock
+                .release_swap_lock(swap_id)
+                .await
+                .expect("Could not release swap lock");
+            Ok::<(), anyhow::Error>(())
+        }
+        .in_current_span(),
+    )
+    .await;
+
+    // the funds of a resumed swap are already reserved or spent
+    let (setup_completed_sender, setup_completed) = oneshot::channel();
+    let _ = setup_completed_sender.send(());
+
+    Ok(SwapHandle {
+        swap_id,
+        quote: None,
+        setup_completed,
+        finished,
+    })
+}
+
+/// Cancels the swap and refunds the locked Bitcoin, returns the state the
+/// swap ended up in.
+pub async fn cancel_and_refund(swap_id: Uuid, context: &Context) -> Result<BobState> {
+    let bitcoin_wallet = context
+        .bitcoin_wallet
+        .as_ref()
+        .context("Could not get Bitcoin wallet")?;
+
+    context.swap_lock.acquire_swap_lock(swap_id).await?;
+
+    let state =
+        cli::cancel_and_refund(swap_id, Arc::clone(bitcoin_wallet), Arc::clone(&context.db)).await;
+
+    context
+        .swap_lock
+        .release_swap_lock(swap_id)
+        .await
+        .expect("Could not release swap lock");
+
+    state
+}
+
+/// Reserves the inputs of lock transactions that were built but not
+/// published before the process restarted, so that other swaps running in
+/// the same context don't spend them.
+///
+/// Swaps that completed the setup longer ago than the seller waits for our
+/// lock transaction are stale, the seller has aborted them already.
+async fn reserve_funds_of_unfinished_swaps(
+    context: &Context,
+    bitcoin_wallet: &bitcoin::Wallet,
+) -> Result<()> {
+    let lock_timeout = context.config.env_config.bitcoin_lock_mempool_timeout;
+
+    for (swap_id, state) in context.db.all().await? {
+        if let State::Bob(BobState::SwapSetupCompleted(state2)) = state {
+            let setup_completed_at = match context.db.history(swap_id).await?.last() {
+                Some((entered_at, _)) => *entered_at,
+                None => continue,
+            };
+
+            if OffsetDateTime::now_utc() - setup_completed_at > lock_timeout {
+                tracing::debug!(%swap_id, "Not reserving the funds of a stale swap");
+                continue;
+            }
+
+            bitcoin_wallet.reserve_utxos(state2.tx_lock.inputs()).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the latest state of every swap in the database.
+pub async fn history(context: &Context) -> Result<Vec<(Uuid, BobState)>> {
+    let mut swaps = Vec::new();
+    for (swap_id, state) in context.db.all().await? {
+        let state: BobState = state.try_into()?;
+        swaps.push((swap_id, state));
+    }
+
+    Ok(swaps)
+}
+
+/// Logs the summary of a swap or, with `--json`, prints it to stdout.
+fn print_swap_summary(json: bool, swap_id: Uuid, summary: &SwapSummary) {
+    if json {
+        Output::swap_summary(swap_id, summary.clone()).print();
+    } else {
+        tracing::info!(%swap_id, %summary, "Swap summary");
+    }
+}
+
+/// Prints the summary recorded for the swap, if any. Failing to load it must
+/// not hide the outcome of the swap, so errors are only logged.
+async fn print_stored_swap_summary(context: &Context, swap_id: Uuid) {
+    match context.db.get_swap_summary(swap_id).await {
+        Ok(Some(summary)) => print_swap_summary(context.config.json, swap_id, &summary),
+        Ok(None) => {}
+        Err(error) => tracing::warn!(%swap_id, "Failed to load swap summary: {:#}", error),
+    }
+}
+
 fn qr_code(value: &impl ToString) -> Result<String> {
     let code = QrCode::new(value.to_string())?;
     let qr_code = code
@@ -841,6 +1528,7 @@ fn qr_code(value: &impl ToString) -> Result<String> {
 pub async fn determine_btc_to_swap<FB, TB, FMG, TMG, FS, TS, FFE, TFE>(
     json: bool,
     bid_quote: BidQuote,
+    requested: Option<Amount>,
     get_new_address: impl Future<Output = Result<bitcoin::Address>>,
     balance: FB,
     max_giveable_fn: FMG,
@@ -867,11 +1555,40 @@ where
         maximum_amount = %bid_quote.max_quantity,
         "Received quote",
     );
+    if json {
+        Output::Quote { quote: bid_quote }.print();
+    }
+
+    // Bob can neither lock less than Alice's minimum nor an amount below dust.
+    let min_swap_amount = max(bid_quote.min_quantity, Amount::from_sat(DUST_AMOUNT));
+    if bid_quote.max_quantity < min_swap_amount {
+        bail!(QuoteBelowMinimumSwapAmount {
+            max_quantity: bid_quote.max_quantity,
+            min_swap_amount,
+        })
+    }
+    if let Some(requested) = requested {
+        if requested < min_swap_amount {
+            bail!(
+                "Requested to swap {} which is below the minimum swap amount of {}",
+                requested,
+                min_swap_amount
+            )
+        }
+    }
+
+    // The deposit we ask for covers the requested amount, or the minimum if
+    // none was requested
+    let max_accepted = bid_quote.max_quantity;
+    let required_amount = match requested {
+        Some(requested) => min(requested, max_accepted),
+        None => min_swap_amount,
+    };
 
     sync().await?;
     let mut max_giveable = max_giveable_fn().await?;
 
-    if max_giveable == Amount::ZERO || max_giveable < bid_quote.min_quantity {
+    if max_giveable < required_amount {
         let deposit_address = get_new_address.await?;
         let minimum_amount = bid_quote.min_quantity;
         let maximum_amount = bid_quote.max_quantity;
@@ -881,16 +1598,23 @@ where
         }
 
         loop {
-            let min_outstanding = bid_quote.min_quantity - max_giveable;
+            let min_outstanding = required_amount - max_giveable;
             let min_bitcoin_lock_tx_fee = estimate_fee(min_outstanding).await?;
             let min_deposit_until_swap_will_start = min_outstanding + min_bitcoin_lock_tx_fee;
             let max_deposit_until_maximum_amount_is_reached =
                 maximum_amount - max_giveable + min_bitcoin_lock_tx_fee;
 
-            tracing::info!(
-                "Deposit at least {} to cover the min quantity with fee!",
-                min_deposit_until_swap_will_start
-            );
+            if requested.is_some() {
+                tracing::info!(
+                    "Deposit at least {} to cover the requested amount with fee!",
+                    min_deposit_until_swap_will_start
+                );
+            } else {
+                tracing::info!(
+                    "Deposit at least {} to cover the min quantity with fee!",
+                    min_deposit_until_swap_will_start
+                );
+            }
             tracing::info!(
                 %deposit_address,
                 %min_deposit_until_swap_will_start,
@@ -902,22 +1626,23 @@ where
                 price = %bid_quote.price,
                 "Waiting for Bitcoin deposit",
             );
-
-            max_giveable = loop {
-                sync().await?;
-                let new_max_givable = max_giveable_fn().await?;
-
-                if new_max_givable > max_giveable {
-                    break new_max_givable;
+            if json {
+                Output::DepositRequired {
+                    deposit_address: deposit_address.clone(),
+                    min_deposit: min_deposit_until_swap_will_start,
+                    max_deposit: max_deposit_until_maximum_amount_is_reached,
+                    max_giveable,
+                    min_bitcoin_lock_tx_fee,
                 }
+                .print();
+            }
 
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            };
+            max_giveable = wait_for_deposit(max_giveable, &sync, &max_giveable_fn).await?;
 
             let new_balance = balance().await?;
             tracing::info!(%new_balance, %max_giveable, "Received Bitcoin");
 
-            if max_giveable < bid_quote.min_quantity {
+            if max_giveable < min_swap_amount {
                 tracing::info!("Deposited amount is not enough to cover `min_quantity` when accounting for network fees");
                 continue;
             }
@@ -928,8 +1653,287 @@ where
 
     let balance = balance().await?;
     let fees = balance - max_giveable;
-    let max_accepted = bid_quote.max_quantity;
-    let btc_swap_amount = min(max_giveable, max_accepted);
+    let btc_swap_amount = match requested {
+        Some(requested) if max_giveable < required_amount => {
+            bail!(
+                "Requested to swap {} but only {} can be spent after fees, deposit more Bitcoin or request a smaller amount",
+                requested,
+                max_giveable
+            )
+        }
+        Some(requested) => min(min(requested, max_giveable), max_accepted),
+        None => min(max_giveable, max_accepted),
+    };
+
+    // Converted exactly like the seller does, unless the price changes before
+    // the swap is set up
+    if let Ok(xmr_amount) =
+        amounts::Rate::from_price(bid_quote.price).btc_to_xmr(btc_swap_amount, Rounding::Down)
+    {
+        tracing::info!(%btc_swap_amount, %xmr_amount, "Determined swap amount");
+    }
 
     Ok((btc_swap_amount, fees))
 }
+
+/// Syncs the wallet every second until the max giveable amount is larger than
+/// `previous`, which means a deposit arrived.
+async fn wait_for_deposit<FMG, TMG, FS, TS>(
+    previous: Amount,
+    sync: &FS,
+    max_giveable_fn: &FMG,
+) -> Result<Amount>
+where
+    TMG: Future<Output = Result<Amount>>,
+    FMG: Fn() -> TMG,
+    TS: Future<Output = Result<()>>,
+    FS: Fn() -> TS,
+{
+    loop {
+        sync().await?;
+        let new_max_givable = max_giveable_fn().await?;
+
+        if new_max_givable > previous {
+            return Ok(new_max_givable);
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::quote::PROTOCOL_VERSION;
+    use proptest::prelude::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    proptest! {
+        #[test]
+        fn determined_amount_respects_quote_wallet_and_dust(
+            min_quantity in 0u64..100_000_000,
+            max_quantity in 0u64..200_000_000,
+            lock_fee in 0u64..100_000,
+            max_giveables in prop::collection::vec(0u64..200_000_000, 1..10),
+        ) {
+            let quote = BidQuote {
+                price: Amount::from_sat(100_000),
+                min_quantity: Amount::from_sat(min_quantity),
+                max_quantity: Amount::from_sat(max_quantity),
+                protocol_version: PROTOCOL_VERSION,
+            };
+            let lock_fee = Amount::from_sat(lock_fee);
+            let wallet = ScriptedWallet::new(&max_giveables, lock_fee);
+
+            let result = determine(quote, &wallet);
+
+            let min_swap_amount = max(quote.min_quantity, Amount::from_sat(DUST_AMOUNT));
+            let enough_deposited = max_giveables
+                .iter()
+                .any(|max_giveable| Amount::from_sat(*max_giveable) >= min_swap_amount);
+            let can_swap = quote.max_quantity >= min_swap_amount && enough_deposited;
+            prop_assert_eq!(result.is_ok(), can_swap);
+
+            if let Ok((amount, fees)) = result {
+                prop_assert!(amount <= wallet.last_max_giveable());
+                prop_assert_eq!(fees, lock_fee);
+                prop_assert!(amount <= quote.max_quantity);
+                prop_assert!(amount >= quote.min_quantity);
+                prop_assert!(amount >= Amount::from_sat(DUST_AMOUNT));
+            }
+        }
+    }
+
+    #[test]
+    fn deposit_of_exactly_min_quantity_plus_fee_starts_swap() {
+        let wallet = ScriptedWallet::new(&[0, 10_000], Amount::from_sat(1_000));
+
+        let (amount, fees) = determine(quote(10_000, 100_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(10_000));
+        assert_eq!(fees, Amount::from_sat(1_000));
+    }
+
+    #[test]
+    fn deposit_below_dust_keeps_waiting() {
+        let wallet =
+            ScriptedWallet::new(&[0, DUST_AMOUNT - 1, DUST_AMOUNT], Amount::from_sat(1_000));
+
+        let (amount, _) = determine(quote(0, 100_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(DUST_AMOUNT));
+    }
+
+    #[test]
+    fn max_giveable_shrinking_between_checks_keeps_waiting() {
+        let wallet = ScriptedWallet::new(&[5_000, 4_000, 9_000, 12_000], Amount::from_sat(1_000));
+
+        let (amount, _) = determine(quote(10_000, 100_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(12_000));
+    }
+
+    #[test]
+    fn deposit_below_min_quantity_keeps_waiting() {
+        let wallet = ScriptedWallet::new(&[0, 5_000, 9_999, 10_000], Amount::from_sat(1_000));
+
+        let (amount, _) = determine(quote(10_000, 100_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn requested_amount_is_swapped_once_deposited() {
+        let wallet = ScriptedWallet::new(&[0, 30_000], Amount::from_sat(1_000));
+
+        let (amount, _) =
+            determine_requested(quote(10_000, 100_000), Amount::from_sat(25_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(25_000));
+    }
+
+    #[test]
+    fn deposit_below_requested_amount_is_rejected() {
+        let wallet = ScriptedWallet::new(&[0, 15_000], Amount::from_sat(1_000));
+
+        let error = determine_requested(quote(10_000, 100_000), Amount::from_sat(25_000), &wallet)
+            .unwrap_err();
+
+        assert!(error.to_string().starts_with("Requested to swap"));
+    }
+
+    #[test]
+    fn requested_amount_above_the_quote_is_capped() {
+        let wallet = ScriptedWallet::new(&[0, 60_000], Amount::from_sat(1_000));
+
+        let (amount, _) =
+            determine_requested(quote(10_000, 50_000), Amount::from_sat(80_000), &wallet).unwrap();
+
+        assert_eq!(amount, Amount::from_sat(50_000));
+    }
+
+    #[test]
+    fn quote_max_below_min_quantity_is_rejected() {
+        let wallet = ScriptedWallet::new(&[100_000], Amount::from_sat(1_000));
+
+        let error = determine(quote(50_000, 40_000), &wallet).unwrap_err();
+
+        let error = error.downcast_ref::<QuoteBelowMinimumSwapAmount>().unwrap();
+        assert_eq!(error.min_swap_amount, Amount::from_sat(50_000));
+    }
+
+    #[test]
+    fn quote_max_below_dust_is_rejected() {
+        let wallet = ScriptedWallet::new(&[100_000], Amount::from_sat(1_000));
+
+        let error = determine(quote(0, DUST_AMOUNT - 1), &wallet).unwrap_err();
+
+        assert!(error
+            .downcast_ref::<QuoteBelowMinimumSwapAmount>()
+            .is_some());
+    }
+
+    fn determine(quote: BidQuote, wallet: &ScriptedWallet) -> Result<(Amount, Amount)> {
+        determine_amount(quote, None, wallet)
+    }
+
+    fn determine_requested(
+        quote: BidQuote,
+        requested: Amount,
+        wallet: &ScriptedWallet,
+    ) -> Result<(Amount, Amount)> {
+        determine_amount(quote, Some(requested), wallet)
+    }
+
+    /// Runs `determine_btc_to_swap` on a paused clock so the one second
+    /// between balance checks passes instantly.
+    fn determine_amount(
+        quote: BidQuote,
+        requested: Option<Amount>,
+        wallet: &ScriptedWallet,
+    ) -> Result<(Amount, Amount)> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            // each scripted balance update is checked a second after the previous one
+            tokio::time::timeout(
+                Duration::from_secs(24 * 60 * 60),
+                determine_btc_to_swap(
+                    true,
+                    quote,
+                    requested,
+                    dummy_address(),
+                    || async move { Ok(wallet.balance()) },
+                    || async move { wallet.max_giveable() },
+                    || async { Ok(()) },
+                    |_| async move { Ok(wallet.lock_fee) },
+                ),
+            )
+            .await
+            .expect("determining the amount to swap must terminate")
+        })
+    }
+
+    fn quote(min_quantity: u64, max_quantity: u64) -> BidQuote {
+        BidQuote {
+            price: Amount::from_sat(100_000),
+            min_quantity: Amount::from_sat(min_quantity),
+            max_quantity: Amount::from_sat(max_quantity),
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    async fn dummy_address() -> Result<bitcoin::Address> {
+        Ok("1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6".parse()?)
+    }
+
+    /// A wallet whose max giveable amount follows a script of balance
+    /// updates. Its balance is always the max giveable amount plus the fee
+    /// for the lock transaction.
+    struct ScriptedWallet {
+        max_giveables: Mutex<VecDeque<Amount>>,
+        last_max_giveable: Mutex<Amount>,
+        lock_fee: Amount,
+    }
+
+    impl ScriptedWallet {
+        fn new(max_giveables: &[u64], lock_fee: Amount) -> Self {
+            Self {
+                max_giveables: Mutex::new(
+                    max_giveables
+                        .iter()
+                        .copied()
+                        .map(Amount::from_sat)
+                        .collect(),
+                ),
+                last_max_giveable: Mutex::new(Amount::ZERO),
+                lock_fee,
+            }
+        }
+
+        fn max_giveable(&self) -> Result<Amount> {
+            let max_giveable = self
+                .max_giveables
+                .lock()
+                .unwrap()
+                .pop_front()
+                .context("No more scripted balance updates")?;
+            *self.last_max_giveable.lock().unwrap() = max_giveable;
+
+            Ok(max_giveable)
+        }
+
+        fn balance(&self) -> Amount {
+            self.last_max_giveable() + self.lock_fee
+        }
+
+        fn last_max_giveable(&self) -> Amount {
+            *self.last_max_giveable.lock().unwrap()
+        }
+    }
+}
@@ -10,7 +10,7 @@ pub use event_loop::{EventLoop, EventLoopHandle, FixedRate, KrakenRate, LatestRa
 pub use network::behaviour::{Behaviour, OutEvent};
 pub use network::rendezvous::RendezvousNode;
 pub use network::transport;
-pub use rate::Rate;
+pub use rate::{Rate, RateConfig};
 pub use recovery::cancel::cancel;
 pub use recovery::punish::punish;
 pub use recovery::redeem::{redeem, Finality};
@@ -25,13 +25,19 @@ where
     let command: RawCommand = args.cmd;
 
     let arguments = match command {
-        RawCommand::Start { resume_only } => Arguments {
+        RawCommand::Start {
+            resume_only,
+            legacy_testnet_peer_id,
+        } => Arguments {
             testnet,
             json,
             disable_timestamp,
             config_path: config_path(config, testnet)?,
             env_config: env_config(testnet),
-            cmd: Command::Start { resume_only },
+            cmd: Command::Start {
+                resume_only,
+                legacy_testnet_peer_id,
+            },
         },
         RawCommand::History => Arguments {
             testnet,
@@ -194,6 +200,7 @@ pub struct Arguments {
 pub enum Command {
     Start {
         resume_only: bool,
+        legacy_testnet_peer_id: bool,
     },
     History,
     Config,
@@ -267,6 +274,11 @@ pub enum RawCommand {
             help = "For maintenance only. When set, no new swap requests will be accepted, but existing unfinished swaps will be resumed."
         )]
         resume_only: bool,
+        #[structopt(
+            long = "legacy-testnet-peer-id",
+            help = "Keep using the peer id from before testnet and mainnet peer ids were separated. Only has an effect on testnet."
+        )]
+        legacy_testnet_peer_id: bool,
     },
     #[structopt(about = "Prints swap-id and the state of each swap ever made.")]
     History,
@@ -369,7 +381,10 @@ mod tests {
             disable_timestamp: false,
             config_path: default_mainnet_conf_path,
             env_config: mainnet_env_config,
-            cmd: Command::Start { resume_only: false },
+            cmd: Command::Start {
+                resume_only: false,
+                legacy_testnet_peer_id: false,
+            },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
@@ -552,7 +567,36 @@ mod tests {
             disable_timestamp: false,
             config_path: default_testnet_conf_path,
             env_config: testnet_env_config,
-            cmd: Command::Start { resume_only: false },
+            cmd: Command::Start {
+                resume_only: false,
+                legacy_testnet_peer_id: false,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+    }
+
+    #[test]
+    fn ensure_start_command_mapping_with_legacy_testnet_peer_id() {
+        let default_testnet_conf_path = env::Testnet::getConfigFileDefaults().unwrap().config_path;
+        let testnet_env_config = env::Testnet::get_config();
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "--testnet",
+            "start",
+            "--legacy-testnet-peer-id",
+        ];
+        let expected_args = Arguments {
+            testnet: true,
+            json: false,
+            disable_timestamp: false,
+            config_path: default_testnet_conf_path,
+            env_config: testnet_env_config,
+            cmd: Command::Start {
+                resume_only: false,
+                legacy_testnet_peer_id: true,
+            },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
@@ -740,7 +784,10 @@ mod tests {
             disable_timestamp: true,
             config_path: default_mainnet_conf_path,
             env_config: mainnet_env_config,
-            cmd: Command::Start { resume_only: false },
+            cmd: Command::Start {
+                resume_only: false,
+                legacy_testnet_peer_id: false,
+            },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
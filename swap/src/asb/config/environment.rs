@@ -0,0 +1,214 @@
+//! Overrides config keys with `ASB__` prefixed environment variables.
+//!
+//! The variable name is the path of the key with `__` as separator, e.g.
+//! `ASB__BITCOIN__ELECTRUM_RPC_URL` sets `electrum_rpc_url` in the `[bitcoin]`
+//! section. Lists are comma separated.
+
+use super::Config;
+use anyhow::{bail, Context, Result};
+
+const PREFIX: &str = "ASB__";
+const SEPARATOR: &str = "__";
+const LIST_SEPARATOR: char = ',';
+
+/// Applies all `ASB__` prefixed variables in `vars` on top of `table`.
+///
+/// `table` has to deserialize into a valid [`Config`] already, that way an
+/// invalid result can be blamed on the variable that was just applied.
+pub fn apply_overrides<I>(table: &mut toml::Table, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, value) in vars {
+        let path = match name.strip_prefix(PREFIX) {
+            Some(path) => path
+                .split(SEPARATOR)
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>(),
+            None => continue,
+        };
+
+        let mut overridden = table.clone();
+        set(&mut overridden, &path, &value)
+            .with_context(|| format!("Invalid environment variable {}", name))?;
+        Config::from_table(&overridden).with_context(|| {
+            format!(
+                "Invalid value `{}` for environment variable {}",
+                value, name
+            )
+        })?;
+
+        tracing::debug!(variable = %name, "Config key overridden by environment variable");
+        *table = overridden;
+    }
+
+    Ok(())
+}
+
+fn set(table: &mut toml::Table, path: &[String], value: &str) -> Result<()> {
+    match path {
+        [] => bail!("Missing key"),
+        [key] => {
+            let value = match table.get(key) {
+                Some(existing) => typed_like(existing, value)?,
+                None => untyped(value),
+            };
+            table.insert(key.clone(), value);
+
+            Ok(())
+        }
+        [section, rest @ ..] => match table
+            .entry(section.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            toml::Value::Table(section) => set(section, rest, value),
+            _ => bail!("`{}` is a key, not a section", section),
+        },
+    }
+}
+
+/// Parses `value` into the type of the value it replaces.
+fn typed_like(existing: &toml::Value, value: &str) -> Result<toml::Value> {
+    let typed = match existing {
+        toml::Value::String(_) => toml::Value::String(value.to_owned()),
+        toml::Value::Integer(_) => toml::Value::Integer(
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("Expected an integer, got `{}`", value))?,
+        ),
+        toml::Value::Float(_) => toml::Value::Float(
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("Expected a number, got `{}`", value))?,
+        ),
+        toml::Value::Boolean(_) => toml::Value::Boolean(
+            value
+                .trim()
+                .parse()
+                .with_context(|| format!("Expected `true` or `false`, got `{}`", value))?,
+        ),
+        toml::Value::Array(_) => toml::Value::Array(
+            value
+                .split(LIST_SEPARATOR)
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_owned()))
+                .collect(),
+        ),
+        toml::Value::Table(_) => bail!("Expected a key, got a section"),
+        toml::Value::Datetime(_) => bail!("Dates cannot be set from the environment"),
+    };
+
+    Ok(typed)
+}
+
+/// Optional keys that are not set have no type to go by.
+fn untyped(value: &str) -> toml::Value {
+    match value.trim().parse() {
+        Ok(integer) => toml::Value::Integer(integer),
+        Err(_) => toml::Value::String(value.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testnet_table() -> toml::Table {
+        toml::Table::try_from(Config::testnet().unwrap()).unwrap()
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn overrides_keys_of_every_type() {
+        let mut table = testnet_table();
+
+        apply_overrides(
+            &mut table,
+            vars(&[
+                ("ASB__BITCOIN__ELECTRUM_RPC_URL", "tcp://127.0.0.1:50001"),
+                ("ASB__BITCOIN__TARGET_BLOCK", "3"),
+                ("ASB__BITCOIN__FINALITY_CONFIRMATIONS", "2"),
+                ("ASB__MAKER__MAX_BUY_BTC", "0.5"),
+                (
+                    "ASB__NETWORK__LISTEN",
+                    "/ip4/127.0.0.1/tcp/9939, /ip4/127.0.0.1/tcp/9940/ws",
+                ),
+                ("UNRELATED", "ignored"),
+            ]),
+        )
+        .unwrap();
+        let config = Config::from_table(&table).unwrap();
+
+        assert_eq!(
+            config.bitcoin.electrum_rpc_url.as_str(),
+            "tcp://127.0.0.1:50001"
+        );
+        assert_eq!(config.bitcoin.target_block, 3);
+        assert_eq!(config.bitcoin.finality_confirmations, Some(2));
+        assert_eq!(
+            config.maker.max_buy_btc,
+            bitcoin::Amount::from_btc(0.5).unwrap()
+        );
+        assert_eq!(
+            config.network.listen,
+            vec![
+                "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+                "/ip4/127.0.0.1/tcp/9940/ws".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn later_layers_take_precedence() {
+        let mut table = testnet_table();
+        table["bitcoin"]
+            .as_table_mut()
+            .unwrap()
+            .insert("target_block".to_owned(), toml::Value::Integer(2));
+        table["maker"]
+            .as_table_mut()
+            .unwrap()
+            .insert("ask_spread".to_owned(), toml::Value::Float(0.5));
+
+        apply_overrides(&mut table, vars(&[("ASB__BITCOIN__TARGET_BLOCK", "4")])).unwrap();
+        let config = Config::from_table(&table).unwrap();
+        let defaults = Config::testnet().unwrap();
+
+        assert_eq!(config.bitcoin.target_block, 4, "environment overrides file");
+        assert_eq!(
+            config.maker.ask_spread.to_string(),
+            "0.5",
+            "file overrides defaults"
+        );
+        assert_eq!(config.maker.min_buy_btc, defaults.maker.min_buy_btc);
+    }
+
+    #[test]
+    fn malformed_value_names_the_variable() {
+        let cases = [
+            ("ASB__BITCOIN__TARGET_BLOCK", "soon"),
+            ("ASB__BITCOIN__ELECTRUM_RPC_URL", "not a url"),
+            ("ASB__BITCOIN__TARGET_BLOK", "1"),
+            ("ASB__BITCOIN", "ssl://example.com"),
+        ];
+
+        for (name, value) in cases {
+            let error = apply_overrides(&mut testnet_table(), vars(&[(name, value)])).unwrap_err();
+
+            assert!(
+                error.to_string().contains(name),
+                "expected `{}` to name {}",
+                error,
+                name
+            );
+        }
+    }
+}
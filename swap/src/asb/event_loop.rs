@@ -1,6 +1,6 @@
-use crate::asb::{Behaviour, OutEvent, Rate};
+use crate::asb::{Behaviour, OutEvent, Rate, RateConfig};
 use crate::monero::Amount;
-use crate::network::quote::BidQuote;
+use crate::network::quote::{BidQuote, PROTOCOL_VERSION};
 use crate::network::swap_setup::alice::WalletSnapshot;
 use crate::network::transfer_proof;
 use crate::protocol::alice::{AliceState, State3, Swap};
@@ -18,9 +18,14 @@ use std::collections::HashMap;
 use std::convert::{Infallible, TryInto};
 use std::fmt::Debug;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use uuid::Uuid;
 
+/// How long to wait before sending a transfer proof again whose request
+/// failed.
+const TRANSFER_PROOF_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 /// A future that resolves to a tuple of `PeerId`, `transfer_proof::Request` and
 /// `Responder`.
 ///
@@ -42,15 +47,19 @@ where
     monero_wallet: Arc<monero::Wallet>,
     db: Arc<dyn Database + Send + Sync>,
     latest_rate: LR,
-    min_buy: bitcoin::Amount,
-    max_buy: bitcoin::Amount,
+    rate_config: watch::Receiver<RateConfig>,
     external_redeem_address: Option<bitcoin::Address>,
 
     swap_sender: mpsc::Sender<Swap>,
 
     /// Stores incoming [`EncryptedSignature`]s per swap.
     recv_encrypted_signature: HashMap<Uuid, bmrng::RequestSender<bitcoin::EncryptedSignature, ()>>,
-    inflight_encrypted_signatures: FuturesUnordered<BoxFuture<'static, ResponseChannel<()>>>,
+    /// Resolve to the id of the swap once it took its encrypted signature.
+    inflight_encrypted_signatures: FuturesUnordered<BoxFuture<'static, Uuid>>,
+    /// The requests of every encrypted signature a swap did not take yet,
+    /// acknowledged once it did. Bob sends the encrypted signature again if a
+    /// request failed, so there can be several per swap.
+    unacknowledged_encrypted_signatures: HashMap<Uuid, Vec<ResponseChannel<()>>>,
 
     send_transfer_proof: FuturesUnordered<OutgoingTransferProof>,
 
@@ -59,8 +68,9 @@ where
     buffered_transfer_proofs: HashMap<PeerId, Vec<(transfer_proof::Request, bmrng::Responder<()>)>>,
 
     /// Tracks [`transfer_proof::Request`]s which are currently inflight and
-    /// awaiting an acknowledgement.
-    inflight_transfer_proofs: HashMap<RequestId, bmrng::Responder<()>>,
+    /// awaiting an acknowledgement. The request is kept to send it again if it
+    /// fails.
+    inflight_transfer_proofs: HashMap<RequestId, (transfer_proof::Request, bmrng::Responder<()>)>,
 }
 
 impl<LR> EventLoop<LR>
@@ -75,8 +85,7 @@ where
         monero_wallet: Arc<monero::Wallet>,
         db: Arc<dyn Database + Send + Sync>,
         latest_rate: LR,
-        min_buy: bitcoin::Amount,
-        max_buy: bitcoin::Amount,
+        rate_config: watch::Receiver<RateConfig>,
         external_redeem_address: Option<bitcoin::Address>,
     ) -> Result<(Self, mpsc::Receiver<Swap>)> {
         let swap_channel = MpscChannels::default();
@@ -89,11 +98,11 @@ where
             db,
             latest_rate,
             swap_sender: swap_channel.sender,
-            min_buy,
-            max_buy,
+            rate_config,
             external_redeem_address,
             recv_encrypted_signature: Default::default(),
             inflight_encrypted_signatures: Default::default(),
+            unacknowledged_encrypted_signatures: Default::default(),
             send_transfer_proof: Default::default(),
             buffered_transfer_proofs: Default::default(),
             inflight_transfer_proofs: Default::default(),
@@ -168,7 +177,15 @@ where
                                 }
                             };
 
-                            let wallet_snapshot = match WalletSnapshot::capture(&self.bitcoin_wallet, &self.monero_wallet, &self.external_redeem_address, btc).await {
+                            let reserved_xmr = match self.reserved_xmr().await {
+                                Ok(reserved_xmr) => reserved_xmr,
+                                Err(error) => {
+                                    tracing::error!("Swap request will be ignored because we were unable to determine the XMR reserved for running swaps: {:#}", error);
+                                    continue;
+                                }
+                            };
+
+                            let wallet_snapshot = match WalletSnapshot::capture(&self.bitcoin_wallet, &self.monero_wallet, &self.external_redeem_address, btc, reserved_xmr).await {
                                 Ok(wallet_snapshot) => wallet_snapshot,
                                 Err(error) => {
                                     tracing::error!("Swap request will be ignored because we were unable to create wallet snapshot for swap: {:#}", error);
@@ -186,7 +203,7 @@ where
                             tracing::warn!(%peer, "Ignoring spot price request: {}", error);
                         }
                         SwarmEvent::Behaviour(OutEvent::QuoteRequested { channel, peer }) => {
-                            let quote = match self.make_quote(self.min_buy, self.max_buy).await {
+                            let quote = match self.make_quote().await {
                                 Ok(quote) => quote,
                                 Err(error) => {
                                     tracing::warn!(%peer, "Failed to make quote: {:#}", error);
@@ -200,10 +217,21 @@ where
                         }
                         SwarmEvent::Behaviour(OutEvent::TransferProofAcknowledged { peer, id }) => {
                             tracing::debug!(%peer, "Bob acknowledged transfer proof");
-                            if let Some(responder) = self.inflight_transfer_proofs.remove(&id) {
+                            if let Some((_, responder)) = self.inflight_transfer_proofs.remove(&id) {
                                 let _ = responder.respond(());
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::TransferProofFailed { peer, id, error }) => {
+                            if let Some((transfer_proof, responder)) = self.inflight_transfer_proofs.remove(&id) {
+                                tracing::warn!(%peer, swap_id = %transfer_proof.swap_id, %error, "Failed to send transfer proof, sending it again in {} seconds", TRANSFER_PROOF_RETRY_DELAY.as_secs());
+
+                                self.send_transfer_proof.push(async move {
+                                    tokio::time::sleep(TRANSFER_PROOF_RETRY_DELAY).await;
+
+                                    Ok((peer, transfer_proof, responder))
+                                }.boxed());
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::EncryptedSignatureReceived{ msg, channel, peer }) => {
                             let swap_id = msg.swap_id;
                             let swap_peer = self.db.get_peer_id(swap_id).await;
@@ -230,6 +258,23 @@ where
                                 continue;
                             }
 
+                            // Bob sends the encrypted signature again if he did not receive our acknowledgement
+                            if let Some(channels) = self.unacknowledged_encrypted_signatures.get_mut(&swap_id) {
+                                tracing::debug!(%swap_id, "Received encrypted signature again before the swap took it");
+                                channels.push(channel);
+                                continue;
+                            }
+
+                            let learned = matches!(
+                                self.db.get_state(swap_id).await,
+                                Ok(State::Alice(state)) if state.encrypted_signature_learned()
+                            );
+                            if learned {
+                                tracing::debug!(%swap_id, "Acknowledging encrypted signature that was already learned");
+                                let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(channel, ());
+                                continue;
+                            }
+
                             let sender = match self.recv_encrypted_signature.remove(&swap_id) {
                                 Some(sender) => sender,
                                 None => {
@@ -247,10 +292,11 @@ where
                                 }
                             };
 
+                            self.unacknowledged_encrypted_signatures.insert(swap_id, vec![channel]);
                             self.inflight_encrypted_signatures.push(async move {
                                 let _ = responder.recv().await;
 
-                                channel
+                                swap_id
                             }.boxed());
                         }
                         SwarmEvent::Behaviour(OutEvent::Rendezvous(libp2p::rendezvous::client::Event::Registered { rendezvous_node, ttl, namespace })) => {
@@ -271,8 +317,8 @@ where
                                 for (transfer_proof, responder) in transfer_proofs {
                                     tracing::debug!(%peer, "Found buffered transfer proof for peer");
 
-                                    let id = self.swarm.behaviour_mut().transfer_proof.send_request(&peer, transfer_proof);
-                                    self.inflight_transfer_proofs.insert(id, responder);
+                                    let id = self.swarm.behaviour_mut().transfer_proof.send_request(&peer, transfer_proof.clone());
+                                    self.inflight_transfer_proofs.insert(id, (transfer_proof, responder));
                                 }
                             }
                         }
@@ -300,8 +346,8 @@ where
                                 continue;
                             }
 
-                            let id = self.swarm.behaviour_mut().transfer_proof.send_request(&peer, transfer_proof);
-                            self.inflight_transfer_proofs.insert(id, responder);
+                            let id = self.swarm.behaviour_mut().transfer_proof.send_request(&peer, transfer_proof.clone());
+                            self.inflight_transfer_proofs.insert(id, (transfer_proof, responder));
                         },
                         Some(Err(error)) => {
                             tracing::debug!("A swap stopped without sending a transfer proof: {:#}", error);
@@ -311,18 +357,16 @@ where
                         }
                     }
                 }
-                Some(response_channel) = self.inflight_encrypted_signatures.next() => {
-                    let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(response_channel, ());
+                Some(swap_id) = self.inflight_encrypted_signatures.next() => {
+                    for channel in self.unacknowledged_encrypted_signatures.remove(&swap_id).unwrap_or_default() {
+                        let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(channel, ());
+                    }
                 }
             }
         }
     }
 
-    async fn make_quote(
-        &mut self,
-        min_buy: bitcoin::Amount,
-        max_buy: bitcoin::Amount,
-    ) -> Result<BidQuote> {
+    async fn make_quote(&mut self) -> Result<BidQuote> {
         let ask_price = self
             .latest_rate
             .latest_rate()
@@ -335,42 +379,30 @@ where
         // use unlocked monero balance for quote
         let xmr = Amount::from_piconero(balance.unlocked_balance);
 
-        let max_bitcoin_for_monero = xmr.max_bitcoin_for_price(ask_price).ok_or_else(|| {
-            anyhow::anyhow!("Bitcoin price ({}) x Monero ({}) overflow", ask_price, xmr)
-        })?;
+        // read the config on every request so changes apply to the next quote
+        let rate_config = *self.rate_config.borrow();
 
-        tracing::debug!(%ask_price, %xmr, %max_bitcoin_for_monero);
+        bid_quote(ask_price, xmr, rate_config.min_buy, rate_config.max_buy)
+    }
 
-        if min_buy > max_bitcoin_for_monero {
-            tracing::warn!(
-                        "Your Monero balance is too low to initiate a swap, as your minimum swap amount is {}. You could at most swap {}",
-                        min_buy, max_bitcoin_for_monero
-                    );
+    /// The XMR, including the lock fees, that running swaps still have to
+    /// lock. It is part of the unlocked balance, but cannot be offered to
+    /// another swap.
+    async fn reserved_xmr(&self) -> Result<monero::Amount> {
+        let mut reserved = monero::Amount::ZERO;
 
-            return Ok(BidQuote {
-                price: ask_price,
-                min_quantity: bitcoin::Amount::ZERO,
-                max_quantity: bitcoin::Amount::ZERO,
-            });
-        }
+        for (_, state) in self.db.all().await? {
+            let state: AliceState = state.try_into()?;
 
-        if max_buy > max_bitcoin_for_monero {
-            tracing::warn!(
-                    "Your Monero balance is too low to initiate a swap with the maximum swap amount {} that you have specified in your config. You can at most swap {}",
-                    max_buy, max_bitcoin_for_monero
-                );
-            return Ok(BidQuote {
-                price: ask_price,
-                min_quantity: min_buy,
-                max_quantity: max_bitcoin_for_monero,
-            });
+            if let Some(xmr) = state.xmr_to_lock() {
+                reserved = reserved
+                    .checked_add(xmr)
+                    .and_then(|reserved| reserved.checked_add(monero::MONERO_FEE))
+                    .context("Reserved XMR overflows")?;
+            }
         }
 
-        Ok(BidQuote {
-            price: ask_price,
-            min_quantity: min_buy,
-            max_quantity: max_buy,
-        })
+        Ok(reserved)
     }
 
     async fn handle_execution_setup_done(
@@ -398,15 +430,24 @@ where
         // TODO: Consider adding separate components for start/resume of swaps
 
         // swaps save peer id so we can resume
-        match self.db.insert_peer_id(swap_id, bob_peer_id).await {
-            Ok(_) => {
-                if let Err(error) = self.swap_sender.send(swap).await {
-                    tracing::warn!(%swap_id, "Failed to start swap: {}", error);
-                }
-            }
-            Err(error) => {
-                tracing::warn!(%swap_id, "Unable to save peer-id in database: {}", error);
-            }
+        if let Err(error) = self.db.insert_peer_id(swap_id, bob_peer_id).await {
+            tracing::warn!(%swap_id, "Unable to save peer-id in database: {}", error);
+            return;
+        }
+
+        // store the initial state right away, the XMR it has to lock is reserved
+        // from now on
+        if let Err(error) = self
+            .db
+            .insert_latest_state(swap_id, swap.state.clone().into())
+            .await
+        {
+            tracing::warn!(%swap_id, "Unable to save swap in database: {:#}", error);
+            return;
+        }
+
+        if let Err(error) = self.swap_sender.send(swap).await {
+            tracing::warn!(%swap_id, "Failed to start swap: {}", error);
         }
     }
 
@@ -443,6 +484,55 @@ where
     }
 }
 
+/// Quotes `min_buy` and `max_buy` at `ask_price`, limited to what `xmr` can
+/// pay for.
+fn bid_quote(
+    ask_price: bitcoin::Amount,
+    xmr: Amount,
+    min_buy: bitcoin::Amount,
+    max_buy: bitcoin::Amount,
+) -> Result<BidQuote> {
+    let max_bitcoin_for_monero = xmr.max_bitcoin_for_price(ask_price).ok_or_else(|| {
+        anyhow::anyhow!("Bitcoin price ({}) x Monero ({}) overflow", ask_price, xmr)
+    })?;
+
+    tracing::debug!(%ask_price, %xmr, %max_bitcoin_for_monero);
+
+    if min_buy > max_bitcoin_for_monero {
+        tracing::warn!(
+                    "Your Monero balance is too low to initiate a swap, as your minimum swap amount is {}. You could at most swap {}",
+                    min_buy, max_bitcoin_for_monero
+                );
+
+        return Ok(BidQuote {
+            price: ask_price,
+            min_quantity: bitcoin::Amount::ZERO,
+            max_quantity: bitcoin::Amount::ZERO,
+            protocol_version: PROTOCOL_VERSION,
+        });
+    }
+
+    if max_buy > max_bitcoin_for_monero {
+        tracing::warn!(
+                "Your Monero balance is too low to initiate a swap with the maximum swap amount {} that you have specified in your config. You can at most swap {}",
+                max_buy, max_bitcoin_for_monero
+            );
+        return Ok(BidQuote {
+            price: ask_price,
+            min_quantity: min_buy,
+            max_quantity: max_bitcoin_for_monero,
+            protocol_version: PROTOCOL_VERSION,
+        });
+    }
+
+    Ok(BidQuote {
+        price: ask_price,
+        min_quantity: min_buy,
+        max_quantity: max_buy,
+        protocol_version: PROTOCOL_VERSION,
+    })
+}
+
 pub trait LatestRate {
     type Error: std::error::Error + Send + Sync + 'static;
 
@@ -477,19 +567,28 @@ impl LatestRate for FixedRate {
     }
 }
 
-/// Produces [`Rate`]s based on [`PriceUpdate`]s from kraken and a configured
-/// spread.
+/// Produces [`Rate`]s based on [`PriceUpdate`]s from kraken and the spread of
+/// the current [`RateConfig`].
+///
+/// Fails instead of producing a rate once the latest update is older than
+/// `max_age`, so no quotes are made at a price the market has moved away from.
 #[derive(Debug, Clone)]
 pub struct KrakenRate {
-    ask_spread: Decimal,
+    rate_config: watch::Receiver<RateConfig>,
     price_updates: kraken::PriceUpdates,
+    max_age: Duration,
 }
 
 impl KrakenRate {
-    pub fn new(ask_spread: Decimal, price_updates: kraken::PriceUpdates) -> Self {
+    pub fn new(
+        rate_config: watch::Receiver<RateConfig>,
+        price_updates: kraken::PriceUpdates,
+        max_age: Duration,
+    ) -> Self {
         Self {
-            ask_spread,
+            rate_config,
             price_updates,
+            max_age,
         }
     }
 }
@@ -499,7 +598,12 @@ impl LatestRate for KrakenRate {
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
         let update = self.price_updates.latest_update()?;
-        let rate = Rate::new(update.ask, self.ask_spread);
+        let age = update.age();
+        if age > self.max_age {
+            return Err(kraken::Error::Stale(age));
+        }
+
+        let rate = Rate::new(update.ask, self.rate_config.borrow().ask_spread);
 
         Ok(rate)
     }
@@ -551,3 +655,115 @@ impl<T> Default for MpscChannels<T> {
         MpscChannels { sender, receiver }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    fn rate_config(ask_spread: Decimal, min_buy: f64, max_buy: f64) -> RateConfig {
+        RateConfig {
+            ask_spread,
+            min_buy: bitcoin::Amount::from_btc(min_buy).unwrap(),
+            max_buy: bitcoin::Amount::from_btc(max_buy).unwrap(),
+        }
+    }
+
+    fn next_quote(
+        latest_rate: &mut KrakenRate,
+        rate_config: &watch::Receiver<RateConfig>,
+    ) -> BidQuote {
+        let ask_price = latest_rate.latest_rate().unwrap().ask().unwrap();
+        let RateConfig {
+            min_buy, max_buy, ..
+        } = *rate_config.borrow();
+
+        bid_quote(
+            ask_price,
+            Amount::from_monero(100.0).unwrap(),
+            min_buy,
+            max_buy,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn next_quote_reflects_updated_rate_config() {
+        let (sender, receiver) = watch::channel(rate_config(Decimal::ZERO, 0.001, 0.01));
+        let mut latest_rate = KrakenRate::new(
+            receiver.clone(),
+            kraken::PriceUpdates::fixed(bitcoin::Amount::from_sat(100_000)),
+            MAX_AGE,
+        );
+
+        let quote = next_quote(&mut latest_rate, &receiver);
+        assert_eq!(quote.price, bitcoin::Amount::from_sat(100_000));
+        assert_eq!(
+            quote.min_quantity,
+            bitcoin::Amount::from_btc(0.001).unwrap()
+        );
+        assert_eq!(quote.max_quantity, bitcoin::Amount::from_btc(0.01).unwrap());
+
+        sender.send_replace(rate_config(Decimal::new(2, 2), 0.002, 0.05));
+
+        let quote = next_quote(&mut latest_rate, &receiver);
+        assert_eq!(quote.price, bitcoin::Amount::from_sat(102_000));
+        assert_eq!(
+            quote.min_quantity,
+            bitcoin::Amount::from_btc(0.002).unwrap()
+        );
+        assert_eq!(quote.max_quantity, bitcoin::Amount::from_btc(0.05).unwrap());
+    }
+
+    #[test]
+    fn swap_set_up_before_an_update_keeps_its_price() {
+        let (sender, receiver) = watch::channel(rate_config(Decimal::ZERO, 0.001, 0.01));
+        let mut latest_rate = KrakenRate::new(
+            receiver,
+            kraken::PriceUpdates::fixed(bitcoin::Amount::from_sat(100_000)),
+            MAX_AGE,
+        );
+        let btc = bitcoin::Amount::from_btc(0.01).unwrap();
+
+        // the swap setup takes the rate once, the swap stores the resulting amounts
+        let swap_rate = latest_rate.latest_rate().unwrap();
+        let swap_xmr = swap_rate.sell_quote(btc).unwrap();
+
+        sender.send_replace(rate_config(Decimal::new(10, 2), 0.001, 0.01));
+
+        assert_eq!(swap_rate.sell_quote(btc).unwrap(), swap_xmr);
+        assert!(latest_rate.latest_rate().unwrap().sell_quote(btc).unwrap() < swap_xmr);
+    }
+
+    #[test]
+    fn spread_is_applied_to_a_recent_price_update() {
+        let (_, receiver) = watch::channel(rate_config(Decimal::new(5, 2), 0.001, 0.01));
+        let received_at = Instant::now() - Duration::from_secs(30);
+        let mut latest_rate = KrakenRate::new(
+            receiver,
+            kraken::PriceUpdates::fixed_at(bitcoin::Amount::from_sat(200_000), received_at),
+            MAX_AGE,
+        );
+
+        let rate = latest_rate.latest_rate().unwrap();
+
+        assert_eq!(rate.ask().unwrap(), bitcoin::Amount::from_sat(210_000));
+    }
+
+    #[test]
+    fn stale_price_update_is_not_quoted() {
+        let (_, receiver) = watch::channel(rate_config(Decimal::ZERO, 0.001, 0.01));
+        let received_at = Instant::now() - Duration::from_secs(61);
+        let mut latest_rate = KrakenRate::new(
+            receiver,
+            kraken::PriceUpdates::fixed_at(bitcoin::Amount::from_sat(200_000), received_at),
+            MAX_AGE,
+        );
+
+        let error = latest_rate.latest_rate().unwrap_err();
+
+        assert!(matches!(error, kraken::Error::Stale(age) if age > MAX_AGE));
+    }
+}
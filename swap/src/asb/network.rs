@@ -1,11 +1,12 @@
 use crate::asb::event_loop::LatestRate;
+use crate::asb::RateConfig;
 use crate::env;
 use crate::network::quote::BidQuote;
 use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::swap_setup::alice;
 use crate::network::swap_setup::alice::WalletSnapshot;
 use crate::network::transport::authenticate_and_multiplex;
-use crate::network::{encrypted_signature, quote, transfer_proof};
+use crate::network::{encrypted_signature, heartbeat, quote, transfer_proof};
 use crate::protocol::alice::State3;
 use anyhow::{anyhow, Error, Result};
 use futures::FutureExt;
@@ -14,8 +15,8 @@ use libp2p::core::muxing::StreamMuxerBox;
 use libp2p::core::transport::Boxed;
 use libp2p::dns::TokioDnsConfig;
 use libp2p::identify::{Identify, IdentifyConfig, IdentifyEvent};
-use libp2p::ping::{Ping, PingConfig, PingEvent};
-use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::ping::{Ping, PingEvent};
+use libp2p::request_response::{OutboundFailure, RequestId, ResponseChannel};
 use libp2p::swarm::dial_opts::PeerCondition;
 use libp2p::swarm::{
     IntoProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
@@ -26,6 +27,7 @@ use libp2p::websocket::WsConfig;
 use libp2p::{identity, Multiaddr, NetworkBehaviour, PeerId, Transport};
 use std::task::Poll;
 use std::time::Duration;
+use tokio::sync::watch;
 use uuid::Uuid;
 
 pub mod transport {
@@ -71,6 +73,11 @@ pub mod behaviour {
             peer: PeerId,
             id: RequestId,
         },
+        TransferProofFailed {
+            peer: PeerId,
+            id: RequestId,
+            error: OutboundFailure,
+        },
         EncryptedSignatureReceived {
             msg: encrypted_signature::Request,
             channel: ResponseChannel<()>,
@@ -117,9 +124,9 @@ pub mod behaviour {
         pub encrypted_signature: encrypted_signature::Behaviour,
         pub identify: Identify,
 
-        /// Ping behaviour that ensures that the underlying network connection
-        /// is still alive. If the ping fails a connection close event
-        /// will be emitted that is picked up as swarm event.
+        /// Heartbeats that ensure that the underlying network connection is
+        /// still alive. If too many heartbeats are missed a connection close
+        /// event will be emitted that is picked up as swarm event.
         ping: Ping,
     }
 
@@ -128,8 +135,7 @@ pub mod behaviour {
         LR: LatestRate + Send + 'static,
     {
         pub fn new(
-            min_buy: bitcoin::Amount,
-            max_buy: bitcoin::Amount,
+            rate_config: watch::Receiver<RateConfig>,
             latest_rate: LR,
             resume_only: bool,
             env_config: env::Config,
@@ -152,15 +158,14 @@ pub mod behaviour {
                 rendezvous: Toggle::from(behaviour),
                 quote: quote::asb(),
                 swap_setup: alice::Behaviour::new(
-                    min_buy,
-                    max_buy,
+                    rate_config,
                     env_config,
                     latest_rate,
                     resume_only,
                 ),
                 transfer_proof: transfer_proof::alice(),
                 encrypted_signature: encrypted_signature::alice(),
-                ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+                ping: heartbeat::behaviour(&env_config),
                 identify: Identify::new(identifyConfig),
             }
         }
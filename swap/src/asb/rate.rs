@@ -1,3 +1,5 @@
+use crate::amounts::{self, Rounding};
+use crate::asb::config::Maker;
 use crate::{bitcoin, monero};
 use anyhow::{Context, Result};
 use rust_decimal::prelude::ToPrimitive;
@@ -43,33 +45,36 @@ impl Rate {
     }
 
     /// Calculate a sell quote for a given BTC amount.
+    ///
+    /// Rounds down to the piconero, like every taker converting at our price.
     pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<monero::Amount> {
-        Self::quote(self.ask()?, quote)
-    }
-
-    fn quote(rate: bitcoin::Amount, quote: bitcoin::Amount) -> Result<monero::Amount> {
-        // quote (btc) = rate * base (xmr)
-        // base = quote / rate
-
-        let quote_in_sats = quote.to_sat();
-        let quote_in_btc = Decimal::from(quote_in_sats)
-            .checked_div(Decimal::from(bitcoin::Amount::ONE_BTC.to_sat()))
-            .context("Division overflow")?;
-
-        let rate_in_btc = Decimal::from(rate.to_sat())
-            .checked_div(Decimal::from(bitcoin::Amount::ONE_BTC.to_sat()))
-            .context("Division overflow")?;
+        let xmr = amounts::Rate::from_price(self.ask()?)
+            .btc_to_xmr(quote, Rounding::Down)
+            .context("Failed to convert quote to XMR")?;
 
-        let base_in_xmr = quote_in_btc
-            .checked_div(rate_in_btc)
-            .context("Division overflow")?;
-        let base_in_piconero = base_in_xmr * Decimal::from(monero::Amount::ONE_XMR.as_piconero());
+        Ok(xmr)
+    }
+}
 
-        let base_in_piconero = base_in_piconero
-            .to_u64()
-            .context("Failed to fit piconero amount into a u64")?;
+/// The part of the maker config that determines the published quote.
+///
+/// It is shared through a [`tokio::sync::watch`] channel so it can be changed
+/// while the ASB is running. Swaps that were already set up keep the amounts
+/// they were set up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateConfig {
+    pub ask_spread: Decimal,
+    pub min_buy: bitcoin::Amount,
+    pub max_buy: bitcoin::Amount,
+}
 
-        Ok(monero::Amount::from_piconero(base_in_piconero))
+impl From<&Maker> for RateConfig {
+    fn from(maker: &Maker) -> Self {
+        Self {
+            ask_spread: maker.ask_spread,
+            min_buy: maker.min_buy_btc,
+            max_buy: maker.max_buy_btc,
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::bitcoin::{parse_rpc_error_code, RpcErrorCode, Txid, Wallet};
+use crate::bitcoin::{Txid, Wallet};
 use crate::protocol::alice::AliceState;
 use crate::protocol::Database;
 use anyhow::{bail, Result};
@@ -42,17 +42,8 @@ pub async fn cancel(
         | AliceState::SafelyAborted => bail!("Swap is in state {} which is not cancelable", state),
     };
 
-    let txid = match state3.submit_tx_cancel(bitcoin_wallet.as_ref()).await {
-        Ok(txid) => txid,
-        Err(err) => {
-            if let Ok(code) = parse_rpc_error_code(&err) {
-                if code == i64::from(RpcErrorCode::RpcVerifyAlreadyInChain) {
-                    tracing::info!("Cancel transaction has already been confirmed on chain")
-                }
-            }
-            bail!(err);
-        }
-    };
+    // A cancel transaction that is already in the chain counts as submitted
+    let txid = state3.submit_tx_cancel(bitcoin_wallet.as_ref()).await?;
 
     let state = AliceState::BtcCancelled {
         monero_wallet_restore_blockheight,
@@ -18,17 +18,24 @@ use libp2p::core::multiaddr::Protocol;
 use libp2p::core::Multiaddr;
 use libp2p::swarm::AddressScore;
 use libp2p::Swarm;
+#[cfg(feature = "zmq")]
+use monero_rpc::zmq::BlockSubscription;
 use std::convert::TryInto;
 use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use structopt::clap;
 use structopt::clap::ErrorKind;
 use swap::asb::command::{parse_args, Arguments, Command};
 use swap::asb::config::{
-    initial_setup, query_user_for_initial_config, read_config, Config, ConfigNotInitialized,
+    initial_setup, query_user_for_initial_config, read_config, validate_config, Config,
+    ConfigNotInitialized,
+};
+use swap::asb::{
+    cancel, punish, redeem, refund, safely_abort, EventLoop, Finality, KrakenRate, RateConfig,
 };
-use swap::asb::{cancel, punish, redeem, refund, safely_abort, EventLoop, Finality, KrakenRate};
 use swap::common::check_latest_version;
 use swap::database::open_db;
 use swap::network::rendezvous::XmrBtcNamespace;
@@ -37,9 +44,11 @@ use swap::protocol::alice::{run, AliceState};
 use swap::seed::Seed;
 use swap::tor::AuthenticatedClient;
 use swap::{asb, bitcoin, kraken, monero, tor};
+use tokio::sync::watch;
 use tracing_subscriber::filter::LevelFilter;
 
 const DEFAULT_WALLET_NAME: &str = "asb-wallet";
+const PRICE_TICKER_STATUS_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -74,14 +83,17 @@ async fn main() -> Result<()> {
 
     asb::tracing::init(LevelFilter::DEBUG, json, !disable_timestamp).expect("initialize tracing");
 
-    let config = match read_config(config_path.clone())? {
+    let config = match read_config(config_path.clone(), testnet)? {
         Ok(config) => config,
         Err(ConfigNotInitialized {}) => {
             initial_setup(config_path.clone(), query_user_for_initial_config(testnet)?)?;
-            read_config(config_path)?.expect("after initial setup config can be read")
+            read_config(config_path.clone(), testnet)?
+                .expect("after initial setup config can be read")
         }
     };
 
+    validate_config(&config_path, &config)?;
+
     if config.monero.network != env_config.monero_network {
         bail!(format!(
             "Expected monero network in config file to be {:?} but was {:?}",
@@ -95,13 +107,19 @@ async fn main() -> Result<()> {
         ));
     }
 
+    // apply the confirmation and fee overrides from the config file
+    let env_config = swap::env::new(testnet, &config);
+
     let db = open_db(config.data.dir.join("sqlite")).await?;
 
-    let seed =
-        Seed::from_file_or_generate(&config.data.dir).expect("Could not retrieve/initialize seed");
+    let seed = Seed::from_file_or_generate(&config.data.dir)
+        .context("Could not retrieve/initialize seed")?;
 
     match cmd {
-        Command::Start { resume_only } => {
+        Command::Start {
+            resume_only,
+            legacy_testnet_peer_id,
+        } => {
             // check and warn for duplicate rendezvous points
             let mut rendezvous_addrs = config.network.rendezvous_point.clone();
             let prev_len = rendezvous_addrs.len();
@@ -163,13 +181,41 @@ async fn main() -> Result<()> {
                 }
             };
 
-            let kraken_rate = KrakenRate::new(config.maker.ask_spread, kraken_price_updates);
+            let (rate_config, rate_config_receiver) =
+                watch::channel(RateConfig::from(&config.maker));
+            tokio::spawn(reload_rate_config_on_sighup(
+                config_path.clone(),
+                testnet,
+                rate_config,
+            ));
+
+            let price_ticker_max_age = config.maker.price_ticker_max_age();
+            tokio::spawn(report_price_ticker_status(
+                kraken_price_updates.clone(),
+                price_ticker_max_age,
+            ));
+
+            let kraken_rate = KrakenRate::new(
+                rate_config_receiver.clone(),
+                kraken_price_updates,
+                price_ticker_max_age,
+            );
             let namespace = XmrBtcNamespace::from_is_testnet(testnet);
+            let identity = if testnet && legacy_testnet_peer_id {
+                seed.derive_legacy_libp2p_identity()
+            } else {
+                seed.derive_libp2p_identity(namespace)
+            };
+            if testnet && !legacy_testnet_peer_id {
+                tracing::info!(
+                    legacy_peer_id = %seed.derive_legacy_libp2p_identity().public().to_peer_id(),
+                    "Using the testnet specific peer id, start with --legacy-testnet-peer-id to keep using the legacy peer id"
+                );
+            }
 
             let mut swarm = swarm::asb(
-                &seed,
-                config.maker.min_buy_btc,
-                config.maker.max_buy_btc,
+                identity,
+                rate_config_receiver.clone(),
                 kraken_rate.clone(),
                 resume_only,
                 env_config,
@@ -199,8 +245,7 @@ async fn main() -> Result<()> {
                 Arc::new(monero_wallet),
                 db,
                 kraken_rate.clone(),
-                config.maker.min_buy_btc,
-                config.maker.max_buy_btc,
+                rate_config_receiver,
                 config.maker.external_bitcoin_redeem_address,
             )
             .unwrap();
@@ -341,6 +386,7 @@ async fn init_bitcoin_wallet(
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
         config.bitcoin.target_block,
+        config.bitcoin.socks5_proxy,
     )
     .await
     .context("Failed to initialize Bitcoin wallet")?;
@@ -359,10 +405,133 @@ async fn init_monero_wallet(
         config.monero.wallet_rpc_url.clone(),
         DEFAULT_WALLET_NAME.to_string(),
         env_config,
+        None,
     )
     .await?;
 
-    Ok(wallet)
+    match &config.monero.daemon_zmq_url {
+        Some(daemon_zmq_url) => Ok(subscribe_to_new_blocks(wallet, daemon_zmq_url).await),
+        None => Ok(wallet),
+    }
+}
+
+#[cfg(feature = "zmq")]
+async fn subscribe_to_new_blocks(
+    wallet: monero::Wallet,
+    daemon_zmq_url: &url::Url,
+) -> monero::Wallet {
+    match BlockSubscription::connect(daemon_zmq_url.as_str()).await {
+        Ok(subscription) => {
+            tracing::debug!(%daemon_zmq_url, "Subscribed to new Monero blocks");
+            wallet.with_block_subscription(subscription)
+        }
+        Err(error) => {
+            tracing::warn!(%daemon_zmq_url, "Failed to subscribe to new Monero blocks, polling instead: {:#}", error);
+            wallet
+        }
+    }
+}
+
+#[cfg(not(feature = "zmq"))]
+async fn subscribe_to_new_blocks(
+    wallet: monero::Wallet,
+    daemon_zmq_url: &url::Url,
+) -> monero::Wallet {
+    tracing::warn!(%daemon_zmq_url, "The asb was built without the zmq feature, polling for new Monero blocks instead");
+    wallet
+}
+
+/// Logs the latest price of the ticker and how old it is, and warns while it is
+/// too old to make quotes.
+async fn report_price_ticker_status(mut price_updates: kraken::PriceUpdates, max_age: Duration) {
+    let mut interval = tokio::time::interval(PRICE_TICKER_STATUS_INTERVAL);
+    // the first tick completes immediately, give the ticker time to connect
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        match price_updates.latest_update() {
+            Ok(update) if update.age() <= max_age => {
+                tracing::debug!(
+                    ask = %update.ask,
+                    age_secs = update.age().as_secs(),
+                    "Price ticker is up to date"
+                );
+            }
+            Ok(update) => {
+                tracing::warn!(
+                    ask = %update.ask,
+                    age_secs = update.age().as_secs(),
+                    max_age_secs = max_age.as_secs(),
+                    "Price ticker is stale, not making quotes until it is updated"
+                );
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "Price ticker is not available, not making quotes: {:#}",
+                    error
+                );
+            }
+        }
+    }
+}
+
+/// Applies the spread and the minimum and maximum amounts of the config file
+/// to the published quote every time the ASB receives a SIGHUP.
+///
+/// All other settings still need a restart. Swaps that were already set up
+/// keep the amounts and the price they were set up with.
+#[cfg(unix)]
+async fn reload_rate_config_on_sighup(
+    config_path: PathBuf,
+    testnet: bool,
+    rate_config: watch::Sender<RateConfig>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            tracing::warn!(
+                "Failed to listen for SIGHUP, the rate config cannot be reloaded: {:#}",
+                error
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        match reload_rate_config(&config_path, testnet) {
+            Ok(reloaded) => {
+                rate_config.send_replace(reloaded);
+                tracing::info!(
+                    ask_spread = %reloaded.ask_spread,
+                    min_buy = %reloaded.min_buy,
+                    max_buy = %reloaded.max_buy,
+                    "Reloaded rate config"
+                );
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "Failed to reload rate config, keeping the current one: {:#}",
+                    error
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_rate_config_on_sighup(_: PathBuf, _: bool, _: watch::Sender<RateConfig>) {}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn reload_rate_config(config_path: &Path, testnet: bool) -> Result<RateConfig> {
+    let config = read_config(config_path.to_path_buf(), testnet)?
+        .context("Config file does not exist anymore")?;
+    validate_config(config_path, &config)?;
+
+    Ok(RateConfig::from(&config.maker))
 }
 
 /// Registers a hidden service for each network.
@@ -25,6 +25,15 @@ async fn main() -> Result<()> {
             println!("{}", message);
             std::process::exit(0);
         }
+        #[cfg(feature = "selftest")]
+        ParseResult::Selftest {
+            data_dir,
+            debug,
+            json,
+        } => {
+            let passed = swap::selftest::run_and_print(data_dir, debug, json).await?;
+            std::process::exit(if passed { 0 } else { 1 });
+        }
     };
 
     if let Err(e) = check_latest_version(env!("CARGO_PKG_VERSION")).await {
@@ -42,7 +51,7 @@ mod tests {
     use std::sync::{Arc, Mutex};
     use std::time::Duration;
     use swap::api::request::determine_btc_to_swap;
-    use swap::network::quote::BidQuote;
+    use swap::network::quote::{BidQuote, PROTOCOL_VERSION};
     use swap::tracing_ext::capture_logs;
     use tracing::level_filters::LevelFilter;
 
@@ -57,6 +66,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_max(0.01),
+            None,
             get_dummy_address(),
             || async { Ok(Amount::from_btc(0.001)?) },
             || async {
@@ -79,6 +89,7 @@ mod tests {
  INFO swap::api::request: Deposit at least 0.00001 BTC to cover the min quantity with fee!
  INFO swap::api::request: Waiting for Bitcoin deposit deposit_address=1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6 min_deposit=0.00001 BTC max_giveable=0 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC
  INFO swap::api::request: Received Bitcoin new_balance=0.001 BTC max_giveable=0.0009 BTC
+ INFO swap::api::request: Determined swap amount btc_swap_amount=0.0009 BTC xmr_amount=0.900000000000 XMR
 "
         );
     }
@@ -94,6 +105,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_max(0.01),
+            None,
             get_dummy_address(),
             || async { Ok(Amount::from_btc(0.1001)?) },
             || async {
@@ -116,6 +128,7 @@ mod tests {
  INFO swap::api::request: Deposit at least 0.00001 BTC to cover the min quantity with fee!
  INFO swap::api::request: Waiting for Bitcoin deposit deposit_address=1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6 min_deposit=0.00001 BTC max_giveable=0 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC
  INFO swap::api::request: Received Bitcoin new_balance=0.1001 BTC max_giveable=0.1 BTC
+ INFO swap::api::request: Determined swap amount btc_swap_amount=0.01 BTC xmr_amount=10.000000000000 XMR
 "
         );
     }
@@ -131,6 +144,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_max(0.01),
+            None,
             async { panic!("should not request new address when initial balance  is > 0") },
             || async { Ok(Amount::from_btc(0.005)?) },
             || async {
@@ -149,7 +163,7 @@ mod tests {
         assert_eq!((amount, fees), (expected_amount, expected_fees));
         assert_eq!(
             writer.captured(),
-            " INFO swap::api::request: Received quote price=0.001 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC\n"
+            " INFO swap::api::request: Received quote price=0.001 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC\n INFO swap::api::request: Determined swap amount btc_swap_amount=0.0049 BTC xmr_amount=4.900000000000 XMR\n"
         );
     }
 
@@ -164,6 +178,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_max(0.01),
+            None,
             async { panic!("should not request new address when initial balance is > 0") },
             || async { Ok(Amount::from_btc(0.1001)?) },
             || async {
@@ -182,7 +197,7 @@ mod tests {
         assert_eq!((amount, fees), (expected_amount, expected_fees));
         assert_eq!(
             writer.captured(),
-            " INFO swap::api::request: Received quote price=0.001 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC\n"
+            " INFO swap::api::request: Received quote price=0.001 BTC minimum_amount=0 BTC maximum_amount=0.01 BTC\n INFO swap::api::request: Determined swap amount btc_swap_amount=0.01 BTC xmr_amount=10.000000000000 XMR\n"
         );
     }
 
@@ -197,6 +212,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_min(0.01),
+            None,
             get_dummy_address(),
             || async { Ok(Amount::from_btc(0.0101)?) },
             || async {
@@ -219,6 +235,7 @@ mod tests {
  INFO swap::api::request: Deposit at least 0.01001 BTC to cover the min quantity with fee!
  INFO swap::api::request: Waiting for Bitcoin deposit deposit_address=1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6 min_deposit=0.01001 BTC max_giveable=0 BTC minimum_amount=0.01 BTC maximum_amount=184467440737.09551615 BTC
  INFO swap::api::request: Received Bitcoin new_balance=0.0101 BTC max_giveable=0.01 BTC
+ INFO swap::api::request: Determined swap amount btc_swap_amount=0.01 BTC xmr_amount=10.000000000000 XMR
 "
         );
     }
@@ -234,6 +251,7 @@ mod tests {
         let (amount, fees) = determine_btc_to_swap(
             true,
             quote_with_min(0.01),
+            None,
             get_dummy_address(),
             || async { Ok(Amount::from_btc(0.0101)?) },
             || async {
@@ -256,6 +274,7 @@ mod tests {
  INFO swap::api::request: Deposit at least 0.00991 BTC to cover the min quantity with fee!
  INFO swap::api::request: Waiting for Bitcoin deposit deposit_address=1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6 min_deposit=0.00991 BTC max_giveable=0.0001 BTC minimum_amount=0.01 BTC maximum_amount=184467440737.09551615 BTC
  INFO swap::api::request: Received Bitcoin new_balance=0.0101 BTC max_giveable=0.01 BTC
+ INFO swap::api::request: Determined swap amount btc_swap_amount=0.01 BTC xmr_amount=10.000000000000 XMR
 "
         );
     }
@@ -276,6 +295,7 @@ mod tests {
             determine_btc_to_swap(
                 true,
                 quote_with_min(0.1),
+                None,
                 get_dummy_address(),
                 || async { Ok(Amount::from_btc(0.0101)?) },
                 || async {
@@ -324,6 +344,7 @@ mod tests {
             determine_btc_to_swap(
                 true,
                 quote_with_min(0.1),
+                None,
                 get_dummy_address(),
                 || async { Ok(Amount::from_btc(0.21)?) },
                 || async {
@@ -345,6 +366,7 @@ mod tests {
  INFO swap::api::request: Deposit at least 0.10001 BTC to cover the min quantity with fee!
  INFO swap::api::request: Waiting for Bitcoin deposit deposit_address=1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6 min_deposit=0.10001 BTC max_giveable=0 BTC minimum_amount=0.1 BTC maximum_amount=184467440737.09551615 BTC
  INFO swap::api::request: Received Bitcoin new_balance=0.21 BTC max_giveable=0.2 BTC
+ INFO swap::api::request: Determined swap amount btc_swap_amount=0.2 BTC xmr_amount=200.000000000000 XMR
 "
         );
     }
@@ -359,6 +381,7 @@ mod tests {
         let determination_error = determine_btc_to_swap(
             true,
             quote_with_max(0.00),
+            None,
             get_dummy_address(),
             || async { Ok(Amount::from_btc(0.0101)?) },
             || async {
@@ -376,6 +399,110 @@ mod tests {
         assert_eq!("Received quote of 0", determination_error);
     }
 
+    #[tokio::test]
+    async fn given_requested_amount_below_max_giveable_swaps_requested_amount() {
+        let givable = Arc::new(Mutex::new(MaxGiveable::new(vec![
+            Amount::from_btc(0.1).unwrap()
+        ])));
+
+        let (amount, fees) = determine_btc_to_swap(
+            true,
+            quote_with_max(0.05),
+            Some(Amount::from_btc(0.02).unwrap()),
+            async { panic!("should not request new address when initial balance is > 0") },
+            || async { Ok(Amount::from_btc(0.1001)?) },
+            || async {
+                let mut result = givable.lock().unwrap();
+                result.give()
+            },
+            || async { Ok(()) },
+            |_| async { Ok(Amount::from_sat(1000)) },
+        )
+        .await
+        .unwrap();
+
+        let expected_amount = Amount::from_btc(0.02).unwrap();
+        let expected_fees = Amount::from_btc(0.0001).unwrap();
+
+        assert_eq!((amount, fees), (expected_amount, expected_fees));
+    }
+
+    #[tokio::test]
+    async fn given_requested_amount_above_max_quantity_swaps_max_quantity() {
+        let givable = Arc::new(Mutex::new(MaxGiveable::new(vec![
+            Amount::from_btc(0.1).unwrap()
+        ])));
+
+        let (amount, _) = determine_btc_to_swap(
+            true,
+            quote_with_max(0.01),
+            Some(Amount::from_btc(0.02).unwrap()),
+            async { panic!("should not request new address when initial balance is > 0") },
+            || async { Ok(Amount::from_btc(0.1001)?) },
+            || async {
+                let mut result = givable.lock().unwrap();
+                result.give()
+            },
+            || async { Ok(()) },
+            |_| async { Ok(Amount::from_sat(1000)) },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(amount, Amount::from_btc(0.01).unwrap());
+    }
+
+    #[tokio::test]
+    async fn given_deposit_below_requested_amount_return_error() {
+        let givable = Arc::new(Mutex::new(MaxGiveable::new(vec![
+            Amount::ZERO,
+            Amount::from_btc(0.1).unwrap(),
+        ])));
+
+        let determination_error = determine_btc_to_swap(
+            true,
+            quote_with_max(1.0),
+            Some(Amount::from_btc(0.5).unwrap()),
+            get_dummy_address(),
+            || async { Ok(Amount::from_btc(0.1001)?) },
+            || async {
+                let mut result = givable.lock().unwrap();
+                result.give()
+            },
+            || async { Ok(()) },
+            |_| async { Ok(Amount::from_sat(1000)) },
+        )
+        .await
+        .err()
+        .unwrap()
+        .to_string();
+
+        assert_eq!(
+            "Requested to swap 0.5 BTC but only 0.1 BTC can be spent after fees, deposit more Bitcoin or request a smaller amount",
+            determination_error
+        );
+    }
+
+    #[tokio::test]
+    async fn given_requested_amount_below_dust_return_error() {
+        let determination_error = determine_btc_to_swap(
+            true,
+            quote_with_max(0.01),
+            Some(Amount::from_sat(1)),
+            get_dummy_address(),
+            || async { Ok(Amount::ZERO) },
+            || async { Ok(Amount::ZERO) },
+            || async { Ok(()) },
+            |_| async { Ok(Amount::from_sat(1000)) },
+        )
+        .await
+        .err()
+        .unwrap()
+        .to_string();
+
+        assert!(determination_error.starts_with("Requested to swap 0.00000001 BTC"));
+    }
+
     struct MaxGiveable {
         amounts: Vec<Amount>,
         call_counter: usize,
@@ -403,6 +530,7 @@ mod tests {
             price: Amount::from_btc(0.001).unwrap(),
             max_quantity: Amount::from_btc(btc).unwrap(),
             min_quantity: Amount::ZERO,
+            protocol_version: PROTOCOL_VERSION,
         }
     }
 
@@ -411,6 +539,7 @@ mod tests {
             price: Amount::from_btc(0.001).unwrap(),
             max_quantity: Amount::max_value(),
             min_quantity: Amount::from_btc(btc).unwrap(),
+            protocol_version: PROTOCOL_VERSION,
         }
     }
 
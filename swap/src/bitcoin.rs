@@ -23,7 +23,7 @@ pub use ecdsa_fun::Signature;
 pub use wallet::Wallet;
 
 #[cfg(test)]
-pub use wallet::WalletBuilder;
+pub use wallet::{FakeChain, WalletBuilder};
 
 use crate::bitcoin::wallet::ScriptStatus;
 use ::bitcoin::hashes::Hash;
@@ -314,6 +314,7 @@ pub fn extract_ecdsa_sig(sig: &[u8]) -> Result<Signature> {
 }
 
 /// Bitcoin error codes: https://github.com/bitcoin/bitcoin/blob/97d3500601c1d28642347d014a6de1e38f53ae4e/src/rpc/protocol.h#L23
+#[derive(Clone, Copy, Debug)]
 pub enum RpcErrorCode {
     /// Transaction or block was rejected by network rules. Error code -26.
     RpcVerifyRejected,
@@ -439,6 +440,95 @@ mod tests {
 
     #[tokio::test]
     async fn calculate_transaction_weights() {
+        let (alice_state3, bob_state3) = swap_states().await;
+
+        let bob_state4 = bob_state3.xmr_locked(monero_rpc::wallet::BlockHeight { height: 0 });
+        let encrypted_signature = bob_state4.tx_redeem_encsig();
+        let bob_state6 = bob_state4.cancel();
+
+        let cancel_transaction = alice_state3.signed_cancel_transaction().unwrap();
+        let punish_transaction = alice_state3.signed_punish_transaction().unwrap();
+        let redeem_transaction = alice_state3
+            .signed_redeem_transaction(encrypted_signature)
+            .unwrap();
+        let refund_transaction = bob_state6.signed_refund_transaction().unwrap();
+
+        assert_weight(redeem_transaction, TxRedeem::weight(), "TxRedeem");
+        assert_weight(cancel_transaction, TxCancel::weight(), "TxCancel");
+        assert_weight(punish_transaction, TxPunish::weight(), "TxPunish");
+        assert_weight(refund_transaction, TxRefund::weight(), "TxRefund");
+    }
+
+    #[tokio::test]
+    async fn expired_timelocks_follow_the_chain() {
+        let (alice_state3, bob_state3) = swap_states().await;
+        let chain = FakeChain::default();
+        let wallet = WalletBuilder::new(Amount::ONE_BTC.to_sat()).build_with_chain(chain.clone());
+        let tx_cancel = alice_state3.tx_cancel();
+
+        // regtest: cancel timelock of 100 blocks, punish timelock of 50 blocks
+        chain.add_to_mempool(alice_state3.tx_lock.txid());
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            matches!(expired, ExpiredTimelocks::None { .. })
+        })
+        .await;
+
+        chain.confirm(alice_state3.tx_lock.txid());
+        chain.mine(98);
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            matches!(expired, ExpiredTimelocks::None { blocks_left: 1 })
+        })
+        .await;
+
+        chain.mine(1);
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            matches!(expired, ExpiredTimelocks::Cancel { .. })
+        })
+        .await;
+
+        chain.confirm(tx_cancel.txid());
+        chain.mine(49);
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            expired == ExpiredTimelocks::Punish
+        })
+        .await;
+
+        // a reorg that drops the cancel transaction leaves only the cancel timelock expired
+        chain.reorg(50);
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            matches!(expired, ExpiredTimelocks::Cancel { .. })
+        })
+        .await;
+
+        chain.reorg(1);
+        assert_both_expired(&alice_state3, &bob_state3, &wallet, |expired| {
+            matches!(expired, ExpiredTimelocks::None { blocks_left: 1 })
+        })
+        .await;
+    }
+
+    async fn assert_both_expired(
+        alice_state3: &alice::State3,
+        bob_state3: &bob::State3,
+        wallet: &Wallet<bdk::database::MemoryDatabase, FakeChain>,
+        expected: impl Fn(ExpiredTimelocks) -> bool,
+    ) {
+        let alice_expired = alice_state3.expired_timelocks(wallet).await.unwrap();
+        let bob_expired = bob_state3.expired_timelock(wallet).await.unwrap();
+
+        assert!(
+            expected(alice_expired),
+            "unexpected for Alice: {:?}",
+            alice_expired
+        );
+        assert!(
+            expected(bob_expired),
+            "unexpected for Bob: {:?}",
+            bob_expired
+        );
+    }
+
+    async fn swap_states() -> (alice::State3, bob::State3) {
         let alice_wallet = WalletBuilder::new(Amount::ONE_BTC.to_sat()).build();
         let bob_wallet = WalletBuilder::new(Amount::ONE_BTC.to_sat()).build();
         let spending_fee = Amount::from_sat(1_000);
@@ -501,21 +591,8 @@ mod tests {
         let alice_state3 = alice_state2.receive(bob_message4).unwrap();
 
         let (bob_state3, _tx_lock) = bob_state2.lock_btc().await.unwrap();
-        let bob_state4 = bob_state3.xmr_locked(monero_rpc::wallet::BlockHeight { height: 0 });
-        let encrypted_signature = bob_state4.tx_redeem_encsig();
-        let bob_state6 = bob_state4.cancel();
 
-        let cancel_transaction = alice_state3.signed_cancel_transaction().unwrap();
-        let punish_transaction = alice_state3.signed_punish_transaction().unwrap();
-        let redeem_transaction = alice_state3
-            .signed_redeem_transaction(encrypted_signature)
-            .unwrap();
-        let refund_transaction = bob_state6.signed_refund_transaction().unwrap();
-
-        assert_weight(redeem_transaction, TxRedeem::weight(), "TxRedeem");
-        assert_weight(cancel_transaction, TxCancel::weight(), "TxCancel");
-        assert_weight(punish_transaction, TxPunish::weight(), "TxPunish");
-        assert_weight(refund_transaction, TxRefund::weight(), "TxRefund");
+        (alice_state3, bob_state3)
     }
 
     // Weights fluctuate because of the length of the signatures. Valid ecdsa
@@ -103,6 +103,12 @@ impl PartialEq<PunishTimelock> for u32 {
     }
 }
 
+impl fmt::Display for PunishTimelock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} blocks", self.0)
+    }
+}
+
 #[derive(Debug)]
 pub struct TxCancel {
     inner: Transaction,
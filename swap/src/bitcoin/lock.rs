@@ -101,6 +101,15 @@ impl TxLock {
         Amount::from_sat(self.inner.clone().extract_tx().output[self.lock_output_vout()].value)
     }
 
+    /// The outputs of Bob's wallet funding the lock transaction.
+    pub fn inputs(&self) -> impl Iterator<Item = OutPoint> + '_ {
+        self.inner
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+    }
+
     pub fn fee(&self) -> Result<Amount> {
         Ok(Amount::from_sat(
             self.inner
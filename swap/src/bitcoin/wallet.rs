@@ -1,5 +1,5 @@
 use crate::bitcoin::timelocks::BlockHeight;
-use crate::bitcoin::{Address, Amount, Transaction};
+use crate::bitcoin::{parse_rpc_error_code, Address, Amount, RpcErrorCode, Transaction};
 use crate::env;
 use ::bitcoin::util::psbt::PartiallySignedTransaction;
 use ::bitcoin::Txid;
@@ -11,19 +11,22 @@ use bdk::sled::Tree;
 use bdk::wallet::export::FullyNodedExport;
 use bdk::wallet::AddressIndex;
 use bdk::{FeeRate, KeychainKind, SignOptions, SyncOptions};
+use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::util::bip32::ExtendedPrivKey;
-use bitcoin::{Network, Script};
+use bitcoin::{BlockHash, Network, OutPoint, Script};
 use reqwest::Url;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::sync::{watch, Mutex};
+use tokio::time::Instant;
 use tracing::{debug_span, Instrument};
 
 const SLED_TREE_NAME: &str = "default_tree";
@@ -32,7 +35,7 @@ const SLED_TREE_NAME: &str = "default_tree";
 /// amount for tx fees.
 const MAX_RELATIVE_TX_FEE: Decimal = dec!(0.03);
 const MAX_ABSOLUTE_TX_FEE: Decimal = dec!(100_000);
-const DUST_AMOUNT: u64 = 546;
+pub(crate) const DUST_AMOUNT: u64 = 546;
 
 const WALLET: &str = "wallet";
 const WALLET_OLD: &str = "wallet-old";
@@ -43,6 +46,19 @@ pub struct Wallet<D = Tree, C = Client> {
     finality_confirmations: u32,
     network: Network,
     target_block: usize,
+    max_fee_rate: FeeRate,
+    fallback_fee_rate: FeeRate,
+    /// Outputs spent by transactions that were built but may not be
+    /// published yet. They are never selected again, so that concurrent swaps
+    /// can't build conflicting lock transactions.
+    reserved_utxos: Arc<Mutex<HashSet<OutPoint>>>,
+    subscriptions: Arc<Mutex<HashMap<(Txid, Script), Subscription>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub confirmed: Amount,
+    pub pending: Amount,
 }
 
 impl Wallet {
@@ -52,24 +68,25 @@ impl Wallet {
         xprivkey: ExtendedPrivKey,
         env_config: env::Config,
         target_block: usize,
+        socks5_proxy: Option<SocketAddr>,
     ) -> Result<Self> {
         let data_dir = data_dir.as_ref();
         let wallet_dir = data_dir.join(WALLET);
         let database = bdk::sled::open(wallet_dir)?.open_tree(SLED_TREE_NAME)?;
         let network = env_config.bitcoin_network;
 
-        let wallet = match bdk::Wallet::new(
-            bdk::template::Bip84(xprivkey, KeychainKind::External),
-            Some(bdk::template::Bip84(xprivkey, KeychainKind::Internal)),
-            network,
-            database,
-        ) {
+        let wallet = match bip84_wallet(xprivkey, network, database) {
             Ok(w) => w,
             Err(bdk::Error::ChecksumMismatch) => Self::migrate(data_dir, xprivkey, network)?,
             err => err?,
         };
 
-        let client = Client::new(electrum_rpc_url, env_config.bitcoin_sync_interval())?;
+        let client = Client::new(
+            electrum_rpc_url,
+            network,
+            env_config.bitcoin_sync_interval(),
+            socks5_proxy,
+        )?;
 
         let network = wallet.network();
 
@@ -79,6 +96,12 @@ impl Wallet {
             finality_confirmations: env_config.bitcoin_finality_confirmations,
             network,
             target_block,
+            max_fee_rate: fee_rate_from_sat_per_vb(env_config.bitcoin_max_fee_rate_sat_vb),
+            fallback_fee_rate: fee_rate_from_sat_per_vb(
+                env_config.bitcoin_fallback_fee_rate_sat_vb,
+            ),
+            reserved_utxos: Arc::new(Mutex::new(HashSet::new())),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
@@ -98,19 +121,111 @@ impl Wallet {
         let wallet_dir = data_dir.join(WALLET);
         let database = bdk::sled::open(wallet_dir)?.open_tree(SLED_TREE_NAME)?;
 
-        let wallet = bdk::Wallet::new(
-            bdk::template::Bip84(xprivkey, KeychainKind::External),
-            Some(bdk::template::Bip84(xprivkey, KeychainKind::Internal)),
-            network,
-            database,
-        )?;
+        let wallet = bip84_wallet(xprivkey, network, database)?;
 
         Ok(wallet)
     }
 
+    /// Speeds up an unconfirmed transaction of ours by spending its change
+    /// output in a child transaction that pays for both (CPFP).
+    ///
+    /// The transaction is not replaced because transactions spending it, e.g.
+    /// the pre-signed cancel transaction of a swap, commit to its ID.
+    ///
+    /// Returns the ID of the child transaction.
+    pub async fn bump_fee(&self, txid: Txid, fee_rate: FeeRate) -> Result<Txid> {
+        let parent = self.get_raw_transaction(txid).await?;
+
+        let status = self
+            .status_of_script(&(txid, parent.output[0].script_pubkey.clone()))
+            .await?;
+        if status.is_confirmed() {
+            bail!("Transaction {} is already confirmed", txid);
+        }
+
+        let parent_fee = self.transaction_fee(txid).await?;
+        let child_fee = cpfp_fee(parent_fee, parent.vsize(), fee_rate).with_context(|| {
+            format!(
+                "Transaction {} already pays at least {} sat/vB",
+                txid,
+                fee_rate.as_sat_per_vb()
+            )
+        })?;
+
+        let psbt = {
+            let wallet = self.wallet.lock().await;
+
+            let mut change = None;
+            for (vout, output) in parent.output.iter().enumerate() {
+                if wallet.is_mine(&output.script_pubkey)? {
+                    change = Some(OutPoint::new(txid, u32::try_from(vout)?));
+                    break;
+                }
+            }
+            let change = change.with_context(|| {
+                format!(
+                    "Transaction {} has no change output that belongs to this wallet",
+                    txid
+                )
+            })?;
+            let drain_script = wallet
+                .get_address(AddressIndex::New)
+                .context("Failed to get Bitcoin address")?
+                .address
+                .script_pubkey();
+
+            let mut tx_builder = wallet.build_tx();
+            tx_builder
+                .add_utxo(change)?
+                .manually_selected_only()
+                .drain_to(drain_script)
+                .fee_absolute(child_fee.to_sat());
+            let (psbt, _details) = tx_builder.finish().with_context(|| {
+                format!(
+                    "The change output of transaction {} cannot pay a fee of {}",
+                    txid, child_fee
+                )
+            })?;
+
+            psbt
+        };
+
+        let child = self.sign_and_finalize(psbt).await?;
+        let (child_txid, _) = self.broadcast(child, "fee bump").await?;
+
+        Ok(child_txid)
+    }
+
+    pub async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
+        self.get_tx(txid)
+            .await?
+            .with_context(|| format!("Could not get raw tx with id: {}", txid))
+    }
+
+    pub async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
+        let wallet = self.wallet.lock().await;
+        match bdk::wallet::export::FullyNodedExport::export_wallet(
+            &wallet,
+            &format!("{}-{}", role, self.network),
+            true,
+        ) {
+            Ok(wallet_export) => Ok(wallet_export),
+            Err(err_msg) => Err(anyhow::Error::msg(err_msg)),
+        }
+    }
+}
+
+impl<D, C> Wallet<D, C>
+where
+    C: GetScriptStatus + BroadcastTransaction + Send + 'static,
+{
     /// Broadcast the given transaction to the network and emit a log statement
     /// if done so successfully.
     ///
+    /// The transaction is broadcast again on every sync until it is confirmed,
+    /// in case it gets dropped from the mempools. A transaction that is
+    /// already in the chain counts as successfully broadcast.
+    ///
     /// Returns the transaction ID and a future for when the transaction meets
     /// the configured finality confirmations.
     pub async fn broadcast(
@@ -125,29 +240,50 @@ impl Wallet {
             .subscribe_to((txid, transaction.output[0].script_pubkey.clone()))
             .await;
 
-        let client = self.client.lock().await;
-        let blockchain = client.blockchain();
+        let mut client = self.client.lock().await;
 
-        blockchain.broadcast(&transaction).with_context(|| {
-            format!("Failed to broadcast Bitcoin {} transaction {}", kind, txid)
-        })?;
+        match client.broadcast_transaction(&transaction) {
+            Ok(()) => {
+                tracing::info!(%txid, %kind, "Published Bitcoin transaction");
+                client.rebroadcast_until_confirmed(transaction);
+            }
+            Err(error) => {
+                if !is_already_in_chain(&error) {
+                    return Err(error.context(format!(
+                        "Failed to broadcast Bitcoin {} transaction {}",
+                        kind, txid
+                    )));
+                }
 
-        tracing::info!(%txid, %kind, "Published Bitcoin transaction");
+                tracing::info!(%txid, %kind, "Bitcoin transaction is already in the chain");
+            }
+        }
 
         Ok((txid, subscription))
     }
 
-    pub async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
-        self.get_tx(txid)
-            .await?
-            .with_context(|| format!("Could not get raw tx with id: {}", txid))
-    }
+    /// Broadcasts the given transactions again on every sync until they are
+    /// confirmed.
+    ///
+    /// Used to resume watching transactions that were broadcast before a
+    /// restart.
+    pub async fn rebroadcast_until_confirmed(
+        &self,
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) {
+        for transaction in transactions {
+            let txid = transaction.txid();
 
-    pub async fn status_of_script<T>(&self, tx: &T) -> Result<ScriptStatus>
-    where
-        T: Watchable,
-    {
-        self.client.lock().await.status_of_script(tx)
+            // keeps the script histories updated, which triggers the rebroadcast
+            let _ = self
+                .subscribe_to((txid, transaction.output[0].script_pubkey.clone()))
+                .await;
+
+            self.client
+                .lock()
+                .await
+                .rebroadcast_until_confirmed(transaction);
+        }
     }
 
     pub async fn subscribe_to(&self, tx: impl Watchable + Send + 'static) -> Subscription {
@@ -155,14 +291,14 @@ impl Wallet {
         let script = tx.script();
 
         let sub = self
-            .client
+            .subscriptions
             .lock()
             .await
-            .subscriptions
             .entry((txid, script.clone()))
             .or_insert_with(|| {
                 let (sender, receiver) = watch::channel(ScriptStatus::Unseen);
                 let client = self.client.clone();
+                let subscriptions = self.subscriptions.clone();
 
                 tokio::spawn(async move {
                     let mut last_status = None;
@@ -184,7 +320,7 @@ impl Wallet {
 
                             if all_receivers_gone {
                                 tracing::debug!(%txid, "All receivers gone, removing subscription");
-                                client.lock().await.subscriptions.remove(&(txid, script));
+                                subscriptions.lock().await.remove(&(txid, script));
                                 return;
                             }
                         }
@@ -203,18 +339,6 @@ impl Wallet {
 
         sub
     }
-
-    pub async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
-        let wallet = self.wallet.lock().await;
-        match bdk::wallet::export::FullyNodedExport::export_wallet(
-            &wallet,
-            &format!("{}-{}", role, self.network),
-            true,
-        ) {
-            Ok(wallet_export) => Ok(wallet_export),
-            Err(err_msg) => Err(anyhow::Error::msg(err_msg)),
-        }
-    }
 }
 
 fn print_status_change(txid: Txid, old: Option<ScriptStatus>, new: ScriptStatus) -> ScriptStatus {
@@ -330,6 +454,37 @@ where
         Ok(Amount::from_sat(balance.get_total()))
     }
 
+    /// Returns the Bitcoin balance split into confirmed and pending funds
+    pub async fn balance_by_confirmation(&self) -> Result<Balance> {
+        let balance = self
+            .wallet
+            .lock()
+            .await
+            .get_balance()
+            .context("Failed to calculate Bitcoin balance")?;
+
+        Ok(Balance {
+            confirmed: Amount::from_sat(balance.confirmed),
+            pending: Amount::from_sat(
+                balance.immature + balance.trusted_pending + balance.untrusted_pending,
+            ),
+        })
+    }
+
+    /// Returns the first address that has not received any funds yet instead
+    /// of deriving a new one
+    pub async fn deposit_address(&self) -> Result<Address> {
+        let address = self
+            .wallet
+            .lock()
+            .await
+            .get_address(AddressIndex::LastUnused)
+            .context("Failed to get Bitcoin deposit address")?
+            .address;
+
+        Ok(address)
+    }
+
     pub async fn new_address(&self) -> Result<Address> {
         let address = self
             .wallet
@@ -379,15 +534,24 @@ where
 
         let wallet = self.wallet.lock().await;
         let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let mut reserved_utxos = self.reserved_utxos.lock().await;
+        let fee_rate = self.fee_rate(&*client);
         let script = address.script_pubkey();
 
         let mut tx_builder = wallet.build_tx();
         tx_builder.add_recipient(script.clone(), amount.to_sat());
         tx_builder.fee_rate(fee_rate);
+        tx_builder.unspendable(reserved_utxos.iter().copied().collect());
         let (psbt, _details) = tx_builder.finish()?;
         let mut psbt: PartiallySignedTransaction = psbt;
 
+        reserved_utxos.extend(
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .map(|input| input.previous_output),
+        );
+
         match psbt.unsigned_tx.output.as_mut_slice() {
             // our primary output is the 2nd one? reverse the vectors
             [_, second_txout] if second_txout.script_pubkey == script => {
@@ -435,7 +599,8 @@ where
             return Ok(Amount::ZERO);
         }
 
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = self.fee_rate(&*client);
+        let reserved_utxos = self.reserved_utxos.lock().await;
 
         let mut tx_builder = wallet.build_tx();
 
@@ -443,6 +608,7 @@ where
         tx_builder.drain_to(dummy_script);
         tx_builder.fee_rate(fee_rate);
         tx_builder.drain_wallet();
+        tx_builder.unspendable(reserved_utxos.iter().copied().collect());
 
         let response = tx_builder.finish();
         match response {
@@ -453,11 +619,29 @@ where
                         .expect("fees are always present with Electrum backend");
                 Ok(Amount::from_sat(max_giveable))
             }
-            Err(bdk::Error::InsufficientFunds { .. }) => Ok(Amount::ZERO),
+            Err(bdk::Error::InsufficientFunds { .. } | bdk::Error::NoUtxosSelected) => {
+                Ok(Amount::ZERO)
+            }
             Err(e) => bail!("Failed to build transaction. {:#}", e),
         }
     }
 
+    /// Excludes the given outputs from all transactions built from now on,
+    /// e.g. the inputs of a lock transaction that was built before a restart.
+    pub async fn reserve_utxos(&self, outpoints: impl IntoIterator<Item = OutPoint>) {
+        self.reserved_utxos.lock().await.extend(outpoints);
+    }
+
+    /// Makes the given outputs spendable again, e.g. the inputs of a
+    /// transaction that is never going to be published.
+    pub async fn release_utxos(&self, outpoints: impl IntoIterator<Item = OutPoint>) {
+        let mut reserved_utxos = self.reserved_utxos.lock().await;
+
+        for outpoint in outpoints {
+            reserved_utxos.remove(&outpoint);
+        }
+    }
+
     /// Estimate total tx fee for a pre-defined target block based on the
     /// transaction weight. The max fee cannot be more than MAX_PERCENTAGE_FEE
     /// of amount
@@ -467,13 +651,141 @@ where
         transfer_amount: bitcoin::Amount,
     ) -> Result<bitcoin::Amount> {
         let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = self.fee_rate(&*client);
         let min_relay_fee = client.min_relay_fee()?;
 
         estimate_fee(weight, transfer_amount, fee_rate, min_relay_fee)
     }
 }
 
+impl<D, C> Wallet<D, C>
+where
+    C: EstimateFeeRate,
+{
+    /// The fee rate that new transactions currently pay, see
+    /// [`Wallet::fee_rate`].
+    pub async fn fee_rate_estimate(&self) -> FeeRate {
+        let client = self.client.lock().await;
+
+        self.fee_rate(&*client)
+    }
+
+    /// Estimates the fee rate for the configured target block.
+    ///
+    /// Uses the fallback fee rate if the electrum server cannot provide an
+    /// estimate. The result never exceeds the max fee rate and is never below
+    /// the min relay fee rate, otherwise the transaction would not propagate.
+    fn fee_rate(&self, client: &C) -> FeeRate {
+        let fee_rate = match client.estimate_feerate(self.target_block) {
+            // electrum servers return -1 if they don't have enough data for an estimate
+            Ok(fee_rate) if fee_rate.as_sat_per_vb() >= 0.0 => fee_rate,
+            Ok(_) => {
+                tracing::warn!(
+                    fallback_sat_per_vb = %self.fallback_fee_rate.as_sat_per_vb(),
+                    "Electrum server could not estimate the fee rate, using fallback fee rate"
+                );
+                self.fallback_fee_rate
+            }
+            Err(error) => {
+                tracing::warn!(
+                    fallback_sat_per_vb = %self.fallback_fee_rate.as_sat_per_vb(),
+                    "Failed to estimate the fee rate, using fallback fee rate: {:#}",
+                    error
+                );
+                self.fallback_fee_rate
+            }
+        };
+
+        let min_fee_rate = match client.min_relay_fee() {
+            Ok(min_relay_fee) => min_relay_fee_rate(min_relay_fee),
+            Err(error) => {
+                tracing::debug!("Failed to get the min relay fee: {:#}", error);
+                FeeRate::from_sat_per_vb(0.0)
+            }
+        };
+
+        clamp_fee_rate(fee_rate, min_fee_rate, self.max_fee_rate)
+    }
+}
+
+/// Limits the estimated fee rate to the max fee rate. A min fee rate above
+/// the max wins, because a transaction paying less would not be relayed.
+fn clamp_fee_rate(estimate: FeeRate, min: FeeRate, max: FeeRate) -> FeeRate {
+    let mut fee_rate = estimate;
+
+    if fee_rate.as_sat_per_vb() > max.as_sat_per_vb() {
+        tracing::warn!(
+            estimated_sat_per_vb = %fee_rate.as_sat_per_vb(),
+            max_sat_per_vb = %max.as_sat_per_vb(),
+            "Estimated fee rate exceeds the max fee rate, using max fee rate"
+        );
+        fee_rate = max;
+    }
+
+    if fee_rate.as_sat_per_vb() < min.as_sat_per_vb() {
+        tracing::debug!(
+            estimated_sat_per_vb = %fee_rate.as_sat_per_vb(),
+            min_sat_per_vb = %min.as_sat_per_vb(),
+            "Estimated fee rate is below the min relay fee rate, using min relay fee rate"
+        );
+        fee_rate = min;
+    }
+
+    fee_rate
+}
+
+/// Derives the wallet's BIP84 descriptors from the extended private key.
+///
+/// The database only caches what can be derived again, so the same seed
+/// always recovers the same addresses and funds, even without the database.
+fn bip84_wallet<D>(
+    xprivkey: ExtendedPrivKey,
+    network: bitcoin::Network,
+    database: D,
+) -> Result<bdk::Wallet<D>, bdk::Error>
+where
+    D: BatchDatabase,
+{
+    bdk::Wallet::new(
+        bdk::template::Bip84(xprivkey, KeychainKind::External),
+        Some(bdk::template::Bip84(xprivkey, KeychainKind::Internal)),
+        network,
+        database,
+    )
+}
+
+/// Virtual size of a transaction that spends a single P2WPKH output to a
+/// single P2WPKH output, which is what [`Wallet::bump_fee`] builds.
+const CPFP_CHILD_VSIZE: usize = 110;
+
+/// The fee a child transaction has to pay so that it and its parent together
+/// pay the given fee rate.
+///
+/// Returns `None` if the parent alone already pays the fee rate.
+fn cpfp_fee(parent_fee: Amount, parent_vsize: usize, fee_rate: FeeRate) -> Option<Amount> {
+    if parent_fee.to_sat() >= fee_rate.fee_vb(parent_vsize) {
+        return None;
+    }
+
+    let package_fee = fee_rate.fee_vb(parent_vsize + CPFP_CHILD_VSIZE);
+
+    Some(Amount::from_sat(package_fee - parent_fee.to_sat()))
+}
+
+// Fee rates are far below 2^24 sat/vB, so converting to f32 is lossless.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn fee_rate_from_sat_per_vb(sat_per_vb: u64) -> FeeRate {
+    FeeRate::from_sat_per_vb(sat_per_vb as f32)
+}
+
+/// Electrum reports the min relay fee per kvB, see
+/// [`EstimateFeeRate::min_relay_fee`].
+// The relay fee is far below 2^24 sat/kvB, so converting to f32 is lossless.
+#[allow(clippy::cast_precision_loss)]
+fn min_relay_fee_rate(min_relay_fee: Amount) -> FeeRate {
+    FeeRate::from_sat_per_vb(min_relay_fee.to_sat() as f32 / 1000.0)
+}
+
 fn estimate_fee(
     weight: usize,
     transfer_amount: Amount,
@@ -546,7 +858,7 @@ fn estimate_fee(
 
 impl<D> Wallet<D>
 where
-    D: BatchDatabase,
+    D: BatchDatabase + Send + 'static,
 {
     pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
         let client = self.client.lock().await;
@@ -555,15 +867,21 @@ where
         Ok(tx)
     }
 
+    /// Syncs the wallet with the Electrum server.
+    ///
+    /// Balance and fee queries only read the state of the last sync. The sync
+    /// itself blocks on network I/O, so it runs on the blocking thread pool
+    /// instead of stalling the tasks of other swaps.
     pub async fn sync(&self) -> Result<()> {
-        let client = self.client.lock().await;
-        let blockchain = client.blockchain();
-        let sync_opts = SyncOptions::default();
-        self.wallet
-            .lock()
-            .await
-            .sync(blockchain, sync_opts)
-            .context("Failed to sync balance of Bitcoin wallet")?;
+        let client = Arc::clone(&self.client).lock_owned().await;
+        let wallet = Arc::clone(&self.wallet).lock_owned().await;
+
+        tokio::task::spawn_blocking(move || {
+            wallet.sync(client.blockchain(), SyncOptions::default())
+        })
+        .await
+        .context("Bitcoin wallet sync task failed")?
+        .context("Failed to sync balance of Bitcoin wallet")?;
 
         Ok(())
     }
@@ -576,11 +894,39 @@ impl<D, C> Wallet<D, C> {
     }
 }
 
+impl<D, C> Wallet<D, C>
+where
+    C: GetScriptStatus,
+{
+    pub async fn status_of_script<T>(&self, tx: &T) -> Result<ScriptStatus>
+    where
+        T: Watchable,
+    {
+        self.client.lock().await.status_of_script(tx)
+    }
+}
+
 pub trait EstimateFeeRate {
     fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate>;
     fn min_relay_fee(&self) -> Result<bitcoin::Amount>;
 }
 
+pub trait GetScriptStatus {
+    fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
+    where
+        T: Watchable;
+}
+
+pub trait BroadcastTransaction {
+    /// Publishes the transaction. A rejection is reported with the RPC error
+    /// of the node, see [`parse_rpc_error_code`].
+    fn broadcast_transaction(&mut self, transaction: &Transaction) -> Result<()>;
+
+    /// Broadcasts the transaction again on every sync until it is confirmed,
+    /// in case it gets dropped from the mempools.
+    fn rebroadcast_until_confirmed(&mut self, transaction: Transaction);
+}
+
 #[cfg(test)]
 pub struct StaticFeeRate {
     fee_rate: FeeRate,
@@ -606,6 +952,8 @@ pub struct WalletBuilder {
     min_relay_fee_sats: u64,
     key: bitcoin::util::bip32::ExtendedPrivKey,
     num_utxos: u8,
+    max_fee_rate_sat_vb: u64,
+    fallback_fee_rate_sat_vb: u64,
 }
 
 #[cfg(test)]
@@ -621,6 +969,8 @@ impl WalletBuilder {
             min_relay_fee_sats: 1000,
             key: "tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m".parse().unwrap(),
             num_utxos: 1,
+            max_fee_rate_sat_vb: 100_000,
+            fallback_fee_rate_sat_vb: 1,
         }
     }
 
@@ -640,6 +990,14 @@ impl WalletBuilder {
         }
     }
 
+    pub fn with_fee_rate_limits(self, max_sat_per_vb: u64, fallback_sat_per_vb: u64) -> Self {
+        Self {
+            max_fee_rate_sat_vb: max_sat_per_vb,
+            fallback_fee_rate_sat_vb: fallback_sat_per_vb,
+            ..self
+        }
+    }
+
     pub fn with_key(self, key: bitcoin::util::bip32::ExtendedPrivKey) -> Self {
         Self { key, ..self }
     }
@@ -652,6 +1010,24 @@ impl WalletBuilder {
     }
 
     pub fn build(self) -> Wallet<bdk::database::MemoryDatabase, StaticFeeRate> {
+        let client = StaticFeeRate {
+            fee_rate: FeeRate::from_sat_per_vb(self.sats_per_vb),
+            min_relay_fee: bitcoin::Amount::from_sat(self.min_relay_fee_sats),
+        };
+
+        self.build_with_client(client)
+    }
+
+    /// Builds a wallet that observes the given fake chain instead of an
+    /// electrum server.
+    pub fn build_with_chain(
+        self,
+        chain: FakeChain,
+    ) -> Wallet<bdk::database::MemoryDatabase, FakeChain> {
+        self.build_with_client(chain)
+    }
+
+    fn build_with_client<C>(self, client: C) -> Wallet<bdk::database::MemoryDatabase, C> {
         use bdk::database::{BatchOperations, MemoryDatabase, SyncTime};
         use bdk::{testutils, BlockTime};
 
@@ -678,145 +1054,350 @@ impl WalletBuilder {
         let wallet = bdk::Wallet::new(&descriptors.0, None, Network::Regtest, database).unwrap();
 
         Wallet {
-            client: Arc::new(Mutex::new(StaticFeeRate {
-                fee_rate: FeeRate::from_sat_per_vb(self.sats_per_vb),
-                min_relay_fee: bitcoin::Amount::from_sat(self.min_relay_fee_sats),
-            })),
+            client: Arc::new(Mutex::new(client)),
             wallet: Arc::new(Mutex::new(wallet)),
             finality_confirmations: 1,
             network: Network::Regtest,
             target_block: 1,
+            max_fee_rate: fee_rate_from_sat_per_vb(self.max_fee_rate_sat_vb),
+            fallback_fee_rate: fee_rate_from_sat_per_vb(self.fallback_fee_rate_sat_vb),
+            reserved_utxos: Arc::new(Mutex::new(HashSet::new())),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
 
-/// Defines a watchable transaction.
+/// An in-memory chain for tests that need to control what the wallet observes.
 ///
-/// For a transaction to be watchable, we need to know two things: Its
-/// transaction ID and the specific output script that is going to change.
-/// A transaction can obviously have multiple outputs but our protocol purposes,
-/// we are usually interested in a specific one.
-pub trait Watchable {
-    fn id(&self) -> Txid;
-    fn script(&self) -> Script;
+/// Clones share the same chain, so a test keeps one clone as a handle to
+/// mine blocks, confirm transactions, trigger reorgs or reject broadcasts
+/// while the wallet built with [`WalletBuilder::build_with_chain`] observes
+/// the changes.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub struct FakeChain {
+    inner: Arc<std::sync::Mutex<FakeChainState>>,
 }
 
-impl Watchable for (Txid, Script) {
-    fn id(&self) -> Txid {
-        self.0
-    }
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct FakeChainState {
+    height: u32,
+    mempool: std::collections::HashSet<Txid>,
+    confirmed: HashMap<Txid, u32>,
+    rejection: Option<(RpcErrorCode, String)>,
+    broadcasts: HashMap<Txid, usize>,
+    unconfirmed_transactions: UnconfirmedTransactions,
+}
 
-    fn script(&self) -> Script {
-        self.1.clone()
+#[cfg(test)]
+impl FakeChainState {
+    fn status_of(&self, txid: Txid) -> ScriptStatus {
+        if let Some(included_in) = self.confirmed.get(&txid) {
+            return ScriptStatus::Confirmed(Confirmed::from_inclusion_and_latest_block(
+                *included_in,
+                self.height,
+            ));
+        }
+        if self.mempool.contains(&txid) {
+            return ScriptStatus::InMempool;
+        }
+
+        ScriptStatus::Unseen
     }
-}
 
-pub struct Client {
-    electrum: bdk::electrum_client::Client,
-    blockchain: ElectrumBlockchain,
-    latest_block_height: BlockHeight,
-    last_sync: Instant,
-    sync_interval: Duration,
-    script_history: BTreeMap<Script, Vec<GetHistoryRes>>,
-    subscriptions: HashMap<(Txid, Script), Subscription>,
-}
+    fn broadcast(&mut self, transaction: &Transaction) -> Result<()> {
+        let txid = transaction.txid();
 
-impl Client {
-    fn new(electrum_rpc_url: Url, interval: Duration) -> Result<Self> {
-        let config = bdk::electrum_client::ConfigBuilder::default()
-            .retry(5)
-            .build();
-        let electrum = bdk::electrum_client::Client::from_config(electrum_rpc_url.as_str(), config)
-            .context("Failed to initialize Electrum RPC client")?;
-        // Initially fetch the latest block for storing the height.
-        // We do not act on this subscription after this call.
-        let latest_block = electrum
-            .block_headers_subscribe()
-            .context("Failed to subscribe to header notifications")?;
+        if let Some((code, message)) = &self.rejection {
+            return Err(rpc_error(*code, message));
+        }
+        if self.confirmed.contains_key(&txid) {
+            return Err(rpc_error(
+                RpcErrorCode::RpcVerifyAlreadyInChain,
+                "Transaction already in block chain",
+            ));
+        }
 
-        let client = bdk::electrum_client::Client::new(electrum_rpc_url.as_str())
-            .context("Failed to initialize Electrum RPC client")?;
-        let blockchain = ElectrumBlockchain::from(client);
-        let last_sync = Instant::now()
-            .checked_sub(interval)
-            .expect("no underflow since block time is only 600 secs");
+        self.mempool.insert(txid);
+        *self.broadcasts.entry(txid).or_default() += 1;
 
-        Ok(Self {
-            electrum,
-            blockchain,
-            latest_block_height: BlockHeight::try_from(latest_block)?,
-            last_sync,
-            sync_interval: interval,
-            script_history: Default::default(),
-            subscriptions: Default::default(),
-        })
+        Ok(())
     }
 
-    fn blockchain(&self) -> &ElectrumBlockchain {
-        &self.blockchain
-    }
+    /// Broadcasts our transactions again that were dropped from the mempool,
+    /// like [`Client`] does on every sync.
+    fn rebroadcast_dropped_transactions(&mut self) {
+        let mut unconfirmed_transactions = std::mem::take(&mut self.unconfirmed_transactions);
 
-    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
-        self.blockchain.get_tx(txid)
+        let dropped = unconfirmed_transactions
+            .take_dropped(|transaction| Ok(self.status_of(transaction.txid())));
+        for transaction in dropped {
+            let result = self.broadcast(&transaction);
+            unconfirmed_transactions.rebroadcast(&transaction, result);
+        }
+
+        self.unconfirmed_transactions = unconfirmed_transactions;
     }
+}
 
-    fn update_state(&mut self, force_sync: bool) -> Result<()> {
-        let now = Instant::now();
+/// Builds the error that electrum reports if the node rejects a transaction.
+#[cfg(test)]
+fn rpc_error(code: RpcErrorCode, message: &str) -> anyhow::Error {
+    let json = serde_json::json!({ "code": i64::from(code), "message": message });
 
-        if !force_sync && now < self.last_sync + self.sync_interval {
-            return Ok(());
+    bdk::Error::Electrum(bdk::electrum_client::Error::Protocol(
+        serde_json::Value::String(format!("sendrawtransaction RPC error: {}", json)),
+    ))
+    .into()
+}
+
+#[cfg(test)]
+impl FakeChain {
+    pub fn height(&self) -> u32 {
+        self.state().height
+    }
+
+    /// Mines the given number of empty blocks.
+    pub fn mine(&self, blocks: u32) {
+        self.state().height += blocks;
+    }
+
+    pub fn add_to_mempool(&self, txid: Txid) {
+        self.state().mempool.insert(txid);
+    }
+
+    /// Mines a block that includes the given transaction.
+    pub fn confirm(&self, txid: Txid) {
+        let mut state = self.state();
+        state.height += 1;
+        let height = state.height;
+
+        state.mempool.remove(&txid);
+        state.confirmed.insert(txid, height);
+    }
+
+    /// Removes the given number of blocks from the tip. Transactions
+    /// included in those blocks go back to the mempool.
+    pub fn reorg(&self, blocks: u32) {
+        let mut state = self.state();
+        state.height = state.height.saturating_sub(blocks);
+        let height = state.height;
+
+        let reorged = state
+            .confirmed
+            .iter()
+            .filter(|(_, included_in)| **included_in > height)
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+        for txid in reorged {
+            state.confirmed.remove(&txid);
+            state.mempool.insert(txid);
         }
+    }
 
-        self.last_sync = now;
-        self.update_latest_block()?;
-        self.update_script_histories()?;
+    /// Makes every broadcast fail with the given RPC error until
+    /// [`FakeChain::accept_broadcasts`] is called.
+    pub fn reject_broadcasts(&self, code: RpcErrorCode, message: &str) {
+        self.state().rejection = Some((code, message.to_owned()));
+    }
 
-        Ok(())
+    pub fn accept_broadcasts(&self) {
+        self.state().rejection = None;
     }
 
+    /// How often the given transaction was accepted by the chain.
+    pub fn broadcasts(&self, txid: Txid) -> usize {
+        self.state()
+            .broadcasts
+            .get(&txid)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Evicts the given transaction from the mempool, as if it expired.
+    pub fn drop_from_mempool(&self, txid: Txid) {
+        self.state().mempool.remove(&txid);
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, FakeChainState> {
+        self.inner
+            .lock()
+            .expect("fake chain lock is never poisoned")
+    }
+}
+
+#[cfg(test)]
+impl GetScriptStatus for FakeChain {
     fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
     where
         T: Watchable,
     {
-        let txid = tx.id();
-        let script = tx.script();
+        let mut state = self.state();
+        state.rebroadcast_dropped_transactions();
 
-        if !self.script_history.contains_key(&script) {
-            self.script_history.insert(script.clone(), vec![]);
+        Ok(state.status_of(tx.id()))
+    }
+}
 
-            // When we first subscribe to a script we want to immediately fetch its status
-            // Otherwise we would have to wait for the next sync interval, which can take a minute
-            // This would result in potentially inaccurate status updates until that next sync interval is hit
-            self.update_state(true)?;
-        } else {
-            self.update_state(false)?;
+#[cfg(test)]
+impl BroadcastTransaction for FakeChain {
+    fn broadcast_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        self.state().broadcast(transaction)
+    }
+
+    fn rebroadcast_until_confirmed(&mut self, transaction: Transaction) {
+        self.state().unconfirmed_transactions.insert(transaction);
+    }
+}
+
+/// Defines a watchable transaction.
+///
+/// For a transaction to be watchable, we need to know two things: Its
+/// transaction ID and the specific output script that is going to change.
+/// A transaction can obviously have multiple outputs but our protocol purposes,
+/// we are usually interested in a specific one.
+pub trait Watchable {
+    fn id(&self) -> Txid;
+    fn script(&self) -> Script;
+}
+
+impl Watchable for (Txid, Script) {
+    fn id(&self) -> Txid {
+        self.0
+    }
+
+    fn script(&self) -> Script {
+        self.1.clone()
+    }
+}
+
+pub struct Client {
+    electrum: bdk::electrum_client::Client,
+    blockchain: ElectrumBlockchain,
+    latest_block_height: BlockHeight,
+    /// Fetched on every sync, fee rates are computed far more often.
+    relay_fee: Amount,
+    last_sync: Instant,
+    sync_interval: Duration,
+    script_history: BTreeMap<Script, Vec<GetHistoryRes>>,
+    unconfirmed_transactions: UnconfirmedTransactions,
+}
+
+/// Electrum servers don't report their network, but the genesis block
+/// identifies it.
+fn ensure_same_network(genesis_hash: BlockHash, network: Network) -> Result<()> {
+    let expected = genesis_block(network).block_hash();
+
+    if genesis_hash != expected {
+        bail!(
+            "Server is not on the {} network, its genesis block is {}",
+            network,
+            genesis_hash
+        );
+    }
+
+    Ok(())
+}
+
+/// Connects through the given SOCKS5 proxy if any. The proxy resolves the
+/// host name, so .onion servers can be used and no DNS request leaks.
+pub fn electrum_config(socks5_proxy: Option<SocketAddr>) -> bdk::electrum_client::Config {
+    bdk::electrum_client::ConfigBuilder::default()
+        .retry(5)
+        .socks5(socks5_proxy.map(bdk::electrum_client::Socks5Config::new))
+        .build()
+}
+
+impl Client {
+    fn new(
+        electrum_rpc_url: Url,
+        network: Network,
+        interval: Duration,
+        socks5_proxy: Option<SocketAddr>,
+    ) -> Result<Self> {
+        let electrum = bdk::electrum_client::Client::from_config(
+            electrum_rpc_url.as_str(),
+            electrum_config(socks5_proxy),
+        )
+        .context("Failed to initialize Electrum RPC client")?;
+
+        let genesis = electrum
+            .block_header(0)
+            .context("Failed to fetch genesis block header")?;
+        ensure_same_network(genesis.block_hash(), network)
+            .with_context(|| format!("Electrum server {} cannot be used", electrum_rpc_url))?;
+
+        // Initially fetch the latest block for storing the height.
+        // We do not act on this subscription after this call.
+        let latest_block = electrum
+            .block_headers_subscribe()
+            .context("Failed to subscribe to header notifications")?;
+
+        let client = bdk::electrum_client::Client::from_config(
+            electrum_rpc_url.as_str(),
+            electrum_config(socks5_proxy),
+        )
+        .context("Failed to initialize Electrum RPC client")?;
+        let blockchain = ElectrumBlockchain::from(client);
+        let relay_fee = fetch_relay_fee(&electrum)?;
+        let last_sync = Instant::now()
+            .checked_sub(interval)
+            .expect("no underflow since block time is only 600 secs");
+
+        Ok(Self {
+            electrum,
+            blockchain,
+            latest_block_height: BlockHeight::try_from(latest_block)?,
+            relay_fee,
+            last_sync,
+            sync_interval: interval,
+            script_history: Default::default(),
+            unconfirmed_transactions: Default::default(),
+        })
+    }
+
+    fn blockchain(&self) -> &ElectrumBlockchain {
+        &self.blockchain
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
+        self.blockchain.get_tx(txid)
+    }
+
+    fn update_state(&mut self, force_sync: bool) -> Result<()> {
+        let now = Instant::now();
+
+        if !force_sync && now < self.last_sync + self.sync_interval {
+            return Ok(());
         }
 
-        let history = self.script_history.entry(script).or_default();
+        self.last_sync = now;
+        self.update_latest_block()?;
+        self.relay_fee = fetch_relay_fee(&self.electrum)?;
+        self.update_script_histories()?;
+        self.rebroadcast_dropped_transactions();
 
-        let history_of_tx = history
-            .iter()
-            .filter(|entry| entry.tx_hash == txid)
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        match history_of_tx.as_slice() {
-            [] => Ok(ScriptStatus::Unseen),
-            [remaining @ .., last] => {
-                if !remaining.is_empty() {
-                    tracing::warn!("Found more than a single history entry for script. This is highly unexpected and those history entries will be ignored")
-                }
+    fn rebroadcast_dropped_transactions(&mut self) {
+        let script_history = &self.script_history;
+        let latest_block_height = self.latest_block_height;
 
-                if last.height <= 0 {
-                    Ok(ScriptStatus::InMempool)
-                } else {
-                    Ok(ScriptStatus::Confirmed(
-                        Confirmed::from_inclusion_and_latest_block(
-                            u32::try_from(last.height)?,
-                            u32::from(self.latest_block_height),
-                        ),
-                    ))
-                }
-            }
+        let dropped = self.unconfirmed_transactions.take_dropped(|transaction| {
+            let history = script_history
+                .get(&transaction.output[0].script_pubkey)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            status_from_history(transaction.txid(), history, latest_block_height)
+        });
+
+        for transaction in dropped {
+            let result = self.broadcast_transaction(&transaction);
+            self.unconfirmed_transactions
+                .rebroadcast(&transaction, result);
         }
     }
 
@@ -866,6 +1447,156 @@ impl Client {
     }
 }
 
+impl GetScriptStatus for Client {
+    fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
+    where
+        T: Watchable,
+    {
+        let txid = tx.id();
+        let script = tx.script();
+
+        if !self.script_history.contains_key(&script) {
+            self.script_history.insert(script.clone(), vec![]);
+
+            // When we first subscribe to a script we want to immediately fetch its status
+            // Otherwise we would have to wait for the next sync interval, which can take a minute
+            // This would result in potentially inaccurate status updates until that next sync interval is hit
+            self.update_state(true)?;
+        } else {
+            self.update_state(false)?;
+        }
+
+        let history = self.script_history.entry(script).or_default();
+
+        status_from_history(txid, history, self.latest_block_height)
+    }
+}
+
+impl BroadcastTransaction for Client {
+    fn broadcast_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        Ok(self.blockchain.broadcast(transaction)?)
+    }
+
+    fn rebroadcast_until_confirmed(&mut self, transaction: Transaction) {
+        // the history of the script tells whether the transaction was dropped
+        self.script_history
+            .entry(transaction.output[0].script_pubkey.clone())
+            .or_default();
+        self.unconfirmed_transactions.insert(transaction);
+    }
+}
+
+/// Our transactions that are broadcast again until they are confirmed.
+#[derive(Debug, Default)]
+struct UnconfirmedTransactions(HashMap<Txid, Transaction>);
+
+impl UnconfirmedTransactions {
+    fn insert(&mut self, transaction: Transaction) {
+        self.0.insert(transaction.txid(), transaction);
+    }
+
+    /// Forgets the transactions that got confirmed and returns those the
+    /// network no longer knows about, which have to be broadcast again.
+    fn take_dropped(
+        &mut self,
+        status_of: impl Fn(&Transaction) -> Result<ScriptStatus>,
+    ) -> Vec<Transaction> {
+        let mut dropped = Vec::new();
+
+        self.0
+            .retain(|_, transaction| match status_of(transaction) {
+                Ok(ScriptStatus::Unseen) => {
+                    dropped.push(transaction.clone());
+                    true
+                }
+                Ok(ScriptStatus::Confirmed(_)) => false,
+                Ok(ScriptStatus::InMempool | ScriptStatus::Retrying) | Err(_) => true,
+            });
+
+        dropped
+    }
+
+    /// Records the result of broadcasting a dropped transaction again.
+    fn rebroadcast(&mut self, transaction: &Transaction, result: Result<()>) {
+        let txid = transaction.txid();
+
+        match result {
+            Ok(()) => {
+                tracing::info!(%txid, "Rebroadcast Bitcoin transaction that was not seen by the network")
+            }
+            Err(error) if is_already_in_chain(&error) => {
+                tracing::info!(%txid, "Bitcoin transaction is already in the chain");
+                self.0.remove(&txid);
+            }
+            Err(error) if can_never_confirm(&error) => {
+                tracing::warn!(%txid, "Bitcoin transaction conflicts with the chain, no longer rebroadcasting it: {:#}", error);
+                self.0.remove(&txid);
+            }
+            Err(error) => {
+                tracing::warn!(%txid, "Failed to rebroadcast Bitcoin transaction: {:#}", error);
+            }
+        }
+    }
+}
+
+fn is_already_in_chain(error: &anyhow::Error) -> bool {
+    matches!(
+        parse_rpc_error_code(error),
+        Ok(code) if code == i64::from(RpcErrorCode::RpcVerifyAlreadyInChain)
+    )
+}
+
+/// Whether the node rejected the transaction because its inputs were already
+/// spent by another transaction in the chain or the mempool.
+fn can_never_confirm(error: &anyhow::Error) -> bool {
+    match parse_rpc_error_code(error) {
+        Ok(code) if code == i64::from(RpcErrorCode::RpcVerifyError) => true,
+        Ok(code) if code == i64::from(RpcErrorCode::RpcVerifyRejected) => {
+            let message = format!("{:#}", error);
+
+            ["txn-mempool-conflict", "missingorspent", "Missing inputs"]
+                .iter()
+                .any(|reason| message.contains(reason))
+        }
+        _ => false,
+    }
+}
+
+/// Derives the status of a transaction from the latest history of its script.
+///
+/// The history is fetched again on every sync, so a transaction that was
+/// reorged out of the chain is reported as in the mempool or unseen again.
+fn status_from_history(
+    txid: Txid,
+    history: &[GetHistoryRes],
+    latest_block_height: BlockHeight,
+) -> Result<ScriptStatus> {
+    let history_of_tx = history
+        .iter()
+        .filter(|entry| entry.tx_hash == txid)
+        .collect::<Vec<_>>();
+
+    match history_of_tx.as_slice() {
+        [] => Ok(ScriptStatus::Unseen),
+        [remaining @ .., last] => {
+            if !remaining.is_empty() {
+                tracing::warn!("Found more than a single history entry for script. This is highly unexpected and those history entries will be ignored")
+            }
+
+            if last.height <= 0 {
+                Ok(ScriptStatus::InMempool)
+            } else {
+                Ok(ScriptStatus::Confirmed(
+                    Confirmed::from_inclusion_and_latest_block(
+                        u32::try_from(last.height)?,
+                        u32::from(latest_block_height),
+                    ),
+                ))
+            }
+        }
+    }
+}
+
 impl EstimateFeeRate for Client {
     fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
         // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L213
@@ -877,13 +1608,20 @@ impl EstimateFeeRate for Client {
     }
 
     fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
-        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
-        // Returned fee is in BTC/kb
-        let relay_fee = bitcoin::Amount::from_btc(self.electrum.relay_fee()?)?;
-        Ok(relay_fee)
+        Ok(self.relay_fee)
     }
 }
 
+fn fetch_relay_fee(electrum: &bdk::electrum_client::Client) -> Result<Amount> {
+    // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
+    // Returned fee is in BTC/kb
+    let relay_fee = electrum
+        .relay_fee()
+        .context("Failed to fetch the min relay fee")?;
+
+    Ok(Amount::from_btc(relay_fee)?)
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ScriptStatus {
     Unseen,
@@ -999,8 +1737,11 @@ impl fmt::Display for ScriptStatus {
 mod tests {
     use super::*;
     use crate::bitcoin::{PublicKey, TxLock};
+    use crate::seed::{Seed, SEED_LENGTH};
     use crate::tracing_ext::capture_logs;
+    use bdk::database::MemoryDatabase;
     use bitcoin::hashes::Hash;
+    use futures::FutureExt;
     use proptest::prelude::*;
     use tracing::level_filters::LevelFilter;
 
@@ -1241,6 +1982,355 @@ mod tests {
         assert!(amount.to_sat() > 0);
     }
 
+    #[tokio::test]
+    async fn given_estimate_above_max_fee_rate_uses_max_fee_rate() {
+        let wallet = WalletBuilder::new(0)
+            .with_fees(1_000.0, 1)
+            .with_fee_rate_limits(10, 1)
+            .build();
+
+        // 400 weight = 100 vbyte
+        let fee = wallet
+            .estimate_fee(400, bitcoin::Amount::ONE_BTC)
+            .await
+            .unwrap();
+
+        assert_eq!(fee, bitcoin::Amount::from_sat(1_000));
+    }
+
+    #[tokio::test]
+    async fn given_negative_estimate_uses_fallback_fee_rate() {
+        // electrum servers return -1 if they cannot estimate the fee rate
+        let wallet = WalletBuilder::new(0)
+            .with_fees(-1.0, 1)
+            .with_fee_rate_limits(100, 5)
+            .build();
+
+        // 400 weight = 100 vbyte
+        let fee = wallet
+            .estimate_fee(400, bitcoin::Amount::ONE_BTC)
+            .await
+            .unwrap();
+
+        assert_eq!(fee, bitcoin::Amount::from_sat(500));
+    }
+
+    #[test]
+    fn reorged_transaction_is_reported_as_unconfirmed_again() {
+        let txid = Txid::all_zeros();
+        let entry = |height| GetHistoryRes {
+            height,
+            tx_hash: txid,
+            fee: None,
+        };
+        let tip = BlockHeight::new(110);
+
+        let confirmed = status_from_history(txid, &[entry(105)], tip).unwrap();
+        let back_in_mempool = status_from_history(txid, &[entry(0)], tip).unwrap();
+        let dropped = status_from_history(txid, &[], tip).unwrap();
+
+        assert_eq!(
+            confirmed,
+            ScriptStatus::Confirmed(Confirmed::from_inclusion_and_latest_block(105, 110))
+        );
+        assert_eq!(back_in_mempool, ScriptStatus::InMempool);
+        assert_eq!(dropped, ScriptStatus::Unseen);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscription_follows_the_transaction_through_a_reorg() {
+        let chain = FakeChain::default();
+        let wallet = WalletBuilder::new(50_000).build_with_chain(chain.clone());
+        let wait_for = |subscription: &Subscription, expected: fn(&ScriptStatus) -> bool| {
+            let subscription = subscription.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(60), subscription.wait_until(expected))
+                    .await
+                    .expect("subscription to be updated")
+                    .unwrap()
+            }
+        };
+
+        let (txid, subscription) = wallet.broadcast(transaction(1), "lock").await.unwrap();
+        wait_for(&subscription, |status| status == &ScriptStatus::InMempool).await;
+
+        chain.confirm(txid);
+        wait_for(&subscription, |status| status.is_confirmed_with(1_u32)).await;
+
+        chain.reorg(1);
+        wait_for(&subscription, |status| status == &ScriptStatus::InMempool).await;
+
+        chain.confirm(txid);
+        chain.mine(1);
+        wait_for(&subscription, |status| status.is_confirmed_with(2_u32)).await;
+    }
+
+    #[test]
+    fn only_transactions_dropped_by_the_network_are_rebroadcast() {
+        let dropped = transaction(1);
+        let in_mempool = transaction(2);
+        let confirmed = transaction(3);
+        let mut unconfirmed_transactions = UnconfirmedTransactions::default();
+        for tx in [dropped.clone(), in_mempool.clone(), confirmed.clone()] {
+            unconfirmed_transactions.insert(tx);
+        }
+
+        let to_rebroadcast = unconfirmed_transactions.take_dropped(|tx| {
+            Ok(match tx.txid() {
+                txid if txid == dropped.txid() => ScriptStatus::Unseen,
+                txid if txid == in_mempool.txid() => ScriptStatus::InMempool,
+                _ => ScriptStatus::from_confirmations(5),
+            })
+        });
+
+        assert_eq!(to_rebroadcast, vec![dropped.clone()]);
+        assert!(unconfirmed_transactions.0.contains_key(&dropped.txid()));
+        assert!(unconfirmed_transactions.0.contains_key(&in_mempool.txid()));
+        assert!(!unconfirmed_transactions.0.contains_key(&confirmed.txid()));
+    }
+
+    #[tokio::test]
+    async fn rejected_broadcast_reports_the_rpc_error() {
+        let chain = FakeChain::default();
+        let wallet = WalletBuilder::new(50_000).build_with_chain(chain.clone());
+        let tx = transaction(1);
+        chain.reject_broadcasts(RpcErrorCode::RpcVerifyRejected, "min relay fee not met");
+
+        let error = wallet.broadcast(tx.clone(), "lock").await.unwrap_err();
+
+        assert_eq!(
+            parse_rpc_error_code(&error).unwrap(),
+            i64::from(RpcErrorCode::RpcVerifyRejected)
+        );
+        assert_eq!(chain.broadcasts(tx.txid()), 0);
+
+        chain.accept_broadcasts();
+        chain.confirm(tx.txid());
+
+        wallet
+            .broadcast(tx, "lock")
+            .await
+            .expect("a transaction already in the chain counts as broadcast");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_transaction_is_rebroadcast_until_it_conflicts() {
+        let chain = FakeChain::default();
+        let wallet = WalletBuilder::new(50_000).build_with_chain(chain.clone());
+        let (txid, _subscription) = wallet.broadcast(transaction(1), "lock").await.unwrap();
+
+        chain.drop_from_mempool(txid);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        assert_eq!(chain.broadcasts(txid), 2);
+
+        chain.drop_from_mempool(txid);
+        chain.reject_broadcasts(RpcErrorCode::RpcVerifyRejected, "txn-mempool-conflict");
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        chain.accept_broadcasts();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        assert_eq!(
+            chain.broadcasts(txid),
+            2,
+            "conflicting transaction is no longer rebroadcast"
+        );
+    }
+
+    #[test]
+    fn only_conflicting_transactions_can_never_confirm() {
+        let conflict = rpc_error(RpcErrorCode::RpcVerifyRejected, "txn-mempool-conflict");
+        let missing_inputs = rpc_error(
+            RpcErrorCode::RpcVerifyError,
+            "bad-txns-inputs-missingorspent",
+        );
+        let fee_too_low = rpc_error(RpcErrorCode::RpcVerifyRejected, "min relay fee not met");
+
+        assert!(can_never_confirm(&conflict));
+        assert!(can_never_confirm(&missing_inputs));
+        assert!(!can_never_confirm(&fee_too_low));
+    }
+
+    fn transaction(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value,
+                script_pubkey: Script::new_op_return(&u64::to_le_bytes(value)),
+            }],
+        }
+    }
+
+    #[test]
+    fn cpfp_child_pays_for_the_whole_package() {
+        let fee_rate = FeeRate::from_sat_per_vb(10.0);
+
+        let child_fee = cpfp_fee(Amount::from_sat(200), 200, fee_rate).unwrap();
+
+        // 10 sat/vB for the parent's 200 vB and the child's 110 vB
+        assert_eq!(child_fee, Amount::from_sat(2_900));
+    }
+
+    #[test]
+    fn no_cpfp_if_parent_already_pays_the_fee_rate() {
+        let fee_rate = FeeRate::from_sat_per_vb(10.0);
+
+        assert_eq!(cpfp_fee(Amount::from_sat(2_000), 200, fee_rate), None);
+        assert_eq!(cpfp_fee(Amount::from_sat(5_000), 200, fee_rate), None);
+    }
+
+    #[test]
+    fn electrum_config_uses_the_given_socks5_proxy() {
+        let proxy = "127.0.0.1:9050".parse().unwrap();
+
+        let with_proxy = electrum_config(Some(proxy));
+        let without_proxy = electrum_config(None);
+
+        assert_eq!(
+            with_proxy
+                .socks5()
+                .as_ref()
+                .map(|socks5| socks5.addr.as_str()),
+            Some("127.0.0.1:9050")
+        );
+        assert!(without_proxy.socks5().is_none());
+    }
+
+    #[test]
+    fn same_seed_derives_the_same_addresses_without_the_database() {
+        let xprivkey = |seed: [u8; SEED_LENGTH]| {
+            Seed::from(seed)
+                .derive_extended_private_key(Network::Testnet)
+                .unwrap()
+        };
+        // a fresh database, as after deleting the wallet directory or on a new machine
+        let first_address = |xprivkey| {
+            bip84_wallet(xprivkey, Network::Testnet, MemoryDatabase::new())
+                .unwrap()
+                .get_address(AddressIndex::Peek(0))
+                .unwrap()
+                .address
+        };
+
+        assert_eq!(
+            first_address(xprivkey([7u8; SEED_LENGTH])),
+            first_address(xprivkey([7u8; SEED_LENGTH]))
+        );
+        assert_ne!(
+            first_address(xprivkey([7u8; SEED_LENGTH])),
+            first_address(xprivkey([8u8; SEED_LENGTH]))
+        );
+    }
+
+    #[test]
+    fn given_estimate_within_limits_uses_estimate() {
+        let fee_rate = clamp_fee_rate(
+            FeeRate::from_sat_per_vb(10.0),
+            FeeRate::from_sat_per_vb(1.0),
+            FeeRate::from_sat_per_vb(100.0),
+        );
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(10.0));
+    }
+
+    #[test]
+    fn given_estimate_above_max_uses_max() {
+        let fee_rate = clamp_fee_rate(
+            FeeRate::from_sat_per_vb(1_000.0),
+            FeeRate::from_sat_per_vb(1.0),
+            FeeRate::from_sat_per_vb(100.0),
+        );
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(100.0));
+    }
+
+    #[test]
+    fn given_estimate_below_min_uses_min() {
+        let fee_rate = clamp_fee_rate(
+            FeeRate::from_sat_per_vb(0.5),
+            FeeRate::from_sat_per_vb(1.0),
+            FeeRate::from_sat_per_vb(100.0),
+        );
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(1.0));
+    }
+
+    #[test]
+    fn given_min_above_max_uses_min() {
+        let fee_rate = clamp_fee_rate(
+            FeeRate::from_sat_per_vb(50.0),
+            FeeRate::from_sat_per_vb(20.0),
+            FeeRate::from_sat_per_vb(10.0),
+        );
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(20.0));
+    }
+
+    #[test]
+    fn given_estimate_below_min_relay_fee_rate_uses_min_relay_fee_rate() {
+        let wallet = WalletBuilder::new(0).build();
+        // 2000 sat/kvB
+        let client = StaticFeeRate {
+            fee_rate: FeeRate::from_sat_per_vb(0.5),
+            min_relay_fee: bitcoin::Amount::from_sat(2_000),
+        };
+
+        assert_eq!(wallet.fee_rate(&client), FeeRate::from_sat_per_vb(2.0));
+    }
+
+    #[tokio::test]
+    async fn given_only_dust_returns_amount_0() {
+        let wallet = WalletBuilder::new(DUST_AMOUNT - 1)
+            .with_fees(1.0, 1)
+            .build();
+        let amount = wallet.max_giveable(TxLock::script_size()).await.unwrap();
+
+        assert_eq!(amount, Amount::ZERO);
+    }
+
+    #[tokio::test]
+    async fn max_giveable_of_many_utxos_can_be_locked() {
+        for num_utxos in [1, 10, 100] {
+            let wallet = WalletBuilder::new(10_000)
+                .with_fees(5.0, 1)
+                .with_num_utxos(num_utxos)
+                .build();
+
+            let amount = wallet.max_giveable(TxLock::script_size()).await.unwrap();
+            let fee_of_all_inputs = Amount::from_sat(u64::from(num_utxos) * 10_000) - amount;
+            let (A, B) = (PublicKey::random(), PublicKey::random());
+            let change = wallet.new_address().await.unwrap();
+            let tx_lock = TxLock::new(&wallet, amount, A, B, change).await.unwrap();
+            let tx = wallet.sign_and_finalize(tx_lock.into()).await;
+
+            assert!(
+                tx.is_ok(),
+                "{} UTXOs: max giveable {} cannot be locked",
+                num_utxos,
+                amount
+            );
+            assert!(
+                fee_of_all_inputs > Amount::from_sat(u64::from(num_utxos) * 5 * 68),
+                "{} UTXOs: fee {} does not pay for every input",
+                num_utxos,
+                fee_of_all_inputs
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn given_higher_fee_rate_max_giveable_is_lower() {
+        let cheap = WalletBuilder::new(100_000).with_fees(1.0, 1).build();
+        let expensive = WalletBuilder::new(100_000).with_fees(50.0, 1).build();
+
+        let cheap = cheap.max_giveable(TxLock::script_size()).await.unwrap();
+        let expensive = expensive.max_giveable(TxLock::script_size()).await.unwrap();
+
+        assert!(expensive < cheap);
+    }
+
     /// This test ensures that the relevant script output of the transaction
     /// created out of the PSBT is at index 0. This is important because
     /// subscriptions to the transaction are on index `0` when broadcasting the
@@ -1278,6 +2368,75 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn transactions_never_spend_reserved_utxos() {
+        let wallet = WalletBuilder::new(50_000).with_num_utxos(3).build();
+
+        let first = wallet
+            .send_to_address(
+                wallet.new_address().await.unwrap(),
+                Amount::from_sat(10_000),
+                None,
+            )
+            .await
+            .unwrap();
+        let second = wallet
+            .send_to_address(
+                wallet.new_address().await.unwrap(),
+                Amount::from_sat(10_000),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let inputs = |psbt: &PartiallySignedTransaction| {
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .map(|input| input.previous_output)
+                .collect::<HashSet<_>>()
+        };
+        assert!(inputs(&first).is_disjoint(&inputs(&second)));
+
+        let single_utxo_wallet = WalletBuilder::new(50_000).with_num_utxos(1).build();
+        assert_eq!(
+            wallet.max_giveable(TxLock::script_size()).await.unwrap(),
+            single_utxo_wallet
+                .max_giveable(TxLock::script_size())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn released_utxos_can_be_spent_again() {
+        let wallet = WalletBuilder::new(50_000).build();
+        let send = || async {
+            wallet
+                .send_to_address(
+                    wallet.new_address().await.unwrap(),
+                    Amount::from_sat(10_000),
+                    None,
+                )
+                .await
+        };
+
+        let aborted = send().await.unwrap();
+        assert!(send().await.is_err(), "the only utxo is reserved");
+
+        wallet
+            .release_utxos(
+                aborted
+                    .unsigned_tx
+                    .input
+                    .iter()
+                    .map(|input| input.previous_output),
+            )
+            .await;
+
+        assert!(send().await.is_ok());
+    }
+
     #[tokio::test]
     async fn can_override_change_address() {
         let wallet = WalletBuilder::new(50_000).build();
@@ -1304,6 +2463,22 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn wait_until_final_waits_for_configured_finality_confirmations() {
+        let (sender, receiver) = watch::channel(ScriptStatus::Unseen);
+        let subscription = Subscription {
+            receiver,
+            finality_confirmations: 3,
+            txid: Txid::from_hash(bitcoin::hashes::sha256d::Hash::all_zeros()),
+        };
+
+        sender.send(confs(2)).unwrap();
+        assert!(subscription.wait_until_final().now_or_never().is_none());
+
+        sender.send(confs(3)).unwrap();
+        assert!(subscription.wait_until_final().now_or_never().is_some());
+    }
+
     #[test]
     fn printing_status_change_doesnt_spam_on_same_status() {
         let writer = capture_logs(LevelFilter::DEBUG);
@@ -1339,6 +2514,22 @@ DEBUG swap::bitcoin::wallet: Bitcoin transaction status changed txid=00000000000
         ScriptStatus::from_confirmations(confirmations)
     }
 
+    #[test]
+    fn electrum_server_on_the_wallet_network_is_accepted() {
+        let genesis_hash = genesis_block(Network::Testnet).block_hash();
+
+        ensure_same_network(genesis_hash, Network::Testnet).unwrap();
+    }
+
+    #[test]
+    fn electrum_server_on_another_network_is_rejected() {
+        let testnet_genesis_hash = genesis_block(Network::Testnet).block_hash();
+
+        let error = ensure_same_network(testnet_genesis_hash, Network::Bitcoin).unwrap_err();
+
+        assert!(error.to_string().contains("not on the bitcoin network"));
+    }
+
     proptest::proptest! {
         #[test]
         fn funding_never_fails_with_insufficient_funds(funding_amount in 3000u32.., num_utxos in 1..5u8, sats_per_vb in 1.0..500.0f32, key in crate::proptest::bitcoin::extended_priv_key(), alice in crate::proptest::ecdsa_fun::point(), bob in crate::proptest::ecdsa_fun::point()) {
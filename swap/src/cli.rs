@@ -1,15 +1,23 @@
 mod behaviour;
+pub mod bump_fee;
 pub mod cancel_and_refund;
+pub mod cleanup;
 pub mod command;
 mod event_loop;
 mod list_sellers;
+pub mod output;
+pub mod preflight;
+mod request_quote;
 pub mod tracing;
 pub mod transport;
 
 pub use behaviour::{Behaviour, OutEvent};
+pub use bump_fee::bump_lock_fee;
 pub use cancel_and_refund::{cancel, cancel_and_refund, refund};
-pub use event_loop::{EventLoop, EventLoopHandle};
-pub use list_sellers::{list_sellers, Seller, Status as SellerStatus};
+pub use cleanup::{cleanup, Cleanup};
+pub use event_loop::{EventLoop, EventLoopHandle, RequestTimedOut};
+pub use list_sellers::{best_seller, list_sellers, Seller, Status as SellerStatus};
+pub use request_quote::{best_quote, request_quote};
 
 #[cfg(test)]
 mod tests {
@@ -17,14 +25,15 @@ mod tests {
     use crate::asb;
     use crate::asb::rendezvous::RendezvousNode;
     use crate::cli::list_sellers::{Seller, Status};
+    use crate::libp2p_ext::MultiAddrExt;
     use crate::network::quote;
-    use crate::network::quote::BidQuote;
+    use crate::network::quote::{BidQuote, PROTOCOL_VERSION};
     use crate::network::rendezvous::XmrBtcNamespace;
     use crate::network::test::{new_swarm, SwarmExt};
     use futures::StreamExt;
     use libp2p::multiaddr::Protocol;
     use libp2p::request_response::RequestResponseEvent;
-    use libp2p::swarm::{AddressScore, NetworkBehaviourEventProcess};
+    use libp2p::swarm::{AddressScore, NetworkBehaviourEventProcess, SwarmEvent};
     use libp2p::{identity, rendezvous, Multiaddr, PeerId};
     use std::collections::HashSet;
     use std::iter::FromIterator;
@@ -55,6 +64,123 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn list_sellers_pages_through_all_registrations() {
+        let namespace = XmrBtcNamespace::Mainnet;
+        let (rendezvous_address, rendezvous_peer_id) = setup_rendezvous_point().await;
+        let mut expected_sellers = HashSet::new();
+        for _ in 0..3 {
+            expected_sellers
+                .insert(setup_asb(rendezvous_peer_id, &rendezvous_address, namespace).await);
+        }
+
+        let list_sellers = list_sellers::list_sellers_in_pages_of(
+            rendezvous_peer_id,
+            rendezvous_address,
+            namespace,
+            0,
+            identity::Keypair::generate_ed25519(),
+            2,
+        );
+        let sellers = tokio::time::timeout(Duration::from_secs(15), list_sellers)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(HashSet::<Seller>::from_iter(sellers), expected_sellers);
+    }
+
+    #[tokio::test]
+    async fn request_quote_returns_the_sellers_quote() {
+        let static_quote = static_quote();
+        let asb_address = spawn_quoting_asb(static_quote).await;
+
+        let quote = request_quote(
+            vec![asb_address],
+            0,
+            identity::Keypair::generate_ed25519(),
+            Duration::from_secs(15),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote, static_quote);
+    }
+
+    #[tokio::test]
+    async fn request_quote_succeeds_if_one_of_the_sellers_addresses_is_reachable() {
+        let static_quote = static_quote();
+        let asb_address = spawn_quoting_asb(static_quote).await;
+        let asb_peer_id = asb_address.extract_peer_id().unwrap();
+        let unresolvable = "/dns4/seller.invalid/tcp/9939"
+            .parse::<Multiaddr>()
+            .unwrap()
+            .with(Protocol::P2p(asb_peer_id.into()));
+
+        let quote = request_quote(
+            vec![unresolvable, asb_address],
+            0,
+            identity::Keypair::generate_ed25519(),
+            Duration::from_secs(15),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote, static_quote);
+    }
+
+    #[tokio::test]
+    async fn request_quote_fails_if_the_seller_is_unreachable() {
+        let seller_peer_id = PeerId::random();
+        let seller = "/ip4/127.0.0.1/tcp/1"
+            .parse::<Multiaddr>()
+            .unwrap()
+            .with(Protocol::P2p(seller_peer_id.into()));
+
+        let result = request_quote(
+            vec![seller],
+            0,
+            identity::Keypair::generate_ed25519(),
+            Duration::from_secs(15),
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+
+    fn static_quote() -> BidQuote {
+        BidQuote {
+            price: bitcoin::Amount::from_sat(1337),
+            min_quantity: bitcoin::Amount::from_sat(42),
+            max_quantity: bitcoin::Amount::from_sat(9001),
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Starts an ASB that answers every quote request with `static_quote`
+    /// and returns its address.
+    async fn spawn_quoting_asb(static_quote: BidQuote) -> Multiaddr {
+        let mut asb = new_swarm(|_, _| quote::asb());
+        let asb_address = asb.listen_on_tcp_localhost().await;
+        let asb_peer_id = *asb.local_peer_id();
+
+        tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                    message: quote::Message::Request { channel, .. },
+                    ..
+                }) = asb.select_next_some().await
+                {
+                    asb.behaviour_mut()
+                        .send_response(channel, static_quote)
+                        .unwrap();
+                }
+            }
+        });
+
+        asb_address.with(Protocol::P2p(asb_peer_id.into()))
+    }
+
     async fn setup_rendezvous_point() -> (Multiaddr, PeerId) {
         let mut rendezvous_node = new_swarm(|_, _| RendezvousPointBehaviour::default());
         let rendezvous_address = rendezvous_node.listen_on_tcp_localhost().await;
@@ -78,6 +204,7 @@ mod tests {
             price: bitcoin::Amount::from_sat(1337),
             min_quantity: bitcoin::Amount::from_sat(42),
             max_quantity: bitcoin::Amount::from_sat(9001),
+            protocol_version: PROTOCOL_VERSION,
         };
 
         let mut asb = new_swarm(|_, identity| {
@@ -114,6 +241,7 @@ mod tests {
         Seller {
             multiaddr: asb_address.with(Protocol::P2p(asb_peer_id.into())),
             status: Status::Online(static_quote),
+            peer_id: asb_peer_id,
         }
     }
 
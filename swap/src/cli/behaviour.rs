@@ -1,14 +1,14 @@
 use crate::network::quote::BidQuote;
 use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::swap_setup::bob;
-use crate::network::{encrypted_signature, quote, redial, transfer_proof};
+use crate::network::{encrypted_signature, heartbeat, quote, redial, transfer_proof};
 use crate::protocol::bob::State2;
 use crate::{bitcoin, env};
 use anyhow::{anyhow, Error, Result};
 use libp2p::core::Multiaddr;
 use libp2p::identify::{Identify, IdentifyConfig, IdentifyEvent};
-use libp2p::ping::{Ping, PingConfig, PingEvent};
-use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::ping::{Ping, PingEvent, PingFailure, PingSuccess};
+use libp2p::request_response::{OutboundFailure, RequestId, ResponseChannel};
 use libp2p::{identity, NetworkBehaviour, PeerId};
 use std::sync::Arc;
 use std::time::Duration;
@@ -28,9 +28,21 @@ pub enum OutEvent {
     EncryptedSignatureAcknowledged {
         id: RequestId,
     },
+    EncryptedSignatureFailed {
+        id: RequestId,
+        error: OutboundFailure,
+    },
     AllRedialAttemptsExhausted {
         peer: PeerId,
     },
+    HeartbeatAnswered {
+        peer: PeerId,
+        rtt: Duration,
+    },
+    HeartbeatMissed {
+        peer: PeerId,
+        failure: PingFailure,
+    },
     Failure {
         peer: PeerId,
         error: Error,
@@ -68,9 +80,9 @@ pub struct Behaviour {
     pub redial: redial::Behaviour,
     pub identify: Identify,
 
-    /// Ping behaviour that ensures that the underlying network connection is
-    /// still alive. If the ping fails a connection close event will be
-    /// emitted that is picked up as swarm event.
+    /// Heartbeats that ensure that the underlying network connection is still
+    /// alive. If too many heartbeats are missed a connection close event will
+    /// be emitted that is picked up as swarm event.
     ping: Ping,
 }
 
@@ -91,8 +103,12 @@ impl Behaviour {
             swap_setup: bob::Behaviour::new(env_config, bitcoin_wallet),
             transfer_proof: transfer_proof::bob(),
             encrypted_signature: encrypted_signature::bob(),
-            redial: redial::Behaviour::new(alice, Duration::from_secs(2)),
-            ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+            redial: redial::Behaviour::new(
+                alice,
+                Duration::from_secs(2),
+                env_config.bob_redial_max_elapsed_time,
+            ),
+            ping: heartbeat::behaviour(&env_config),
             identify: Identify::new(identifyConfig),
         }
     }
@@ -106,8 +122,19 @@ impl Behaviour {
 }
 
 impl From<PingEvent> for OutEvent {
-    fn from(_: PingEvent) -> Self {
-        OutEvent::Other
+    fn from(event: PingEvent) -> Self {
+        match event.result {
+            Ok(PingSuccess::Ping { rtt }) => OutEvent::HeartbeatAnswered {
+                peer: event.peer,
+                rtt,
+            },
+            // The peer does not take part in heartbeats, nothing to track
+            Ok(PingSuccess::Pong) | Err(PingFailure::Unsupported) => OutEvent::Other,
+            Err(failure) => OutEvent::HeartbeatMissed {
+                peer: event.peer,
+                failure,
+            },
+        }
     }
 }
 
@@ -0,0 +1,57 @@
+use crate::bitcoin::wallet::fee_rate_from_sat_per_vb;
+use crate::bitcoin::{Txid, Wallet};
+use crate::protocol::bob::BobState;
+use crate::protocol::Database;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Speeds up the unconfirmed lock transaction of a swap by spending its change
+/// output in a child transaction that pays the given fee rate for both.
+///
+/// Uses the current fee rate estimate if no fee rate is given. Returns the ID
+/// of the child transaction.
+pub async fn bump_lock_fee(
+    swap_id: Uuid,
+    fee_rate_sat_vb: Option<u64>,
+    bitcoin_wallet: Arc<Wallet>,
+    db: Arc<dyn Database + Send + Sync>,
+) -> Result<Txid> {
+    let state = db.get_state(swap_id).await?.try_into()?;
+
+    let tx_lock_id = match state {
+        BobState::BtcLocked { state3, .. } => state3.tx_lock_id(),
+        BobState::XmrLockProofReceived { state, .. } => state.tx_lock_id(),
+
+        BobState::Started { .. }
+        | BobState::SwapSetupCompleted(_)
+        | BobState::XmrLocked(_)
+        | BobState::EncSigSent(_)
+        | BobState::BtcRedeemed(_)
+        | BobState::CancelTimelockExpired(_)
+        | BobState::BtcCancelled(_)
+        | BobState::BtcRefunded(_)
+        | BobState::XmrRedeemed { .. }
+        | BobState::BtcPunished { .. }
+        | BobState::SafelyAborted => bail!(
+            "Cannot bump the fee of the lock transaction of swap {} because it is in state {}, in which the lock transaction is either not published or already confirmed",
+            swap_id,
+            state
+        ),
+    };
+
+    bitcoin_wallet.sync().await?;
+
+    let fee_rate = match fee_rate_sat_vb {
+        Some(fee_rate_sat_vb) => fee_rate_from_sat_per_vb(fee_rate_sat_vb),
+        None => bitcoin_wallet.fee_rate_estimate().await,
+    };
+
+    tracing::info!(%swap_id, %tx_lock_id, sat_per_vb = %fee_rate.as_sat_per_vb(), "Bumping the fee of the lock transaction");
+
+    let txid = bitcoin_wallet.bump_fee(tx_lock_id, fee_rate).await?;
+
+    tracing::info!(%txid, "Published transaction that pays for the lock transaction");
+
+    Ok(txid)
+}
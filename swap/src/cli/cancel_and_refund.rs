@@ -1,8 +1,8 @@
 use crate::bitcoin::wallet::Subscription;
-use crate::bitcoin::{parse_rpc_error_code, RpcErrorCode, Wallet};
+use crate::bitcoin::Wallet;
 use crate::protocol::bob::BobState;
 use crate::protocol::Database;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use bitcoin::Txid;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -12,14 +12,23 @@ pub async fn cancel_and_refund(
     bitcoin_wallet: Arc<Wallet>,
     db: Arc<dyn Database + Send + Sync>,
 ) -> Result<BobState> {
-    if let Err(err) = cancel(swap_id, bitcoin_wallet.clone(), db.clone()).await {
-        tracing::info!(%err, "Could not submit cancel transaction");
+    match cancel(swap_id, bitcoin_wallet.clone(), db.clone()).await {
+        Ok((txid, subscription, _)) => {
+            tracing::info!(%txid, "Waiting for the cancel transaction to be confirmed");
+            subscription.wait_until_final().await.context(
+                "Failed to wait for the cancel transaction to be confirmed. It has been published, running cancel-and-refund again is safe and continues with the refund",
+            )?;
+        }
+        Err(err) => {
+            // Most likely the cancel transaction was published by a previous
+            // attempt, in which case the refund below succeeds.
+            tracing::info!(%err, "Could not submit cancel transaction, continuing with the refund");
+        }
     };
 
-    let state = match refund(swap_id, bitcoin_wallet, db).await {
-        Ok(s) => s,
-        Err(e) => bail!(e),
-    };
+    let state = refund(swap_id, bitcoin_wallet, db).await.context(
+        "Failed to refund. Running cancel-and-refund again is safe, the refund can only pay to your refund address",
+    )?;
 
     tracing::info!("Refund transaction submitted");
     Ok(state)
@@ -55,20 +64,19 @@ pub async fn cancel(
 
     tracing::info!(%swap_id, "Manually cancelling swap");
 
-    let (txid, subscription) = match state6.submit_tx_cancel(bitcoin_wallet.as_ref()).await {
-        Ok(txid) => txid,
-        Err(err) => {
-            if let Ok(error_code) = parse_rpc_error_code(&err) {
-                tracing::debug!(%error_code, "parse rpc error");
-                if error_code == i64::from(RpcErrorCode::RpcVerifyAlreadyInChain) {
-                    tracing::info!("Cancel transaction has already been confirmed on chain");
-                } else if error_code == i64::from(RpcErrorCode::RpcVerifyError) {
-                    tracing::info!("General error trying to submit cancel transaction");
-                }
-            }
-            bail!(err);
-        }
-    };
+    bitcoin_wallet
+        .rebroadcast_until_confirmed(db.get_broadcast_transactions(swap_id).await?)
+        .await;
+
+    // A cancel transaction that is already in the chain counts as submitted
+    let (txid, subscription) = state6.submit_tx_cancel(bitcoin_wallet.as_ref()).await?;
+    db.insert_broadcast_transaction(swap_id, state6.signed_cancel_transaction()?)
+        .await?;
+
+    let mut summary = db.get_swap_summary(swap_id).await?.unwrap_or_default();
+    summary.tx_cancel_id = Some(txid);
+    summary.tx_cancel_fee = Some(state6.tx_cancel_fee);
+    db.insert_swap_summary(swap_id, summary).await?;
 
     let state = BobState::BtcCancelled(state6);
     db.insert_latest_state(swap_id, state.clone().into())
@@ -105,7 +113,19 @@ pub async fn refund(
     };
 
     tracing::info!(%swap_id, "Manually refunding swap");
-    state6.publish_refund_btc(bitcoin_wallet.as_ref()).await?;
+
+    bitcoin_wallet
+        .rebroadcast_until_confirmed(db.get_broadcast_transactions(swap_id).await?)
+        .await;
+
+    let txid = state6.publish_refund_btc(bitcoin_wallet.as_ref()).await?;
+    db.insert_broadcast_transaction(swap_id, state6.signed_refund_transaction()?)
+        .await?;
+
+    let mut summary = db.get_swap_summary(swap_id).await?.unwrap_or_default();
+    summary.tx_refund_id = Some(txid);
+    summary.tx_refund_fee = Some(state6.tx_refund_fee);
+    db.insert_swap_summary(swap_id, summary).await?;
 
     let state = BobState::BtcRefunded(state6);
     db.insert_latest_state(swap_id, state.clone().into())
@@ -0,0 +1,228 @@
+use crate::protocol::Database;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Files the Monero wallet RPC writes for the wallet a swap redeems into.
+const MONERO_WALLET_FILE_SUFFIXES: [&str; 3] = ["", ".keys", ".address.txt"];
+
+#[derive(Debug, PartialEq)]
+pub struct Cleanup {
+    /// Swaps that were removed, or would be removed on a dry run.
+    pub swaps: Vec<Uuid>,
+    /// Bytes freed on disk. A dry run only counts the wallet files because
+    /// compacting the database cannot be simulated.
+    pub reclaimed_bytes: u64,
+}
+
+/// Removes the database records and Monero wallet files of swaps that
+/// finished more than `older_than` ago and compacts the database.
+///
+/// Swaps that are not finished are never touched. Each swap is deleted from
+/// the database in a single transaction before its wallet files are removed,
+/// so an interrupted cleanup leaves at most some wallet files behind.
+pub async fn cleanup(
+    db: Arc<dyn Database + Send + Sync>,
+    data_dir: &Path,
+    older_than: Duration,
+    dry_run: bool,
+) -> Result<Cleanup> {
+    let cutoff = time::Duration::try_from(older_than)
+        .ok()
+        .and_then(|older_than| OffsetDateTime::now_utc().checked_sub(older_than))
+        .context("The cutoff for finished swaps is too far in the past")?;
+    let monero_wallet_dir = data_dir.join("monero").join("monero-data");
+    let db_files = [data_dir.join("sqlite"), data_dir.join("sqlite-wal")];
+    let db_size_before = total_size(&db_files).await?;
+
+    let mut cleanup = Cleanup {
+        swaps: vec![],
+        reclaimed_bytes: 0,
+    };
+
+    for (swap_id, state) in db.all().await? {
+        if !state.swap_finished() {
+            continue;
+        }
+
+        let finished_at = match db.history(swap_id).await?.last() {
+            Some((entered_at, _)) => *entered_at,
+            None => continue,
+        };
+        if finished_at > cutoff {
+            continue;
+        }
+
+        let wallet_files = MONERO_WALLET_FILE_SUFFIXES
+            .iter()
+            .map(|suffix| monero_wallet_dir.join(format!("{}{}", swap_id, suffix)))
+            .collect::<Vec<_>>();
+        let wallet_size = total_size(&wallet_files).await?;
+
+        if dry_run {
+            tracing::info!(%swap_id, %finished_at, "Would remove swap");
+        } else {
+            db.delete_swap(swap_id).await?;
+            for file in &wallet_files {
+                remove_if_exists(file).await?;
+            }
+            tracing::info!(%swap_id, %finished_at, "Removed swap");
+        }
+
+        cleanup.swaps.push(swap_id);
+        cleanup.reclaimed_bytes += wallet_size;
+    }
+
+    if !dry_run && !cleanup.swaps.is_empty() {
+        db.compact().await?;
+        let db_size_after = total_size(&db_files).await?;
+        cleanup.reclaimed_bytes += db_size_before.saturating_sub(db_size_after);
+    }
+
+    Ok(cleanup)
+}
+
+async fn total_size(files: &[PathBuf]) -> Result<u64> {
+    let mut total = 0;
+
+    for file in files {
+        match tokio::fs::metadata(file).await {
+            Ok(metadata) => total += metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", file.display())),
+        }
+    }
+
+    Ok(total)
+}
+
+async fn remove_if_exists(file: &Path) -> Result<()> {
+    match tokio::fs::remove_file(file).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", file.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::open_db;
+    use crate::protocol::bob::BobState;
+    use crate::protocol::SwapNotFinished;
+    use tempfile::{tempdir, TempDir};
+
+    #[tokio::test]
+    async fn dry_run_removes_nothing() -> Result<()> {
+        let (data_dir, db) = setup().await?;
+        let finished = insert_swap(&db, data_dir.path(), BobState::SafelyAborted).await?;
+
+        let cleanup = cleanup(db.clone(), data_dir.path(), Duration::ZERO, true).await?;
+
+        assert_eq!(cleanup.swaps, vec![finished]);
+        assert_eq!(cleanup.reclaimed_bytes, WALLET_FILE_SIZE * 2);
+        assert_eq!(
+            db.get_state(finished).await?,
+            BobState::SafelyAborted.into()
+        );
+        assert!(wallet_file(data_dir.path(), finished).exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_finished_swaps_are_removed() -> Result<()> {
+        let (data_dir, db) = setup().await?;
+        let finished = insert_swap(&db, data_dir.path(), BobState::SafelyAborted).await?;
+        let unfinished = insert_swap(&db, data_dir.path(), started()).await?;
+
+        let cleanup = cleanup(db.clone(), data_dir.path(), Duration::ZERO, false).await?;
+
+        assert_eq!(cleanup.swaps, vec![finished]);
+        assert!(cleanup.reclaimed_bytes >= WALLET_FILE_SIZE * 2);
+        assert!(db.get_states(finished).await?.is_empty());
+        assert!(!wallet_file(data_dir.path(), finished).exists());
+        assert_eq!(db.get_state(unfinished).await?, started().into());
+        assert!(wallet_file(data_dir.path(), unfinished).exists());
+
+        let error = db.delete_swap(unfinished).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SwapNotFinished>(),
+            Some(&SwapNotFinished(unfinished))
+        );
+        assert_eq!(db.get_state(unfinished).await?, started().into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recently_finished_swaps_are_kept() -> Result<()> {
+        let (data_dir, db) = setup().await?;
+        let finished = insert_swap(&db, data_dir.path(), BobState::SafelyAborted).await?;
+
+        let cleanup = cleanup(
+            db.clone(),
+            data_dir.path(),
+            Duration::from_secs(24 * 60 * 60),
+            false,
+        )
+        .await?;
+
+        assert!(cleanup.swaps.is_empty());
+        assert_eq!(
+            db.get_state(finished).await?,
+            BobState::SafelyAborted.into()
+        );
+
+        Ok(())
+    }
+
+    const WALLET_FILE_SIZE: u64 = 16;
+
+    async fn setup() -> Result<(TempDir, Arc<dyn Database + Send + Sync>)> {
+        let data_dir = tempdir()?;
+        std::fs::create_dir_all(data_dir.path().join("monero").join("monero-data"))?;
+        let db = open_db(data_dir.path().join("sqlite")).await?;
+
+        Ok((data_dir, db))
+    }
+
+    /// Records a swap in `state` and creates two of the files of its wallet.
+    async fn insert_swap(
+        db: &Arc<dyn Database + Send + Sync>,
+        data_dir: &Path,
+        state: BobState,
+    ) -> Result<Uuid> {
+        let swap_id = Uuid::new_v4();
+        db.insert_latest_state(swap_id, state.into()).await?;
+
+        let wallet_file = wallet_file(data_dir, swap_id);
+        std::fs::write(&wallet_file, [0; WALLET_FILE_SIZE as usize])?;
+        std::fs::write(
+            wallet_file.with_extension("keys"),
+            [0; WALLET_FILE_SIZE as usize],
+        )?;
+
+        Ok(swap_id)
+    }
+
+    fn wallet_file(data_dir: &Path, swap_id: Uuid) -> PathBuf {
+        data_dir
+            .join("monero")
+            .join("monero-data")
+            .join(swap_id.to_string())
+    }
+
+    fn started() -> BobState {
+        BobState::Started {
+            btc_amount: crate::bitcoin::Amount::from_sat(100_000),
+            change_address: "bc1qe4epnfklcaa0mun26yz5g8k24em5u9f92hy325"
+                .parse()
+                .unwrap(),
+        }
+    }
+}
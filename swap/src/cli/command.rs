@@ -1,15 +1,20 @@
-use crate::api::request::{Method, Request};
+use crate::api::request::{BuyXmrArgs, Method, Request};
 use crate::api::Context;
 use crate::bitcoin::{bitcoin_address, Amount};
+use crate::env;
+use crate::libp2p_ext::group_by_peer_id;
 use crate::monero;
 use crate::monero::monero_address;
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 use libp2p::core::Multiaddr;
+use libp2p::PeerId;
+use monero_rpc::wallet::BlockHeight;
 use std::ffi::OsString;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use structopt::{clap, StructOpt};
 use url::Url;
 use uuid::Uuid;
@@ -28,6 +33,8 @@ pub const DEFAULT_BITCOIN_CONFIRMATION_TARGET_TESTNET: usize = 1;
 
 const DEFAULT_TOR_SOCKS5_PORT: &str = "9050";
 
+const DEFAULT_QUOTE_TIMEOUT_SECS: &str = "30";
+
 /// Represents the result of parsing the command-line parameters.
 
 #[derive(Debug)]
@@ -39,6 +46,13 @@ pub enum ParseResult {
     ///
     /// The caller should exit the program with exit code 0.
     PrintAndExitZero { message: String },
+    /// Run a swap against a local regtest stack, see [`crate::selftest`].
+    #[cfg(feature = "selftest")]
+    Selftest {
+        data_dir: Option<PathBuf>,
+        debug: bool,
+        json: bool,
+    },
 }
 
 pub async fn parse_args_and_apply_defaults<I, T>(raw_args: I) -> Result<ParseResult>
@@ -62,11 +76,14 @@ where
     let data = args.data;
     let (context, request) = match args.cmd {
         CliCommand::BuyXmr {
-            seller: Seller { seller },
+            sellers,
+            quote_timeout,
             bitcoin,
             bitcoin_change_address,
             monero,
             monero_receive_address,
+            btc_amount,
+            count,
             tor,
         } => {
             let monero_receive_address =
@@ -74,38 +91,137 @@ where
             let bitcoin_change_address =
                 bitcoin_address::validate_is_testnet(bitcoin_change_address, is_testnet)?;
 
-            let request = Request::new(Method::BuyXmr {
-                seller,
-                bitcoin_change_address,
-                monero_receive_address,
-                swap_id: Uuid::new_v4(),
+            let Sellers {
+                sellers,
+                rendezvous_point,
+                seller_peer_id,
+            } = sellers;
+            let sellers = group_by_peer_id(sellers).context("Invalid seller address")?;
+
+            let request = match (count, rendezvous_point) {
+                (0, _) => bail!("The number of swaps must be at least 1"),
+                (1, Some(rendezvous_point)) => Request::new(Method::BuyXmrFromRendezvousPoint {
+                    rendezvous_point,
+                    seller_peer_id,
+                    bitcoin_change_address,
+                    monero_receive_address,
+                    swap_id: Uuid::new_v4(),
+                    btc_amount,
+                }),
+                (_, Some(_)) => {
+                    bail!("Several swaps can only be started with a seller given by `--seller`")
+                }
+                (1, None) if sellers.len() > 1 => Request::new(Method::BuyXmrFromBestSeller {
+                    sellers: sellers
+                        .into_iter()
+                        .map(|(_, addresses)| addresses)
+                        .collect(),
+                    quote_timeout: Duration::from_secs(quote_timeout),
+                    bitcoin_change_address,
+                    monero_receive_address,
+                    swap_id: Uuid::new_v4(),
+                    btc_amount,
+                }),
+                (_, None) if sellers.len() > 1 => {
+                    bail!("Several swaps can only be started with a single seller")
+                }
+                (1, None) => Request::new(Method::BuyXmr {
+                    seller_addresses: single_seller(sellers)?,
+                    bitcoin_change_address,
+                    monero_receive_address,
+                    swap_id: Uuid::new_v4(),
+                    btc_amount,
+                }),
+                (count, None) => {
+                    let seller_addresses = single_seller(sellers)?;
+                    Request::new(Method::BuyXmrConcurrently {
+                        swaps: (0..count)
+                            .map(|_| BuyXmrArgs {
+                                seller_addresses: seller_addresses.clone(),
+                                bitcoin_change_address: bitcoin_change_address.clone(),
+                                monero_receive_address,
+                                swap_id: Uuid::new_v4(),
+                                btc_amount,
+                            })
+                            .collect(),
+                    })
+                }
+            };
+
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_monero(monero)
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+            (context, request)
+        }
+        CliCommand::History { only_unfinished } => {
+            let request = Request::new(Method::History { only_unfinished });
+
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+            (context, request)
+        }
+        CliCommand::Cleanup {
+            older_than,
+            dry_run,
+        } => {
+            let request = Request::new(Method::Cleanup {
+                older_than: older_than.into(),
+                dry_run,
             });
 
-            let context = Context::build(
-                Some(bitcoin),
-                Some(monero),
-                Some(tor),
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
-        CliCommand::History => {
-            let request = Request::new(Method::History);
-
-            let context =
-                Context::build(None, None, None, data, is_testnet, debug, json, None).await?;
+        CliCommand::Log {
+            swap_id: SwapId { swap_id },
+        } => {
+            let request = Request::new(Method::Log { swap_id });
+
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+            (context, request)
+        }
+        CliCommand::Show {
+            swap_id: SwapId { swap_id },
+        } => {
+            let request = Request::new(Method::Show { swap_id });
+
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::Config => {
             let request = Request::new(Method::Config);
 
-            let context =
-                Context::build(None, None, None, data, is_testnet, debug, json, None).await?;
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::Balance { bitcoin } => {
@@ -113,17 +229,13 @@ where
                 force_refresh: true,
             });
 
-            let context = Context::build(
-                Some(bitcoin),
-                None,
-                None,
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::StartDaemon {
@@ -134,17 +246,16 @@ where
         } => {
             let request = Request::new(Method::StartDaemon { server_address });
 
-            let context = Context::build(
-                Some(bitcoin),
-                Some(monero),
-                Some(tor),
-                data,
-                is_testnet,
-                debug,
-                json,
-                server_address,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_monero(monero)
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .with_server_address(server_address)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::WithdrawBtc {
@@ -155,58 +266,76 @@ where
             let address = bitcoin_address::validate_is_testnet(address, is_testnet)?;
             let request = Request::new(Method::WithdrawBtc { amount, address });
 
-            let context = Context::build(
-                Some(bitcoin),
-                None,
-                None,
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::Resume {
             swap_id: SwapId { swap_id },
             bitcoin,
             monero,
+            monero_restore_height,
             tor,
+            force,
         } => {
             let request = Request::new(Method::Resume { swap_id });
 
-            let context = Context::build(
-                Some(bitcoin),
-                Some(monero),
-                Some(tor),
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_monero(monero)
+                .with_monero_restore_height(
+                    monero_restore_height.map(|height| BlockHeight { height }),
+                )
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .with_force_swap_lock(force)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::CancelAndRefund {
             swap_id: SwapId { swap_id },
             bitcoin,
             tor,
+            force,
         } => {
             let request = Request::new(Method::CancelAndRefund { swap_id });
 
-            let context = Context::build(
-                Some(bitcoin),
-                None,
-                Some(tor),
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .with_force_swap_lock(force)
+                .build()
+                .await?;
+            (context, request)
+        }
+        CliCommand::BumpFee {
+            swap_id: SwapId { swap_id },
+            bitcoin,
+            fee_rate_sat_vb,
+        } => {
+            let request = Request::new(Method::BumpFee {
+                swap_id,
+                fee_rate_sat_vb,
+            });
+
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::ListSellers {
@@ -215,25 +344,46 @@ where
         } => {
             let request = Request::new(Method::ListSellers { rendezvous_point });
 
-            let context =
-                Context::build(None, None, Some(tor), data, is_testnet, debug, json, None).await?;
+            let context = Context::builder(is_testnet)
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+
+            (context, request)
+        }
+        CliCommand::Quote {
+            seller: Seller { seller },
+            timeout,
+            tor,
+        } => {
+            let request = Request::new(Method::Quote {
+                seller,
+                timeout: Duration::from_secs(timeout),
+            });
+
+            let context = Context::builder(is_testnet)
+                .with_tor(tor)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
 
             (context, request)
         }
         CliCommand::ExportBitcoinWallet { bitcoin } => {
             let request = Request::new(Method::ExportBitcoinWallet);
 
-            let context = Context::build(
-                Some(bitcoin),
-                None,
-                None,
-                data,
-                is_testnet,
-                debug,
-                json,
-                None,
-            )
-            .await?;
+            let context = Context::builder(is_testnet)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
             (context, request)
         }
         CliCommand::MoneroRecovery {
@@ -241,11 +391,50 @@ where
         } => {
             let request = Request::new(Method::MoneroRecovery { swap_id });
 
-            let context =
-                Context::build(None, None, None, data, is_testnet, debug, json, None).await?;
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+
+            (context, request)
+        }
+        CliCommand::Export {
+            swap_id: SwapId { swap_id },
+            file,
+        } => {
+            let request = Request::new(Method::ExportSwap { swap_id, file });
+
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
 
             (context, request)
         }
+        CliCommand::Import { file, force } => {
+            let request = Request::new(Method::ImportSwap { file, force });
+
+            let context = Context::builder(is_testnet)
+                .with_data_dir(data)
+                .with_debug(debug)
+                .with_json(json)
+                .build()
+                .await?;
+
+            (context, request)
+        }
+        #[cfg(feature = "selftest")]
+        CliCommand::Selftest => {
+            return Ok(ParseResult::Selftest {
+                data_dir: data,
+                debug,
+                json,
+            })
+        }
     };
 
     Ok(ParseResult::Context(Arc::new(context), Box::new(request)))
@@ -280,7 +469,7 @@ struct Arguments {
     #[structopt(
         short,
         long = "json",
-        help = "Outputs all logs in JSON format instead of plain text"
+        help = "Outputs all logs in JSON format instead of plain text and prints quotes, deposit requests, swap progress and history as JSON lines to stdout"
     )]
     json: bool,
 
@@ -293,7 +482,14 @@ enum CliCommand {
     /// Start a BTC for XMR swap
     BuyXmr {
         #[structopt(flatten)]
-        seller: Seller,
+        sellers: Sellers,
+
+        #[structopt(
+            long,
+            help = "How many seconds to wait for the sellers' quotes if several sellers are given",
+            default_value = DEFAULT_QUOTE_TIMEOUT_SECS
+        )]
+        quote_timeout: u64,
 
         #[structopt(flatten)]
         bitcoin: Bitcoin,
@@ -314,11 +510,53 @@ enum CliCommand {
         )]
         monero_receive_address: monero::Address,
 
+        #[structopt(
+            long = "btc-amount",
+            help = "Optionally specify the amount of Bitcoin to swap, e.g. \"0.02 BTC\". If not specified as much of the wallet's balance as the seller accepts is swapped."
+        )]
+        btc_amount: Option<Amount>,
+
+        #[structopt(
+            long,
+            help = "Start this many swaps with the seller. Each swap starts once the previous one reserved its Bitcoin and waits for its own deposit if the remaining balance is too low",
+            default_value = "1"
+        )]
+        count: u32,
+
         #[structopt(flatten)]
         tor: Tor,
     },
     /// Show a list of past, ongoing and completed swaps
-    History,
+    History {
+        #[structopt(
+            long,
+            help = "Only show swaps that are still in progress, e.g. because they need to be resumed or refunded"
+        )]
+        only_unfinished: bool,
+    },
+    /// Remove swaps that finished a while ago, together with their Monero
+    /// wallet files, and compact the database
+    Cleanup {
+        #[structopt(
+            long = "older-than",
+            help = "Only remove swaps that finished at least this long ago, e.g. `30days` or `12h`",
+            default_value = "30days"
+        )]
+        older_than: humantime::Duration,
+
+        #[structopt(long = "dry-run", help = "Only list the swaps that would be removed")]
+        dry_run: bool,
+    },
+    /// Show every state a swap went through and when it entered it
+    Log {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+    },
+    /// Show the amounts, transactions and fees of a swap
+    Show {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+    },
     #[structopt(about = "Prints the current config")]
     Config,
     #[structopt(about = "Allows withdrawing BTC from the internal Bitcoin wallet.")]
@@ -371,8 +609,20 @@ enum CliCommand {
         #[structopt(flatten)]
         monero: Monero,
 
+        #[structopt(
+            long = "monero-restore-height",
+            help = "Block height from which a newly created Monero wallet scans the chain, defaults to the current height of the daemon. Set it when recovering a swap whose Monero were locked before that"
+        )]
+        monero_restore_height: Option<u32>,
+
         #[structopt(flatten)]
         tor: Tor,
+
+        #[structopt(
+            long,
+            help = "Take over the swap even if another process seems to be handling it"
+        )]
+        force: bool,
     },
     /// Force the submission of the cancel and refund transactions of a swap
     #[structopt(aliases = &["cancel", "refund"])]
@@ -385,6 +635,27 @@ enum CliCommand {
 
         #[structopt(flatten)]
         tor: Tor,
+
+        #[structopt(
+            long,
+            help = "Take over the swap even if another process seems to be handling it"
+        )]
+        force: bool,
+    },
+    /// Speed up the unconfirmed lock transaction of a swap by spending its
+    /// change output with a higher fee (child pays for parent)
+    BumpFee {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+
+        #[structopt(flatten)]
+        bitcoin: Bitcoin,
+
+        #[structopt(
+            long = "fee-rate",
+            help = "The fee rate in sat/vB that the lock transaction and the new transaction pay together. If not specified the current fee rate estimate is used."
+        )]
+        fee_rate_sat_vb: Option<u64>,
     },
     /// Discover and list sellers (i.e. ASB providers)
     ListSellers {
@@ -397,6 +668,22 @@ enum CliCommand {
         #[structopt(flatten)]
         tor: Tor,
     },
+    /// Fetch the current price and quantity limits of a seller without
+    /// starting a swap
+    Quote {
+        #[structopt(flatten)]
+        seller: Seller,
+
+        #[structopt(
+            long,
+            help = "Give up if the seller did not send a quote within this many seconds",
+            default_value = DEFAULT_QUOTE_TIMEOUT_SECS
+        )]
+        timeout: u64,
+
+        #[structopt(flatten)]
+        tor: Tor,
+    },
     /// Print the internal bitcoin wallet descriptor
     ExportBitcoinWallet {
         #[structopt(flatten)]
@@ -409,18 +696,75 @@ enum CliCommand {
         #[structopt(flatten)]
         swap_id: SwapId,
     },
+    /// Write an encrypted backup of a swap to a file, to finish the swap on
+    /// another machine that uses the same seed
+    Export {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+
+        #[structopt(long = "file", help = "Where to write the backup to")]
+        file: PathBuf,
+    },
+    /// Restore a swap from a backup created with the export subcommand
+    Import {
+        #[structopt(long = "file", help = "The backup to restore")]
+        file: PathBuf,
+
+        #[structopt(long, help = "Replace the swap if it already exists in the database")]
+        force: bool,
+    },
+    /// Run a complete swap against a local regtest stack in docker to check
+    /// that swapping works on this machine. No real funds are involved.
+    #[cfg(feature = "selftest")]
+    Selftest,
 }
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct Monero {
     #[structopt(
         long = "monero-daemon-address",
-        help = "Specify to connect to a monero daemon of your choice: <host>:<port>"
+        help = "Specify to connect to a monero daemon of your choice: <host>:<port> or a URL such as https://node.example:443"
     )]
     pub monero_daemon_address: Option<String>,
+
+    #[structopt(
+        long = "monero-wallet-rpc-binary",
+        alias = "monero-wallet-rpc-path",
+        help = "Use this monero-wallet-rpc binary instead of downloading one"
+    )]
+    pub monero_wallet_rpc_binary: Option<PathBuf>,
+
+    #[structopt(
+        long = "monero-socks5-proxy",
+        help = "Connect to the monero daemon through this SOCKS5 proxy, e.g. 127.0.0.1:9050 for Tor"
+    )]
+    pub monero_socks5_proxy: Option<SocketAddr>,
+
+    #[structopt(
+        long = "monero-daemon-username",
+        help = "Username for a monero daemon started with --rpc-login",
+        requires = "monero-daemon-password"
+    )]
+    pub monero_daemon_username: Option<String>,
+
+    #[structopt(
+        long = "monero-daemon-password",
+        help = "Password for a monero daemon started with --rpc-login",
+        requires = "monero-daemon-username"
+    )]
+    pub monero_daemon_password: Option<String>,
 }
 
 impl Monero {
+    pub fn daemon_credentials(&self) -> Option<monero_rpc::Credentials> {
+        match (&self.monero_daemon_username, &self.monero_daemon_password) {
+            (Some(username), Some(password)) => {
+                Some(monero_rpc::Credentials::new(username, password))
+            }
+            _ => None,
+        }
+    }
+
     pub fn apply_defaults(self, testnet: bool) -> String {
         if let Some(address) = self.monero_daemon_address {
             address
@@ -442,6 +786,24 @@ pub struct Bitcoin {
         help = "Estimate Bitcoin fees such that transactions are confirmed within the specified number of blocks"
     )]
     pub bitcoin_target_block: Option<usize>,
+
+    #[structopt(
+        long = "bitcoin-max-fee-rate",
+        help = "Never pay more than this fee rate in sat/vB, regardless of the estimate"
+    )]
+    pub bitcoin_max_fee_rate: Option<u64>,
+
+    #[structopt(
+        long = "bitcoin-fallback-fee-rate",
+        help = "Fee rate in sat/vB to use if the Electrum server cannot estimate one"
+    )]
+    pub bitcoin_fallback_fee_rate: Option<u64>,
+
+    #[structopt(
+        long = "bitcoin-socks5-proxy",
+        help = "Connect to the Electrum server through this SOCKS5 proxy, e.g. 127.0.0.1:9050 for Tor. Required to use a .onion Electrum server"
+    )]
+    pub bitcoin_socks5_proxy: Option<SocketAddr>,
 }
 
 impl Bitcoin {
@@ -464,6 +826,22 @@ impl Bitcoin {
 
         Ok((bitcoin_electrum_rpc_url, bitcoin_target_block))
     }
+
+    pub fn apply_fee_rate_limits(&self, env_config: env::Config) -> Result<env::Config> {
+        let env_config = env::Config {
+            bitcoin_max_fee_rate_sat_vb: self
+                .bitcoin_max_fee_rate
+                .unwrap_or(env_config.bitcoin_max_fee_rate_sat_vb),
+            bitcoin_fallback_fee_rate_sat_vb: self
+                .bitcoin_fallback_fee_rate
+                .unwrap_or(env_config.bitcoin_fallback_fee_rate_sat_vb),
+            ..env_config
+        };
+
+        env_config.check_fee_rates()?;
+
+        Ok(env_config)
+    }
 }
 
 #[derive(structopt::StructOpt, Debug)]
@@ -485,6 +863,40 @@ struct SwapId {
     swap_id: Uuid,
 }
 
+#[derive(structopt::StructOpt, Debug)]
+struct Sellers {
+    #[structopt(
+        long = "seller",
+        help = "The seller's address. Must include a peer ID part, i.e. `/p2p/`. Can be given several times: addresses with the same peer ID are dialled at once as alternative addresses of one seller, among several sellers the one with the best price is chosen",
+        required_unless = "rendezvous-point",
+        conflicts_with = "rendezvous-point",
+        number_of_values = 1
+    )]
+    sellers: Vec<Multiaddr>,
+
+    #[structopt(
+        long = "rendezvous-point",
+        help = "Discover the sellers registered at this rendezvous point instead of giving them with `--seller`. The seller with the best price is chosen"
+    )]
+    rendezvous_point: Option<Multiaddr>,
+
+    #[structopt(
+        long = "seller-peer-id",
+        help = "Only swap with the seller with this peer ID among the sellers discovered at the rendezvous point",
+        requires = "rendezvous-point"
+    )]
+    seller_peer_id: Option<PeerId>,
+}
+
+fn single_seller(sellers: Vec<(PeerId, Vec<Multiaddr>)>) -> Result<Vec<Multiaddr>> {
+    let (_, addresses) = sellers
+        .into_iter()
+        .next()
+        .context("At least one seller must be given")?;
+
+    Ok(addresses)
+}
+
 #[derive(structopt::StructOpt, Debug)]
 struct Seller {
     #[structopt(
@@ -500,11 +912,49 @@ mod tests {
 
     use crate::api::api_test::*;
     use crate::api::Config;
+    use crate::env::GetConfig;
     use crate::monero::monero_address::MoneroAddressNetworkMismatch;
 
     const BINARY_NAME: &str = "swap";
     const ARGS_DATA_DIR: &str = "/tmp/dir/";
 
+    #[test]
+    fn fee_rate_flags_override_the_env_config() {
+        let bitcoin = Bitcoin::from_iter_safe([
+            BINARY_NAME,
+            "--bitcoin-max-fee-rate",
+            "50",
+            "--bitcoin-fallback-fee-rate",
+            "5",
+        ])
+        .unwrap();
+
+        let env_config = bitcoin
+            .apply_fee_rate_limits(env::Testnet::get_config())
+            .unwrap();
+
+        assert_eq!(env_config.bitcoin_max_fee_rate_sat_vb, 50);
+        assert_eq!(env_config.bitcoin_fallback_fee_rate_sat_vb, 5);
+    }
+
+    #[test]
+    fn max_fee_rate_flag_below_the_fallback_is_rejected() {
+        let bitcoin =
+            Bitcoin::from_iter_safe([BINARY_NAME, "--bitcoin-max-fee-rate", "1"]).unwrap();
+
+        let error = bitcoin
+            .apply_fee_rate_limits(env::Testnet::get_config())
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<env::InvalidFeeRates>(),
+            Some(&env::InvalidFeeRates::MaxBelowFallback {
+                max: 1,
+                fallback: env::Testnet::get_config().bitcoin_fallback_fee_rate_sat_vb,
+            })
+        );
+    }
+
     #[tokio::test]
 
     // this test is very long, however it just checks that various CLI arguments sets the
@@ -1111,6 +1561,112 @@ mod tests {
         assert_eq!(actual_config, expected_config);
         assert_eq!(actual_request, Box::new(expected_request));
 
+        // given_buy_xmr_with_btc_amount_then_amount_set
+        let raw_ars = vec![
+            BINARY_NAME,
+            "buy-xmr",
+            "--receive-address",
+            MONERO_MAINNET_ADDRESS,
+            "--change-address",
+            BITCOIN_MAINNET_ADDRESS,
+            "--seller",
+            MULTI_ADDRESS,
+            "--btc-amount",
+            "0.02 BTC",
+        ];
+        let args = parse_args_and_apply_defaults(raw_ars).await.unwrap();
+
+        let actual_request = match args {
+            ParseResult::Context(_, request) => request,
+            _ => panic!("Couldn't parse result"),
+        };
+
+        match actual_request.cmd {
+            Method::BuyXmr { btc_amount, .. } => {
+                assert_eq!(btc_amount, Some(Amount::from_btc(0.02).unwrap()))
+            }
+            _ => panic!("Not the Method we expected"),
+        }
+
+        // given_buy_xmr_with_two_addresses_of_one_seller_then_both_are_used
+        let dns_address = MULTI_ADDRESS.replace("/ip4/127.0.0.1", "/dns4/seller.example");
+        let raw_ars = vec![
+            BINARY_NAME,
+            "buy-xmr",
+            "--receive-address",
+            MONERO_MAINNET_ADDRESS,
+            "--change-address",
+            BITCOIN_MAINNET_ADDRESS,
+            "--seller",
+            MULTI_ADDRESS,
+            "--seller",
+            &dns_address,
+        ];
+        let args = parse_args_and_apply_defaults(raw_ars).await.unwrap();
+
+        let actual_request = match args {
+            ParseResult::Context(_, request) => request,
+            _ => panic!("Couldn't parse result"),
+        };
+
+        match actual_request.cmd {
+            Method::BuyXmr {
+                seller_addresses, ..
+            } => assert_eq!(
+                seller_addresses,
+                vec![MULTI_ADDRESS.parse().unwrap(), dns_address.parse().unwrap()]
+            ),
+            _ => panic!("Not the Method we expected"),
+        }
+
+        // given_buy_xmr_with_rendezvous_point_then_sellers_are_discovered
+        let seller_peer_id = "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi";
+        let raw_ars = vec![
+            BINARY_NAME,
+            "buy-xmr",
+            "--receive-address",
+            MONERO_MAINNET_ADDRESS,
+            "--change-address",
+            BITCOIN_MAINNET_ADDRESS,
+            "--rendezvous-point",
+            MULTI_ADDRESS,
+            "--seller-peer-id",
+            seller_peer_id,
+        ];
+        let args = parse_args_and_apply_defaults(raw_ars).await.unwrap();
+
+        let actual_request = match args {
+            ParseResult::Context(_, request) => request,
+            _ => panic!("Couldn't parse result"),
+        };
+
+        match actual_request.cmd {
+            Method::BuyXmrFromRendezvousPoint {
+                rendezvous_point,
+                seller_peer_id: actual_seller_peer_id,
+                ..
+            } => {
+                assert_eq!(rendezvous_point, MULTI_ADDRESS.parse().unwrap());
+                assert_eq!(actual_seller_peer_id, Some(seller_peer_id.parse().unwrap()));
+            }
+            _ => panic!("Not the Method we expected"),
+        }
+
+        // given_buy_xmr_with_seller_and_rendezvous_point_then_fails
+        let raw_ars = vec![
+            BINARY_NAME,
+            "buy-xmr",
+            "--receive-address",
+            MONERO_MAINNET_ADDRESS,
+            "--change-address",
+            BITCOIN_MAINNET_ADDRESS,
+            "--seller",
+            MULTI_ADDRESS,
+            "--rendezvous-point",
+            MULTI_ADDRESS,
+        ];
+        parse_args_and_apply_defaults(raw_ars).await.unwrap_err();
+
         // given_resume_on_mainnet_with_json_then_json_set
         let raw_ars = vec![BINARY_NAME, "--json", "resume", "--swap-id", SWAP_ID];
         let args = parse_args_and_apply_defaults(raw_ars).await.unwrap();
@@ -1,26 +1,55 @@
 use crate::bitcoin::EncryptedSignature;
 use crate::cli::behaviour::{Behaviour, OutEvent};
-use crate::monero;
 use crate::network::encrypted_signature;
+use crate::network::heartbeat::{ConnectionHealth, Heartbeat};
 use crate::network::quote::BidQuote;
 use crate::network::swap_setup::bob::NewSwap;
 use crate::protocol::bob::State2;
-use anyhow::{Context, Result};
+use crate::protocol::{Database, State};
+use crate::{env, monero};
+use anyhow::{bail, Context, Result};
 use futures::future::{BoxFuture, OptionFuture};
 use futures::{FutureExt, StreamExt};
-use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::request_response::{OutboundFailure, RequestId, ResponseChannel};
 use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::SwarmEvent;
 use libp2p::{PeerId, Swarm};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
 use uuid::Uuid;
 
+/// How long the event loop waits for the swap to take an incoming transfer
+/// proof.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a quote is requested before giving up on a seller that does not
+/// answer. Requesting a quote has no side effects, so it is safe to retry.
+const QUOTE_REQUEST_ATTEMPTS: u32 = 3;
+
+/// How long to wait before sending an encrypted signature again whose request
+/// failed while we stayed connected to Alice.
+const ENCRYPTED_SIGNATURE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Alice did not answer a request in time.
+///
+/// Both requests are made before any funds are locked, so nothing is lost by
+/// trying another seller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RequestTimedOut {
+    #[error("The seller did not send a quote within {} seconds, try another seller", .0.as_secs())]
+    Quote(Duration),
+    #[error("The seller did not set up the swap within {} seconds, try another seller", .0.as_secs())]
+    SwapSetup(Duration),
+}
+
 #[allow(missing_debug_implementations)]
 pub struct EventLoop {
     swap_id: Uuid,
     swarm: libp2p::Swarm<Behaviour>,
     alice_peer_id: PeerId,
+    db: Arc<dyn Database + Send + Sync>,
 
     // these streams represents outgoing requests that we have to make
     quote_requests: bmrng::RequestReceiverStream<(), BidQuote>,
@@ -31,9 +60,20 @@ pub struct EventLoop {
     // once we get a response to a matching [`RequestId`], we will use the responder to relay the
     // response.
     inflight_quote_requests: HashMap<RequestId, bmrng::Responder<BidQuote>>,
-    inflight_encrypted_signature_requests: HashMap<RequestId, bmrng::Responder<()>>,
+    inflight_encrypted_signature_requests:
+        HashMap<RequestId, (EncryptedSignature, bmrng::Responder<()>)>,
     inflight_swap_setup: Option<bmrng::Responder<Result<State2>>>,
 
+    /// Encrypted signatures whose request was lost together with the
+    /// connection to Alice, sent again once we reconnected.
+    ///
+    /// Without acknowledgement Bob cannot know whether Alice received the
+    /// encrypted signature, so it must never be dropped.
+    unacknowledged_encrypted_signatures: Vec<(EncryptedSignature, bmrng::Responder<()>)>,
+    /// Resolves once the encrypted signatures whose request failed while we
+    /// stayed connected to Alice shall be sent again.
+    retry_encrypted_signatures: OptionFuture<BoxFuture<'static, ()>>,
+
     /// The sender we will use to relay incoming transfer proofs.
     transfer_proof: bmrng::RequestSender<monero::TransferProof, ()>,
     /// The future representing the successful handling of an incoming transfer
@@ -41,9 +81,17 @@ pub struct EventLoop {
     ///
     /// Once we've sent a transfer proof to the ongoing swap, this future waits
     /// until the swap took it "out" of the `EventLoopHandle`. As this future
-    /// resolves, we use the `ResponseChannel` returned from it to send an ACK
-    /// to Alice that we have successfully processed the transfer proof.
-    pending_transfer_proof: OptionFuture<BoxFuture<'static, ResponseChannel<()>>>,
+    /// resolves, we use the `ResponseChannel`s in
+    /// `unacknowledged_transfer_proofs` to send an ACK to Alice that we have
+    /// successfully processed the transfer proof.
+    pending_transfer_proof: OptionFuture<BoxFuture<'static, ()>>,
+    /// The requests of the transfer proof the swap did not take yet. Alice
+    /// sends the transfer proof again if a request failed, so there can be
+    /// several.
+    unacknowledged_transfer_proofs: Vec<ResponseChannel<()>>,
+
+    /// Publishes the health of the connection to Alice to the swap.
+    heartbeat: watch::Sender<Heartbeat>,
 }
 
 impl EventLoop {
@@ -51,31 +99,28 @@ impl EventLoop {
         swap_id: Uuid,
         swarm: Swarm<Behaviour>,
         alice_peer_id: PeerId,
+        db: Arc<dyn Database + Send + Sync>,
+        env_config: env::Config,
     ) -> Result<(Self, EventLoopHandle)> {
-        let execution_setup = bmrng::channel_with_timeout(1, Duration::from_secs(60));
-        let transfer_proof = bmrng::channel_with_timeout(1, Duration::from_secs(60));
-        let encrypted_signature = bmrng::channel(1);
-        let quote = bmrng::channel_with_timeout(1, Duration::from_secs(60));
+        let (channels, handle) = channels(&env_config);
 
         let event_loop = EventLoop {
             swap_id,
             swarm,
             alice_peer_id,
-            swap_setup_requests: execution_setup.1.into(),
-            transfer_proof: transfer_proof.0,
-            encrypted_signatures: encrypted_signature.1.into(),
-            quote_requests: quote.1.into(),
+            db,
+            swap_setup_requests: channels.swap_setup_requests,
+            transfer_proof: channels.transfer_proof,
+            encrypted_signatures: channels.encrypted_signatures,
+            quote_requests: channels.quote_requests,
             inflight_quote_requests: HashMap::default(),
             inflight_swap_setup: None,
             inflight_encrypted_signature_requests: HashMap::default(),
+            unacknowledged_encrypted_signatures: Vec::new(),
+            retry_encrypted_signatures: OptionFuture::from(None),
             pending_transfer_proof: OptionFuture::from(None),
-        };
-
-        let handle = EventLoopHandle {
-            swap_setup: execution_setup.0,
-            transfer_proof: transfer_proof.1,
-            encrypted_signature: encrypted_signature.0,
-            quote: quote.0,
+            unacknowledged_transfer_proofs: Vec::new(),
+            heartbeat: channels.heartbeat,
         };
 
         Ok((event_loop, handle))
@@ -127,6 +172,23 @@ impl EventLoop {
                                 continue;
                             }
 
+                            // Alice sends the transfer proof again if she did not receive our acknowledgement
+                            if !self.unacknowledged_transfer_proofs.is_empty() {
+                                tracing::debug!("Received transfer proof again before the swap took it");
+                                self.unacknowledged_transfer_proofs.push(channel);
+                                continue;
+                            }
+
+                            let received = matches!(
+                                self.db.get_state(swap_id).await,
+                                Ok(State::Bob(state)) if state.transfer_proof_received()
+                            );
+                            if received {
+                                tracing::debug!("Acknowledging transfer proof that was already received");
+                                let _ = self.swarm.behaviour_mut().transfer_proof.send_response(channel, ());
+                                continue;
+                            }
+
                             let mut responder = match self.transfer_proof.send(msg.tx_lock_proof).await {
                                 Ok(responder) => responder,
                                 Err(e) => {
@@ -135,37 +197,85 @@ impl EventLoop {
                                 }
                             };
 
+                            self.unacknowledged_transfer_proofs.push(channel);
                             self.pending_transfer_proof = OptionFuture::from(Some(async move {
                                 let _ = responder.recv().await;
-
-                                channel
                             }.boxed()));
                         }
                         SwarmEvent::Behaviour(OutEvent::EncryptedSignatureAcknowledged { id }) => {
-                            if let Some(responder) = self.inflight_encrypted_signature_requests.remove(&id) {
+                            if let Some((_, responder)) = self.inflight_encrypted_signature_requests.remove(&id) {
                                 let _ = responder.respond(());
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::EncryptedSignatureFailed { id, error }) => {
+                            let request = match self.inflight_encrypted_signature_requests.remove(&id) {
+                                Some(request) => request,
+                                None => continue,
+                            };
+
+                            if let OutboundFailure::UnsupportedProtocols = error {
+                                tracing::error!("Alice does not support receiving encrypted signatures");
+                                continue;
+                            }
+
+                            tracing::warn!(%error, "Failed to send encrypted signature, sending it again in {} seconds", ENCRYPTED_SIGNATURE_RETRY_DELAY.as_secs());
+                            self.unacknowledged_encrypted_signatures.push(request);
+                            self.retry_encrypted_signatures = OptionFuture::from(Some(tokio::time::sleep(ENCRYPTED_SIGNATURE_RETRY_DELAY).boxed()));
+                        }
                         SwarmEvent::Behaviour(OutEvent::AllRedialAttemptsExhausted { peer }) if peer == self.alice_peer_id => {
                             tracing::error!("Exhausted all re-dial attempts to Alice");
                             return;
                         }
+                        SwarmEvent::Behaviour(OutEvent::HeartbeatAnswered { peer, rtt }) if peer == self.alice_peer_id => {
+                            tracing::trace!(rtt_ms = %rtt.as_millis(), "Alice answered heartbeat");
+                            self.heartbeat.send_modify(|heartbeat| heartbeat.answered(rtt));
+                        }
+                        SwarmEvent::Behaviour(OutEvent::HeartbeatMissed { peer, failure }) if peer == self.alice_peer_id => {
+                            self.heartbeat.send_modify(Heartbeat::missed);
+                            if let ConnectionHealth::Degraded { missed_heartbeats } = self.heartbeat.borrow().health {
+                                tracing::debug!(%missed_heartbeats, %failure, "Alice did not answer heartbeat");
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::Failure { peer, error }) if peer == self.alice_peer_id && !self.is_connected_to_alice() => {
+                            // The connection to Alice was lost, we are redialling her
+                            tracing::debug!(%peer, err = %error, "Request failed because the connection to Alice was lost");
+                        }
                         SwarmEvent::Behaviour(OutEvent::Failure { peer, error }) => {
                             tracing::warn!(%peer, err = %error, "Communication error");
                             return;
                         }
                         SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } if peer_id == self.alice_peer_id => {
                             tracing::info!(peer_id = %endpoint.get_remote_address(), "Connected to Alice");
+                            self.heartbeat.send_modify(Heartbeat::connected);
+
+                            // Alice's addresses are dialled at once, remember which one worked for resuming the swap
+                            if endpoint.is_dialer() {
+                                let address = endpoint.get_remote_address().clone();
+                                if let Err(error) = self.db.record_connected_address(peer_id, address).await {
+                                    tracing::warn!("Failed to record the address Alice is reachable at: {:#}", error);
+                                }
+                            }
+
+                            for (tx_redeem_encsig, responder) in std::mem::take(&mut self.unacknowledged_encrypted_signatures) {
+                                tracing::info!("Sending encrypted signature to Alice again");
+                                self.send_encrypted_signature(tx_redeem_encsig, responder);
+                            }
                         }
                         SwarmEvent::Dialing(peer_id) if peer_id == self.alice_peer_id => {
                             tracing::debug!(%peer_id, "Dialling Alice");
                         }
                         SwarmEvent::ConnectionClosed { peer_id, endpoint, num_established, cause: Some(error) } if peer_id == self.alice_peer_id && num_established == 0 => {
                             tracing::warn!(peer_id = %endpoint.get_remote_address(), cause = %error, "Lost connection to Alice");
+                            self.heartbeat.send_modify(Heartbeat::disconnected);
+
+                            self.unacknowledged_encrypted_signatures.extend(
+                                self.inflight_encrypted_signature_requests.drain().map(|(_, request)| request)
+                            );
                         }
                         SwarmEvent::ConnectionClosed { peer_id, num_established, cause: None, .. } if peer_id == self.alice_peer_id && num_established == 0 => {
                             // no error means the disconnection was requested
                             tracing::info!("Successfully closed connection to Alice");
+                            self.heartbeat.send_modify(Heartbeat::disconnected);
                             return;
                         }
                         SwarmEvent::OutgoingConnectionError { peer_id: Some(alice_peer_id),  error } if alice_peer_id == self.alice_peer_id => {
@@ -191,17 +301,21 @@ impl EventLoop {
                     self.inflight_swap_setup = Some(responder);
                 },
                 Some((tx_redeem_encsig, responder)) = self.encrypted_signatures.next().fuse(), if self.is_connected_to_alice() => {
-                    let request = encrypted_signature::Request {
-                        swap_id: self.swap_id,
-                        tx_redeem_encsig
-                    };
-
-                    let id = self.swarm.behaviour_mut().encrypted_signature.send_request(&self.alice_peer_id, request);
-                    self.inflight_encrypted_signature_requests.insert(id, responder);
+                    self.send_encrypted_signature(tx_redeem_encsig, responder);
                 },
 
-                Some(response_channel) = &mut self.pending_transfer_proof => {
-                    let _ = self.swarm.behaviour_mut().transfer_proof.send_response(response_channel, ());
+                Some(()) = &mut self.retry_encrypted_signatures, if self.is_connected_to_alice() => {
+                    self.retry_encrypted_signatures = OptionFuture::from(None);
+
+                    for (tx_redeem_encsig, responder) in std::mem::take(&mut self.unacknowledged_encrypted_signatures) {
+                        self.send_encrypted_signature(tx_redeem_encsig, responder);
+                    }
+                }
+
+                Some(()) = &mut self.pending_transfer_proof => {
+                    for channel in std::mem::take(&mut self.unacknowledged_transfer_proofs) {
+                        let _ = self.swarm.behaviour_mut().transfer_proof.send_response(channel, ());
+                    }
 
                     self.pending_transfer_proof = OptionFuture::from(None);
                 }
@@ -209,22 +323,85 @@ impl EventLoop {
         }
     }
 
+    fn send_encrypted_signature(
+        &mut self,
+        tx_redeem_encsig: EncryptedSignature,
+        responder: bmrng::Responder<()>,
+    ) {
+        let request = encrypted_signature::Request {
+            swap_id: self.swap_id,
+            tx_redeem_encsig: tx_redeem_encsig.clone(),
+        };
+
+        let id = self
+            .swarm
+            .behaviour_mut()
+            .encrypted_signature
+            .send_request(&self.alice_peer_id, request);
+        self.inflight_encrypted_signature_requests
+            .insert(id, (tx_redeem_encsig, responder));
+    }
+
     fn is_connected_to_alice(&self) -> bool {
         self.swarm.is_connected(&self.alice_peer_id)
     }
 }
 
+/// The [`EventLoop`]'s ends of the channels to its [`EventLoopHandle`].
+struct Channels {
+    quote_requests: bmrng::RequestReceiverStream<(), BidQuote>,
+    encrypted_signatures: bmrng::RequestReceiverStream<EncryptedSignature, ()>,
+    swap_setup_requests: bmrng::RequestReceiverStream<NewSwap, Result<State2>>,
+    transfer_proof: bmrng::RequestSender<monero::TransferProof, ()>,
+    heartbeat: watch::Sender<Heartbeat>,
+}
+
+fn channels(env_config: &env::Config) -> (Channels, EventLoopHandle) {
+    let execution_setup = bmrng::channel(1);
+    let transfer_proof = bmrng::channel_with_timeout(1, REQUEST_TIMEOUT);
+    let encrypted_signature = bmrng::channel(1);
+    let quote = bmrng::channel(1);
+    let heartbeat = watch::channel(Heartbeat::default());
+
+    let channels = Channels {
+        quote_requests: quote.1.into(),
+        encrypted_signatures: encrypted_signature.1.into(),
+        swap_setup_requests: execution_setup.1.into(),
+        transfer_proof: transfer_proof.0,
+        heartbeat: heartbeat.0,
+    };
+
+    let handle = EventLoopHandle {
+        swap_setup: execution_setup.0,
+        transfer_proof: transfer_proof.1,
+        encrypted_signature: encrypted_signature.0,
+        quote: quote.0,
+        heartbeat: heartbeat.1,
+        quote_timeout: env_config.quote_timeout,
+        swap_setup_timeout: env_config.swap_setup_timeout,
+    };
+
+    (channels, handle)
+}
+
 #[derive(Debug)]
 pub struct EventLoopHandle {
     swap_setup: bmrng::RequestSender<NewSwap, Result<State2>>,
     transfer_proof: bmrng::RequestReceiver<monero::TransferProof, ()>,
     encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
     quote: bmrng::RequestSender<(), BidQuote>,
+    heartbeat: watch::Receiver<Heartbeat>,
+    quote_timeout: Duration,
+    swap_setup_timeout: Duration,
 }
 
 impl EventLoopHandle {
+    /// Sets up the swap with Alice. Fails with [`RequestTimedOut::SwapSetup`]
+    /// if she does not finish within the configured timeout.
     pub async fn setup_swap(&mut self, swap: NewSwap) -> Result<State2> {
-        self.swap_setup.send_receive(swap).await?
+        tokio::time::timeout(self.swap_setup_timeout, self.swap_setup.send_receive(swap))
+            .await
+            .map_err(|_| RequestTimedOut::SwapSetup(self.swap_setup_timeout))??
     }
 
     pub async fn recv_transfer_proof(&mut self) -> Result<monero::TransferProof> {
@@ -240,9 +417,30 @@ impl EventLoopHandle {
         Ok(transfer_proof)
     }
 
+    /// Requests a quote from Alice. Fails with [`RequestTimedOut::Quote`] if
+    /// she did not answer any of the attempts within the configured timeout.
     pub async fn request_quote(&mut self) -> Result<BidQuote> {
-        tracing::debug!("Requesting quote");
-        Ok(self.quote.send_receive(()).await?)
+        for attempt in 1..=QUOTE_REQUEST_ATTEMPTS {
+            tracing::debug!(%attempt, "Requesting quote");
+
+            match tokio::time::timeout(self.quote_timeout, self.quote.send_receive(())).await {
+                Ok(quote) => {
+                    let quote = quote?;
+                    quote.ensure_protocol_version_supported()?;
+
+                    return Ok(quote);
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        %attempt,
+                        "The seller did not send a quote within {} seconds",
+                        self.quote_timeout.as_secs()
+                    );
+                }
+            }
+        }
+
+        bail!(RequestTimedOut::Quote(self.quote_timeout))
     }
 
     pub async fn send_encrypted_signature(
@@ -253,4 +451,147 @@ impl EventLoopHandle {
             .send_receive(tx_redeem_encsig)
             .await
     }
+
+    pub fn connection_health(&self) -> ConnectionHealth {
+        self.heartbeat.borrow().health
+    }
+
+    /// The round-trip time of the last heartbeat Alice answered.
+    pub fn latest_rtt(&self) -> Option<Duration> {
+        self.heartbeat.borrow().latest_rtt
+    }
+
+    /// Watches the health of the connection to Alice.
+    pub fn heartbeat(&self) -> watch::Receiver<Heartbeat> {
+        self.heartbeat.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::GetConfig;
+    use crate::network::quote::{UnsupportedProtocolVersion, PROTOCOL_VERSION};
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn quote_request_times_out_if_event_loop_does_not_answer() {
+        let env_config = env::Mainnet::get_config();
+        let (_channels, mut handle) = channels(&env_config);
+
+        let start = Instant::now();
+        let error = handle.request_quote().await.unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<RequestTimedOut>(),
+            Some(&RequestTimedOut::Quote(env_config.quote_timeout))
+        );
+        assert!(start.elapsed() >= env_config.quote_timeout * QUOTE_REQUEST_ATTEMPTS);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quote_request_is_retried_after_a_timeout() {
+        let (mut channels, mut handle) = channels(&env::Mainnet::get_config());
+
+        tokio::spawn(async move {
+            let (_, _unanswered) = channels.quote_requests.next().await.unwrap();
+            let (_, responder) = channels.quote_requests.next().await.unwrap();
+            responder.respond(quote()).unwrap();
+        });
+
+        assert_eq!(handle.request_quote().await.unwrap(), quote());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quote_request_answered_just_before_timeout_succeeds() {
+        let env_config = env::Mainnet::get_config();
+        let (mut channels, mut handle) = channels(&env_config);
+
+        tokio::spawn(async move {
+            let (_, responder) = channels.quote_requests.next().await.unwrap();
+            tokio::time::sleep(env_config.quote_timeout - Duration::from_secs(1)).await;
+            responder.respond(quote()).unwrap();
+        });
+
+        assert_eq!(handle.request_quote().await.unwrap(), quote());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn swap_setup_times_out_without_being_retried() {
+        let env_config = env::Mainnet::get_config();
+        let (mut channels, mut handle) = channels(&env_config);
+
+        let error = handle
+            .setup_swap(NewSwap {
+                swap_id: Uuid::new_v4(),
+                btc: bitcoin::Amount::from_sat(100_000),
+                tx_refund_fee: bitcoin::Amount::from_sat(1_000),
+                tx_cancel_fee: bitcoin::Amount::from_sat(1_000),
+                bitcoin_refund_address: "1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6".parse().unwrap(),
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<RequestTimedOut>(),
+            Some(&RequestTimedOut::SwapSetup(env_config.swap_setup_timeout))
+        );
+        assert!(channels.swap_setup_requests.next().now_or_never().is_some());
+        assert!(channels.swap_setup_requests.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn quote_of_unknown_protocol_version_is_refused() {
+        let (mut channels, mut handle) = channels(&env::Mainnet::get_config());
+
+        tokio::spawn(async move {
+            let (_, responder) = channels.quote_requests.next().await.unwrap();
+            responder
+                .respond(BidQuote {
+                    price: bitcoin::Amount::from_sat(1_000),
+                    min_quantity: bitcoin::Amount::ZERO,
+                    max_quantity: bitcoin::Amount::ONE_BTC,
+                    protocol_version: PROTOCOL_VERSION + 1,
+                })
+                .unwrap();
+        });
+
+        let error = handle.request_quote().await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<UnsupportedProtocolVersion>(),
+            Some(&UnsupportedProtocolVersion {
+                required: PROTOCOL_VERSION + 1,
+                supported: PROTOCOL_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn handle_reports_the_latest_heartbeat() {
+        let (channels, handle) = channels(&env::Mainnet::get_config());
+        assert_eq!(handle.connection_health(), ConnectionHealth::Unreachable);
+
+        channels.heartbeat.send_modify(|heartbeat| {
+            heartbeat.connected();
+            heartbeat.answered(Duration::from_millis(50));
+            heartbeat.missed();
+        });
+
+        assert_eq!(
+            handle.connection_health(),
+            ConnectionHealth::Degraded {
+                missed_heartbeats: 1
+            }
+        );
+        assert_eq!(handle.latest_rtt(), Some(Duration::from_millis(50)));
+    }
+
+    fn quote() -> BidQuote {
+        BidQuote {
+            price: bitcoin::Amount::from_sat(1_000),
+            min_quantity: bitcoin::Amount::ZERO,
+            max_quantity: bitcoin::Amount::ONE_BTC,
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
 }
@@ -1,3 +1,4 @@
+use crate::cli::request_quote::pick_best;
 use crate::network::quote::BidQuote;
 use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::{quote, swarm};
@@ -15,6 +16,9 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::time::Duration;
 
+/// How many registrations are requested from the rendezvous point at once.
+const DISCOVERY_PAGE_SIZE: u64 = 100;
+
 /// Returns sorted list of sellers, with [Online](Status::Online) listed first.
 ///
 /// First uses the rendezvous node to discover peers in the given namespace,
@@ -27,6 +31,27 @@ pub async fn list_sellers(
     namespace: XmrBtcNamespace,
     tor_socks5_port: u16,
     identity: identity::Keypair,
+) -> Result<Vec<Seller>> {
+    list_sellers_in_pages_of(
+        rendezvous_node_peer_id,
+        rendezvous_node_addr,
+        namespace,
+        tor_socks5_port,
+        identity,
+        DISCOVERY_PAGE_SIZE,
+    )
+    .await
+}
+
+/// Like [`list_sellers`], but discovers at most `page_size` sellers per
+/// request to the rendezvous point.
+pub(crate) async fn list_sellers_in_pages_of(
+    rendezvous_node_peer_id: PeerId,
+    rendezvous_node_addr: Multiaddr,
+    namespace: XmrBtcNamespace,
+    tor_socks5_port: u16,
+    identity: identity::Keypair,
+    page_size: u64,
 ) -> Result<Vec<Seller>> {
     let behaviour = Behaviour {
         rendezvous: rendezvous::client::Behaviour::new(identity.clone()),
@@ -53,18 +78,46 @@ pub async fn list_sellers(
         rendezvous_node_peer_id,
         rendezvous_node_addr,
         namespace,
+        page_size,
     );
     let sellers = event_loop.run().await;
 
     Ok(sellers)
 }
 
+/// Picks the seller with the best quote among the online `sellers`. If
+/// `peer_id` is given only that seller is considered.
+pub fn best_seller(sellers: Vec<Seller>, peer_id: Option<PeerId>) -> Result<(Multiaddr, BidQuote)> {
+    let number_of_sellers = sellers.len();
+    let quotes = sellers
+        .into_iter()
+        .filter(|seller| peer_id.map_or(true, |peer_id| seller.peer_id == peer_id))
+        .filter_map(|seller| match seller.status {
+            Status::Online(quote) => Some((seller.multiaddr, quote)),
+            Status::Unreachable => None,
+        })
+        .collect();
+
+    pick_best(quotes).with_context(|| match peer_id {
+        Some(peer_id) => format!(
+            "Seller {} is not registered at the rendezvous point or did not send a quote for a non-zero amount",
+            peer_id
+        ),
+        None => format!(
+            "None of the {} sellers registered at the rendezvous point sent a quote for a non-zero amount",
+            number_of_sellers
+        ),
+    })
+}
+
 #[serde_as]
-#[derive(Debug, Serialize, PartialEq, Eq, Hash, Ord, PartialOrd)]
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct Seller {
     pub status: Status,
     #[serde_as(as = "DisplayFromStr")]
     pub multiaddr: Multiaddr,
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_id: PeerId,
 }
 
 #[derive(Debug, Serialize, PartialEq, Eq, Hash, Copy, Clone, Ord, PartialOrd)]
@@ -121,6 +174,7 @@ struct EventLoop {
     reachable_asb_address: HashMap<PeerId, Multiaddr>,
     unreachable_asb_address: HashMap<PeerId, Multiaddr>,
     asb_quote_status: HashMap<PeerId, QuoteStatus>,
+    page_size: u64,
     state: State,
 }
 
@@ -130,6 +184,7 @@ impl EventLoop {
         rendezvous_peer_id: PeerId,
         rendezvous_addr: Multiaddr,
         namespace: XmrBtcNamespace,
+        page_size: u64,
     ) -> Self {
         Self {
             swarm,
@@ -139,6 +194,7 @@ impl EventLoop {
             reachable_asb_address: Default::default(),
             unreachable_asb_address: Default::default(),
             asb_quote_status: Default::default(),
+            page_size,
             state: State::WaitForDiscovery,
         }
     }
@@ -155,12 +211,7 @@ impl EventLoop {
                                     self.namespace
                                 );
 
-                                self.swarm.behaviour_mut().rendezvous.discover(
-                                    Some(rendezvous::Namespace::new(self.namespace.to_string()).expect("our namespace to be a correct string")),
-                                    None,
-                                    None,
-                                    self.rendezvous_peer_id,
-                                );
+                                self.discover(None);
                             } else {
                                 let address = endpoint.get_remote_address();
                                 tracing::debug!(%peer_id, %address, "Connection established to peer");
@@ -201,9 +252,14 @@ impl EventLoop {
                             }
                         }
                         SwarmEvent::Behaviour(OutEvent::Rendezvous(
-                                                  libp2p::rendezvous::client::Event::Discovered { registrations, .. },
+                                                  libp2p::rendezvous::client::Event::Discovered { registrations, cookie, .. },
                                               )) => {
-                            self.state = State::WaitForQuoteCompletion;
+                            // A full page means there may be more registrations
+                            if registrations.len() as u64 >= self.page_size {
+                                self.discover(Some(cookie));
+                            } else {
+                                self.state = State::WaitForQuoteCompletion;
+                            }
 
                             for registration in registrations {
                                 let peer = registration.record.peer_id();
@@ -229,6 +285,14 @@ impl EventLoop {
                                 let _request_id = self.swarm.behaviour_mut().quote.send_request(&peer, ());
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::Rendezvous(
+                                                  libp2p::rendezvous::client::Event::DiscoverFailed { error, .. },
+                                              )) => {
+                            tracing::error!("Failed to discover sellers at the rendezvous point: {:?}", error);
+
+                            // Still report the sellers discovered on previous pages
+                            self.state = State::WaitForQuoteCompletion;
+                        }
                         SwarmEvent::Behaviour(OutEvent::Quote(quote_response)) => {
                             match quote_response {
                                 RequestResponseEvent::Message { peer, message } => {
@@ -285,6 +349,7 @@ impl EventLoop {
                                 Ok(Seller {
                                     multiaddr: address.clone(),
                                     status: Status::Online(*quote),
+                                    peer_id: *peer_id,
                                 })
                             }
                             QuoteStatus::Received(Status::Unreachable) => {
@@ -296,6 +361,7 @@ impl EventLoop {
                                 Ok(Seller {
                                     multiaddr: address.clone(),
                                     status: Status::Unreachable,
+                                    peer_id: *peer_id,
                                 })
                             }
                         })
@@ -312,6 +378,18 @@ impl EventLoop {
             }
         }
     }
+
+    fn discover(&mut self, cookie: Option<rendezvous::Cookie>) {
+        self.swarm.behaviour_mut().rendezvous.discover(
+            Some(
+                rendezvous::Namespace::new(self.namespace.to_string())
+                    .expect("our namespace to be a correct string"),
+            ),
+            cookie,
+            Some(self.page_size),
+            self.rendezvous_peer_id,
+        );
+    }
 }
 
 #[derive(Debug)]
@@ -326,24 +404,30 @@ impl From<PingEvent> for OutEvent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::quote::PROTOCOL_VERSION;
 
     #[test]
     fn sellers_sort_with_unreachable_coming_last() {
+        let peer_id = PeerId::random();
         let mut list = vec![
             Seller {
                 multiaddr: "/ip4/127.0.0.1/tcp/1234".parse().unwrap(),
+                peer_id,
                 status: Status::Unreachable,
             },
             Seller {
                 multiaddr: Multiaddr::empty(),
+                peer_id,
                 status: Status::Unreachable,
             },
             Seller {
                 multiaddr: "/ip4/127.0.0.1/tcp/5678".parse().unwrap(),
+                peer_id,
                 status: Status::Online(BidQuote {
                     price: Default::default(),
                     min_quantity: Default::default(),
                     max_quantity: Default::default(),
+                    protocol_version: PROTOCOL_VERSION,
                 }),
             },
         ];
@@ -355,21 +439,60 @@ mod tests {
             vec![
                 Seller {
                     multiaddr: "/ip4/127.0.0.1/tcp/5678".parse().unwrap(),
+                    peer_id,
                     status: Status::Online(BidQuote {
                         price: Default::default(),
                         min_quantity: Default::default(),
                         max_quantity: Default::default(),
+                        protocol_version: PROTOCOL_VERSION,
                     })
                 },
                 Seller {
                     multiaddr: Multiaddr::empty(),
+                    peer_id,
                     status: Status::Unreachable
                 },
                 Seller {
                     multiaddr: "/ip4/127.0.0.1/tcp/1234".parse().unwrap(),
+                    peer_id,
                     status: Status::Unreachable
                 },
             ]
         )
     }
+
+    #[test]
+    fn best_seller_can_be_restricted_to_one_peer() {
+        let cheap = online_seller(900);
+        let expensive = online_seller(1_000);
+        let expensive_peer_id = expensive.peer_id;
+        let sellers = || vec![cheap.clone(), expensive.clone()];
+
+        let (address, quote) = best_seller(sellers(), None).unwrap();
+        assert_eq!(address, cheap.multiaddr);
+        assert_eq!(quote.price, bitcoin::Amount::from_sat(900));
+
+        let (address, _) = best_seller(sellers(), Some(expensive_peer_id)).unwrap();
+        assert_eq!(address, expensive.multiaddr);
+
+        assert!(best_seller(sellers(), Some(PeerId::random())).is_err());
+    }
+
+    fn online_seller(price: u64) -> Seller {
+        let peer_id = PeerId::random();
+
+        Seller {
+            multiaddr: "/ip4/127.0.0.1/tcp/9939"
+                .parse::<Multiaddr>()
+                .unwrap()
+                .with(Protocol::P2p(peer_id.into())),
+            status: Status::Online(BidQuote {
+                price: bitcoin::Amount::from_sat(price),
+                min_quantity: bitcoin::Amount::ZERO,
+                max_quantity: bitcoin::Amount::ONE_BTC,
+                protocol_version: PROTOCOL_VERSION,
+            }),
+            peer_id,
+        }
+    }
 }
@@ -0,0 +1,269 @@
+//! Machine readable output of the CLI.
+//!
+//! With `--json` the CLI prints one JSON object per line to stdout for the
+//! events a program wrapping the CLI needs to follow a swap. Logs stay on
+//! stderr. Every object has a `type` field; amounts are in satoshi. The field
+//! names are part of the CLI's interface and must not change.
+
+use crate::bitcoin;
+use crate::network::quote::BidQuote;
+use crate::protocol::bob::{BobState, SwapStatus, SwapSummary};
+use anyhow::Result;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Output {
+    Quote {
+        #[serde(flatten)]
+        quote: BidQuote,
+    },
+    DepositRequired {
+        deposit_address: bitcoin::Address,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        min_deposit: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        max_deposit: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        max_giveable: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        min_bitcoin_lock_tx_fee: bitcoin::Amount,
+    },
+    SwapState {
+        swap_id: Uuid,
+        state: String,
+    },
+    SwapCompleted {
+        swap_id: Uuid,
+        state: String,
+    },
+    SwapFailed {
+        swap_id: Uuid,
+        error: String,
+    },
+    History {
+        swaps: Vec<HistoryEntry>,
+    },
+    SwapSummary {
+        swap_id: Uuid,
+        #[serde(flatten)]
+        summary: SwapSummary,
+    },
+    SwapLog {
+        swap_id: Uuid,
+        states: Vec<LogEntry>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub swap_id: Uuid,
+    pub state: String,
+    pub status: SwapStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogEntry {
+    /// RFC 3339 timestamp of when the swap entered the state.
+    pub entered_at: String,
+    pub state: String,
+}
+
+impl Output {
+    pub fn swap_state(swap_id: Uuid, state: &BobState) -> Self {
+        Output::SwapState {
+            swap_id,
+            state: state.to_string(),
+        }
+    }
+
+    pub fn swap_result(swap_id: Uuid, result: &Result<BobState>) -> Self {
+        match result {
+            Ok(state) => Output::SwapCompleted {
+                swap_id,
+                state: state.to_string(),
+            },
+            Err(error) => Output::SwapFailed {
+                swap_id,
+                error: format!("{:#}", error),
+            },
+        }
+    }
+
+    pub fn history(swaps: &[(Uuid, BobState)]) -> Self {
+        Output::History {
+            swaps: swaps
+                .iter()
+                .map(|(swap_id, state)| HistoryEntry {
+                    swap_id: *swap_id,
+                    state: state.to_string(),
+                    status: state.status(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn swap_summary(swap_id: Uuid, summary: SwapSummary) -> Self {
+        Output::SwapSummary { swap_id, summary }
+    }
+
+    pub fn swap_log(swap_id: Uuid, history: &[(OffsetDateTime, BobState)]) -> Result<Self> {
+        let states = history
+            .iter()
+            .map(|(entered_at, state)| {
+                Ok(LogEntry {
+                    entered_at: entered_at.format(&Rfc3339)?,
+                    state: state.to_string(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Output::SwapLog { swap_id, states })
+    }
+
+    /// Prints the output as a single line of JSON to stdout.
+    pub fn print(&self) {
+        match serde_json::to_string(self) {
+            Ok(line) => println!("{}", line),
+            Err(error) => tracing::warn!("Failed to serialize output: {:#}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::quote::PROTOCOL_VERSION;
+    use anyhow::anyhow;
+    use std::str::FromStr;
+
+    const SWAP_ID: &str = "e4ea1ce2-4d4a-4b87-8c5d-1f7f0d0dfb0c";
+
+    fn swap_id() -> Uuid {
+        Uuid::from_str(SWAP_ID).unwrap()
+    }
+
+    #[test]
+    fn quote() {
+        let output = Output::Quote {
+            quote: BidQuote {
+                price: bitcoin::Amount::from_sat(1337),
+                min_quantity: bitcoin::Amount::from_sat(42),
+                max_quantity: bitcoin::Amount::from_sat(9001),
+                protocol_version: PROTOCOL_VERSION,
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            r#"{"type":"quote","price":1337,"min_quantity":42,"max_quantity":9001,"protocol_version":1}"#
+        );
+    }
+
+    #[test]
+    fn deposit_required() {
+        let output = Output::DepositRequired {
+            deposit_address: bitcoin::Address::from_str(
+                "tb1qhgw9jy7sy3ucmxwvvjn0ejcxhu4rk4x9hqm4ju",
+            )
+            .unwrap(),
+            min_deposit: bitcoin::Amount::from_sat(10_000),
+            max_deposit: bitcoin::Amount::from_sat(1_000_000),
+            max_giveable: bitcoin::Amount::from_sat(500),
+            min_bitcoin_lock_tx_fee: bitcoin::Amount::from_sat(1_000),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            r#"{"type":"deposit_required","deposit_address":"tb1qhgw9jy7sy3ucmxwvvjn0ejcxhu4rk4x9hqm4ju","min_deposit":10000,"max_deposit":1000000,"max_giveable":500,"min_bitcoin_lock_tx_fee":1000}"#
+        );
+    }
+
+    #[test]
+    fn swap_state() {
+        let output = Output::swap_state(swap_id(), &BobState::SafelyAborted);
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            format!(
+                r#"{{"type":"swap_state","swap_id":"{}","state":"safely aborted"}}"#,
+                SWAP_ID
+            )
+        );
+    }
+
+    #[test]
+    fn swap_result() {
+        let completed = Output::swap_result(swap_id(), &Ok(BobState::SafelyAborted));
+        let failed = Output::swap_result(swap_id(), &Err(anyhow!("Boom")));
+
+        assert_eq!(
+            serde_json::to_string(&completed).unwrap(),
+            format!(
+                r#"{{"type":"swap_completed","swap_id":"{}","state":"safely aborted"}}"#,
+                SWAP_ID
+            )
+        );
+        assert_eq!(
+            serde_json::to_string(&failed).unwrap(),
+            format!(
+                r#"{{"type":"swap_failed","swap_id":"{}","error":"Boom"}}"#,
+                SWAP_ID
+            )
+        );
+    }
+
+    #[test]
+    fn history() {
+        let output = Output::history(&[(swap_id(), BobState::SafelyAborted)]);
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            format!(
+                r#"{{"type":"history","swaps":[{{"swap_id":"{}","state":"safely aborted","status":{{"description":"Aborted before any Bitcoin was locked","progress":"aborted","recovery":"not_needed"}}}}]}}"#,
+                SWAP_ID
+            )
+        );
+    }
+
+    #[test]
+    fn swap_log() {
+        let entered_at = time::Date::from_calendar_date(2021, time::Month::September, 3)
+            .unwrap()
+            .with_hms(5, 3, 45)
+            .unwrap()
+            .assume_utc();
+        let output = Output::swap_log(swap_id(), &[(entered_at, BobState::SafelyAborted)]).unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            format!(
+                r#"{{"type":"swap_log","swap_id":"{}","states":[{{"entered_at":"2021-09-03T05:03:45Z","state":"safely aborted"}}]}}"#,
+                SWAP_ID
+            )
+        );
+    }
+
+    #[test]
+    fn swap_summary() {
+        let output = Output::swap_summary(
+            swap_id(),
+            SwapSummary {
+                btc_lock_amount: Some(bitcoin::Amount::from_sat(1_000_000)),
+                tx_lock_fee: Some(bitcoin::Amount::from_sat(1_000)),
+                ..SwapSummary::default()
+            },
+        );
+
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            format!(
+                r#"{{"type":"swap_summary","swap_id":"{}","btc_lock_amount":1000000,"tx_lock_id":null,"tx_lock_fee":1000,"xmr_amount":null,"xmr_lock_tx_hash":null,"tx_redeem_id":null,"xmr_redeem_tx_hashes":[],"tx_cancel_id":null,"tx_cancel_fee":null,"tx_refund_id":null,"tx_refund_fee":null}}"#,
+                SWAP_ID
+            )
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Checks that the servers the CLI depends on are reachable before the
+//! wallets are initialized, so that a wrong URL or an unavailable server
+//! results in an error that tells the user what to change.
+
+use crate::bitcoin::wallet::electrum_config;
+use anyhow::{Context, Result};
+use bdk::electrum_client::{Client, ElectrumApi};
+use monero_rpc::wallet::BlockHeight;
+use monero_rpc::Credentials;
+use std::net::SocketAddr;
+use url::Url;
+
+/// Connects to the Electrum server, through the SOCKS5 proxy if one is
+/// given, and requests its features.
+pub async fn check_electrum(url: &Url, socks5_proxy: Option<SocketAddr>) -> Result<()> {
+    let electrum_url = url.clone();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let client = Client::from_config(electrum_url.as_str(), electrum_config(socks5_proxy))?;
+        client.server_features()?;
+
+        Ok(())
+    })
+    .await?
+    .with_context(|| {
+        format!(
+            "Cannot reach the Electrum server at {}. Check that the URL is correct, including the tcp:// or ssl:// prefix and the port, or choose a different server with --electrum-rpc",
+            url
+        )
+    })
+}
+
+/// Requests the status of the Monero daemon that monero-wallet-rpc will
+/// connect to and returns its current height.
+pub async fn check_monero_daemon(
+    daemon_address: &str,
+    network: monero::Network,
+    socks5_proxy: Option<SocketAddr>,
+    credentials: Option<Credentials>,
+) -> Result<BlockHeight> {
+    crate::monero::check_daemon(daemon_address, network, socks5_proxy, credentials)
+        .await
+        .with_context(|| {
+            format!(
+                "Cannot use the Monero daemon at {}. Check that the address is given as <host>:<port> or as a URL and the node is synchronized, or choose a different node with --monero-daemon-address",
+                daemon_address
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_electrum_server_is_reported_with_its_url() {
+        let url = Url::parse("tcp://127.0.0.1:1").unwrap();
+
+        let error = check_electrum(&url, None).await.unwrap_err();
+
+        assert!(format!("{:#}", error).contains("tcp://127.0.0.1:1"));
+        assert!(format!("{:#}", error).contains("--electrum-rpc"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_monero_daemon_is_reported_with_its_address() {
+        let error = check_monero_daemon("127.0.0.1:1", monero::Network::Mainnet, None, None)
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", error).contains("127.0.0.1:1"));
+        assert!(format!("{:#}", error).contains("--monero-daemon-address"));
+    }
+}
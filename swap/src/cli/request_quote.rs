@@ -0,0 +1,198 @@
+use crate::bitcoin;
+use crate::libp2p_ext::peer_id_of;
+use crate::network::quote::BidQuote;
+use crate::network::{quote, swarm};
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::join_all;
+use futures::StreamExt;
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity, Multiaddr};
+use std::time::Duration;
+
+/// Requests a quote from a single seller, dialling all of its addresses at
+/// once.
+///
+/// Only the quote protocol is spoken, so neither wallets nor a swap are
+/// needed. Fails if the seller did not respond within `timeout`.
+pub async fn request_quote(
+    seller_addresses: Vec<Multiaddr>,
+    tor_socks5_port: u16,
+    identity: identity::Keypair,
+    timeout: Duration,
+) -> Result<BidQuote> {
+    let seller_peer_id = peer_id_of(&seller_addresses).context("Invalid seller address")?;
+
+    let mut swarm = swarm::cli(identity, tor_socks5_port, quote::cli()).await?;
+    for address in seller_addresses {
+        swarm.behaviour_mut().add_address(&seller_peer_id, address);
+    }
+    swarm.behaviour_mut().send_request(&seller_peer_id, ());
+
+    let response = async {
+        loop {
+            match swarm.select_next_some().await {
+                SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::Response { response, .. },
+                }) if peer == seller_peer_id => return Ok(response),
+                SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                    peer,
+                    error,
+                    ..
+                }) if peer == seller_peer_id => {
+                    bail!("Failed to request quote from seller: {}", error)
+                }
+                _ => {}
+            }
+        }
+    };
+
+    let quote = tokio::time::timeout(timeout, response)
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "Seller {} did not send a quote within {} seconds, check that the address is correct and the seller is online",
+                seller_peer_id,
+                timeout.as_secs()
+            )
+        })??;
+    quote.ensure_protocol_version_supported()?;
+
+    Ok(quote)
+}
+
+/// Requests quotes from all `sellers`, each given by its addresses, at once
+/// and returns the addresses of the seller with the best quote.
+///
+/// Sellers that cannot be reached or do not respond within `timeout` are
+/// skipped, this only fails if no seller sent a usable quote.
+pub async fn best_quote(
+    sellers: Vec<Vec<Multiaddr>>,
+    tor_socks5_port: u16,
+    identity: identity::Keypair,
+    timeout: Duration,
+) -> Result<(Vec<Multiaddr>, BidQuote)> {
+    let number_of_sellers = sellers.len();
+    let sellers = sellers
+        .into_iter()
+        .map(|addresses| Ok((peer_id_of(&addresses)?, addresses)))
+        .collect::<Result<Vec<_>>>()
+        .context("Invalid seller address")?;
+
+    let responses = join_all(sellers.into_iter().map(|(peer_id, addresses)| {
+        let identity = identity.clone();
+        async move {
+            let quote = request_quote(addresses.clone(), tor_socks5_port, identity, timeout).await;
+            (peer_id, addresses, quote)
+        }
+    }))
+    .await;
+
+    let mut quotes = Vec::new();
+    for (seller, addresses, quote) in responses {
+        match quote {
+            Ok(quote) => {
+                tracing::info!(
+                    %seller,
+                    price = %quote.price,
+                    min_quantity = %quote.min_quantity,
+                    max_quantity = %quote.max_quantity,
+                    "Received quote"
+                );
+                quotes.push((addresses, quote));
+            }
+            Err(error) => {
+                tracing::warn!(%seller, "Failed to get quote: {:#}", error);
+            }
+        }
+    }
+
+    pick_best(quotes).with_context(|| {
+        format!(
+            "None of the {} sellers sent a quote for a non-zero amount",
+            number_of_sellers
+        )
+    })
+}
+
+/// Picks the lowest price among the quotes that allow swapping anything at
+/// all with a seller that speaks our version of the swap protocol. If several
+/// sellers ask the same price, the one that sells the most wins.
+pub(crate) fn pick_best<S>(quotes: Vec<(S, BidQuote)>) -> Option<(S, BidQuote)> {
+    quotes
+        .into_iter()
+        .filter(|(_, quote)| quote.max_quantity > bitcoin::Amount::ZERO)
+        .filter(|(_, quote)| quote.ensure_protocol_version_supported().is_ok())
+        .min_by(|(_, a), (_, b)| {
+            a.price
+                .cmp(&b.price)
+                .then_with(|| b.max_quantity.cmp(&a.max_quantity))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::quote::PROTOCOL_VERSION;
+
+    fn quote(price: u64, max_quantity: u64) -> BidQuote {
+        BidQuote {
+            price: bitcoin::Amount::from_sat(price),
+            min_quantity: bitcoin::Amount::ZERO,
+            max_quantity: bitcoin::Amount::from_sat(max_quantity),
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    fn seller(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn picks_the_lowest_price() {
+        let best = pick_best(vec![
+            (seller(1), quote(1_000, 100)),
+            (seller(2), quote(900, 100)),
+            (seller(3), quote(1_100, 100)),
+        ]);
+
+        assert_eq!(best, Some((seller(2), quote(900, 100))));
+    }
+
+    #[test]
+    fn prefers_the_larger_quantity_at_the_same_price() {
+        let best = pick_best(vec![
+            (seller(1), quote(1_000, 100)),
+            (seller(2), quote(1_000, 200)),
+        ]);
+
+        assert_eq!(best, Some((seller(2), quote(1_000, 200))));
+    }
+
+    #[test]
+    fn ignores_sellers_without_liquidity() {
+        let best = pick_best(vec![
+            (seller(1), quote(500, 0)),
+            (seller(2), quote(1_000, 100)),
+        ]);
+
+        assert_eq!(best, Some((seller(2), quote(1_000, 100))));
+        assert_eq!(pick_best(vec![(seller(1), quote(500, 0))]), None);
+    }
+
+    #[test]
+    fn ignores_sellers_of_another_protocol_version() {
+        let incompatible = BidQuote {
+            protocol_version: PROTOCOL_VERSION + 1,
+            ..quote(500, 100)
+        };
+
+        let best = pick_best(vec![
+            (seller(1), incompatible),
+            (seller(2), quote(1_000, 100)),
+        ]);
+
+        assert_eq!(best, Some((seller(2), quote(1_000, 100))));
+    }
+}
@@ -1,6 +1,8 @@
 pub use alice::Alice;
+pub use backup::{BackupState, BackupTampered, SwapBackup};
 pub use bob::Bob;
 pub use sqlite::SqliteDatabase;
+pub use state_format::DatabaseFromNewerVersion;
 
 use crate::fs::ensure_directory_exists;
 use crate::protocol::{Database, State};
@@ -11,8 +13,10 @@ use std::path::Path;
 use std::sync::Arc;
 
 mod alice;
+mod backup;
 mod bob;
 mod sqlite;
+mod state_format;
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Swap {
@@ -0,0 +1,157 @@
+use crate::database::Swap;
+use crate::monero;
+use crate::protocol::bob::SwapSummary;
+use crate::seed::Seed;
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use libp2p::{Multiaddr, PeerId};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use uuid::Uuid;
+
+const BACKUP_VERSION: u8 = 1;
+const NONCE_LENGTH: usize = 12;
+
+/// Everything the database knows about a single swap, so the swap can be
+/// resumed from another machine.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SwapBackup {
+    pub swap_id: Uuid,
+    /// All states the swap went through, oldest first.
+    pub states: Vec<BackupState>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub peer_id: Option<PeerId>,
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub peer_addresses: Vec<Multiaddr>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub monero_address: Option<monero::Address>,
+    pub summary: Option<SwapSummary>,
+    pub broadcast_transactions: Vec<bitcoin::Transaction>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BackupState {
+    pub entered_at: String,
+    pub state: Swap,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct EncryptedBackup {
+    version: u8,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+#[error("The swap backup was modified or created with a different seed")]
+pub struct BackupTampered;
+
+impl SwapBackup {
+    /// Serializes the backup and encrypts it with a key derived from `seed`.
+    pub fn encrypt(&self, seed: &Seed) -> Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec(self)?;
+
+        let mut nonce = [0u8; NONCE_LENGTH];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = cipher(seed)
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt swap backup"))?;
+
+        let backup = EncryptedBackup {
+            version: BACKUP_VERSION,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+
+        Ok(serde_json::to_vec_pretty(&backup)?)
+    }
+
+    /// Decrypts a backup created by [`SwapBackup::encrypt`] with the same
+    /// seed.
+    pub fn decrypt(bytes: &[u8], seed: &Seed) -> Result<Self> {
+        let backup: EncryptedBackup =
+            serde_json::from_slice(bytes).context("Swap backup is not a valid backup file")?;
+
+        if backup.version != BACKUP_VERSION {
+            bail!("Unsupported swap backup version {}", backup.version)
+        }
+        let nonce = hex::decode(&backup.nonce).map_err(|_| BackupTampered)?;
+        let ciphertext = hex::decode(&backup.ciphertext).map_err(|_| BackupTampered)?;
+        if nonce.len() != NONCE_LENGTH {
+            bail!(BackupTampered)
+        }
+
+        let plaintext = cipher(seed)
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| BackupTampered)?;
+
+        serde_json::from_slice(&plaintext).context("Failed to deserialize swap backup")
+    }
+}
+
+fn cipher(seed: &Seed) -> ChaCha20Poly1305 {
+    let key = seed.derive_swap_backup_key();
+
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup() -> SwapBackup {
+        SwapBackup {
+            swap_id: Uuid::new_v4(),
+            states: vec![],
+            peer_id: Some(PeerId::random()),
+            peer_addresses: vec!["/ip4/127.0.0.1/tcp/9939".parse().unwrap()],
+            monero_address: None,
+            summary: None,
+            broadcast_transactions: vec![],
+        }
+    }
+
+    #[test]
+    fn encrypted_backup_round_trips() {
+        let seed = Seed::random().unwrap();
+        let backup = backup();
+
+        let encrypted = backup.encrypt(&seed).unwrap();
+        let decrypted = SwapBackup::decrypt(&encrypted, &seed).unwrap();
+
+        assert_eq!(decrypted, backup);
+    }
+
+    #[test]
+    fn modified_backup_is_rejected() {
+        let seed = Seed::random().unwrap();
+        let encrypted = backup().encrypt(&seed).unwrap();
+
+        let mut file: EncryptedBackup = serde_json::from_slice(&encrypted).unwrap();
+        let mut ciphertext = hex::decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = hex::encode(ciphertext);
+        let tampered = serde_json::to_vec(&file).unwrap();
+
+        let error = SwapBackup::decrypt(&tampered, &seed).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BackupTampered>(),
+            Some(&BackupTampered)
+        );
+    }
+
+    #[test]
+    fn backup_cannot_be_decrypted_with_another_seed() {
+        let encrypted = backup().encrypt(&Seed::random().unwrap()).unwrap();
+
+        let error = SwapBackup::decrypt(&encrypted, &Seed::random().unwrap()).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BackupTampered>(),
+            Some(&BackupTampered)
+        );
+    }
+}
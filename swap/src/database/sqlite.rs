@@ -1,15 +1,20 @@
-use crate::database::Swap;
+use crate::database::state_format::{self, DatabaseFromNewerVersion, CURRENT_VERSION};
+use crate::database::{BackupState, Swap, SwapBackup};
 use crate::monero::Address;
-use crate::protocol::{Database, State};
-use anyhow::{anyhow, Context, Result};
+use crate::protocol::bob::SwapSummary;
+use crate::protocol::{Database, State, SwapAlreadyExists, SwapLocked, SwapNotFinished};
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
 use libp2p::{Multiaddr, PeerId};
-use sqlx::sqlite::Sqlite;
-use sqlx::{Pool, SqlitePool};
+use sqlx::migrate::MigrateError;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{Pool, SqlitePool, Transaction};
 use std::collections::HashMap;
 use std::path::Path;
 use std::str::FromStr;
-use time::OffsetDateTime;
+use std::time::Duration;
+use time::{format_description, OffsetDateTime};
 use uuid::Uuid;
 
 pub struct SqliteDatabase {
@@ -21,15 +26,93 @@ impl SqliteDatabase {
     where
         Self: std::marker::Sized,
     {
-        let path_str = format!("sqlite:{}", path.as_ref().display());
-        let pool = SqlitePool::connect(&path_str).await?;
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            // Lets another process, e.g. `swap history`, read the database while a swap is running
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
         let mut sqlite = Self { pool };
         sqlite.run_migrations().await?;
         Ok(sqlite)
     }
 
     async fn run_migrations(&mut self) -> anyhow::Result<()> {
-        sqlx::migrate!("./migrations").run(&self.pool).await?;
+        match sqlx::migrate!("./migrations").run(&self.pool).await {
+            // A newer version added tables this version does not know about
+            Err(MigrateError::VersionMissing(_)) => bail!(DatabaseFromNewerVersion),
+            result => result?,
+        }
+
+        self.migrate_states().await
+    }
+
+    /// Rewrites all stored states in the current [`state_format`].
+    async fn migrate_states(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query!(
+            r#"
+        SELECT version
+        FROM state_format
+        "#
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        let version = u32::try_from(row.version)?;
+        if version > CURRENT_VERSION {
+            bail!(DatabaseFromNewerVersion)
+        }
+        if version == CURRENT_VERSION {
+            return Ok(());
+        }
+
+        tracing::info!(
+            from = version,
+            to = CURRENT_VERSION,
+            "Upgrading the format of the swap states in the database"
+        );
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT id, state
+        FROM swap_states
+        "#
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        for row in rows {
+            let state = state_format::migrate(&row.state, version)
+                .with_context(|| format!("Failed to upgrade swap state {}", row.id))?;
+
+            sqlx::query!(
+                r#"
+        UPDATE swap_states
+        SET state = ?
+        WHERE id = ?
+        "#,
+                state,
+                row.id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let version = i64::from(CURRENT_VERSION);
+
+        sqlx::query!(
+            r#"
+        UPDATE state_format
+        SET version = ?
+        "#,
+            version
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
 }
@@ -150,9 +233,11 @@ impl Database for SqliteDatabase {
 
         let rows = sqlx::query!(
             r#"
-        SELECT DISTINCT address
+        SELECT address
         FROM peer_addresses
         WHERE peer_id = ?
+        GROUP BY address
+        ORDER BY MAX(last_connected_at) IS NULL, MAX(last_connected_at) DESC, MIN(rowid)
         "#,
             peer_id,
         )
@@ -170,6 +255,29 @@ impl Database for SqliteDatabase {
         addresses
     }
 
+    async fn record_connected_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let peer_id = peer_id.to_string();
+        let address = address.to_string();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        sqlx::query!(
+            r#"
+        UPDATE peer_addresses
+        SET last_connected_at = ?
+        WHERE peer_id = ? AND address = ?
+        "#,
+            now,
+            peer_id,
+            address
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_swap_start_date(&self, swap_id: Uuid) -> Result<String> {
         let mut conn = self.pool.acquire().await?;
         let swap_id = swap_id.to_string();
@@ -194,7 +302,7 @@ impl Database for SqliteDatabase {
         let entered_at = OffsetDateTime::now_utc();
 
         let swap_id = swap_id.to_string();
-        let swap = serde_json::to_string(&Swap::from(state))?;
+        let swap = state_format::serialize(&Swap::from(state))?;
         let entered_at = entered_at.to_string();
 
         sqlx::query!(
@@ -235,7 +343,7 @@ impl Database for SqliteDatabase {
         let row = row
             .first()
             .context(format!("No state in database for swap: {}", swap_id))?;
-        let swap: Swap = serde_json::from_str(&row.state)?;
+        let swap = state_format::deserialize(&row.state)?;
 
         Ok(swap.into())
     }
@@ -259,10 +367,7 @@ impl Database for SqliteDatabase {
             .iter()
             .map(|row| {
                 let swap_id = Uuid::from_str(&row.swap_id)?;
-                let state = match serde_json::from_str::<Swap>(&row.state) {
-                    Ok(a) => Ok(State::from(a)),
-                    Err(e) => Err(e),
-                }?;
+                let state = State::from(state_format::deserialize(&row.state)?);
                 Ok((swap_id, state))
             })
             .collect::<Result<Vec<(Uuid, State)>>>();
@@ -292,10 +397,7 @@ impl Database for SqliteDatabase {
             .map(|row| {
                 let state_str: &str = &row.state;
 
-                let state = match serde_json::from_str::<Swap>(state_str) {
-                    Ok(a) => Ok(State::from(a)),
-                    Err(e) => Err(e),
-                }?;
+                let state = State::from(state_format::deserialize(state_str)?);
                 Ok(state)
             })
             .collect::<Result<Vec<State>>>();
@@ -303,6 +405,33 @@ impl Database for SqliteDatabase {
         result
     }
 
+    async fn history(&self, swap_id: Uuid) -> Result<Vec<(OffsetDateTime, State)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let id = swap_id.to_string();
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT entered_at, state
+        FROM swap_states
+        WHERE swap_id = ?
+        ORDER BY id
+        "#,
+            id
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let entered_at = parse_entered_at(&row.entered_at)?;
+                let state = State::from(state_format::deserialize(&row.state)?);
+
+                Ok((entered_at, state))
+            })
+            .collect()
+    }
+
     async fn raw_all(&self) -> Result<HashMap<Uuid, Vec<serde_json::Value>>> {
         let mut conn = self.pool.acquire().await?;
         let rows = sqlx::query!(
@@ -318,7 +447,7 @@ impl Database for SqliteDatabase {
 
         for row in &rows {
             let swap_id = Uuid::from_str(&row.swap_id)?;
-            let state = serde_json::from_str(&row.state)?;
+            let state = state_format::payload(&row.state)?;
 
             if let std::collections::hash_map::Entry::Vacant(e) = swaps.entry(swap_id) {
                 e.insert(vec![state]);
@@ -332,14 +461,537 @@ impl Database for SqliteDatabase {
 
         Ok(swaps)
     }
+
+    async fn insert_swap_summary(&self, swap_id: Uuid, summary: SwapSummary) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+        let summary = serde_json::to_string(&summary)?;
+
+        sqlx::query!(
+            r#"
+        insert or replace into swap_summaries (
+            swap_id,
+            summary
+            ) values (?, ?);
+        "#,
+            swap_id,
+            summary
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_swap_summary(&self, swap_id: Uuid) -> Result<Option<SwapSummary>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+
+        let row = sqlx::query!(
+            r#"
+        SELECT summary
+        FROM swap_summaries
+        WHERE swap_id = ?
+        "#,
+            swap_id
+        )
+        .fetch_optional(&mut conn)
+        .await?;
+
+        row.map(|row| Ok(serde_json::from_str(&row.summary)?))
+            .transpose()
+    }
+
+    async fn insert_broadcast_transaction(
+        &self,
+        swap_id: Uuid,
+        transaction: bitcoin::Transaction,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+        let txid = transaction.txid().to_string();
+        let tx = serialize_hex(&transaction);
+
+        sqlx::query!(
+            r#"
+        insert or ignore into broadcast_transactions (
+            swap_id,
+            txid,
+            tx
+            ) values (?, ?, ?);
+        "#,
+            swap_id,
+            txid,
+            tx
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_broadcast_transactions(&self, swap_id: Uuid) -> Result<Vec<bitcoin::Transaction>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT tx
+        FROM broadcast_transactions
+        WHERE swap_id = ?
+        "#,
+            swap_id
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let bytes = hex::decode(&row.tx)?;
+                let transaction = deserialize(&bytes)?;
+
+                Ok(transaction)
+            })
+            .collect()
+    }
+
+    async fn acquire_swap_lock(
+        &self,
+        swap_id: Uuid,
+        pid: u32,
+        stale_after: Duration,
+        force: bool,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let id = swap_id.to_string();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let stale_before = now - i64::try_from(stale_after.as_secs())?;
+
+        // Inserting and taking over a stale lock in one statement makes sure
+        // only one of several contending processes gets the lock
+        let result = sqlx::query!(
+            r#"
+        INSERT INTO swap_locks (swap_id, pid, refreshed_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT (swap_id) DO UPDATE
+        SET pid = excluded.pid, refreshed_at = excluded.refreshed_at
+        WHERE swap_locks.refreshed_at < ? OR ?
+        "#,
+            id,
+            pid,
+            now,
+            stale_before,
+            force
+        )
+        .execute(&mut conn)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            let row = sqlx::query!(
+                r#"
+        SELECT pid
+        FROM swap_locks
+        WHERE swap_id = ?
+        "#,
+                id
+            )
+            .fetch_one(&mut conn)
+            .await
+            .context("Failed to find the process holding the lock")?;
+
+            bail!(SwapLocked {
+                swap_id,
+                pid: u32::try_from(row.pid)?,
+            })
+        }
+
+        Ok(())
+    }
+
+    async fn refresh_swap_lock(&self, swap_id: Uuid, pid: u32) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+
+        let id = swap_id.to_string();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let result = sqlx::query!(
+            r#"
+        UPDATE swap_locks
+        SET refreshed_at = ?
+        WHERE swap_id = ? AND pid = ?
+        "#,
+            now,
+            id,
+            pid
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn release_swap_lock(&self, swap_id: Uuid, pid: u32) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let id = swap_id.to_string();
+
+        sqlx::query!(
+            r#"
+        DELETE FROM swap_locks
+        WHERE swap_id = ? AND pid = ?
+        "#,
+            id,
+            pid
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn export_swap(&self, swap_id: Uuid) -> Result<SwapBackup> {
+        let mut conn = self.pool.acquire().await?;
+
+        let id = swap_id.to_string();
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT entered_at, state
+        FROM swap_states
+        WHERE swap_id = ?
+        ORDER BY id
+        "#,
+            id
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        if rows.is_empty() {
+            bail!("No state in database for swap: {}", swap_id)
+        }
+
+        let states = rows
+            .iter()
+            .map(|row| {
+                Ok(BackupState {
+                    entered_at: row.entered_at.clone(),
+                    state: state_format::deserialize(&row.state)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let peer_id = sqlx::query!(
+            r#"
+        SELECT peer_id
+        FROM peers
+        WHERE swap_id = ?
+        "#,
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await?
+        .map(|row| PeerId::from_str(&row.peer_id))
+        .transpose()?;
+
+        let monero_address: Option<Address> = sqlx::query!(
+            r#"
+        SELECT address
+        FROM monero_addresses
+        WHERE swap_id = ?
+        "#,
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await?
+        .map(|row| row.address.parse())
+        .transpose()?;
+
+        let peer_addresses = match peer_id {
+            Some(peer_id) => self.get_addresses(peer_id).await?,
+            None => vec![],
+        };
+
+        Ok(SwapBackup {
+            swap_id,
+            states,
+            peer_id,
+            peer_addresses,
+            monero_address,
+            summary: self.get_swap_summary(swap_id).await?,
+            broadcast_transactions: self.get_broadcast_transactions(swap_id).await?,
+        })
+    }
+
+    async fn import_swap(&self, backup: SwapBackup, replace: bool) -> Result<()> {
+        if !self.get_states(backup.swap_id).await?.is_empty() && !replace {
+            bail!(SwapAlreadyExists(backup.swap_id))
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let swap_id = backup.swap_id.to_string();
+
+        if replace {
+            delete_swap_records(&mut tx, &swap_id).await?;
+        }
+
+        for state in &backup.states {
+            let swap = state_format::serialize(&state.state)?;
+
+            sqlx::query!(
+                r#"
+            insert into swap_states (
+                swap_id,
+                entered_at,
+                state
+                ) values (?, ?, ?);
+        "#,
+                swap_id,
+                state.entered_at,
+                swap
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        if let Some(peer_id) = backup.peer_id {
+            let peer_id = peer_id.to_string();
+
+            sqlx::query!(
+                r#"
+        insert into peers (
+            swap_id,
+            peer_id
+            ) values (?, ?);
+        "#,
+                swap_id,
+                peer_id
+            )
+            .execute(&mut tx)
+            .await?;
+
+            for address in &backup.peer_addresses {
+                let address = address.to_string();
+
+                sqlx::query!(
+                    r#"
+        insert into peer_addresses (
+            peer_id,
+            address
+            ) values (?, ?);
+        "#,
+                    peer_id,
+                    address
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+
+        if let Some(address) = backup.monero_address {
+            let address = address.to_string();
+
+            sqlx::query!(
+                r#"
+        insert into monero_addresses (
+            swap_id,
+            address
+            ) values (?, ?);
+        "#,
+                swap_id,
+                address
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        if let Some(summary) = &backup.summary {
+            let summary = serde_json::to_string(summary)?;
+
+            sqlx::query!(
+                r#"
+        insert or replace into swap_summaries (
+            swap_id,
+            summary
+            ) values (?, ?);
+        "#,
+                swap_id,
+                summary
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        for transaction in &backup.broadcast_transactions {
+            let txid = transaction.txid().to_string();
+            let transaction = serialize_hex(transaction);
+
+            sqlx::query!(
+                r#"
+        insert or ignore into broadcast_transactions (
+            swap_id,
+            txid,
+            tx
+            ) values (?, ?, ?);
+        "#,
+                swap_id,
+                txid,
+                transaction
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_swap(&self, swap_id: Uuid) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let id = swap_id.to_string();
+
+        // Checked in the same transaction, so the swap cannot progress in between
+        let row = sqlx::query!(
+            r#"
+           SELECT state
+           FROM swap_states
+           WHERE swap_id = ?
+           ORDER BY id desc
+           LIMIT 1;
+
+        "#,
+            id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let row = row
+            .first()
+            .context(format!("No state in database for swap: {}", swap_id))?;
+        let state = State::from(state_format::deserialize(&row.state)?);
+        if !state.swap_finished() {
+            bail!(SwapNotFinished(swap_id))
+        }
+
+        delete_swap_records(&mut tx, &id).await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM swap_locks
+        WHERE swap_id = ?
+        "#,
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        sqlx::query!(
+            r#"
+        VACUUM
+        "#
+        )
+        .execute(&mut conn)
+        .await?;
+        // Otherwise the freed pages are only returned once SQLite checkpoints
+        // the write-ahead log on its own
+        sqlx::query!(
+            r#"
+        PRAGMA wal_checkpoint(TRUNCATE)
+        "#
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Deletes everything recorded about the swap except the addresses of its
+/// peer, which other swaps with the same peer might still need.
+async fn delete_swap_records(tx: &mut Transaction<'_, Sqlite>, swap_id: &str) -> Result<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM swap_states
+        WHERE swap_id = ?
+        "#,
+        swap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM peers
+        WHERE swap_id = ?
+        "#,
+        swap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM monero_addresses
+        WHERE swap_id = ?
+        "#,
+        swap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM swap_summaries
+        WHERE swap_id = ?
+        "#,
+        swap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM broadcast_transactions
+        WHERE swap_id = ?
+        "#,
+        swap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// States are stored with the time they were entered in the `Display` format
+/// of [`OffsetDateTime`].
+fn parse_entered_at(entered_at: &str) -> Result<OffsetDateTime> {
+    let format = format_description::parse(
+        "[year]-[month]-[day] [hour padding:none]:[minute]:[second].[subsecond] \
+         [offset_hour sign:mandatory]:[offset_minute]:[offset_second]",
+    )?;
+
+    OffsetDateTime::parse(entered_at, &format)
+        .with_context(|| format!("Invalid state timestamp {}", entered_at))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bitcoin;
     use crate::protocol::alice::AliceState;
     use crate::protocol::bob::BobState;
     use std::fs::File;
+    use std::path::PathBuf;
+    use std::sync::Arc;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -433,6 +1085,29 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_last_connected_address_is_loaded_first() -> Result<()> {
+        let db = setup_test_db().await?;
+
+        let peer_id = PeerId::random();
+        let ip = "/ip4/203.0.113.7/tcp/9939".parse::<Multiaddr>()?;
+        let dns = "/dns4/seller.example.com/tcp/9939".parse::<Multiaddr>()?;
+        db.insert_address(peer_id, ip.clone()).await?;
+        db.insert_address(peer_id, dns.clone()).await?;
+        db.insert_address(peer_id, ip.clone()).await?;
+
+        assert_eq!(
+            db.get_addresses(peer_id).await?,
+            vec![ip.clone(), dns.clone()]
+        );
+
+        db.record_connected_address(peer_id, dns.clone()).await?;
+
+        assert_eq!(db.get_addresses(peer_id).await?, vec![dns, ip]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_insert_and_load_peer_id() -> Result<()> {
         let db = setup_test_db().await?;
@@ -453,14 +1128,392 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_insert_and_update_swap_summary() -> Result<()> {
+        let db = setup_test_db().await?;
+        let swap_id = Uuid::new_v4();
+
+        assert_eq!(db.get_swap_summary(swap_id).await?, None);
+
+        let mut summary = SwapSummary {
+            btc_lock_amount: Some(bitcoin::Amount::from_sat(1_000_000)),
+            ..SwapSummary::default()
+        };
+        db.insert_swap_summary(swap_id, summary.clone()).await?;
+
+        summary.tx_lock_fee = Some(bitcoin::Amount::from_sat(1_000));
+        db.insert_swap_summary(swap_id, summary.clone()).await?;
+
+        assert_eq!(db.get_swap_summary(swap_id).await?, Some(summary));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_load_broadcast_transactions() -> Result<()> {
+        let db = setup_test_db().await?;
+        let swap_id = Uuid::new_v4();
+
+        let transaction = bitcoin::Transaction {
+            version: 2,
+            lock_time: ::bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![::bitcoin::TxOut {
+                value: 10_000,
+                script_pubkey: ::bitcoin::Script::new(),
+            }],
+        };
+
+        db.insert_broadcast_transaction(swap_id, transaction.clone())
+            .await?;
+        db.insert_broadcast_transaction(swap_id, transaction.clone())
+            .await?;
+
+        assert_eq!(
+            db.get_broadcast_transactions(swap_id).await?,
+            vec![transaction]
+        );
+        assert!(db
+            .get_broadcast_transactions(Uuid::new_v4())
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_swap() -> Result<()> {
+        let db = setup_test_db().await?;
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let multiaddr = "/ip4/127.0.0.1".parse::<Multiaddr>()?;
+        let monero_address = "53gEuGZUhP9JMEBZoGaFNzhwEgiG7hwQdMCqFxiyiTeFPmkbt1mAoNybEUvYBKHcnrSgxnVWgZsTvRBaHBNXPa8tHiCU51a".parse()?;
+        let transaction = bitcoin::Transaction {
+            version: 2,
+            lock_time: ::bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+
+        db.insert_latest_state(swap_id, State::Bob(BobState::SafelyAborted))
+            .await?;
+        db.insert_latest_state(
+            swap_id,
+            State::Bob(BobState::XmrRedeemed {
+                tx_lock_id: transaction.txid(),
+            }),
+        )
+        .await?;
+        db.insert_peer_id(swap_id, peer_id).await?;
+        db.insert_address(peer_id, multiaddr.clone()).await?;
+        db.insert_monero_address(swap_id, monero_address).await?;
+        db.insert_broadcast_transaction(swap_id, transaction.clone())
+            .await?;
+
+        let backup = db.export_swap(swap_id).await?;
+
+        let other_db = setup_test_db().await?;
+        other_db.import_swap(backup.clone(), false).await?;
+
+        assert_eq!(
+            other_db.get_states(swap_id).await?,
+            db.get_states(swap_id).await?
+        );
+        assert_eq!(other_db.get_peer_id(swap_id).await?, peer_id);
+        assert_eq!(other_db.get_addresses(peer_id).await?, vec![multiaddr]);
+        assert_eq!(other_db.get_monero_address(swap_id).await?, monero_address);
+        assert_eq!(
+            other_db.get_broadcast_transactions(swap_id).await?,
+            vec![transaction]
+        );
+        assert_eq!(other_db.export_swap(swap_id).await?, backup);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_does_not_overwrite_existing_swap_unless_replacing() -> Result<()> {
+        let db = setup_test_db().await?;
+        let swap_id = Uuid::new_v4();
+
+        db.insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+            .await?;
+        let backup = db.export_swap(swap_id).await?;
+
+        db.insert_latest_state(swap_id, State::Alice(AliceState::SafelyAborted))
+            .await?;
+
+        let error = db.import_swap(backup.clone(), false).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SwapAlreadyExists>(),
+            Some(&SwapAlreadyExists(swap_id))
+        );
+        assert_eq!(db.get_states(swap_id).await?.len(), 2);
+
+        db.import_swap(backup, true).await?;
+        assert_eq!(
+            db.get_states(swap_id).await?,
+            vec![State::Alice(AliceState::BtcRedeemed)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_database_can_be_read_while_another_connection_writes() -> Result<()> {
+        let path = temp_db_path();
+        let writer = SqliteDatabase::open(&path).await?;
+        let reader = SqliteDatabase::open(&path).await?;
+        let swap_id = Uuid::new_v4();
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&reader.pool)
+            .await?;
+        assert_eq!(journal_mode, "wal");
+
+        writer
+            .insert_latest_state(swap_id, State::Alice(AliceState::SafelyAborted))
+            .await?;
+
+        let mut tx = writer.pool.begin().await?;
+        sqlx::query("insert into swap_states (swap_id, entered_at, state) values (?, ?, ?)")
+            .bind(swap_id.to_string())
+            .bind(OffsetDateTime::now_utc().to_string())
+            .bind(state_format::serialize(&Swap::from(State::Alice(
+                AliceState::BtcRedeemed,
+            )))?)
+            .execute(&mut tx)
+            .await?;
+
+        assert_eq!(
+            reader.get_state(swap_id).await?,
+            State::Alice(AliceState::SafelyAborted)
+        );
+
+        tx.commit().await?;
+
+        assert_eq!(
+            reader.get_state(swap_id).await?,
+            State::Alice(AliceState::BtcRedeemed)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_states_without_envelope_are_migrated_on_open() -> Result<()> {
+        let path = temp_db_path();
+        let db = SqliteDatabase::open(&path).await?;
+        let swap_id = Uuid::new_v4();
+
+        // States as they were stored before the state format was versioned
+        for state in [
+            r#"{"Alice":{"Done":"SafelyAborted"}}"#,
+            r#"{"Alice":{"Done":"BtcRedeemed"}}"#,
+        ] {
+            sqlx::query("insert into swap_states (swap_id, entered_at, state) values (?, ?, ?)")
+                .bind(swap_id.to_string())
+                .bind(OffsetDateTime::now_utc().to_string())
+                .bind(state)
+                .execute(&db.pool)
+                .await?;
+        }
+        sqlx::query("update state_format set version = 0")
+            .execute(&db.pool)
+            .await?;
+        drop(db);
+
+        let db = SqliteDatabase::open(&path).await?;
+
+        assert_eq!(
+            db.get_states(swap_id).await?,
+            vec![
+                State::Alice(AliceState::SafelyAborted),
+                State::Alice(AliceState::BtcRedeemed)
+            ]
+        );
+        assert_eq!(
+            db.get_state(swap_id).await?,
+            State::Alice(AliceState::BtcRedeemed)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_database_from_newer_version_is_rejected() -> Result<()> {
+        let path = temp_db_path();
+        let db = SqliteDatabase::open(&path).await?;
+
+        let newer = i64::from(CURRENT_VERSION) + 1;
+        sqlx::query("update state_format set version = ?")
+            .bind(newer)
+            .execute(&db.pool)
+            .await?;
+        drop(db);
+
+        let error = SqliteDatabase::open(&path).await.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<DatabaseFromNewerVersion>(),
+            Some(&DatabaseFromNewerVersion)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_history_of_interleaved_writers() -> Result<()> {
+        let db = Arc::new(setup_test_db().await?);
+        let states = [
+            State::Alice(AliceState::SafelyAborted),
+            State::Alice(AliceState::BtcPunished),
+            State::Alice(AliceState::XmrRefunded),
+            State::Alice(AliceState::BtcRedeemed),
+        ];
+        let swap_ids = (0..10).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+
+        let writers = swap_ids
+            .iter()
+            .map(|swap_id| {
+                let db = db.clone();
+                let swap_id = *swap_id;
+                let states = states.clone();
+
+                tokio::spawn(async move {
+                    for state in states {
+                        db.insert_latest_state(swap_id, state).await?;
+                    }
+
+                    anyhow::Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.await??;
+        }
+
+        for swap_id in swap_ids {
+            let history = db.history(swap_id).await?;
+
+            let entered_at = history.iter().map(|(entered_at, _)| *entered_at);
+            assert!(entered_at
+                .clone()
+                .zip(entered_at.skip(1))
+                .all(|(a, b)| a <= b));
+            assert_eq!(
+                history
+                    .into_iter()
+                    .map(|(_, state)| state)
+                    .collect::<Vec<_>>(),
+                states
+            );
+            assert_eq!(db.get_state(swap_id).await?, states[3]);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_only_one_of_two_contending_processes_gets_the_swap_lock() -> Result<()> {
+        let db = Arc::new(setup_test_db().await?);
+        let swap_id = Uuid::new_v4();
+
+        let contenders = [1, 2].map(|pid| {
+            let db = db.clone();
+
+            tokio::spawn(async move {
+                db.acquire_swap_lock(swap_id, pid, Duration::from_secs(60), false)
+                    .await
+                    .map(|_| pid)
+            })
+        });
+        let mut results = vec![];
+        for contender in contenders {
+            results.push(contender.await?);
+        }
+
+        let winners = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .collect::<Vec<_>>();
+        assert_eq!(winners.len(), 1);
+        let winner = *winners[0];
+
+        let error = results.into_iter().find_map(Result::err).unwrap();
+        assert_eq!(
+            error.downcast_ref::<SwapLocked>(),
+            Some(&SwapLocked {
+                swap_id,
+                pid: winner
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Swap {} is already being handled by PID {}",
+                swap_id, winner
+            )
+        );
+
+        db.release_swap_lock(swap_id, winner).await?;
+        db.acquire_swap_lock(swap_id, 3, Duration::from_secs(60), false)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_or_forced_swap_lock_is_taken_over() -> Result<()> {
+        let db = setup_test_db().await?;
+        let swap_id = Uuid::new_v4();
+        let stale_after = Duration::from_secs(60);
+
+        db.acquire_swap_lock(swap_id, 1, stale_after, false).await?;
+        assert!(db.refresh_swap_lock(swap_id, 1).await?);
+
+        db.acquire_swap_lock(swap_id, 2, stale_after, true).await?;
+        assert!(!db.refresh_swap_lock(swap_id, 1).await?);
+
+        let stale = OffsetDateTime::now_utc().unix_timestamp() - 61;
+        sqlx::query("update swap_locks set refreshed_at = ?")
+            .bind(stale)
+            .execute(&db.pool)
+            .await?;
+
+        db.acquire_swap_lock(swap_id, 3, stale_after, false).await?;
+        assert!(!db.refresh_swap_lock(swap_id, 2).await?);
+        assert!(db.refresh_swap_lock(swap_id, 3).await?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_entered_at() {
+        let now = OffsetDateTime::now_utc();
+
+        assert_eq!(parse_entered_at(&now.to_string()).unwrap(), now);
+        assert_eq!(
+            parse_entered_at("2021-09-03 5:03:45.1 +00:00:00").unwrap(),
+            time::Date::from_calendar_date(2021, time::Month::September, 3)
+                .unwrap()
+                .with_hms_milli(5, 3, 45, 100)
+                .unwrap()
+                .assume_utc()
+        );
+    }
+
     async fn setup_test_db() -> Result<SqliteDatabase> {
+        let db = SqliteDatabase::open(temp_db_path()).await?;
+
+        Ok(db)
+    }
+
+    fn temp_db_path() -> PathBuf {
         let temp_db = tempdir().unwrap().into_path().join("tempdb");
 
         // file has to exist in order to connect with sqlite
         File::create(temp_db.clone()).unwrap();
 
-        let db = SqliteDatabase::open(temp_db).await?;
-
-        Ok(db)
+        temp_db
     }
 }
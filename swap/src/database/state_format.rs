@@ -0,0 +1,144 @@
+//! Swap states are stored wrapped in an [`Envelope`] that records the version
+//! of their format. States written by an older version are upgraded when the
+//! database is opened instead of failing to deserialize, which would leave the
+//! user unable to refund.
+//!
+//! To change the format of a stored state, bump [`CURRENT_VERSION`] and append
+//! a migration from the previous version to [`MIGRATIONS`].
+
+use crate::database::Swap;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the format states are written in.
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Upgrades a payload from the version equal to its index in [`MIGRATIONS`]
+/// to the next version.
+type Migration = fn(Value) -> Result<Value>;
+
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1];
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Envelope {
+    version: u32,
+    payload: Value,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The database is from a newer version of swap, upgrade to open it")]
+pub struct DatabaseFromNewerVersion;
+
+pub fn serialize(swap: &Swap) -> Result<String> {
+    let envelope = Envelope {
+        version: CURRENT_VERSION,
+        payload: serde_json::to_value(swap)?,
+    };
+
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+pub fn deserialize(state: &str) -> Result<Swap> {
+    serde_json::from_value(payload(state)?).context("Failed to deserialize swap state")
+}
+
+/// Returns the payload of a stored state in the current format.
+pub fn payload(state: &str) -> Result<Value> {
+    let envelope: Envelope =
+        serde_json::from_str(state).context("Swap state is not wrapped in an envelope")?;
+
+    upgrade(envelope.payload, envelope.version)
+}
+
+/// Rewrites a state stored by a database whose states are at `version` in the
+/// current format.
+pub fn migrate(state: &str, version: u32) -> Result<String> {
+    let envelope = match version {
+        // Before version 1 states were stored without an envelope
+        0 => Envelope {
+            version,
+            payload: serde_json::from_str(state)?,
+        },
+        _ => serde_json::from_str(state)?,
+    };
+
+    let envelope = Envelope {
+        version: CURRENT_VERSION,
+        payload: upgrade(envelope.payload, envelope.version)?,
+    };
+
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+fn upgrade(payload: Value, version: u32) -> Result<Value> {
+    let pending = match MIGRATIONS.get(version as usize..) {
+        Some(pending) => pending,
+        None => bail!(DatabaseFromNewerVersion),
+    };
+
+    pending
+        .iter()
+        .try_fold(payload, |payload, migration| migration(payload))
+}
+
+/// Version 1 introduced the envelope, the payload itself is unchanged.
+fn v0_to_v1(payload: Value) -> Result<Value> {
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::alice::AliceEndState;
+    use crate::database::bob::BobEndState;
+    use crate::database::{Alice, Bob};
+
+    const V0_BOB_STARTED: &str = r#"{"Bob":{"Started":{"btc_amount":100000,"change_address":"bc1qe4epnfklcaa0mun26yz5g8k24em5u9f92hy325"}}}"#;
+    const V0_ALICE_BTC_REDEEMED: &str = r#"{"Alice":{"Done":"BtcRedeemed"}}"#;
+
+    #[test]
+    fn v0_states_are_wrapped_in_an_envelope() {
+        let migrated = migrate(V0_BOB_STARTED, 0).unwrap();
+
+        let envelope: Envelope = serde_json::from_str(&migrated).unwrap();
+        assert_eq!(envelope.version, CURRENT_VERSION);
+        assert_eq!(
+            deserialize(&migrated).unwrap(),
+            Swap::Bob(Bob::Started {
+                btc_amount: bitcoin::Amount::from_sat(100_000),
+                change_address: "bc1qe4epnfklcaa0mun26yz5g8k24em5u9f92hy325"
+                    .parse()
+                    .unwrap(),
+            })
+        );
+
+        let migrated = migrate(V0_ALICE_BTC_REDEEMED, 0).unwrap();
+        assert_eq!(
+            deserialize(&migrated).unwrap(),
+            Swap::Alice(Alice::Done(AliceEndState::BtcRedeemed))
+        );
+    }
+
+    #[test]
+    fn current_states_are_not_changed_by_migrating() {
+        let state = serialize(&Swap::Bob(Bob::Done(BobEndState::SafelyAborted))).unwrap();
+
+        assert_eq!(migrate(&state, CURRENT_VERSION).unwrap(), state);
+    }
+
+    #[test]
+    fn states_from_a_newer_version_are_rejected() {
+        let state = format!(
+            r#"{{"version":{},"payload":{}}}"#,
+            CURRENT_VERSION + 1,
+            V0_ALICE_BTC_REDEEMED
+        );
+
+        let error = deserialize(&state).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<DatabaseFromNewerVersion>(),
+            Some(&DatabaseFromNewerVersion)
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use crate::asb;
 use crate::bitcoin::{CancelTimelock, PunishTimelock};
+use crate::monero::TransferPriority;
+use anyhow::{bail, Result};
 use serde::Serialize;
 use std::cmp::max;
+use std::num::NonZeroU32;
 use std::time::Duration;
 use time::ext::NumericalStdDuration;
 
@@ -10,6 +13,10 @@ pub struct Config {
     pub bitcoin_lock_mempool_timeout: Duration,
     pub bitcoin_lock_confirmed_timeout: Duration,
     pub bitcoin_finality_confirmations: u32,
+    /// Upper bound for the estimated fee rate in sat/vB.
+    pub bitcoin_max_fee_rate_sat_vb: u64,
+    /// Fee rate in sat/vB used if the electrum server cannot estimate one.
+    pub bitcoin_fallback_fee_rate_sat_vb: u64,
     pub bitcoin_avg_block_time: Duration,
     pub bitcoin_cancel_timelock: CancelTimelock,
     pub bitcoin_punish_timelock: PunishTimelock,
@@ -18,6 +25,23 @@ pub struct Config {
     pub monero_finality_confirmations: u64,
     #[serde(with = "monero_network")]
     pub monero_network: monero::Network,
+    /// Fee priority of the transaction that sweeps the redeemed Monero to
+    /// the receive address.
+    pub monero_sweep_priority: TransferPriority,
+    /// How long Bob keeps redialling Alice after losing the connection to
+    /// her before he gives up on the swap, which then has to be resumed.
+    pub bob_redial_max_elapsed_time: Duration,
+    /// How often Alice and Bob check that their connection is still alive.
+    pub heartbeat_interval: Duration,
+    /// How many heartbeats in a row may go unanswered before the connection
+    /// is closed as dead, which makes Bob redial Alice.
+    pub max_missed_heartbeats: NonZeroU32,
+    /// How long Bob waits for Alice to answer a quote request. Requesting a
+    /// quote is retried a few times.
+    pub quote_timeout: Duration,
+    /// How long Bob waits for Alice to set up a swap with him, including
+    /// agreeing on the price. The swap setup is never retried.
+    pub swap_setup_timeout: Duration,
 }
 
 impl Config {
@@ -28,6 +52,61 @@ impl Config {
     pub fn monero_sync_interval(&self) -> Duration {
         sync_interval(self.monero_avg_block_time)
     }
+
+    /// Checks the invariants the protocol relies on, independent of the
+    /// network the config is used for.
+    pub fn check_invariants(&self) -> Result<()> {
+        let cancel_timelock = u32::from(self.bitcoin_cancel_timelock);
+        let punish_timelock = u32::from(self.bitcoin_punish_timelock);
+
+        if self.bitcoin_finality_confirmations == 0 {
+            bail!("Bitcoin finality confirmations must be at least 1");
+        }
+        if self.monero_finality_confirmations == 0 {
+            bail!("Monero finality confirmations must be at least 1");
+        }
+        if cancel_timelock <= self.bitcoin_finality_confirmations {
+            bail!(
+                "Cancel timelock of {} blocks must be longer than the {} Bitcoin finality confirmations",
+                cancel_timelock,
+                self.bitcoin_finality_confirmations
+            );
+        }
+        if punish_timelock <= self.bitcoin_finality_confirmations {
+            bail!(
+                "Punish timelock of {} blocks must be longer than the {} Bitcoin finality confirmations",
+                punish_timelock,
+                self.bitcoin_finality_confirmations
+            );
+        }
+        self.check_fee_rates()?;
+
+        Ok(())
+    }
+
+    /// Checks that the fee rate limits can be satisfied, shared by the asb
+    /// config and the CLI flags that override them.
+    pub fn check_fee_rates(&self) -> Result<(), InvalidFeeRates> {
+        let max = self.bitcoin_max_fee_rate_sat_vb;
+        let fallback = self.bitcoin_fallback_fee_rate_sat_vb;
+
+        if fallback == 0 {
+            return Err(InvalidFeeRates::ZeroFallback);
+        }
+        if max < fallback {
+            return Err(InvalidFeeRates::MaxBelowFallback { max, fallback });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidFeeRates {
+    #[error("Bitcoin fallback fee rate must be at least 1 sat/vB")]
+    ZeroFallback,
+    #[error("Bitcoin max fee rate of {max} sat/vB is lower than the fallback fee rate of {fallback} sat/vB")]
+    MaxBelowFallback { max: u64, fallback: u64 },
 }
 
 pub trait GetConfig {
@@ -49,6 +128,8 @@ impl GetConfig for Mainnet {
             bitcoin_lock_mempool_timeout: 10.std_minutes(),
             bitcoin_lock_confirmed_timeout: 2.std_hours(),
             bitcoin_finality_confirmations: 1,
+            bitcoin_max_fee_rate_sat_vb: 300,
+            bitcoin_fallback_fee_rate_sat_vb: 10,
             bitcoin_avg_block_time: 10.std_minutes(),
             bitcoin_cancel_timelock: CancelTimelock::new(72),
             bitcoin_punish_timelock: PunishTimelock::new(72),
@@ -56,6 +137,12 @@ impl GetConfig for Mainnet {
             monero_avg_block_time: 2.std_minutes(),
             monero_finality_confirmations: 10,
             monero_network: monero::Network::Mainnet,
+            monero_sweep_priority: TransferPriority::Default,
+            bob_redial_max_elapsed_time: 5.std_minutes(),
+            heartbeat_interval: 15.std_seconds(),
+            max_missed_heartbeats: NonZeroU32::new(3).expect("not zero"),
+            quote_timeout: 30.std_seconds(),
+            swap_setup_timeout: 60.std_seconds(),
         }
     }
 }
@@ -66,6 +153,8 @@ impl GetConfig for Testnet {
             bitcoin_lock_mempool_timeout: 10.std_minutes(),
             bitcoin_lock_confirmed_timeout: 1.std_hours(),
             bitcoin_finality_confirmations: 1,
+            bitcoin_max_fee_rate_sat_vb: 100,
+            bitcoin_fallback_fee_rate_sat_vb: 2,
             bitcoin_avg_block_time: 10.std_minutes(),
             bitcoin_cancel_timelock: CancelTimelock::new(12),
             bitcoin_punish_timelock: PunishTimelock::new(6),
@@ -73,6 +162,12 @@ impl GetConfig for Testnet {
             monero_avg_block_time: 2.std_minutes(),
             monero_finality_confirmations: 10,
             monero_network: monero::Network::Stagenet,
+            monero_sweep_priority: TransferPriority::Default,
+            bob_redial_max_elapsed_time: 5.std_minutes(),
+            heartbeat_interval: 15.std_seconds(),
+            max_missed_heartbeats: NonZeroU32::new(3).expect("not zero"),
+            quote_timeout: 30.std_seconds(),
+            swap_setup_timeout: 60.std_seconds(),
         }
     }
 }
@@ -83,6 +178,8 @@ impl GetConfig for Regtest {
             bitcoin_lock_mempool_timeout: 30.std_seconds(),
             bitcoin_lock_confirmed_timeout: 1.std_minutes(),
             bitcoin_finality_confirmations: 1,
+            bitcoin_max_fee_rate_sat_vb: 1000,
+            bitcoin_fallback_fee_rate_sat_vb: 1,
             bitcoin_avg_block_time: 5.std_seconds(),
             bitcoin_cancel_timelock: CancelTimelock::new(100),
             bitcoin_punish_timelock: PunishTimelock::new(50),
@@ -90,6 +187,12 @@ impl GetConfig for Regtest {
             monero_avg_block_time: 1.std_seconds(),
             monero_finality_confirmations: 10,
             monero_network: monero::Network::Mainnet, // yes this is strange
+            monero_sweep_priority: TransferPriority::Default,
+            bob_redial_max_elapsed_time: 5.std_minutes(),
+            heartbeat_interval: 2.std_seconds(),
+            max_missed_heartbeats: NonZeroU32::new(3).expect("not zero"),
+            quote_timeout: 30.std_seconds(),
+            swap_setup_timeout: 60.std_seconds(),
         }
     }
 }
@@ -105,23 +208,23 @@ pub fn new(is_testnet: bool, asb_config: &asb::config::Config) -> Config {
         Mainnet::get_config()
     };
 
-    let env_config =
-        if let Some(bitcoin_finality_confirmations) = asb_config.bitcoin.finality_confirmations {
-            Config {
-                bitcoin_finality_confirmations,
-                ..env_config
-            }
-        } else {
-            env_config
-        };
+    let bitcoin = &asb_config.bitcoin;
+    let monero = &asb_config.monero;
 
-    if let Some(monero_finality_confirmations) = asb_config.monero.finality_confirmations {
-        Config {
-            monero_finality_confirmations,
-            ..env_config
-        }
-    } else {
-        env_config
+    Config {
+        bitcoin_finality_confirmations: bitcoin
+            .finality_confirmations
+            .unwrap_or(env_config.bitcoin_finality_confirmations),
+        bitcoin_max_fee_rate_sat_vb: bitcoin
+            .max_fee_rate_sat_vb
+            .unwrap_or(env_config.bitcoin_max_fee_rate_sat_vb),
+        bitcoin_fallback_fee_rate_sat_vb: bitcoin
+            .fallback_fee_rate_sat_vb
+            .unwrap_or(env_config.bitcoin_fallback_fee_rate_sat_vb),
+        monero_finality_confirmations: monero
+            .finality_confirmations
+            .unwrap_or(env_config.monero_finality_confirmations),
+        ..env_config
     }
 }
 
@@ -159,4 +262,40 @@ mod tests {
 
         assert_eq!(interval, Duration::from_secs(10))
     }
+
+    #[test]
+    fn network_configs_satisfy_invariants() {
+        Mainnet::get_config().check_invariants().unwrap();
+        Testnet::get_config().check_invariants().unwrap();
+        Regtest::get_config().check_invariants().unwrap();
+    }
+
+    #[test]
+    fn cancel_timelock_within_finality_confirmations_is_rejected() {
+        let config = Config {
+            bitcoin_finality_confirmations: 3,
+            bitcoin_cancel_timelock: CancelTimelock::new(3),
+            ..Regtest::get_config()
+        };
+
+        assert!(config.check_invariants().is_err());
+    }
+
+    #[test]
+    fn asb_config_overrides_network_defaults() {
+        let mut asb_config = asb::config::Config::testnet().unwrap();
+        asb_config.bitcoin.finality_confirmations = Some(3);
+        asb_config.bitcoin.max_fee_rate_sat_vb = Some(50);
+        asb_config.monero.finality_confirmations = Some(20);
+
+        let config = new(true, &asb_config);
+
+        assert_eq!(config.bitcoin_finality_confirmations, 3);
+        assert_eq!(config.bitcoin_max_fee_rate_sat_vb, 50);
+        assert_eq!(
+            config.bitcoin_fallback_fee_rate_sat_vb,
+            Testnet::get_config().bitcoin_fallback_fee_rate_sat_vb
+        );
+        assert_eq!(config.monero_finality_confirmations, 20);
+    }
 }
@@ -3,7 +3,7 @@ use futures::{SinkExt, StreamExt, TryStreamExt};
 use serde::Deserialize;
 use std::convert::{Infallible, TryFrom};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::watch;
 use url::Url;
 
@@ -91,12 +91,30 @@ impl PriceUpdates {
     }
 }
 
+#[cfg(test)]
+impl PriceUpdates {
+    /// Price updates that always report `ask` as the asking price.
+    pub fn fixed(ask: bitcoin::Amount) -> Self {
+        Self::fixed_at(ask, Instant::now())
+    }
+
+    /// Price updates that always report `ask` as the asking price, received
+    /// at `received_at`.
+    pub fn fixed_at(ask: bitcoin::Amount, received_at: Instant) -> Self {
+        let (_, inner) = watch::channel(Ok(wire::PriceUpdate { ask, received_at }));
+
+        Self { inner }
+    }
+}
+
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum Error {
     #[error("Rate is not yet available")]
     NotYetAvailable,
     #[error("Permanently failed to retrieve rate from Kraken")]
     PermanentFailure,
+    #[error("Rate is stale, the latest update was received {}s ago", .0.as_secs())]
+    Stale(Duration),
 }
 
 type PriceUpdate = Result<wire::PriceUpdate, Error>;
@@ -258,6 +276,14 @@ mod wire {
     #[serde(try_from = "TickerUpdate")]
     pub struct PriceUpdate {
         pub ask: bitcoin::Amount,
+        /// When the update was parsed, i.e. received from the ticker.
+        pub received_at: Instant,
+    }
+
+    impl PriceUpdate {
+        pub fn age(&self) -> Duration {
+            self.received_at.elapsed()
+        }
     }
 
     #[derive(Debug, Deserialize)]
@@ -304,7 +330,10 @@ mod wire {
                 _ => return Err(Error::UnexpectedAskRateElementType),
             };
 
-            Ok(PriceUpdate { ask })
+            Ok(PriceUpdate {
+                ask,
+                received_at: Instant::now(),
+            })
         }
     }
 
@@ -16,6 +16,7 @@
     missing_copy_implementations
 )]
 
+pub mod amounts;
 pub mod api;
 pub mod asb;
 pub mod bitcoin;
@@ -31,6 +32,8 @@ pub mod network;
 pub mod protocol;
 pub mod rpc;
 pub mod seed;
+#[cfg(feature = "selftest")]
+pub mod selftest;
 pub mod tor;
 pub mod tracing_ext;
 
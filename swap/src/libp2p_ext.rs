@@ -1,3 +1,4 @@
+use anyhow::{bail, Context, Result};
 use libp2p::multiaddr::Protocol;
 use libp2p::{Multiaddr, PeerId};
 
@@ -13,3 +14,102 @@ impl MultiAddrExt for Multiaddr {
         }
     }
 }
+
+/// Returns the peer ID shared by all addresses of a single peer.
+pub fn peer_id_of(addresses: &[Multiaddr]) -> Result<PeerId> {
+    let mut peer_ids = addresses.iter().map(|address| {
+        address
+            .extract_peer_id()
+            .with_context(|| format!("Address {} must contain a peer ID", address))
+    });
+
+    let peer_id = peer_ids
+        .next()
+        .context("At least one address must be given")??;
+    for other in peer_ids {
+        if other? != peer_id {
+            bail!("The addresses of a single peer must all contain the same peer ID")
+        }
+    }
+
+    Ok(peer_id)
+}
+
+/// Groups addresses by the peer ID they contain, keeping the order in which
+/// peers and their addresses were given.
+pub fn group_by_peer_id(addresses: Vec<Multiaddr>) -> Result<Vec<(PeerId, Vec<Multiaddr>)>> {
+    let mut peers: Vec<(PeerId, Vec<Multiaddr>)> = Vec::new();
+
+    for address in addresses {
+        let peer_id = address
+            .extract_peer_id()
+            .with_context(|| format!("Address {} must contain a peer ID", address))?;
+
+        match peers.iter_mut().find(|(known, _)| *known == peer_id) {
+            Some((_, addresses)) => addresses.push(address),
+            None => peers.push((peer_id, vec![address])),
+        }
+    }
+
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_grouped_by_peer_id_in_order() {
+        let alice = PeerId::random();
+        let carol = PeerId::random();
+
+        let grouped = group_by_peer_id(vec![
+            address("/dns4/alice.example.com/tcp/9939", alice),
+            address("/ip4/203.0.113.1/tcp/9939", carol),
+            address("/ip4/203.0.113.2/tcp/9939", alice),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            grouped,
+            vec![
+                (
+                    alice,
+                    vec![
+                        address("/dns4/alice.example.com/tcp/9939", alice),
+                        address("/ip4/203.0.113.2/tcp/9939", alice)
+                    ]
+                ),
+                (carol, vec![address("/ip4/203.0.113.1/tcp/9939", carol)])
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_of_one_peer_must_share_the_peer_id() {
+        let alice = PeerId::random();
+
+        assert_eq!(
+            peer_id_of(&[
+                address("/dns4/alice.example.com/tcp/9939", alice),
+                address("/ip4/203.0.113.2/tcp/9939", alice),
+            ])
+            .unwrap(),
+            alice
+        );
+        assert!(peer_id_of(&[
+            address("/dns4/alice.example.com/tcp/9939", alice),
+            address("/ip4/203.0.113.2/tcp/9939", PeerId::random()),
+        ])
+        .is_err());
+        assert!(peer_id_of(&["/ip4/203.0.113.2/tcp/9939".parse().unwrap()]).is_err());
+        assert!(peer_id_of(&[]).is_err());
+    }
+
+    fn address(address: &str, peer_id: PeerId) -> Multiaddr {
+        address
+            .parse::<Multiaddr>()
+            .unwrap()
+            .with(Protocol::P2p(peer_id.into()))
+    }
+}
@@ -4,9 +4,11 @@ mod wallet_rpc;
 pub use ::monero::network::Network;
 pub use ::monero::{Address, PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
+pub use monero_rpc::wallet::TransferPriority;
 pub use wallet::Wallet;
-pub use wallet_rpc::{WalletRpc, WalletRpcProcess};
+pub use wallet_rpc::{check_daemon, WalletRpc, WalletRpcProcess};
 
+use crate::amounts::{self, Rounding};
 use crate::bitcoin;
 use anyhow::Result;
 use rand::{CryptoRng, RngCore};
@@ -20,6 +22,9 @@ use std::str::FromStr;
 
 pub const PICONERO_OFFSET: u64 = 1_000_000_000_000;
 
+/// Monero outputs can only be spent once they have this many confirmations.
+pub const SPENDABLE_AGE: u64 = 10;
+
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "Network")]
 #[allow(non_camel_case_types)]
@@ -116,16 +121,18 @@ impl Amount {
             return Some(bitcoin::Amount::ZERO);
         }
 
-        // safely convert the BTC/XMR rate to sat/pico
-        let ask_sats = Decimal::from(ask_price.to_sat());
-        let pico_per_xmr = Decimal::from(PICONERO_OFFSET);
-        let ask_sats_per_pico = ask_sats / pico_per_xmr;
+        // Rounding down ensures the BTC never buys more than the balance
+        amounts::Rate::from_price(ask_price)
+            .xmr_to_btc(Amount::from_piconero(pico_minus_fee), Rounding::Down)
+            .ok()
+    }
 
-        let pico = Decimal::from(pico_minus_fee);
-        let max_sats = pico.checked_mul(ask_sats_per_pico)?;
-        let satoshi = max_sats.to_u64()?;
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
 
-        Some(bitcoin::Amount::from_sat(satoshi))
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
     }
 
     pub fn from_monero(amount: f64) -> Result<Self> {
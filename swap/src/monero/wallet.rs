@@ -1,14 +1,20 @@
 use crate::env::Config;
 use crate::monero::{
-    Amount, InsufficientFunds, PrivateViewKey, PublicViewKey, TransferProof, TxHash,
+    Amount, InsufficientFunds, PrivateViewKey, PublicViewKey, Scalar, TransferPriority,
+    TransferProof, TxHash,
 };
 use ::monero::{Address, Network, PrivateKey, PublicKey};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use monero_rpc::wallet::{BlockHeight, MoneroWalletRpc as _, Refreshed};
-use monero_rpc::{jsonrpc, wallet};
+#[cfg(feature = "zmq")]
+use monero_rpc::zmq::BlockSubscription;
+use monero_rpc::{auth, jsonrpc, wallet};
+use rand::rngs::OsRng;
+use std::future::Future;
 use std::str::FromStr;
 use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex, MutexGuard};
 use tokio::time::Interval;
 use url::Url;
 
@@ -19,21 +25,47 @@ pub struct Wallet {
     name: String,
     main_address: monero::Address,
     sync_interval: Duration,
+    sweep_priority: TransferPriority,
+    /// Heights of new blocks announced by monerod, see
+    /// `Wallet::with_block_subscription`.
+    new_blocks: Option<watch::Receiver<u64>>,
+    /// Held while a swap works with a wallet it loaded itself, see
+    /// [`Wallet::claim_loaded_wallet`].
+    loaded_wallet: Mutex<()>,
+    /// The wallet RPC process we started ourselves, see
+    /// [`Wallet::with_rpc_process`].
+    rpc_process: Option<Box<dyn Restart>>,
+}
+
+/// A wallet RPC that can be started again on the same endpoint after it
+/// stopped.
+#[async_trait]
+pub trait Restart: Send + Sync {
+    async fn restart(&self) -> Result<()>;
 }
 
 impl Wallet {
     /// Connect to a wallet RPC and load the given wallet by name.
-    pub async fn open_or_create(url: Url, name: String, env_config: Config) -> Result<Self> {
+    ///
+    /// If the wallet does not exist yet it is created. With a
+    /// `restore_height` the new wallet only scans the chain from that height
+    /// on, monero-wallet-rpc stores the height in the wallet file.
+    pub async fn open_or_create(
+        url: Url,
+        name: String,
+        env_config: Config,
+        restore_height: Option<BlockHeight>,
+    ) -> Result<Self> {
         let client = wallet::Client::new(url)?;
 
         match client.open_wallet(name.clone()).await {
             Err(error) => {
                 tracing::debug!(%error, "Open wallet response error");
-                client.create_wallet(name.clone(), "English".to_owned()).await.context(
+                create_wallet(&client, name.clone(), env_config.monero_network, restore_height).await.context(
                     "Unable to create Monero wallet, please ensure that the monero-wallet-rpc is available",
                 )?;
 
-                tracing::debug!(monero_wallet_name = %name, "Created Monero wallet");
+                tracing::debug!(monero_wallet_name = %name, ?restore_height, "Created Monero wallet");
             }
             Ok(_) => tracing::debug!(monero_wallet_name = %name, "Opened Monero wallet"),
         }
@@ -52,9 +84,29 @@ impl Wallet {
             name,
             main_address,
             sync_interval: env_config.monero_sync_interval(),
+            sweep_priority: env_config.monero_sweep_priority,
+            new_blocks: None,
+            loaded_wallet: Mutex::new(()),
+            rpc_process: None,
         })
     }
 
+    /// Checks for confirmations whenever monerod announces a new block
+    /// instead of polling every sync interval.
+    #[cfg(feature = "zmq")]
+    pub fn with_block_subscription(mut self, subscription: BlockSubscription) -> Self {
+        self.new_blocks = Some(subscription.heights());
+        self
+    }
+
+    /// Takes ownership of the wallet RPC process so it lives as long as the
+    /// wallet. If the process stops accepting connections it is restarted
+    /// once per call and the wallet re-opened.
+    pub fn with_rpc_process(mut self, process: impl Restart + 'static) -> Self {
+        self.rpc_process = Some(Box::new(process));
+        self
+    }
+
     /// Re-open the wallet using the internally stored name.
     pub async fn re_open(&self) -> Result<()> {
         self.inner
@@ -70,20 +122,27 @@ impl Wallet {
         Ok(())
     }
 
+    /// Claims the wallet RPC for a sequence of calls that load another wallet
+    /// and work with it, e.g. generating a wallet from keys and sweeping it.
+    ///
+    /// Swaps running concurrently wait until the returned guard is dropped,
+    /// so they never refresh or sweep a wallet that another swap loaded.
+    pub async fn claim_loaded_wallet(&self) -> MutexGuard<'_, ()> {
+        self.loaded_wallet.lock().await
+    }
+
     /// Close the wallet and open (load) another wallet by generating it from
     /// keys. The generated wallet will remain loaded.
-    pub async fn create_from_and_load(
+    ///
+    /// If a wallet with this name exists already, e.g. because an earlier
+    /// attempt to redeem failed after generating it, it is opened instead.
+    pub async fn create_from_keys(
         &self,
         file_name: String,
         private_spend_key: PrivateKey,
         private_view_key: PrivateViewKey,
         restore_height: BlockHeight,
     ) -> Result<()> {
-        let public_spend_key = PublicKey::from_private_key(&private_spend_key);
-        let public_view_key = PublicKey::from_private_key(&private_view_key.into());
-
-        let address = Address::standard(self.network, public_spend_key, public_view_key);
-
         let wallet = self.inner.lock().await;
 
         // Properly close the wallet before generating the other wallet to ensure that
@@ -93,20 +152,15 @@ impl Wallet {
             .await
             .context("Failed to close wallet")?;
 
-        let _ = wallet
-            .generate_from_keys(
-                file_name,
-                address.to_string(),
-                private_spend_key.to_string(),
-                PrivateKey::from(private_view_key).to_string(),
-                restore_height.height,
-                String::from(""),
-                true,
-            )
-            .await
-            .context("Failed to generate new wallet from keys")?;
-
-        Ok(())
+        generate_or_open(
+            &*wallet,
+            file_name,
+            self.network,
+            private_spend_key,
+            private_view_key,
+            restore_height,
+        )
+        .await
     }
 
     /// Close the wallet and open (load) another wallet by generating it from
@@ -151,7 +205,7 @@ impl Wallet {
                 .inner
                 .lock()
                 .await
-                .sweep_all(self.main_address.to_string())
+                .sweep_all(self.main_address.to_string(), self.sweep_priority)
                 .await
             {
                 Ok(sweep_all) => {
@@ -185,8 +239,6 @@ impl Wallet {
     }
 
     pub async fn transfer(&self, request: TransferRequest) -> Result<TransferProof> {
-        let inner = self.inner.lock().await;
-
         let TransferRequest {
             public_spend_key,
             public_view_key,
@@ -196,8 +248,16 @@ impl Wallet {
         let destination_address =
             Address::standard(self.network, public_spend_key, public_view_key.into());
 
-        let res = inner
-            .transfer_single(0, amount.as_piconero(), &destination_address.to_string())
+        let destination_address = destination_address.to_string();
+        let res = self
+            .call(|client| {
+                let destination_address = destination_address.clone();
+                async move {
+                    client
+                        .transfer_single(0, amount.as_piconero(), &destination_address)
+                        .await
+                }
+            })
             .await?;
 
         tracing::debug!(
@@ -233,7 +293,10 @@ impl Wallet {
 
         let address = Address::standard(self.network, public_spend_key, public_view_key.into());
 
-        let check_interval = tokio::time::interval(self.sync_interval);
+        let checks = match &self.new_blocks {
+            Some(new_blocks) => Checks::on_new_blocks(new_blocks.clone(), self.sync_interval),
+            None => Checks::polling(self.sync_interval),
+        };
 
         wait_for_confirmations(
             &self.inner,
@@ -241,7 +304,7 @@ impl Wallet {
             address,
             expected,
             conf_target,
-            check_interval,
+            checks,
             self.name.clone(),
         )
         .await?;
@@ -249,25 +312,77 @@ impl Wallet {
         Ok(())
     }
 
+    /// Transfers all unlocked funds of the loaded wallet to `address`.
+    ///
+    /// Retries while the daemon is busy or the wallet RPC lost its connection
+    /// to it, other errors are returned right away.
     pub async fn sweep_all(&self, address: Address) -> Result<Vec<TxHash>> {
-        let sweep_all = self
-            .inner
-            .lock()
-            .await
-            .sweep_all(address.to_string())
-            .await?;
+        const MAX_ATTEMPTS: usize = 10;
+        const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+        let mut attempt = 1;
+        let sweep_all = loop {
+            let result = self
+                .call(|client| async move {
+                    client
+                        .sweep_all(address.to_string(), self.sweep_priority)
+                        .await
+                })
+                .await;
+
+            match result {
+                Ok(sweep_all) => break sweep_all,
+                Err(error) if attempt < MAX_ATTEMPTS && is_temporary_daemon_error(&error) => {
+                    tracing::warn!(%attempt, %address, "Failed to sweep Monero, retrying: {:#}", error);
+                }
+                Err(error) => return Err(error),
+            }
+
+            attempt += 1;
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        };
+
+        for ((tx_hash, amount), fee) in sweep_all
+            .tx_hash_list
+            .iter()
+            .zip(&sweep_all.amount_list)
+            .zip(&sweep_all.fee_list)
+        {
+            tracing::debug!(
+                txid = %tx_hash,
+                amount = %Amount::from_piconero(*amount),
+                fee = %Amount::from_piconero(*fee),
+                "Swept Monero",
+            );
+        }
 
         let tx_hashes = sweep_all.tx_hash_list.into_iter().map(TxHash).collect();
         Ok(tx_hashes)
     }
 
+    /// Looks up a transaction of the loaded wallet, e.g. to learn the amount
+    /// it received and the fee that was paid.
+    pub async fn get_transfer(&self, tx_hash: &TxHash) -> Result<wallet::TransferEntry> {
+        let txid = tx_hash.0.clone();
+        let response = self
+            .call(|client| {
+                let txid = txid.clone();
+                async move { client.get_transfer_by_txid(txid).await }
+            })
+            .await?;
+
+        Ok(response.transfer)
+    }
+
     /// Get the balance of the primary account.
     pub async fn get_balance(&self) -> Result<wallet::GetBalance> {
-        Ok(self.inner.lock().await.get_balance(0).await?)
+        self.call(|client| async move { client.get_balance(0).await })
+            .await
     }
 
     pub async fn block_height(&self) -> Result<BlockHeight> {
-        Ok(self.inner.lock().await.get_height().await?)
+        self.call(|client| async move { client.get_height().await })
+            .await
     }
 
     pub fn get_main_address(&self) -> Address {
@@ -313,6 +428,29 @@ impl Wallet {
         }
         unreachable!("Loop should have returned by now");
     }
+
+    /// Calls the wallet RPC. If it refuses the connection and we started it
+    /// ourselves, it is restarted, the wallet re-opened and the call tried
+    /// once more.
+    async fn call<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: Fn(wallet::Client) -> Fut,
+        Fut: Future<Output = Result<T, jsonrpc::Error<auth::Error>>>,
+    {
+        let client = self.inner.lock().await;
+
+        match (call(client.clone()).await, &self.rpc_process) {
+            (Err(jsonrpc::Error::Client(error)), Some(process)) if error.is_connect() => {
+                tracing::warn!(%error, "Monero wallet RPC is not reachable, restarting it");
+
+                process.restart().await?;
+                client.open_wallet(self.name.clone()).await?;
+
+                Ok(call(client.clone()).await?)
+            }
+            (result, _) => Ok(result?),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -331,19 +469,118 @@ pub struct WatchRequest {
     pub expected: Amount,
 }
 
-async fn wait_for_confirmations<C: monero_rpc::wallet::MoneroWalletRpc<reqwest::Client> + Sync>(
+/// Creates and loads a new wallet. Without a `restore_height`
+/// monero-wallet-rpc decides where the wallet starts scanning, which can be
+/// the genesis block.
+async fn create_wallet<C: monero_rpc::wallet::MoneroWalletRpc<monero_rpc::auth::Client> + Sync>(
+    client: &C,
+    name: String,
+    network: Network,
+    restore_height: Option<BlockHeight>,
+) -> Result<()> {
+    let restore_height = match restore_height {
+        Some(restore_height) => restore_height,
+        None => {
+            client.create_wallet(name, "English".to_owned()).await?;
+            return Ok(());
+        }
+    };
+
+    // create_wallet does not take a restore height, so we generate the keys
+    // ourselves
+    let spend_key = PrivateKey::from_scalar(Scalar::random(&mut OsRng));
+    let view_key = PrivateViewKey::new_random(&mut OsRng);
+    let address = Address::standard(
+        network,
+        PublicKey::from_private_key(&spend_key),
+        view_key.public().into(),
+    );
+
+    client
+        .generate_from_keys(
+            name,
+            address.to_string(),
+            spend_key.to_string(),
+            PrivateKey::from(view_key).to_string(),
+            restore_height.height,
+            String::new(),
+            true,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Generates a wallet from keys, or opens it if a wallet with that name
+/// exists already.
+async fn generate_or_open<
+    C: monero_rpc::wallet::MoneroWalletRpc<monero_rpc::auth::Client> + Sync,
+>(
+    client: &C,
+    file_name: String,
+    network: Network,
+    private_spend_key: PrivateKey,
+    private_view_key: PrivateViewKey,
+    restore_height: BlockHeight,
+) -> Result<()> {
+    let public_spend_key = PublicKey::from_private_key(&private_spend_key);
+    let public_view_key = PublicKey::from_private_key(&private_view_key.into());
+    let address = Address::standard(network, public_spend_key, public_view_key);
+
+    let generated = client
+        .generate_from_keys(
+            file_name.clone(),
+            address.to_string(),
+            private_spend_key.to_string(),
+            PrivateKey::from(private_view_key).to_string(),
+            restore_height.height,
+            String::from(""),
+            true,
+        )
+        .await;
+
+    if let Err(error) = generated {
+        tracing::debug!(%file_name, %error, "Failed to generate wallet from keys, trying to open it");
+
+        client
+            .open_wallet(file_name)
+            .await
+            .map_err(anyhow::Error::from)
+            .context(error)
+            .context("Failed to generate new wallet from keys")?;
+    }
+
+    Ok(())
+}
+
+/// Whether monero-wallet-rpc failed because the daemon is busy or it lost
+/// the connection to the daemon, which usually resolves itself.
+fn is_temporary_daemon_error(error: &anyhow::Error) -> bool {
+    const DAEMON_IS_BUSY: i64 = -3;
+    const NO_DAEMON_CONNECTION: i64 = -38;
+
+    matches!(
+        error.downcast_ref::<jsonrpc::Error<auth::Error>>(),
+        Some(jsonrpc::Error::JsonRpc(jsonrpc::JsonRpcError { code, .. }))
+            if *code == DAEMON_IS_BUSY || *code == NO_DAEMON_CONNECTION
+    )
+}
+
+async fn wait_for_confirmations<
+    C: monero_rpc::wallet::MoneroWalletRpc<monero_rpc::auth::Client> + Sync,
+>(
     client: &Mutex<C>,
     transfer_proof: TransferProof,
     to_address: Address,
     expected: Amount,
     conf_target: u64,
-    mut check_interval: Interval,
+    mut checks: Checks,
     wallet_name: String,
 ) -> Result<(), InsufficientFunds> {
     let mut seen_confirmations = 0u64;
 
     while seen_confirmations < conf_target {
-        check_interval.tick().await; // tick() at the beginning of the loop so every `continue` tick()s as well
+        checks.next().await; // wait at the beginning of the loop so every `continue` waits as well
 
         let txid = transfer_proof.tx_hash().to_string();
         let client = client.lock().await;
@@ -410,12 +647,65 @@ async fn wait_for_confirmations<C: monero_rpc::wallet::MoneroWalletRpc<reqwest::
     Ok(())
 }
 
+/// Decides when to check a transaction for new confirmations again.
+#[derive(Debug)]
+struct Checks {
+    interval: Interval,
+    sync_interval: Duration,
+    new_blocks: Option<watch::Receiver<u64>>,
+}
+
+impl Checks {
+    /// Only checked in case a block notification got lost.
+    const MISSED_BLOCK_INTERVAL: Duration = Duration::from_secs(120);
+
+    fn polling(sync_interval: Duration) -> Self {
+        Self {
+            interval: tokio::time::interval(sync_interval),
+            sync_interval,
+            new_blocks: None,
+        }
+    }
+
+    fn on_new_blocks(new_blocks: watch::Receiver<u64>, sync_interval: Duration) -> Self {
+        Self {
+            interval: tokio::time::interval(Self::MISSED_BLOCK_INTERVAL),
+            sync_interval,
+            new_blocks: Some(new_blocks),
+        }
+    }
+
+    /// Completes immediately the first time, afterwards on the next tick or
+    /// new block.
+    async fn next(&mut self) {
+        let new_blocks = match &mut self.new_blocks {
+            Some(new_blocks) => new_blocks,
+            None => {
+                self.interval.tick().await;
+                return;
+            }
+        };
+
+        let subscription_closed = tokio::select! {
+            _ = self.interval.tick() => false,
+            changed = new_blocks.changed() => changed.is_err(),
+        };
+
+        if subscription_closed {
+            tracing::debug!("Lost block subscription, falling back to polling");
+            self.new_blocks = None;
+            self.interval = tokio::time::interval(self.sync_interval);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tracing_ext::capture_logs;
     use monero_rpc::wallet::CheckTxKey;
     use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
     use tracing::metadata::LevelFilter;
 
     #[tokio::test]
@@ -433,7 +723,7 @@ mod tests {
             "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
             Amount::from_piconero(100),
             10,
-            tokio::time::interval(Duration::from_millis(10)),
+            Checks::polling(Duration::from_millis(10)),
             "foo-wallet".to_owned()
         )
         .await;
@@ -484,7 +774,7 @@ mod tests {
             "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
             Amount::from_piconero(100),
             5,
-            tokio::time::interval(Duration::from_millis(10)),
+            Checks::polling(Duration::from_millis(10)),
             "foo-wallet".to_owned()
         )
         .await
@@ -531,7 +821,7 @@ mod tests {
             "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
             Amount::from_piconero(100),
             5,
-            tokio::time::interval(Duration::from_millis(10)),
+            Checks::polling(Duration::from_millis(10)),
             "foo-wallet".to_owned()
         )
         .await
@@ -555,6 +845,239 @@ DEBUG swap::monero::wallet: Opening wallet `foo-wallet` because no wallet is loa
         );
     }
 
+    #[tokio::test]
+    async fn checks_again_as_soon_as_a_block_is_announced() {
+        let client = Mutex::new(DummyClient::new(vec![
+            Ok(CheckTxKey {
+                confirmations: 0,
+                received: 100,
+            }),
+            Ok(CheckTxKey {
+                confirmations: 1,
+                received: 100,
+            }),
+        ]));
+        let (new_blocks, heights) = watch::channel(0);
+        // The sync interval is never used while the subscription is alive.
+        let checks = Checks::on_new_blocks(heights, Duration::from_secs(3600));
+
+        let announce_block = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            new_blocks.send(1).unwrap();
+        };
+        let waiting = wait_for_confirmations(
+            &client,
+            TransferProof::new(TxHash("<FOO>".to_owned()), PrivateKey {
+                scalar: crate::monero::Scalar::random(&mut rand::thread_rng())
+            }),
+            "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
+            Amount::from_piconero(100),
+            1,
+            checks,
+            "foo-wallet".to_owned()
+        );
+
+        let (result, ()) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(waiting, announce_block)
+        })
+        .await
+        .expect("confirmation to be seen right after the block was announced");
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_polling_when_block_subscription_closes() {
+        let client = Mutex::new(DummyClient::new(vec![
+            Ok(CheckTxKey {
+                confirmations: 0,
+                received: 100,
+            }),
+            Ok(CheckTxKey {
+                confirmations: 0,
+                received: 100,
+            }),
+            Ok(CheckTxKey {
+                confirmations: 1,
+                received: 100,
+            }),
+        ]));
+        let (new_blocks, heights) = watch::channel(0);
+        drop(new_blocks);
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            wait_for_confirmations(
+                &client,
+                TransferProof::new(TxHash("<FOO>".to_owned()), PrivateKey {
+                    scalar: crate::monero::Scalar::random(&mut rand::thread_rng())
+                }),
+                "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
+                Amount::from_piconero(100),
+                1,
+                Checks::on_new_blocks(heights, Duration::from_millis(10)),
+                "foo-wallet".to_owned()
+            ),
+        )
+        .await
+        .expect("polling to take over from the closed subscription");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            client
+                .lock()
+                .await
+                .check_tx_key_invocations
+                .load(Ordering::SeqCst),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn fresh_wallet_starts_scanning_at_the_restore_height() {
+        let client = DummyClient::new(vec![]);
+
+        create_wallet(
+            &client,
+            "foo-wallet".to_owned(),
+            Network::Stagenet,
+            Some(BlockHeight { height: 1_700_000 }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *client.generated_restore_heights.lock().unwrap(),
+            vec![1_700_000]
+        );
+        assert_eq!(client.create_wallet_invocations.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn wallet_without_restore_height_is_created_by_wallet_rpc() {
+        let client = DummyClient::new(vec![]);
+
+        create_wallet(&client, "foo-wallet".to_owned(), Network::Stagenet, None)
+            .await
+            .unwrap();
+
+        assert!(client.generated_restore_heights.lock().unwrap().is_empty());
+        assert_eq!(client.create_wallet_invocations.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn generating_an_existing_wallet_from_keys_opens_it() {
+        let client = DummyClient::new(vec![]);
+        let spend_key = PrivateKey::from_scalar(Scalar::random(&mut OsRng));
+        let view_key = PrivateViewKey::new_random(&mut OsRng);
+        let generate = || {
+            generate_or_open(
+                &client,
+                "swap-id".to_owned(),
+                Network::Stagenet,
+                spend_key,
+                view_key,
+                BlockHeight { height: 1000 },
+            )
+        };
+
+        generate().await.unwrap();
+        generate().await.unwrap();
+
+        assert_eq!(client.generated_wallets.lock().unwrap().len(), 1);
+        assert_eq!(client.open_wallet_invocations.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn only_busy_or_unreachable_daemon_is_a_temporary_error() {
+        let error = |code| {
+            anyhow::Error::new(jsonrpc::Error::<auth::Error>::JsonRpc(
+                jsonrpc::JsonRpcError {
+                    code,
+                    message: "error".to_owned(),
+                    data: None,
+                },
+            ))
+        };
+
+        assert!(is_temporary_daemon_error(&error(-3)));
+        assert!(is_temporary_daemon_error(&error(-38)));
+        assert!(!is_temporary_daemon_error(&error(-4)));
+        assert!(!is_temporary_daemon_error(&anyhow::anyhow!(
+            "not a wallet RPC error"
+        )));
+    }
+
+    #[tokio::test]
+    async fn block_height_recovers_after_wallet_rpc_stopped() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let restarts = Arc::new(AtomicU32::new(0));
+        let wallet = Wallet {
+            inner: Mutex::new(wallet::Client::localhost(port).unwrap()),
+            network: Network::Stagenet,
+            name: "foo-wallet".to_owned(),
+            main_address: "53H3QthYLckeCXh9u38vohb2gZ4QgEG3FMWHNxccR6MqV1LdDVYwF1FKsRJPj4tTupWLf9JtGPBcn2MVN6c9oR7p5Uf7JdJ".parse().unwrap(),
+            sync_interval: Duration::from_secs(1),
+            sweep_priority: TransferPriority::Default,
+            new_blocks: None,
+            loaded_wallet: Mutex::new(()),
+            rpc_process: None,
+        }
+        .with_rpc_process(StoppedWalletRpc {
+            port,
+            server: Mutex::new(None),
+            restarts: restarts.clone(),
+        });
+
+        let height = wallet.block_height().await.unwrap();
+
+        assert_eq!(height.height, 3250000);
+        assert_eq!(restarts.load(Ordering::SeqCst), 1);
+    }
+
+    /// A wallet RPC that is not running until it is restarted.
+    struct StoppedWalletRpc {
+        port: u16,
+        server: Mutex<Option<mockito::ServerGuard>>,
+        restarts: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Restart for StoppedWalletRpc {
+        async fn restart(&self) -> Result<()> {
+            self.restarts.fetch_add(1, Ordering::SeqCst);
+
+            let mut server = mockito::Server::new_with_opts_async(mockito::ServerOpts {
+                port: self.port,
+                ..Default::default()
+            })
+            .await;
+            let _ = server
+                .mock("POST", "/json_rpc")
+                .match_body(mockito::Matcher::PartialJson(
+                    serde_json::json!({ "method": "open_wallet" }),
+                ))
+                .with_body(r#"{ "jsonrpc": "2.0", "id": "1", "result": {} }"#)
+                .create_async()
+                .await;
+            let _ = server
+                .mock("POST", "/json_rpc")
+                .match_body(mockito::Matcher::PartialJson(
+                    serde_json::json!({ "method": "get_height" }),
+                ))
+                .with_body(r#"{ "jsonrpc": "2.0", "id": "1", "result": { "height": 3250000 } }"#)
+                .create_async()
+                .await;
+            *self.server.lock().await = Some(server);
+
+            Ok(())
+        }
+    }
+
     type ErrorCode = i64;
     type ErrorMessage = String;
 
@@ -563,6 +1086,9 @@ DEBUG swap::monero::wallet: Opening wallet `foo-wallet` because no wallet is loa
 
         check_tx_key_invocations: AtomicU32,
         open_wallet_invocations: AtomicU32,
+        create_wallet_invocations: AtomicU32,
+        generated_restore_heights: std::sync::Mutex<Vec<u32>>,
+        generated_wallets: std::sync::Mutex<Vec<String>>,
     }
 
     impl DummyClient {
@@ -573,27 +1099,79 @@ DEBUG swap::monero::wallet: Opening wallet `foo-wallet` because no wallet is loa
                 check_tx_key_responses,
                 check_tx_key_invocations: Default::default(),
                 open_wallet_invocations: Default::default(),
+                create_wallet_invocations: Default::default(),
+                generated_restore_heights: Default::default(),
+                generated_wallets: Default::default(),
             }
         }
     }
 
     #[async_trait::async_trait]
-    impl monero_rpc::wallet::MoneroWalletRpc<reqwest::Client> for DummyClient {
+    impl monero_rpc::wallet::MoneroWalletRpc<monero_rpc::auth::Client> for DummyClient {
         async fn open_wallet(
             &self,
             _: String,
-        ) -> Result<wallet::WalletOpened, monero_rpc::jsonrpc::Error<reqwest::Error>> {
+        ) -> Result<wallet::WalletOpened, monero_rpc::jsonrpc::Error<monero_rpc::auth::Error>>
+        {
             self.open_wallet_invocations.fetch_add(1, Ordering::SeqCst);
 
             Ok(monero_rpc::wallet::Empty {})
         }
 
+        async fn create_wallet(
+            &self,
+            _: String,
+            _: String,
+        ) -> Result<wallet::WalletCreated, monero_rpc::jsonrpc::Error<monero_rpc::auth::Error>>
+        {
+            self.create_wallet_invocations
+                .fetch_add(1, Ordering::SeqCst);
+
+            Ok(monero_rpc::wallet::Empty {})
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        async fn generate_from_keys(
+            &self,
+            filename: String,
+            _: String,
+            _: String,
+            _: String,
+            restore_height: u32,
+            _: String,
+            _: bool,
+        ) -> Result<wallet::GenerateFromKeys, monero_rpc::jsonrpc::Error<monero_rpc::auth::Error>>
+        {
+            let mut generated_wallets = self.generated_wallets.lock().unwrap();
+            if generated_wallets.contains(&filename) {
+                return Err(monero_rpc::jsonrpc::Error::JsonRpc(
+                    monero_rpc::jsonrpc::JsonRpcError {
+                        code: -21,
+                        message: "Wallet already exists.".to_owned(),
+                        data: None,
+                    },
+                ));
+            }
+            generated_wallets.push(filename);
+
+            self.generated_restore_heights
+                .lock()
+                .unwrap()
+                .push(restore_height);
+
+            Ok(wallet::GenerateFromKeys {
+                address: String::new(),
+                info: String::new(),
+            })
+        }
+
         async fn check_tx_key(
             &self,
             _: String,
             _: String,
             _: String,
-        ) -> Result<wallet::CheckTxKey, monero_rpc::jsonrpc::Error<reqwest::Error>> {
+        ) -> Result<wallet::CheckTxKey, monero_rpc::jsonrpc::Error<monero_rpc::auth::Error>>
+        {
             let index = self.check_tx_key_invocations.fetch_add(1, Ordering::SeqCst);
 
             self.check_tx_key_responses[index as usize]
@@ -610,7 +1188,7 @@ DEBUG swap::monero::wallet: Opening wallet `foo-wallet` because no wallet is loa
         async fn send_request<P>(
             &self,
             _: String,
-        ) -> Result<monero_rpc::jsonrpc::Response<P>, reqwest::Error>
+        ) -> Result<monero_rpc::jsonrpc::Response<P>, monero_rpc::auth::Error>
         where
             P: serde::de::DeserializeOwned,
         {
@@ -1,22 +1,27 @@
+use crate::monero::wallet::Restart;
 use ::monero::Network;
 use anyhow::{bail, Context, Error, Result};
+use async_trait::async_trait;
 use big_bytes::BigByte;
 use data_encoding::HEXLOWER;
 use futures::{StreamExt, TryStreamExt};
-use monero_rpc::wallet::{Client, MoneroWalletRpc as _};
-use reqwest::header::CONTENT_LENGTH;
+use monero_rpc::wallet::{BlockHeight, Client, MoneroWalletRpc as _};
+use monero_rpc::{auth, Credentials};
 use reqwest::Url;
 use serde::Deserialize;
 use sha2::{Digest, Sha256};
+use std::ffi::OsString;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::io::ErrorKind;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::time::Duration;
 use tokio::fs::{remove_file, OpenOptions};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
 use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use tokio_util::io::StreamReader;
 
@@ -77,14 +82,47 @@ const PACKED_FILE: &str = "monero-wallet-rpc";
 #[cfg(target_os = "windows")]
 const PACKED_FILE: &str = "monero-wallet-rpc.exe";
 
+/// How long monero-wallet-rpc gets to store the wallet and exit before it is
+/// killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 const WALLET_RPC_VERSION: &str = "v0.18.3.1";
 
+/// Major and minor version of the monero-wallet-rpc releases we have tested
+/// against. A system binary outside this range is used but logs a warning.
+const TESTED_WALLET_RPC_RELEASE: (u32, u32) = (0, 18);
+
 #[derive(Debug, Clone, Copy, thiserror::Error)]
 #[error("monero wallet rpc executable not found in downloaded archive")]
 pub struct ExecutableNotFoundInArchive;
 
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("monero-wallet-rpc binary not found at {}", .0.display())]
+pub struct BinaryNotFound(pub PathBuf);
+
+/// The monero-wallet-rpc archive could not be downloaded, e.g. because there
+/// is no internet connection. Retrying later may help.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Failed to download monero-wallet-rpc from {0}")]
+pub struct DownloadFailed(pub &'static str);
+
+/// The downloaded monero-wallet-rpc archive is not the one we pinned. It was
+/// corrupted in transit or tampered with and is not extracted.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("SHA256 of the downloaded monero-wallet-rpc archive ({actual}) does not match the expected {expected}")]
+pub struct ChecksumMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+/// A monero-wallet-rpc started by [`WalletRpc::run`].
+///
+/// The process is killed when this is dropped, use
+/// [`WalletRpcProcess::shutdown`] to let it store the wallet first.
 pub struct WalletRpcProcess {
-    _child: Child,
+    child: Mutex<Child>,
+    exec_path: PathBuf,
+    args: Vec<OsString>,
     port: u16,
 }
 
@@ -104,26 +142,12 @@ impl MoneroDaemon {
     }
 
     /// Checks if the Monero daemon is available by sending a request to its `get_info` endpoint.
-    async fn is_available(&self, client: &reqwest::Client) -> Result<bool, Error> {
-        let url = format!("http://{}:{}/get_info", self.address, self.port);
-        let res = client
-            .get(url)
-            .send()
-            .await
-            .context("Failed to send request to get_info endpoint")?;
-
-        let json: MoneroDaemonGetInfoResponse = res
-            .json()
-            .await
-            .context("Failed to deserialize daemon get_info response")?;
+    async fn is_available(&self, client: &auth::Client) -> Result<bool, Error> {
+        let json = get_info(client, &self.to_string()).await?;
 
         let is_status_ok = json.status == "OK";
         let is_synchronized = json.synchronized;
-        let is_correct_network = match self.network {
-            Network::Mainnet => json.mainnet,
-            Network::Stagenet => json.stagenet,
-            Network::Testnet => json.testnet,
-        };
+        let is_correct_network = json.is_on(self.network);
 
         Ok(is_status_ok && is_synchronized && is_correct_network)
     }
@@ -138,18 +162,117 @@ impl Display for MoneroDaemon {
 #[derive(Deserialize)]
 struct MoneroDaemonGetInfoResponse {
     status: String,
+    height: u64,
     synchronized: bool,
     mainnet: bool,
     stagenet: bool,
     testnet: bool,
 }
 
+impl MoneroDaemonGetInfoResponse {
+    fn is_on(&self, network: Network) -> bool {
+        match network {
+            Network::Mainnet => self.mainnet,
+            Network::Stagenet => self.stagenet,
+            Network::Testnet => self.testnet,
+        }
+    }
+}
+
+async fn get_info(
+    client: &auth::Client,
+    daemon_address: &str,
+) -> Result<MoneroDaemonGetInfoResponse> {
+    let url = monero_rpc::monerod::endpoint(&daemon_url(daemon_address)?, "get_info")?;
+    let res = client
+        .get(url)
+        .await
+        .context("Failed to send request to get_info endpoint")?;
+
+    res.json()
+        .await
+        .context("Failed to deserialize daemon get_info response")
+}
+
+/// Parses a daemon address given either as `<host>:<port>`, which is served
+/// over plain http, or as a full URL such as `https://node.example/monero`.
+fn daemon_url(daemon_address: &str) -> Result<Url> {
+    let url = if daemon_address.contains("://") {
+        daemon_address.to_owned()
+    } else {
+        format!("http://{}", daemon_address)
+    };
+
+    Url::parse(&url).with_context(|| format!("Invalid Monero daemon address {}", daemon_address))
+}
+
+/// Checks that the Monero daemon at `daemon_address`, given as `host:port` or
+/// URL, responds, is synchronized and runs on `network`.
+///
+/// Connects through the SOCKS5 proxy if one is given and logs in with
+/// `credentials` if the daemon asks for them. Returns the daemon's current
+/// block height.
+pub async fn check_daemon(
+    daemon_address: &str,
+    network: Network,
+    socks5_proxy: Option<SocketAddr>,
+    credentials: Option<Credentials>,
+) -> Result<BlockHeight> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .https_only(false);
+    if let Some(socks5_proxy) = socks5_proxy {
+        // socks5h lets the proxy resolve the host name, e.g. of a .onion node
+        builder = builder.proxy(reqwest::Proxy::all(format!("socks5h://{}", socks5_proxy))?);
+    }
+    let client = auth::Client::new(builder.build()?, credentials);
+
+    let info = get_info(&client, daemon_address).await?;
+
+    if info.status != "OK" {
+        bail!("Daemon reported status {}", info.status);
+    }
+    if !info.is_on(network) {
+        bail!("Daemon does not run on {:?}", network);
+    }
+    if !info.synchronized {
+        bail!("Daemon is not synchronized yet");
+    }
+
+    Ok(BlockHeight {
+        height: u32::try_from(info.height).context("Daemon height does not fit into u32")?,
+    })
+}
+
+fn daemon_args(
+    daemon_address: String,
+    socks5_proxy: Option<SocketAddr>,
+    credentials: Option<Credentials>,
+) -> Vec<String> {
+    let mut args = vec!["--daemon-address".to_string(), daemon_address];
+
+    if let Some(credentials) = credentials {
+        args.push("--daemon-login".to_string());
+        args.push(format!("{}:{}", credentials.username, credentials.password));
+    }
+
+    if let Some(socks5_proxy) = socks5_proxy {
+        args.push("--proxy".to_string());
+        args.push(socks5_proxy.to_string());
+    }
+
+    args
+}
+
 /// Chooses an available Monero daemon based on the specified network.
 async fn choose_monero_daemon(network: Network) -> Result<&'static MoneroDaemon, Error> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(30))
-        .https_only(false)
-        .build()?;
+    let client = auth::Client::new(
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .https_only(false)
+            .build()?,
+        None,
+    );
 
     // We only want to check for daemons that match the specified network
     let network_matching_daemons = MONERO_DAEMONS
@@ -178,10 +301,152 @@ impl WalletRpcProcess {
         Url::parse(&format!("http://127.0.0.1:{}/json_rpc", self.port))
             .expect("Static url template is always valid")
     }
+
+    /// Whether the process is still running.
+    pub async fn is_alive(&self) -> bool {
+        matches!(self.child.lock().await.try_wait(), Ok(None))
+    }
+
+    /// Asks monero-wallet-rpc to exit and kills it if it is still running
+    /// after [`SHUTDOWN_TIMEOUT`].
+    pub async fn shutdown(self) -> Result<()> {
+        let mut child = self.child.lock().await;
+
+        terminate(&mut child)?;
+
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, child.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                timeout = ?SHUTDOWN_TIMEOUT,
+                "monero-wallet-rpc did not exit in time, killing it"
+            );
+            child.kill().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts monero-wallet-rpc and waits until it answers RPC requests.
+    ///
+    /// Its output is forwarded to the log. The pipes must be read for the
+    /// whole lifetime of the process, it dies once it cannot write to them.
+    async fn spawn(exec_path: &Path, args: &[OsString], port: u16) -> Result<Child> {
+        let mut child = Command::new(exec_path)
+            .env("LANG", "en_AU.UTF-8")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(args)
+            .spawn()?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .expect("monero wallet rpc stdout was not piped parent process");
+        let stderr = child
+            .stderr
+            .take()
+            .expect("monero wallet rpc stderr was not piped parent process");
+
+        let mut reader = BufReader::new(stdout).lines();
+
+        #[cfg(not(target_os = "windows"))]
+        while let Some(line) = reader.next_line().await? {
+            tracing::debug!(%line, "monero-wallet-rpc output");
+
+            if line.contains("Starting wallet RPC server") {
+                break;
+            }
+        }
+
+        // If we do not hear from the monero_wallet_rpc process for 3 seconds we assume
+        // it is ready
+        #[cfg(target_os = "windows")]
+        while let Ok(line) =
+            tokio::time::timeout(std::time::Duration::from_secs(3), reader.next_line()).await
+        {
+            if let Some(line) = line? {
+                tracing::debug!(%line, "monero-wallet-rpc output");
+            }
+        }
+
+        tokio::spawn(forward_output(reader));
+        tokio::spawn(forward_output(BufReader::new(stderr).lines()));
+
+        // Send a json rpc request to make sure monero_wallet_rpc is ready
+        Client::localhost(port)?
+            .get_version()
+            .await
+            .context("monero-wallet-rpc did not respond to get_version")?;
+
+        Ok(child)
+    }
+}
+
+#[async_trait]
+impl Restart for WalletRpcProcess {
+    /// Starts monero-wallet-rpc again on the same port, killing the old
+    /// process first in case it is still running but stopped responding.
+    async fn restart(&self) -> Result<()> {
+        let mut child = self.child.lock().await;
+
+        if let Ok(None) = child.try_wait() {
+            child.kill().await?;
+        }
+
+        tracing::info!(port = %self.port, "Restarting monero-wallet-rpc");
+
+        *child = Self::spawn(&self.exec_path, &self.args, self.port).await?;
+
+        Ok(())
+    }
+}
+
+impl Drop for WalletRpcProcess {
+    fn drop(&mut self) {
+        // Fails if the process already exited, which is what we want anyway.
+        let _ = self.child.get_mut().start_kill();
+    }
+}
+
+async fn forward_output<R>(mut lines: Lines<R>)
+where
+    R: AsyncBufRead + Unpin,
+{
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!(%line, "monero-wallet-rpc output");
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn terminate(child: &mut Child) -> Result<()> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    // The process was already reaped
+    let pid = match child.id() {
+        Some(pid) => pid,
+        None => return Ok(()),
+    };
+    let pid = i32::try_from(pid).context("Process id does not fit into a pid_t")?;
+
+    kill(Pid::from_raw(pid), Signal::SIGTERM)?;
+
+    Ok(())
+}
+
+// Windows has no SIGTERM, the process is killed right away
+#[cfg(target_os = "windows")]
+fn terminate(child: &mut Child) -> Result<()> {
+    child.start_kill()?;
+
+    Ok(())
 }
 
 pub struct WalletRpc {
     working_dir: PathBuf,
+    exec_path: PathBuf,
 }
 
 impl WalletRpc {
@@ -194,6 +459,7 @@ impl WalletRpc {
 
         let monero_wallet_rpc = WalletRpc {
             working_dir: working_dir.to_path_buf(),
+            exec_path: working_dir.join(PACKED_FILE),
         };
 
         if monero_wallet_rpc.archive_path().exists() {
@@ -204,15 +470,9 @@ impl WalletRpc {
         let exec_path = monero_wallet_rpc.exec_path();
         tracing::debug!("RPC exec path: {}", exec_path.display());
 
-        if exec_path.exists() {
-            let output = Command::new(&exec_path).arg("--version").output().await?;
-            let version = String::from_utf8_lossy(&output.stdout);
-            tracing::debug!("RPC version output: {}", version);
-
-            if !version.contains(WALLET_RPC_VERSION) {
-                tracing::info!("Removing old version of monero-wallet-rpc");
-                tokio::fs::remove_file(exec_path).await?;
-            }
+        if exec_path.exists() && !has_pinned_version(&exec_path).await {
+            tracing::info!("Removing old version of monero-wallet-rpc");
+            tokio::fs::remove_file(exec_path).await?;
         }
 
         // if monero-wallet-rpc doesn't exist then download it
@@ -225,26 +485,19 @@ impl WalletRpc {
                 .open(monero_wallet_rpc.archive_path())
                 .await?;
 
-            let response = reqwest::get(DOWNLOAD_URL).await?;
-
-            let content_length = response.headers()[CONTENT_LENGTH]
-                .to_str()
-                .context("Failed to convert content-length to string")?
-                .parse::<u64>()?;
-
-            tracing::info!(
-                progress="0%",
-                size=%content_length.big_byte(2),
-                download_url=DOWNLOAD_URL,
-                "Downloading monero-wallet-rpc",
-            );
+            let response = reqwest::get(DOWNLOAD_URL)
+                .await
+                .and_then(|response| response.error_for_status())
+                .context(DownloadFailed(DOWNLOAD_URL))?;
 
+            let mut progress = DownloadProgress::new(response.content_length());
             let mut hasher = Sha256::new();
 
             let byte_stream = response
                 .bytes_stream()
                 .map_ok(|bytes| {
                     hasher.update(&bytes);
+                    progress.update(bytes.len());
                     bytes
                 })
                 .map_err(|err| std::io::Error::new(ErrorKind::Other, err));
@@ -260,44 +513,17 @@ impl WalletRpc {
             let mut stream = FramedRead::new(StreamReader::new(byte_stream), BytesCodec::new())
                 .map_ok(|bytes| bytes.freeze());
 
-            let (mut received, mut notified) = (0, 0);
+            // the stream is decompressed as it is downloaded
             while let Some(chunk) = stream.next().await {
-                let bytes = chunk?;
-                received += bytes.len();
-                // the stream is decompressed as it is downloaded
-                // file is compressed approx 3:1 in bz format
-                let total = 3 * content_length;
-                let percent = 100 * received as u64 / total;
-                if percent != notified && percent % 10 == 0 {
-                    tracing::info!(
-                        progress=format!("{}%", percent),
-                        size=%content_length.big_byte(2),
-                        download_url=DOWNLOAD_URL,
-                        "Downloading monero-wallet-rpc",
-                    );
-                    notified = percent;
-                }
+                let bytes = chunk.map_err(download_error)?;
                 file.write_all(&bytes).await?;
             }
 
-            tracing::info!(
-                progress="100%",
-                size=%content_length.big_byte(2),
-                download_url=DOWNLOAD_URL,
-                "Downloading monero-wallet-rpc",
-            );
+            drop(stream);
+            progress.finish();
 
-            let result = hasher.finalize();
-            let result_hash = HEXLOWER.encode(result.as_ref());
-            if result_hash != DOWNLOAD_HASH {
-                bail!(
-                    "SHA256 of download ({}) does not match expected ({})!",
-                    result_hash,
-                    DOWNLOAD_HASH
-                );
-            } else {
-                tracing::debug!("Hashes match");
-            }
+            verify_checksum(hasher, DOWNLOAD_HASH)?;
+            tracing::debug!("Hashes match");
 
             file.flush().await?;
 
@@ -307,10 +533,82 @@ impl WalletRpc {
         Ok(monero_wallet_rpc)
     }
 
+    /// Uses an already installed monero-wallet-rpc binary instead of
+    /// downloading one.
+    ///
+    /// Fails if the binary does not exist or does not respond to `--version`.
+    pub async fn with_binary(
+        working_dir: impl AsRef<Path>,
+        binary: impl AsRef<Path>,
+    ) -> Result<WalletRpc> {
+        let working_dir = working_dir.as_ref();
+        let binary = binary.as_ref();
+
+        if !binary.is_file() {
+            bail!(BinaryNotFound(binary.to_path_buf()));
+        }
+
+        let output = tokio::time::timeout(
+            Duration::from_secs(10),
+            Command::new(binary).arg("--version").output(),
+        )
+        .await
+        .with_context(|| format!("{} did not respond to --version", binary.display()))?
+        .with_context(|| format!("Failed to execute {}", binary.display()))?;
+
+        if !output.status.success() {
+            bail!(
+                "{} --version exited with {}",
+                binary.display(),
+                output.status
+            );
+        }
+
+        let version_output = String::from_utf8_lossy(&output.stdout);
+        match parse_version(&version_output) {
+            Some(version) if (version[0], version[1]) == TESTED_WALLET_RPC_RELEASE => {
+                tracing::info!(
+                    binary = %binary.display(),
+                    version = %format_version(version),
+                    "Using system monero-wallet-rpc"
+                );
+            }
+            Some(version) => {
+                tracing::warn!(
+                    binary = %binary.display(),
+                    version = %format_version(version),
+                    tested = %format!("v{}.{}", TESTED_WALLET_RPC_RELEASE.0, TESTED_WALLET_RPC_RELEASE.1),
+                    "Using system monero-wallet-rpc with an untested version"
+                );
+            }
+            None => {
+                tracing::warn!(
+                    binary = %binary.display(),
+                    output = %version_output.trim(),
+                    "Could not detect the version of the system monero-wallet-rpc"
+                );
+            }
+        }
+
+        if !working_dir.exists() {
+            tokio::fs::create_dir(working_dir).await?;
+        }
+
+        Ok(WalletRpc {
+            working_dir: working_dir.to_path_buf(),
+            exec_path: binary.to_path_buf(),
+        })
+    }
+
+    /// Starts monero-wallet-rpc, which connects to the daemon through the
+    /// SOCKS5 proxy if one is given and logs in with `daemon_credentials`
+    /// if the daemon was started with `--rpc-login`.
     pub async fn run(
         &self,
         network: Network,
         daemon_address: Option<String>,
+        socks5_proxy: Option<SocketAddr>,
+        daemon_credentials: Option<Credentials>,
     ) -> Result<WalletRpcProcess> {
         let port = tokio::net::TcpListener::bind("127.0.0.1:0")
             .await?
@@ -340,49 +638,30 @@ impl WalletRpc {
             }
         };
 
-        let mut child = Command::new(self.exec_path())
-            .env("LANG", "en_AU.UTF-8")
-            .stdout(Stdio::piped())
-            .kill_on_drop(true)
-            .args(network_flag)
-            .arg("--daemon-address")
-            .arg(daemon_address)
-            .arg("--rpc-bind-port")
-            .arg(format!("{}", port))
-            .arg("--disable-rpc-login")
-            .arg("--wallet-dir")
-            .arg(self.working_dir.join("monero-data"))
-            .arg("--no-initial-sync")
-            .spawn()?;
-
-        let stdout = child
-            .stdout
-            .take()
-            .expect("monero wallet rpc stdout was not piped parent process");
-
-        let mut reader = BufReader::new(stdout).lines();
-
-        #[cfg(not(target_os = "windows"))]
-        while let Some(line) = reader.next_line().await? {
-            if line.contains("Starting wallet RPC server") {
-                break;
-            }
-        }
-
-        // If we do not hear from the monero_wallet_rpc process for 3 seconds we assume
-        // it is ready
-        #[cfg(target_os = "windows")]
-        while let Ok(line) =
-            tokio::time::timeout(std::time::Duration::from_secs(3), reader.next_line()).await
-        {
-            line?;
-        }
-
-        // Send a json rpc request to make sure monero_wallet_rpc is ready
-        Client::localhost(port)?.get_version().await?;
+        let args = network_flag
+            .into_iter()
+            .map(OsString::from)
+            .chain(
+                daemon_args(daemon_address, socks5_proxy, daemon_credentials)
+                    .into_iter()
+                    .map(OsString::from),
+            )
+            .chain([
+                "--rpc-bind-port".into(),
+                port.to_string().into(),
+                "--disable-rpc-login".into(),
+                "--wallet-dir".into(),
+                self.working_dir.join("monero-data").into_os_string(),
+                "--no-initial-sync".into(),
+            ])
+            .collect::<Vec<_>>();
+
+        let child = WalletRpcProcess::spawn(&self.exec_path, &args, port).await?;
 
         Ok(WalletRpcProcess {
-            _child: child,
+            child: Mutex::new(child),
+            exec_path: self.exec_path(),
+            args,
             port,
         })
     }
@@ -392,7 +671,7 @@ impl WalletRpc {
     }
 
     fn exec_path(&self) -> PathBuf {
-        self.working_dir.join(PACKED_FILE)
+        self.exec_path.clone()
     }
 
     #[cfg(not(target_os = "windows"))]
@@ -462,10 +741,174 @@ impl WalletRpc {
     }
 }
 
+/// Logs the progress of the monero-wallet-rpc download every 10%.
+struct DownloadProgress {
+    size: Option<u64>,
+    received: u64,
+    notified_percent: u64,
+}
+
+impl DownloadProgress {
+    fn new(size: Option<u64>) -> Self {
+        let progress = Self {
+            size,
+            received: 0,
+            notified_percent: 0,
+        };
+        progress.log("0%");
+
+        progress
+    }
+
+    fn update(&mut self, received: usize) {
+        self.received += received as u64;
+
+        let percent = match self.size {
+            Some(size) if size > 0 => 100 * self.received / size,
+            _ => return,
+        };
+
+        if percent / 10 > self.notified_percent / 10 && percent < 100 {
+            self.notified_percent = percent;
+            self.log(&format!("{}%", percent - percent % 10));
+        }
+    }
+
+    fn finish(&self) {
+        self.log("100%");
+    }
+
+    fn log(&self, progress: &str) {
+        let size = match self.size {
+            Some(size) => size.big_byte(2),
+            None => "unknown".to_owned(),
+        };
+
+        tracing::info!(
+            %progress,
+            %size,
+            download_url = DOWNLOAD_URL,
+            "Downloading monero-wallet-rpc",
+        );
+    }
+}
+
+/// Tells network errors apart from errors decompressing the archive.
+fn download_error(error: std::io::Error) -> Error {
+    let network_error = error
+        .get_ref()
+        .map_or(false, |source| source.is::<reqwest::Error>());
+
+    if network_error {
+        Error::new(error).context(DownloadFailed(DOWNLOAD_URL))
+    } else {
+        Error::new(error).context("Downloaded monero-wallet-rpc archive is corrupted")
+    }
+}
+
+fn verify_checksum(hasher: Sha256, expected: &str) -> Result<(), ChecksumMismatch> {
+    let actual = HEXLOWER.encode(hasher.finalize().as_ref());
+
+    if actual != expected {
+        return Err(ChecksumMismatch {
+            expected: expected.to_owned(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether the binary at `exec_path` reports the version we download. A
+/// binary that cannot be executed, e.g. because an earlier extraction was
+/// interrupted, needs to be downloaded again as well.
+async fn has_pinned_version(exec_path: &Path) -> bool {
+    let output = match Command::new(exec_path).arg("--version").output().await {
+        Ok(output) => output,
+        Err(error) => {
+            tracing::debug!(%error, "Failed to execute monero-wallet-rpc --version");
+            return false;
+        }
+    };
+
+    let version = String::from_utf8_lossy(&output.stdout);
+    tracing::debug!("RPC version output: {}", version);
+
+    parse_version(&version).map(format_version).as_deref() == Some(WALLET_RPC_VERSION)
+}
+
+/// Extracts the version from the output of `monero-wallet-rpc --version`,
+/// e.g. `Monero 'Fluorine Fermi' (v0.18.3.1-release)`.
+fn parse_version(output: &str) -> Option<[u32; 4]> {
+    let start = output.find("(v")? + 2;
+    let version = output[start..].split(|c| c == '-' || c == ')').next()?;
+
+    let mut parts = [0; 4];
+    let mut numbers = version.split('.');
+    for part in parts.iter_mut() {
+        *part = numbers.next()?.parse().ok()?;
+    }
+
+    Some(parts)
+}
+
+fn format_version([major, minor, patch, build]: [u32; 4]) -> String {
+    format!("v{}.{}.{}.{}", major, minor, patch, build)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn daemon_is_reached_through_the_given_socks5_proxy() {
+        let proxy = "127.0.0.1:9050".parse().unwrap();
+
+        assert_eq!(
+            daemon_args("node.example:18081".to_string(), Some(proxy), None),
+            vec![
+                "--daemon-address",
+                "node.example:18081",
+                "--proxy",
+                "127.0.0.1:9050"
+            ]
+        );
+        assert_eq!(
+            daemon_args("node.example:18081".to_string(), None, None),
+            vec!["--daemon-address", "node.example:18081"]
+        );
+    }
+
+    #[test]
+    fn daemon_address_without_scheme_is_served_over_http() {
+        assert_eq!(
+            daemon_url("node.example:18081").unwrap().as_str(),
+            "http://node.example:18081/"
+        );
+        assert_eq!(
+            daemon_url("https://node.example:443/monero")
+                .unwrap()
+                .as_str(),
+            "https://node.example/monero"
+        );
+        assert!(daemon_url("not a url").is_err());
+    }
+
+    #[test]
+    fn daemon_login_is_passed_to_wallet_rpc() {
+        let credentials = Credentials::new("alice", "secret");
+
+        assert_eq!(
+            daemon_args("node.example:18081".to_string(), None, Some(credentials)),
+            vec![
+                "--daemon-address",
+                "node.example:18081",
+                "--daemon-login",
+                "alice:secret"
+            ]
+        );
+    }
+
     fn extract_host_and_port(address: String) -> (&'static str, u16) {
         let parts: Vec<&str> = address.split(':').collect();
 
@@ -489,6 +932,7 @@ mod tests {
                 r#"
                 {
                     "status": "OK",
+                    "height": 3250000,
                     "synchronized": true,
                     "mainnet": true,
                     "stagenet": false,
@@ -500,7 +944,7 @@ mod tests {
 
         let (host, port) = extract_host_and_port(server.host_with_port());
 
-        let client = reqwest::Client::new();
+        let client = auth::Client::new(reqwest::Client::new(), None);
         let result = MoneroDaemon::new(host, port, Network::Mainnet)
             .is_available(&client)
             .await;
@@ -520,6 +964,7 @@ mod tests {
                 r#"
                 {
                     "status": "OK",
+                    "height": 3250000,
                     "synchronized": true,
                     "mainnet": true,
                     "stagenet": false,
@@ -531,7 +976,7 @@ mod tests {
 
         let (host, port) = extract_host_and_port(server.host_with_port());
 
-        let client = reqwest::Client::new();
+        let client = auth::Client::new(reqwest::Client::new(), None);
         let result = MoneroDaemon::new(host, port, Network::Stagenet)
             .is_available(&client)
             .await;
@@ -551,6 +996,7 @@ mod tests {
                 r#"
                 {
                     "status": "OK",
+                    "height": 3250000,
                     "synchronized": false,
                     "mainnet": true,
                     "stagenet": false,
@@ -562,7 +1008,7 @@ mod tests {
 
         let (host, port) = extract_host_and_port(server.host_with_port());
 
-        let client = reqwest::Client::new();
+        let client = auth::Client::new(reqwest::Client::new(), None);
         let result = MoneroDaemon::new(host, port, Network::Mainnet)
             .is_available(&client)
             .await;
@@ -571,13 +1017,186 @@ mod tests {
         assert!(!result.unwrap());
     }
 
+    #[tokio::test]
+    async fn check_daemon_returns_the_daemon_height() {
+        let mut server = mockito::Server::new();
+
+        let _ = server
+            .mock("GET", "/get_info")
+            .with_status(200)
+            .with_body(
+                r#"
+                {
+                    "status": "OK",
+                    "height": 3250000,
+                    "synchronized": true,
+                    "mainnet": true,
+                    "stagenet": false,
+                    "testnet": false
+                }
+                "#,
+            )
+            .create();
+
+        let height = check_daemon(&server.host_with_port(), Network::Mainnet, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(height.height, 3250000);
+    }
+
     #[tokio::test]
     async fn test_is_daemon_available_network_error_failure() {
-        let client = reqwest::Client::new();
+        let client = auth::Client::new(reqwest::Client::new(), None);
         let result = MoneroDaemon::new("does.not.exist.com", 18081, Network::Mainnet)
             .is_available(&client)
             .await;
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parses_version_from_version_output() {
+        assert_eq!(
+            parse_version("Monero 'Fluorine Fermi' (v0.18.3.1-release)\n"),
+            Some([0, 18, 3, 1])
+        );
+        assert_eq!(
+            parse_version("Monero 'Fluorine Fermi' (v0.18.2.2-0d5b7c6)"),
+            Some([0, 18, 2, 2])
+        );
+        assert_eq!(parse_version("monero-wallet-rpc"), None);
+    }
+
+    #[cfg(unix)]
+    fn stub_binary(dir: &Path, version_output: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("monero-wallet-rpc");
+        std::fs::write(&path, format!("#!/bin/sh\necho \"{}\"\n", version_output)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        path
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn system_binary_is_used_without_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = stub_binary(dir.path(), "Monero 'Fluorine Fermi' (v0.18.3.1-release)");
+        let working_dir = dir.path().join("monero");
+
+        let wallet_rpc = WalletRpc::with_binary(&working_dir, &binary).await.unwrap();
+
+        assert_eq!(wallet_rpc.exec_path(), binary);
+        assert!(!wallet_rpc.archive_path().exists());
+        assert!(!working_dir.join(PACKED_FILE).exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn system_binary_with_untested_version_is_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = stub_binary(dir.path(), "Monero 'Zero' (v0.19.0.0-release)");
+
+        let result = WalletRpc::with_binary(dir.path().join("monero"), &binary).await;
+
+        assert!(result.is_ok());
+    }
+
+    const FIXTURE_ARCHIVE: &[u8] = b"not really a monero archive";
+    const FIXTURE_ARCHIVE_HASH: &str =
+        "86a174a16e71f8190613457c1fe4092e208ad12e96d5ce2d8e5f8d49f70ea5c3";
+
+    #[test]
+    fn archive_with_pinned_hash_is_accepted() {
+        let mut hasher = Sha256::new();
+        hasher.update(FIXTURE_ARCHIVE);
+
+        assert!(verify_checksum(hasher, FIXTURE_ARCHIVE_HASH).is_ok());
+    }
+
+    #[test]
+    fn tampered_archive_is_rejected() {
+        let mut hasher = Sha256::new();
+        hasher.update(b"not really a monero archive, with a backdoor");
+
+        let mismatch = verify_checksum(hasher, FIXTURE_ARCHIVE_HASH).unwrap_err();
+
+        assert_eq!(mismatch.expected, FIXTURE_ARCHIVE_HASH);
+        assert_ne!(mismatch.actual, FIXTURE_ARCHIVE_HASH);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn binary_with_pinned_version_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = stub_binary(dir.path(), "Monero 'Fluorine Fermi' (v0.18.3.1-release)");
+
+        assert!(has_pinned_version(&binary).await);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn binary_with_other_version_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = stub_binary(dir.path(), "Monero 'Fluorine Fermi' (v0.18.2.2-release)");
+
+        assert!(!has_pinned_version(&binary).await);
+    }
+
+    #[tokio::test]
+    async fn binary_that_cannot_be_executed_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join(PACKED_FILE);
+        std::fs::write(&binary, FIXTURE_ARCHIVE).unwrap();
+
+        assert!(!has_pinned_version(&binary).await);
+    }
+
+    #[tokio::test]
+    async fn missing_system_binary_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("does-not-exist");
+
+        let error = WalletRpc::with_binary(dir.path().join("monero"), &binary)
+            .await
+            .err()
+            .unwrap();
+
+        let not_found = error.downcast_ref::<BinaryNotFound>().unwrap();
+        assert_eq!(not_found.0, binary);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn shutdown_terminates_the_process() {
+        let process = WalletRpcProcess {
+            child: Mutex::new(Command::new("sleep").arg("60").spawn().unwrap()),
+            exec_path: PathBuf::from("sleep"),
+            args: vec![],
+            port: 0,
+        };
+        assert!(process.is_alive().await);
+
+        tokio::time::timeout(Duration::from_secs(5), process.shutdown())
+            .await
+            .expect("sleep exits on SIGTERM")
+            .unwrap();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn killed_process_is_not_alive() {
+        let process = WalletRpcProcess {
+            child: Mutex::new(Command::new("sleep").arg("60").spawn().unwrap()),
+            exec_path: PathBuf::from("sleep"),
+            args: vec![],
+            port: 0,
+        };
+
+        process.child.lock().await.kill().await.unwrap();
+
+        assert!(!process.is_alive().await);
+    }
 }
@@ -72,4 +72,21 @@ impl From<(PeerId, Message)> for cli::OutEvent {
         }
     }
 }
-crate::impl_from_rr_event!(OutEvent, cli::OutEvent, PROTOCOL);
+
+/// Bob only sends encrypted signatures. A failed request is reported with its
+/// id so he can send the encrypted signature again.
+impl From<OutEvent> for cli::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            RequestResponseEvent::Message { peer, message } => Self::from((peer, message)),
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => Self::EncryptedSignatureFailed {
+                id: request_id,
+                error,
+            },
+            RequestResponseEvent::InboundFailure { .. }
+            | RequestResponseEvent::ResponseSent { .. } => Self::Other,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::env;
+use libp2p::ping::{Ping, PingConfig};
+use std::time::Duration;
+
+/// A [`Ping`] behaviour that sends a heartbeat every
+/// `env_config.heartbeat_interval` and closes the connection once
+/// `env_config.max_missed_heartbeats` heartbeats in a row went unanswered.
+///
+/// A connection that died silently, e.g. because a NAT dropped it, is
+/// noticed within a few heartbeats instead of only when the next request
+/// times out. Closing it makes Bob redial Alice.
+pub fn behaviour(env_config: &env::Config) -> Ping {
+    Ping::new(
+        PingConfig::new()
+            .with_interval(env_config.heartbeat_interval)
+            .with_timeout(env_config.heartbeat_interval)
+            .with_max_failures(env_config.max_missed_heartbeats)
+            .with_keep_alive(true),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionHealth {
+    /// Connected and the last heartbeat, if any, was answered.
+    Healthy,
+    /// Connected, but the given number of heartbeats in a row went
+    /// unanswered.
+    Degraded { missed_heartbeats: u32 },
+    /// Not connected, the peer is being dialled.
+    Unreachable,
+}
+
+/// The health of the connection to a peer as seen by its heartbeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub health: ConnectionHealth,
+    /// The round-trip time of the last answered heartbeat.
+    pub latest_rtt: Option<Duration>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            health: ConnectionHealth::Unreachable,
+            latest_rtt: None,
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn connected(&mut self) {
+        self.health = ConnectionHealth::Healthy;
+    }
+
+    pub fn disconnected(&mut self) {
+        self.health = ConnectionHealth::Unreachable;
+    }
+
+    pub fn answered(&mut self, rtt: Duration) {
+        self.health = ConnectionHealth::Healthy;
+        self.latest_rtt = Some(rtt);
+    }
+
+    pub fn missed(&mut self) {
+        self.health = match self.health {
+            ConnectionHealth::Healthy => ConnectionHealth::Degraded {
+                missed_heartbeats: 1,
+            },
+            ConnectionHealth::Degraded { missed_heartbeats } => ConnectionHealth::Degraded {
+                missed_heartbeats: missed_heartbeats + 1,
+            },
+            // A heartbeat of a connection that is already closed
+            ConnectionHealth::Unreachable => ConnectionHealth::Unreachable,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_heartbeats_are_counted_until_one_is_answered() {
+        let mut heartbeat = Heartbeat::default();
+        heartbeat.connected();
+        heartbeat.answered(Duration::from_millis(80));
+
+        heartbeat.missed();
+        heartbeat.missed();
+        assert_eq!(
+            heartbeat.health,
+            ConnectionHealth::Degraded {
+                missed_heartbeats: 2
+            }
+        );
+        assert_eq!(heartbeat.latest_rtt, Some(Duration::from_millis(80)));
+
+        heartbeat.answered(Duration::from_millis(120));
+        assert_eq!(
+            heartbeat,
+            Heartbeat {
+                health: ConnectionHealth::Healthy,
+                latest_rtt: Some(Duration::from_millis(120)),
+            }
+        );
+    }
+
+    #[test]
+    fn heartbeats_of_a_closed_connection_do_not_revive_it() {
+        let mut heartbeat = Heartbeat::default();
+        heartbeat.connected();
+        heartbeat.missed();
+        heartbeat.disconnected();
+
+        heartbeat.missed();
+        assert_eq!(heartbeat.health, ConnectionHealth::Unreachable);
+
+        heartbeat.connected();
+        assert_eq!(heartbeat.health, ConnectionHealth::Healthy);
+    }
+}
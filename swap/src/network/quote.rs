@@ -9,6 +9,10 @@ use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 
 const PROTOCOL: &str = "/comit/xmr/btc/bid-quote/1.0.0";
+
+/// The version of the swap protocol this version of swap speaks. Bumped
+/// whenever a change makes it unable to swap with a previous version.
+pub const PROTOCOL_VERSION: u32 = 1;
 pub type OutEvent = RequestResponseEvent<(), BidQuote>;
 pub type Message = RequestResponseMessage<(), BidQuote>;
 
@@ -35,12 +39,49 @@ pub struct BidQuote {
     /// The maximum quantity the maker is willing to buy.
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     pub max_quantity: bitcoin::Amount,
+    /// The version of the swap protocol the maker speaks. Makers that do not
+    /// send it speak the first version.
+    #[serde(default = "first_protocol_version")]
+    pub protocol_version: u32,
+}
+
+impl BidQuote {
+    /// Fails if the maker speaks a version of the swap protocol we don't, so
+    /// the incompatibility is noticed before any funds are locked.
+    pub fn ensure_protocol_version_supported(&self) -> Result<(), UnsupportedProtocolVersion> {
+        if self.protocol_version != PROTOCOL_VERSION {
+            return Err(UnsupportedProtocolVersion {
+                required: self.protocol_version,
+                supported: PROTOCOL_VERSION,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn first_protocol_version() -> u32 {
+    1
 }
 
 #[derive(Clone, Copy, Debug, thiserror::Error)]
 #[error("Received quote of 0")]
 pub struct ZeroQuoteReceived;
 
+#[derive(Clone, Copy, Debug, thiserror::Error, PartialEq, Eq)]
+#[error("The seller requires version {required} of the swap protocol but this version of swap only supports version {supported}")]
+pub struct UnsupportedProtocolVersion {
+    pub required: u32,
+    pub supported: u32,
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("Received quote with a maximum of {max_quantity} which is below the minimum swap amount of {min_swap_amount}")]
+pub struct QuoteBelowMinimumSwapAmount {
+    pub max_quantity: bitcoin::Amount,
+    pub min_swap_amount: bitcoin::Amount,
+}
+
 /// Constructs a new instance of the `quote` behaviour to be used by the ASB.
 ///
 /// The ASB is always listening and only supports inbound connections, i.e.
@@ -90,3 +131,36 @@ impl From<(PeerId, Message)> for cli::OutEvent {
     }
 }
 crate::impl_from_rr_event!(OutEvent, cli::OutEvent, PROTOCOL);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_without_protocol_version_is_from_the_first_version() {
+        let quote: BidQuote =
+            serde_json::from_str(r#"{"price":1337,"min_quantity":42,"max_quantity":9001}"#)
+                .unwrap();
+
+        assert_eq!(quote.protocol_version, 1);
+        assert!(quote.ensure_protocol_version_supported().is_ok());
+    }
+
+    #[test]
+    fn quote_of_unknown_protocol_version_is_rejected() {
+        let quote = BidQuote {
+            price: bitcoin::Amount::from_sat(1337),
+            min_quantity: bitcoin::Amount::from_sat(42),
+            max_quantity: bitcoin::Amount::from_sat(9001),
+            protocol_version: PROTOCOL_VERSION + 1,
+        };
+
+        assert_eq!(
+            quote.ensure_protocol_version_supported(),
+            Err(UnsupportedProtocolVersion {
+                required: PROTOCOL_VERSION + 1,
+                supported: PROTOCOL_VERSION,
+            })
+        );
+    }
+}
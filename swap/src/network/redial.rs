@@ -1,6 +1,7 @@
 use crate::cli;
 use backoff::backoff::Backoff;
-use backoff::ExponentialBackoff;
+use backoff::exponential::ExponentialBackoff;
+use backoff::Clock;
 use futures::future::FutureExt;
 use libp2p::core::connection::ConnectionId;
 use libp2p::core::Multiaddr;
@@ -14,6 +15,7 @@ use std::time::Duration;
 use tokio::time::{Instant, Sleep};
 use void::Void;
 
+#[derive(Debug)]
 pub enum OutEvent {
     AllAttemptsExhausted { peer: PeerId },
 }
@@ -27,19 +29,31 @@ pub struct Behaviour {
     /// If present, tracks for how long we need to sleep until we dial again.
     sleep: Option<Pin<Box<Sleep>>>,
     /// Tracks the current backoff state.
-    backoff: ExponentialBackoff,
+    backoff: ExponentialBackoff<TokioClock>,
+}
+
+/// Measures the backoff's elapsed time with tokio's clock, the same clock the
+/// redial sleeps use, so the two stay consistent when tokio's time is paused.
+#[derive(Debug, Clone, Copy, Default)]
+struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> std::time::Instant {
+        Instant::now().into_std()
+    }
 }
 
 impl Behaviour {
-    pub fn new(peer: PeerId, interval: Duration) -> Self {
+    /// Redials with an exponential backoff starting at `interval` and gives
+    /// up once `max_elapsed_time` passed since the connection was lost.
+    pub fn new(peer: PeerId, interval: Duration, max_elapsed_time: Duration) -> Self {
         Self {
             peer,
             sleep: None,
             backoff: ExponentialBackoff {
                 initial_interval: interval,
                 current_interval: interval,
-                // give up dialling after 5 minutes
-                max_elapsed_time: Some(Duration::from_secs(5 * 60)),
+                max_elapsed_time: Some(max_elapsed_time),
                 ..ExponentialBackoff::default()
             },
         }
@@ -131,3 +145,83 @@ impl From<OutEvent> for cli::OutEvent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::swarm::AddressRecord;
+    use std::iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_redialling_after_five_minutes() {
+        let peer = PeerId::random();
+        let mut behaviour = Behaviour::new(peer, Duration::from_secs(1), FIVE_MINUTES);
+        behaviour.backoff.randomization_factor = 0.0;
+        let mut params = NoPollParameters(PeerId::random());
+
+        let start = Instant::now();
+        behaviour.inject_disconnected(&peer);
+
+        let mut dial_attempts = 0;
+        loop {
+            match futures::future::poll_fn(|cx| behaviour.poll(cx, &mut params)).await {
+                NetworkBehaviourAction::Dial { .. } => dial_attempts += 1,
+                NetworkBehaviourAction::GenerateEvent(OutEvent::AllAttemptsExhausted {
+                    peer: exhausted,
+                }) => {
+                    assert_eq!(exhausted, peer);
+                    break;
+                }
+                _ => unreachable!("redial behaviour only dials and gives up"),
+            }
+        }
+
+        // waits 1s, 1s, 1.5s, 2.25s, ... until the next wait would end after 5 minutes
+        assert_eq!(dial_attempts, 13);
+        assert_eq!(start.elapsed().as_secs(), 291);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnecting_stops_redialling() {
+        let peer = PeerId::random();
+        let mut behaviour = Behaviour::new(peer, Duration::from_secs(1), FIVE_MINUTES);
+        let mut params = NoPollParameters(PeerId::random());
+
+        behaviour.inject_disconnected(&peer);
+        assert!(behaviour.until_next_redial().is_some());
+
+        behaviour.inject_connected(&peer);
+        assert!(behaviour.until_next_redial().is_none());
+
+        tokio::time::advance(Duration::from_secs(10 * 60)).await;
+        let poll =
+            futures::future::poll_fn(|cx| Poll::Ready(behaviour.poll(cx, &mut params))).await;
+        assert!(poll.is_pending());
+    }
+
+    const FIVE_MINUTES: Duration = Duration::from_secs(5 * 60);
+
+    struct NoPollParameters(PeerId);
+
+    impl PollParameters for NoPollParameters {
+        type SupportedProtocolsIter = iter::Empty<Vec<u8>>;
+        type ListenedAddressesIter = iter::Empty<Multiaddr>;
+        type ExternalAddressesIter = iter::Empty<AddressRecord>;
+
+        fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+            iter::empty()
+        }
+
+        fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+            iter::empty()
+        }
+
+        fn external_addresses(&self) -> Self::ExternalAddressesIter {
+            iter::empty()
+        }
+
+        fn local_peer_id(&self) -> &PeerId {
+            &self.0
+        }
+    }
+}
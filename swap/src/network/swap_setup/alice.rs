@@ -1,4 +1,4 @@
-use crate::asb::LatestRate;
+use crate::asb::{LatestRate, RateConfig};
 use crate::monero::Amount;
 use crate::network::swap_setup;
 use crate::network::swap_setup::{
@@ -21,6 +21,7 @@ use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::task::Poll;
 use std::time::{Duration, Instant};
+use tokio::sync::watch;
 use uuid::Uuid;
 use void::Void;
 
@@ -45,6 +46,8 @@ pub enum OutEvent {
 pub struct WalletSnapshot {
     balance: monero_rpc::wallet::GetBalance,
     lock_fee: monero::Amount,
+    /// XMR, including the lock fees, that running swaps did not lock yet.
+    reserved: monero::Amount,
 
     // TODO: Consider using the same address for punish and redeem (they are mutually exclusive, so
     // effectively the address will only be used once)
@@ -61,6 +64,7 @@ impl WalletSnapshot {
         monero_wallet: &monero::Wallet,
         external_redeem_address: &Option<bitcoin::Address>,
         transfer_amount: bitcoin::Amount,
+        reserved: monero::Amount,
     ) -> Result<Self> {
         let balance = monero_wallet.get_balance().await?;
         let redeem_address = external_redeem_address
@@ -80,12 +84,37 @@ impl WalletSnapshot {
         Ok(Self {
             balance,
             lock_fee: monero::MONERO_FEE,
+            reserved,
             redeem_address,
             punish_address,
             redeem_fee,
             punish_fee,
         })
     }
+
+    /// Fails if the unlocked balance cannot pay for locking `xmr` on top of
+    /// what is reserved for running swaps.
+    fn ensure_balance_covers(
+        &self,
+        xmr: monero::Amount,
+        buy: bitcoin::Amount,
+    ) -> Result<(), Error> {
+        let unlocked = Amount::from_piconero(self.balance.unlocked_balance);
+        let required = xmr
+            .checked_add(self.lock_fee)
+            .and_then(|required| required.checked_add(self.reserved))
+            .ok_or_else(|| Error::SellQuoteCalculationFailed(anyhow!("XMR amount overflows")))?;
+
+        if unlocked < required {
+            return Err(Error::BalanceTooLow {
+                balance: self.balance,
+                reserved: self.reserved,
+                buy,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl From<OutEvent> for asb::OutEvent {
@@ -116,8 +145,7 @@ impl From<OutEvent> for asb::OutEvent {
 #[allow(missing_debug_implementations)]
 pub struct Behaviour<LR> {
     events: VecDeque<OutEvent>,
-    min_buy: bitcoin::Amount,
-    max_buy: bitcoin::Amount,
+    rate_config: watch::Receiver<RateConfig>,
     env_config: env::Config,
 
     latest_rate: LR,
@@ -126,16 +154,14 @@ pub struct Behaviour<LR> {
 
 impl<LR> Behaviour<LR> {
     pub fn new(
-        min_buy: bitcoin::Amount,
-        max_buy: bitcoin::Amount,
+        rate_config: watch::Receiver<RateConfig>,
         env_config: env::Config,
         latest_rate: LR,
         resume_only: bool,
     ) -> Self {
         Self {
             events: Default::default(),
-            min_buy,
-            max_buy,
+            rate_config,
             env_config,
             latest_rate,
             resume_only,
@@ -152,8 +178,7 @@ where
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
         Handler::new(
-            self.min_buy,
-            self.max_buy,
+            self.rate_config.clone(),
             self.env_config,
             self.latest_rate.clone(),
             self.resume_only,
@@ -207,8 +232,7 @@ pub struct Handler<LR> {
     inbound_stream: OptionFuture<InboundStream>,
     events: VecDeque<HandlerOutEvent>,
 
-    min_buy: bitcoin::Amount,
-    max_buy: bitcoin::Amount,
+    rate_config: watch::Receiver<RateConfig>,
     env_config: env::Config,
 
     latest_rate: LR,
@@ -220,8 +244,7 @@ pub struct Handler<LR> {
 
 impl<LR> Handler<LR> {
     fn new(
-        min_buy: bitcoin::Amount,
-        max_buy: bitcoin::Amount,
+        rate_config: watch::Receiver<RateConfig>,
         env_config: env::Config,
         latest_rate: LR,
         resume_only: bool,
@@ -229,8 +252,7 @@ impl<LR> Handler<LR> {
         Self {
             inbound_stream: OptionFuture::from(None),
             events: Default::default(),
-            min_buy,
-            max_buy,
+            rate_config,
             env_config,
             latest_rate,
             resume_only,
@@ -275,8 +297,11 @@ where
             Duration::from_secs(5),
         );
         let resume_only = self.resume_only;
-        let min_buy = self.min_buy;
-        let max_buy = self.max_buy;
+        // the amounts and the rate are fixed when the swap is set up, later
+        // changes to the rate config do not affect it
+        let RateConfig {
+            min_buy, max_buy, ..
+        } = *self.rate_config.borrow();
         let latest_rate = self.latest_rate.latest_rate();
         let env_config = self.env_config;
 
@@ -330,13 +355,7 @@ where
                     .sell_quote(btc)
                     .map_err(Error::SellQuoteCalculationFailed)?;
 
-                let unlocked = Amount::from_piconero(wallet_snapshot.balance.unlocked_balance);
-                if unlocked < xmr + wallet_snapshot.lock_fee {
-                    return Err(Error::BalanceTooLow {
-                        balance: wallet_snapshot.balance,
-                        buy: btc,
-                    });
-                }
+                wallet_snapshot.ensure_balance_covers(xmr, btc)?;
 
                 Ok(xmr)
             };
@@ -487,9 +506,10 @@ pub enum Error {
         max: bitcoin::Amount,
         buy: bitcoin::Amount,
     },
-    #[error("Unlocked balance ({balance}) too low to fulfill swapping {buy}")]
+    #[error("Unlocked balance ({balance}) too low to fulfill swapping {buy}, {reserved} of it is reserved for running swaps")]
     BalanceTooLow {
         balance: monero_rpc::wallet::GetBalance,
+        reserved: monero::Amount,
         buy: bitcoin::Amount,
     },
     #[error("Failed to fetch latest rate")]
@@ -528,3 +548,64 @@ impl Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet_snapshot(unlocked: f64, reserved: monero::Amount) -> WalletSnapshot {
+        let unlocked = monero::Amount::from_monero(unlocked).unwrap().as_piconero();
+        let address = "bc1qe4epnfklcaa0mun26yz5g8k24em5u9f92hy325"
+            .parse::<bitcoin::Address>()
+            .unwrap();
+
+        WalletSnapshot {
+            balance: monero_rpc::wallet::GetBalance {
+                balance: unlocked,
+                unlocked_balance: unlocked,
+                multisig_import_needed: false,
+                blocks_to_unlock: 0,
+                time_to_unlock: 0,
+            },
+            lock_fee: monero::MONERO_FEE,
+            reserved,
+            redeem_address: address.clone(),
+            punish_address: address,
+            redeem_fee: bitcoin::Amount::from_sat(1_000),
+            punish_fee: bitcoin::Amount::from_sat(1_000),
+        }
+    }
+
+    #[test]
+    fn second_swap_exceeding_the_balance_together_with_the_first_is_rejected() {
+        let xmr = monero::Amount::from_monero(1.0).unwrap();
+        let buy = bitcoin::Amount::from_btc(0.01).unwrap();
+
+        let first = wallet_snapshot(1.5, monero::Amount::ZERO);
+        assert!(first.ensure_balance_covers(xmr, buy).is_ok());
+
+        // the first swap did not lock its XMR yet, the balance is unchanged
+        let reserved = xmr.checked_add(monero::MONERO_FEE).unwrap();
+        let second = wallet_snapshot(1.5, reserved);
+        let error = second.ensure_balance_covers(xmr, buy).unwrap_err();
+
+        assert!(
+            matches!(error, Error::BalanceTooLow { reserved: r, buy: b, .. } if r == reserved && b == buy)
+        );
+        assert!(matches!(
+            error.to_error_response(),
+            SpotPriceError::BalanceTooLow { buy: b } if b == buy
+        ));
+    }
+
+    #[test]
+    fn swap_within_the_unreserved_balance_is_accepted() {
+        let xmr = monero::Amount::from_monero(1.0).unwrap();
+        let buy = bitcoin::Amount::from_btc(0.01).unwrap();
+        let reserved = monero::Amount::from_monero(1.0).unwrap();
+
+        let snapshot = wallet_snapshot(2.5, reserved);
+
+        assert!(snapshot.ensure_balance_covers(xmr, buy).is_ok());
+    }
+}
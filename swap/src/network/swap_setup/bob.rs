@@ -153,52 +153,68 @@ impl ProtocolsHandler for Handler {
     ) {
         let bitcoin_wallet = self.bitcoin_wallet.clone();
         let env_config = self.env_config;
-
-        let protocol = tokio::time::timeout(self.timeout, async move {
-            write_cbor_message(
-                &mut substream,
-                SpotPriceRequest {
-                    btc: info.btc,
-                    blockchain_network: BlockchainNetwork {
-                        bitcoin: env_config.bitcoin_network,
-                        monero: env_config.monero_network,
+        let timeout = self.timeout;
+
+        let protocol = async move {
+            // reserved by the wallet once our lock transaction is built
+            let mut tx_lock_inputs = Vec::new();
+
+            let setup: Result<Result<State2>, _> = tokio::time::timeout(timeout, async {
+                write_cbor_message(
+                    &mut substream,
+                    SpotPriceRequest {
+                        btc: info.btc,
+                        blockchain_network: BlockchainNetwork {
+                            bitcoin: env_config.bitcoin_network,
+                            monero: env_config.monero_network,
+                        },
                     },
-                },
-            )
-            .await?;
-
-            let xmr = Result::from(read_cbor_message::<SpotPriceResponse>(&mut substream).await?)?;
-
-            let state0 = State0::new(
-                info.swap_id,
-                &mut rand::thread_rng(),
-                info.btc,
-                xmr,
-                env_config.bitcoin_cancel_timelock,
-                env_config.bitcoin_punish_timelock,
-                info.bitcoin_refund_address,
-                env_config.monero_finality_confirmations,
-                info.tx_refund_fee,
-                info.tx_cancel_fee,
-            );
-
-            write_cbor_message(&mut substream, state0.next_message()).await?;
-            let message1 = read_cbor_message::<Message1>(&mut substream).await?;
-            let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
-
-            write_cbor_message(&mut substream, state1.next_message()).await?;
-            let message3 = read_cbor_message::<Message3>(&mut substream).await?;
-            let state2 = state1.receive(message3)?;
-
-            write_cbor_message(&mut substream, state2.next_message()).await?;
-
-            substream.flush().await?;
-            substream.close().await?;
-
-            Ok(state2)
-        });
-
-        let max_seconds = self.timeout.as_secs();
+                )
+                .await?;
+
+                let xmr =
+                    Result::from(read_cbor_message::<SpotPriceResponse>(&mut substream).await?)?;
+
+                let state0 = State0::new(
+                    info.swap_id,
+                    &mut rand::thread_rng(),
+                    info.btc,
+                    xmr,
+                    env_config.bitcoin_cancel_timelock,
+                    env_config.bitcoin_punish_timelock,
+                    info.bitcoin_refund_address,
+                    env_config.monero_finality_confirmations,
+                    info.tx_refund_fee,
+                    info.tx_cancel_fee,
+                );
+
+                write_cbor_message(&mut substream, state0.next_message()).await?;
+                let message1 = read_cbor_message::<Message1>(&mut substream).await?;
+                let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+                tx_lock_inputs = state1.tx_lock_inputs().collect();
+
+                write_cbor_message(&mut substream, state1.next_message()).await?;
+                let message3 = read_cbor_message::<Message3>(&mut substream).await?;
+                let state2 = state1.receive(message3)?;
+
+                write_cbor_message(&mut substream, state2.next_message()).await?;
+
+                substream.flush().await?;
+                substream.close().await?;
+
+                Ok(state2)
+            })
+            .await;
+
+            if !matches!(setup, Ok(Ok(_))) {
+                // the lock transaction is never published, other swaps may spend its inputs
+                bitcoin_wallet.release_utxos(tx_lock_inputs).await;
+            }
+
+            setup
+        };
+
+        let max_seconds = timeout.as_secs();
         self.outbound_stream = OptionFuture::from(Some(
             async move {
                 protocol.await.map_err(|_| Error::Timeout {
@@ -1,18 +1,17 @@
-use crate::asb::{LatestRate, RendezvousNode};
+use crate::asb::{LatestRate, RateConfig, RendezvousNode};
 use crate::libp2p_ext::MultiAddrExt;
 use crate::network::rendezvous::XmrBtcNamespace;
-use crate::seed::Seed;
-use crate::{asb, bitcoin, cli, env, tor};
+use crate::{asb, cli, env, tor};
 use anyhow::Result;
 use libp2p::swarm::{NetworkBehaviour, SwarmBuilder};
 use libp2p::{identity, Multiaddr, Swarm};
 use std::fmt::Debug;
+use tokio::sync::watch;
 
 #[allow(clippy::too_many_arguments)]
 pub fn asb<LR>(
-    seed: &Seed,
-    min_buy: bitcoin::Amount,
-    max_buy: bitcoin::Amount,
+    identity: identity::Keypair,
+    rate_config: watch::Receiver<RateConfig>,
     latest_rate: LR,
     resume_only: bool,
     env_config: env::Config,
@@ -22,8 +21,6 @@ pub fn asb<LR>(
 where
     LR: LatestRate + Send + 'static + Debug + Clone,
 {
-    let identity = seed.derive_libp2p_identity();
-
     let rendezvous_nodes = rendezvous_addrs
         .iter()
         .map(|addr| {
@@ -36,8 +33,7 @@ where
         .collect();
 
     let behaviour = asb::Behaviour::new(
-        min_buy,
-        max_buy,
+        rate_config,
         latest_rate,
         resume_only,
         env_config,
@@ -57,7 +57,27 @@ impl From<(PeerId, Message)> for asb::OutEvent {
         }
     }
 }
-crate::impl_from_rr_event!(OutEvent, asb::OutEvent, PROTOCOL);
+
+/// Alice only sends transfer proofs. A failed request is reported with its id
+/// so she can send the transfer proof again.
+impl From<OutEvent> for asb::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            RequestResponseEvent::Message { peer, message } => Self::from((peer, message)),
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => Self::TransferProofFailed {
+                peer,
+                id: request_id,
+                error,
+            },
+            RequestResponseEvent::InboundFailure { .. }
+            | RequestResponseEvent::ResponseSent { .. } => Self::Other,
+        }
+    }
+}
 
 impl From<(PeerId, Message)> for cli::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
@@ -1,7 +1,8 @@
+use crate::database::SwapBackup;
 use crate::protocol::alice::swap::is_complete as alice_is_complete;
 use crate::protocol::alice::AliceState;
 use crate::protocol::bob::swap::is_complete as bob_is_complete;
-use crate::protocol::bob::BobState;
+use crate::protocol::bob::{BobState, SwapSummary};
 use crate::{bitcoin, monero};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -13,6 +14,8 @@ use sigma_fun::ext::dl_secp256k1_ed25519_eq::{CrossCurveDLEQ, CrossCurveDLEQProo
 use sigma_fun::HashTranscript;
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::time::Duration;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 pub mod alice;
@@ -55,6 +58,12 @@ pub struct Message1 {
     tx_redeem_fee: bitcoin::Amount,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_punish_fee: bitcoin::Amount,
+    /// The timelocks Alice builds her transactions with. Sellers running an
+    /// older version do not send them.
+    #[serde(default)]
+    cancel_timelock: Option<bitcoin::CancelTimelock>,
+    #[serde(default)]
+    punish_timelock: Option<bitcoin::PunishTimelock>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -110,6 +119,21 @@ pub struct NotAlice;
 #[error("Not in the role of Bob")]
 pub struct NotBob;
 
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Swap {swap_id} is already being handled by PID {pid}")]
+pub struct SwapLocked {
+    pub swap_id: Uuid,
+    pub pid: u32,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Swap {0} already exists in the database")]
+pub struct SwapAlreadyExists(pub Uuid);
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Swap {0} is not finished")]
+pub struct SwapNotFinished(pub Uuid);
+
 impl TryInto<BobState> for State {
     type Error = NotBob;
 
@@ -139,11 +163,47 @@ pub trait Database {
     async fn insert_monero_address(&self, swap_id: Uuid, address: monero::Address) -> Result<()>;
     async fn get_monero_address(&self, swap_id: Uuid) -> Result<monero::Address>;
     async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()>;
+    /// Returns the addresses of the peer, the one we most recently connected
+    /// to first.
     async fn get_addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>>;
+    async fn record_connected_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()>;
     async fn get_swap_start_date(&self, swap_id: Uuid) -> Result<String>;
     async fn insert_latest_state(&self, swap_id: Uuid, state: State) -> Result<()>;
     async fn get_state(&self, swap_id: Uuid) -> Result<State>;
     async fn get_states(&self, swap_id: Uuid) -> Result<Vec<State>>;
+    /// Returns every state the swap went through together with the time it
+    /// was entered, oldest first.
+    async fn history(&self, swap_id: Uuid) -> Result<Vec<(OffsetDateTime, State)>>;
     async fn all(&self) -> Result<Vec<(Uuid, State)>>;
     async fn raw_all(&self) -> Result<HashMap<Uuid, Vec<serde_json::Value>>>;
+    async fn insert_swap_summary(&self, swap_id: Uuid, summary: SwapSummary) -> Result<()>;
+    async fn get_swap_summary(&self, swap_id: Uuid) -> Result<Option<SwapSummary>>;
+    async fn insert_broadcast_transaction(
+        &self,
+        swap_id: Uuid,
+        transaction: bitcoin::Transaction,
+    ) -> Result<()>;
+    async fn get_broadcast_transactions(&self, swap_id: Uuid) -> Result<Vec<bitcoin::Transaction>>;
+    /// Records that the process `pid` handles the swap. Fails with
+    /// [`SwapLocked`] if another process holds the lock, unless the lock was
+    /// not refreshed for `stale_after` or `force` is set.
+    async fn acquire_swap_lock(
+        &self,
+        swap_id: Uuid,
+        pid: u32,
+        stale_after: Duration,
+        force: bool,
+    ) -> Result<()>;
+    /// Returns `false` if another process took over the lock.
+    async fn refresh_swap_lock(&self, swap_id: Uuid, pid: u32) -> Result<bool>;
+    async fn release_swap_lock(&self, swap_id: Uuid, pid: u32) -> Result<()>;
+    async fn export_swap(&self, swap_id: Uuid) -> Result<SwapBackup>;
+    /// Fails with [`SwapAlreadyExists`] if the database already knows the
+    /// swap, unless `replace` is set.
+    async fn import_swap(&self, backup: SwapBackup, replace: bool) -> Result<()>;
+    /// Deletes everything recorded about the swap in a single transaction.
+    /// Fails with [`SwapNotFinished`] unless the swap is in a final state.
+    async fn delete_swap(&self, swap_id: Uuid) -> Result<()>;
+    /// Returns the space freed by deleted records to the file system.
+    async fn compact(&self) -> Result<()>;
 }
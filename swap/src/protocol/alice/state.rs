@@ -1,3 +1,4 @@
+use crate::bitcoin::wallet::GetScriptStatus;
 use crate::bitcoin::{
     current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxPunish, TxRedeem, TxRefund, Txid,
@@ -107,6 +108,30 @@ impl fmt::Display for AliceState {
     }
 }
 
+impl AliceState {
+    /// Whether the swap already learned Bob's encrypted signature. Bob sends it
+    /// again if our acknowledgement got lost.
+    pub fn encrypted_signature_learned(&self) -> bool {
+        matches!(
+            self,
+            AliceState::EncSigLearned { .. }
+                | AliceState::BtcRedeemTransactionPublished { .. }
+                | AliceState::BtcRedeemed
+        )
+    }
+
+    /// The XMR the swap still has to lock. It is not yet deducted from the
+    /// unlocked balance of the wallet.
+    pub fn xmr_to_lock(&self) -> Option<monero::Amount> {
+        match self {
+            AliceState::Started { state3 }
+            | AliceState::BtcLockTransactionSeen { state3 }
+            | AliceState::BtcLocked { state3 } => Some(state3.xmr),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct State0 {
     a: bitcoin::SecretKey,
@@ -250,6 +275,8 @@ impl State1 {
             punish_address: self.punish_address.clone(),
             tx_redeem_fee: self.tx_redeem_fee,
             tx_punish_fee: self.tx_punish_fee,
+            cancel_timelock: Some(self.cancel_timelock),
+            punish_timelock: Some(self.punish_timelock),
         }
     }
 
@@ -403,10 +430,13 @@ pub struct State3 {
 }
 
 impl State3 {
-    pub async fn expired_timelocks(
+    pub async fn expired_timelocks<D, C>(
         &self,
-        bitcoin_wallet: &bitcoin::Wallet,
-    ) -> Result<ExpiredTimelocks> {
+        bitcoin_wallet: &bitcoin::Wallet<D, C>,
+    ) -> Result<ExpiredTimelocks>
+    where
+        C: GetScriptStatus,
+    {
         let tx_cancel = self.tx_cancel();
 
         let tx_lock_status = bitcoin_wallet.status_of_script(&self.tx_lock).await?;
@@ -513,10 +543,10 @@ impl State3 {
     ) -> Result<()> {
         let view_key = self.v;
 
-        // Ensure that the XMR to be refunded are spendable by awaiting 10 confirmations
-        // on the lock transaction
+        // Ensure that the XMR to be refunded are spendable by awaiting enough
+        // confirmations on the lock transaction
         monero_wallet
-            .watch_for_transfer(self.lock_xmr_watch_request(transfer_proof, 10))
+            .watch_for_transfer(self.lock_xmr_watch_request(transfer_proof, monero::SPENDABLE_AGE))
             .await?;
 
         monero_wallet
@@ -7,10 +7,12 @@ use crate::protocol::Database;
 use crate::{bitcoin, cli, env, monero};
 
 pub use self::state::*;
-pub use self::swap::{run, run_until};
+pub use self::summary::SwapSummary;
+pub use self::swap::{run, run_and_observe, run_until};
 use std::convert::TryInto;
 
 pub mod state;
+pub mod summary;
 pub mod swap;
 
 pub struct Swap {
@@ -1,4 +1,4 @@
-use crate::bitcoin::wallet::{EstimateFeeRate, Subscription};
+use crate::bitcoin::wallet::{EstimateFeeRate, GetScriptStatus, Subscription};
 use crate::bitcoin::{
     self, current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxLock, Txid,
@@ -75,6 +75,140 @@ impl fmt::Display for BobState {
     }
 }
 
+impl BobState {
+    /// What this state means for the user of the CLI.
+    pub fn status(&self) -> SwapStatus {
+        use Recovery::*;
+        use SwapProgress::*;
+
+        let (description, progress, recovery) = match self {
+            BobState::Started { .. } => {
+                ("Waiting for the swap to be set up", InProgress, NotNeeded)
+            }
+            BobState::SwapSetupCompleted(..) => (
+                "Swap set up, the Bitcoin is not locked yet",
+                InProgress,
+                NotNeeded,
+            ),
+            BobState::BtcLocked { state3, .. } => (
+                "Bitcoin locked, waiting for the seller to lock the Monero",
+                InProgress,
+                CancelAfterTimelock(state3.cancel_timelock),
+            ),
+            BobState::XmrLockProofReceived { state, .. } => (
+                "The seller locked the Monero, waiting for confirmations",
+                InProgress,
+                CancelAfterTimelock(state.cancel_timelock),
+            ),
+            BobState::XmrLocked(state4) => (
+                "Monero locked, sending the encrypted signature to the seller",
+                InProgress,
+                CancelAfterTimelock(state4.cancel_timelock),
+            ),
+            BobState::EncSigSent(state4) => (
+                "Waiting for the seller to redeem the Bitcoin",
+                InProgress,
+                CancelAfterTimelock(state4.cancel_timelock),
+            ),
+            BobState::BtcRedeemed(..) => (
+                "The seller redeemed the Bitcoin, the Monero can be redeemed",
+                InProgress,
+                NotNeeded,
+            ),
+            BobState::CancelTimelockExpired(..) => {
+                ("The cancel timelock expired", InProgress, Cancel)
+            }
+            BobState::BtcCancelled(..) => ("The swap was cancelled", InProgress, Refund),
+            BobState::BtcRefunded(..) => ("The Bitcoin was refunded", Refunded, NotNeeded),
+            BobState::XmrRedeemed { .. } => ("The Monero was redeemed", Completed, NotNeeded),
+            BobState::BtcPunished { .. } => (
+                "The seller punished the swap and took the Bitcoin",
+                Punished,
+                NotNeeded,
+            ),
+            BobState::SafelyAborted => {
+                ("Aborted before any Bitcoin was locked", Aborted, NotNeeded)
+            }
+        };
+
+        SwapStatus {
+            description,
+            progress,
+            recovery,
+        }
+    }
+
+    /// Whether the swap already received Alice's transfer proof. Alice sends
+    /// it again if our acknowledgement got lost.
+    pub fn transfer_proof_received(&self) -> bool {
+        matches!(
+            self,
+            BobState::XmrLockProofReceived { .. }
+                | BobState::XmrLocked(..)
+                | BobState::EncSigSent(..)
+                | BobState::BtcRedeemed(..)
+                | BobState::XmrRedeemed { .. }
+        )
+    }
+}
+
+/// A summary of a [`BobState`] for users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SwapStatus {
+    pub description: &'static str,
+    pub progress: SwapProgress,
+    pub recovery: Recovery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "lowercase")]
+pub enum SwapProgress {
+    #[strum(serialize = "in progress")]
+    InProgress,
+    Completed,
+    Refunded,
+    Punished,
+    Aborted,
+}
+
+/// Whether the locked Bitcoin can be recovered with `cancel` and `refund`.
+///
+/// This only depends on the stored state. Whether a timelock already expired
+/// can only be told by looking at the blockchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recovery {
+    /// No Bitcoin is locked or there is nothing left to recover.
+    NotNeeded,
+    /// The swap can be cancelled once this many blocks have been mined on top
+    /// of the Bitcoin lock transaction.
+    CancelAfterTimelock(CancelTimelock),
+    /// The swap can be cancelled now.
+    Cancel,
+    /// The swap is cancelled and the Bitcoin can be refunded now.
+    Refund,
+}
+
+impl fmt::Display for SwapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}", self.description, self.progress)?;
+
+        match self.recovery {
+            Recovery::NotNeeded => {}
+            Recovery::CancelAfterTimelock(cancel_timelock) => write!(
+                f,
+                ", cancel possible {} blocks after the Bitcoin lock transaction was confirmed",
+                u32::from(cancel_timelock)
+            )?,
+            Recovery::Cancel => write!(f, ", cancel possible now")?,
+            Recovery::Refund => write!(f, ", refund possible now")?,
+        }
+
+        write!(f, ")")
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct State0 {
     swap_id: Uuid,
@@ -94,6 +228,42 @@ pub struct State0 {
     tx_cancel_fee: bitcoin::Amount,
 }
 
+/// Alice announced timelocks that differ from Bob's. Her signatures would not
+/// be valid for the transactions Bob builds, and Bob must not lock his Bitcoin
+/// with timelocks he did not agree to.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Seller proposed timelocks that differ from the required cancel timelock of {cancel} and punish timelock of {punish}")]
+pub struct TimelocksMismatch {
+    pub cancel: CancelTimelock,
+    pub punish: PunishTimelock,
+    pub seller_cancel: Option<CancelTimelock>,
+    pub seller_punish: Option<PunishTimelock>,
+}
+
+/// Accepts the timelocks Alice announced if they are the ones Bob builds his
+/// transactions with. Sellers that do not announce them are accepted, their
+/// signatures are still checked against Bob's transactions.
+fn check_timelocks(
+    cancel: CancelTimelock,
+    punish: PunishTimelock,
+    seller_cancel: Option<CancelTimelock>,
+    seller_punish: Option<PunishTimelock>,
+) -> Result<(), TimelocksMismatch> {
+    let cancel_matches = seller_cancel.map_or(true, |seller_cancel| seller_cancel == cancel);
+    let punish_matches = seller_punish.map_or(true, |seller_punish| seller_punish == punish);
+
+    if cancel_matches && punish_matches {
+        Ok(())
+    } else {
+        Err(TimelocksMismatch {
+            cancel,
+            punish,
+            seller_cancel,
+            seller_punish,
+        })
+    }
+}
+
 impl State0 {
     #[allow(clippy::too_many_arguments)]
     pub fn new<R: RngCore + CryptoRng>(
@@ -174,6 +344,13 @@ impl State0 {
             bail!("Alice's dleq proof doesn't verify")
         }
 
+        check_timelocks(
+            self.cancel_timelock,
+            self.punish_timelock,
+            msg.cancel_timelock,
+            msg.punish_timelock,
+        )?;
+
         let tx_lock = bitcoin::TxLock::new(
             wallet,
             self.btc,
@@ -236,6 +413,11 @@ impl State1 {
         }
     }
 
+    /// The outputs of our wallet that the lock transaction spends.
+    pub fn tx_lock_inputs(&self) -> impl Iterator<Item = ::bitcoin::OutPoint> + '_ {
+        self.tx_lock.inputs()
+    }
+
     pub fn receive(self, msg: Message3) -> Result<State2> {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
@@ -395,7 +577,8 @@ impl State3 {
             public_spend_key: S,
             public_view_key: self.v.public(),
             transfer_proof,
-            conf_target: self.min_monero_confirmations,
+            // Only count the XMR as locked once we could actually spend them.
+            conf_target: self.min_monero_confirmations.max(monero::SPENDABLE_AGE),
             expected: self.xmr,
         }
     }
@@ -441,10 +624,13 @@ impl State3 {
         self.tx_lock.txid()
     }
 
-    pub async fn expired_timelock(
+    pub async fn expired_timelock<D, C>(
         &self,
-        bitcoin_wallet: &bitcoin::Wallet,
-    ) -> Result<ExpiredTimelocks> {
+        bitcoin_wallet: &bitcoin::Wallet<D, C>,
+    ) -> Result<ExpiredTimelocks>
+    where
+        C: GetScriptStatus,
+    {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
             self.cancel_timelock,
@@ -510,6 +696,10 @@ impl State4 {
         })
     }
 
+    pub fn tx_redeem_id(&self) -> Txid {
+        bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee).txid()
+    }
+
     pub fn tx_redeem_encsig(&self) -> bitcoin::EncryptedSignature {
         let tx_redeem =
             bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee);
@@ -543,10 +733,13 @@ impl State4 {
         })
     }
 
-    pub async fn expired_timelock(
+    pub async fn expired_timelock<D, C>(
         &self,
-        bitcoin_wallet: &bitcoin::Wallet,
-    ) -> Result<ExpiredTimelocks> {
+        bitcoin_wallet: &bitcoin::Wallet<D, C>,
+    ) -> Result<ExpiredTimelocks>
+    where
+        C: GetScriptStatus,
+    {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
             self.cancel_timelock,
@@ -624,10 +817,13 @@ pub struct State6 {
 }
 
 impl State6 {
-    pub async fn expired_timelock(
+    pub async fn expired_timelock<D, C>(
         &self,
-        bitcoin_wallet: &bitcoin::Wallet,
-    ) -> Result<ExpiredTimelocks> {
+        bitcoin_wallet: &bitcoin::Wallet<D, C>,
+    ) -> Result<ExpiredTimelocks>
+    where
+        C: GetScriptStatus,
+    {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
             self.cancel_timelock,
@@ -668,7 +864,14 @@ impl State6 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<(Txid, Subscription)> {
-        let transaction = bitcoin::TxCancel::new(
+        let transaction = self.signed_cancel_transaction()?;
+        let (tx_id, subscription) = bitcoin_wallet.broadcast(transaction, "cancel").await?;
+
+        Ok((tx_id, subscription))
+    }
+
+    pub fn signed_cancel_transaction(&self) -> Result<Transaction> {
+        bitcoin::TxCancel::new(
             &self.tx_lock,
             self.cancel_timelock,
             self.A,
@@ -676,18 +879,14 @@ impl State6 {
             self.tx_cancel_fee,
         )?
         .complete_as_bob(self.A, self.b.clone(), self.tx_cancel_sig_a.clone())
-        .context("Failed to complete Bitcoin cancel transaction")?;
-
-        let (tx_id, subscription) = bitcoin_wallet.broadcast(transaction, "cancel").await?;
-
-        Ok((tx_id, subscription))
+        .context("Failed to complete Bitcoin cancel transaction")
     }
 
-    pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<()> {
+    pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
         let signed_tx_refund = self.signed_refund_transaction()?;
-        bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
+        let (txid, _) = bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
 
-        Ok(())
+        Ok(txid)
     }
 
     pub fn signed_refund_transaction(&self) -> Result<Transaction> {
@@ -716,3 +915,267 @@ impl State6 {
         self.tx_lock.txid()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::wallet::WalletBuilder;
+    use crate::bitcoin::{TxPunish, TxRedeem};
+    use crate::env::{self, GetConfig, Regtest};
+    use crate::protocol::alice;
+    use rand::rngs::OsRng;
+
+    #[tokio::test]
+    async fn every_state_has_a_status() {
+        let states = all_states().await;
+        let cancel_timelock = Regtest::get_config().bitcoin_cancel_timelock;
+
+        let statuses: Vec<_> = states
+            .iter()
+            .map(|state| {
+                let status = state.status();
+                (status.progress, status.recovery)
+            })
+            .collect();
+
+        assert_eq!(
+            statuses,
+            vec![
+                (SwapProgress::InProgress, Recovery::NotNeeded),
+                (SwapProgress::InProgress, Recovery::NotNeeded),
+                (
+                    SwapProgress::InProgress,
+                    Recovery::CancelAfterTimelock(cancel_timelock)
+                ),
+                (
+                    SwapProgress::InProgress,
+                    Recovery::CancelAfterTimelock(cancel_timelock)
+                ),
+                (
+                    SwapProgress::InProgress,
+                    Recovery::CancelAfterTimelock(cancel_timelock)
+                ),
+                (
+                    SwapProgress::InProgress,
+                    Recovery::CancelAfterTimelock(cancel_timelock)
+                ),
+                (SwapProgress::InProgress, Recovery::NotNeeded),
+                (SwapProgress::InProgress, Recovery::Cancel),
+                (SwapProgress::InProgress, Recovery::Refund),
+                (SwapProgress::Refunded, Recovery::NotNeeded),
+                (SwapProgress::Completed, Recovery::NotNeeded),
+                (SwapProgress::Punished, Recovery::NotNeeded),
+                (SwapProgress::Aborted, Recovery::NotNeeded),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn status_descriptions_are_distinct() {
+        let states = all_states().await;
+
+        let mut descriptions: Vec<_> = states
+            .iter()
+            .map(|state| state.status().description)
+            .collect();
+        descriptions.sort_unstable();
+        descriptions.dedup();
+
+        assert_eq!(descriptions.len(), states.len());
+    }
+
+    #[test]
+    fn status_display_mentions_the_possible_recovery() {
+        let in_progress = SwapStatus {
+            description: "Waiting for the seller to redeem the Bitcoin",
+            progress: SwapProgress::InProgress,
+            recovery: Recovery::CancelAfterTimelock(CancelTimelock::new(72)),
+        };
+        let refunded = SwapStatus {
+            description: "The Bitcoin was refunded",
+            progress: SwapProgress::Refunded,
+            recovery: Recovery::NotNeeded,
+        };
+
+        assert_eq!(
+            in_progress.to_string(),
+            "Waiting for the seller to redeem the Bitcoin (in progress, cancel possible 72 blocks after the Bitcoin lock transaction was confirmed)"
+        );
+        assert_eq!(refunded.to_string(), "The Bitcoin was refunded (refunded)");
+    }
+
+    #[test]
+    fn only_the_expected_timelocks_are_accepted() {
+        let cancel = CancelTimelock::new(72);
+        let punish = PunishTimelock::new(72);
+
+        assert!(check_timelocks(cancel, punish, Some(cancel), Some(punish)).is_ok());
+        assert!(check_timelocks(cancel, punish, None, None).is_ok());
+
+        for (seller_cancel, seller_punish) in [
+            (Some(CancelTimelock::new(71)), Some(punish)),
+            (Some(CancelTimelock::new(73)), Some(punish)),
+            (Some(cancel), Some(PunishTimelock::new(1))),
+            (Some(cancel), Some(PunishTimelock::new(73))),
+            (None, Some(PunishTimelock::new(1))),
+        ] {
+            assert_eq!(
+                check_timelocks(cancel, punish, seller_cancel, seller_punish),
+                Err(TimelocksMismatch {
+                    cancel,
+                    punish,
+                    seller_cancel,
+                    seller_punish,
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn bob_rejects_a_seller_with_a_shorter_punish_timelock() {
+        let alice_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat()).build();
+        let bob_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat()).build();
+        let spending_fee = bitcoin::Amount::from_sat(1_000);
+        let btc_amount = bitcoin::Amount::from_sat(500_000);
+        let xmr_amount = monero::Amount::from_piconero(10000);
+        let config = Regtest::get_config();
+        let alice_config = env::Config {
+            bitcoin_punish_timelock: PunishTimelock::new(1),
+            ..config
+        };
+
+        let alice_state0 = alice::State0::new(
+            btc_amount,
+            xmr_amount,
+            alice_config,
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet.new_address().await.unwrap(),
+            spending_fee,
+            spending_fee,
+            &mut OsRng,
+        );
+        let bob_state0 = State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            btc_amount,
+            xmr_amount,
+            config.bitcoin_cancel_timelock,
+            config.bitcoin_punish_timelock,
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            spending_fee,
+            spending_fee,
+        );
+
+        let (_, alice_state1) = alice_state0.receive(bob_state0.next_message()).unwrap();
+        let error = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<TimelocksMismatch>(),
+            Some(&TimelocksMismatch {
+                cancel: config.bitcoin_cancel_timelock,
+                punish: config.bitcoin_punish_timelock,
+                seller_cancel: Some(config.bitcoin_cancel_timelock),
+                seller_punish: Some(PunishTimelock::new(1)),
+            })
+        );
+    }
+
+    /// One state of every variant, in the order they are declared.
+    async fn all_states() -> Vec<BobState> {
+        let alice_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat()).build();
+        let bob_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat()).build();
+        let spending_fee = bitcoin::Amount::from_sat(1_000);
+        let btc_amount = bitcoin::Amount::from_sat(500_000);
+        let xmr_amount = monero::Amount::from_piconero(10000);
+        let config = Regtest::get_config();
+
+        let alice_state0 = alice::State0::new(
+            btc_amount,
+            xmr_amount,
+            config,
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet
+                .estimate_fee(TxRedeem::weight(), btc_amount)
+                .await
+                .unwrap(),
+            alice_wallet
+                .estimate_fee(TxPunish::weight(), btc_amount)
+                .await
+                .unwrap(),
+            &mut OsRng,
+        );
+        let change_address = bob_wallet.new_address().await.unwrap();
+        let bob_state0 = State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            btc_amount,
+            xmr_amount,
+            config.bitcoin_cancel_timelock,
+            config.bitcoin_punish_timelock,
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            spending_fee,
+            spending_fee,
+        );
+
+        let (_, alice_state1) = alice_state0.receive(bob_state0.next_message()).unwrap();
+        let bob_state1 = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message())
+            .await
+            .unwrap();
+        let alice_state2 = alice_state1.receive(bob_state1.next_message()).unwrap();
+        let bob_state2 = bob_state1.receive(alice_state2.next_message()).unwrap();
+
+        let (state3, _) = bob_state2.clone().lock_btc().await.unwrap();
+        let restore_height = BlockHeight { height: 0 };
+        let state4 = state3.clone().xmr_locked(restore_height);
+        let state5 = State5 {
+            s_a: monero::PrivateKey {
+                scalar: monero::Scalar::random(&mut OsRng),
+            },
+            s_b: state4.s_b,
+            v: state4.v,
+            tx_lock: state4.tx_lock.clone(),
+            monero_wallet_restore_blockheight: restore_height,
+        };
+        let state6 = state4.clone().cancel();
+        let transfer_proof = TransferProof::new(
+            monero::TxHash("tx-hash".to_owned()),
+            monero::PrivateKey {
+                scalar: monero::Scalar::random(&mut OsRng),
+            },
+        );
+        let tx_lock_id = state3.tx_lock.txid();
+
+        vec![
+            BobState::Started {
+                btc_amount,
+                change_address,
+            },
+            BobState::SwapSetupCompleted(bob_state2),
+            BobState::BtcLocked {
+                state3: state3.clone(),
+                monero_wallet_restore_blockheight: restore_height,
+            },
+            BobState::XmrLockProofReceived {
+                state: state3,
+                lock_transfer_proof: transfer_proof,
+                monero_wallet_restore_blockheight: restore_height,
+            },
+            BobState::XmrLocked(state4.clone()),
+            BobState::EncSigSent(state4),
+            BobState::BtcRedeemed(state5),
+            BobState::CancelTimelockExpired(state6.clone()),
+            BobState::BtcCancelled(state6.clone()),
+            BobState::BtcRefunded(state6),
+            BobState::XmrRedeemed { tx_lock_id },
+            BobState::BtcPunished { tx_lock_id },
+            BobState::SafelyAborted,
+        ]
+    }
+}
@@ -0,0 +1,123 @@
+use crate::bitcoin::{self, Txid};
+use crate::monero::{self, TxHash};
+use crate::protocol::bob::state::State2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The amounts, transactions and fees of a swap, recorded while the swap
+/// progresses so they can be shown once it is done.
+///
+/// Fields stay empty until the swap reaches the point where they are known.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapSummary {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat::opt")]
+    pub btc_lock_amount: Option<bitcoin::Amount>,
+    pub tx_lock_id: Option<Txid>,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat::opt")]
+    pub tx_lock_fee: Option<bitcoin::Amount>,
+    /// The amount of Monero Alice has to lock. The redeemed amount is lower by
+    /// the Monero network fee of the sweep.
+    pub xmr_amount: Option<monero::Amount>,
+    pub xmr_lock_tx_hash: Option<TxHash>,
+    pub tx_redeem_id: Option<Txid>,
+    pub xmr_redeem_tx_hashes: Vec<TxHash>,
+    pub tx_cancel_id: Option<Txid>,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat::opt")]
+    pub tx_cancel_fee: Option<bitcoin::Amount>,
+    pub tx_refund_id: Option<Txid>,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat::opt")]
+    pub tx_refund_fee: Option<bitcoin::Amount>,
+}
+
+impl SwapSummary {
+    pub fn swap_setup_completed(&mut self, state2: &State2) {
+        self.btc_lock_amount = Some(state2.tx_lock.lock_amount());
+        self.tx_lock_id = Some(state2.tx_lock.txid());
+        self.xmr_amount = Some(state2.xmr);
+    }
+}
+
+impl fmt::Display for SwapSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = Vec::new();
+
+        if let Some(amount) = self.btc_lock_amount {
+            entries.push(format!("btc_lock_amount={}", amount));
+        }
+        if let Some(txid) = self.tx_lock_id {
+            entries.push(format!("tx_lock_id={}", txid));
+        }
+        if let Some(fee) = self.tx_lock_fee {
+            entries.push(format!("tx_lock_fee={}", fee));
+        }
+        if let Some(amount) = self.xmr_amount {
+            entries.push(format!("xmr_amount={}", amount));
+        }
+        if let Some(tx_hash) = &self.xmr_lock_tx_hash {
+            entries.push(format!("xmr_lock_tx_hash={}", tx_hash));
+        }
+        if let Some(txid) = self.tx_redeem_id {
+            entries.push(format!("tx_redeem_id={}", txid));
+        }
+        for tx_hash in &self.xmr_redeem_tx_hashes {
+            entries.push(format!("xmr_redeem_tx_hash={}", tx_hash));
+        }
+        if let Some(txid) = self.tx_cancel_id {
+            entries.push(format!("tx_cancel_id={}", txid));
+        }
+        if let Some(fee) = self.tx_cancel_fee {
+            entries.push(format!("tx_cancel_fee={}", fee));
+        }
+        if let Some(txid) = self.tx_refund_id {
+            entries.push(format!("tx_refund_id={}", txid));
+        }
+        if let Some(fee) = self.tx_refund_fee {
+            entries.push(format!("tx_refund_fee={}", fee));
+        }
+
+        write!(f, "{}", entries.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const TXID: &str = "b5a2bf5a1ddd3d2e2e4cbf2e7e1b0d8c6fef3dcff8d1b4bb0e2dcb3b2e3f1c9a";
+
+    #[test]
+    fn only_known_fields_are_displayed() {
+        let summary = SwapSummary {
+            btc_lock_amount: Some(bitcoin::Amount::from_sat(1_000_000)),
+            tx_lock_id: Some(Txid::from_str(TXID).unwrap()),
+            tx_lock_fee: Some(bitcoin::Amount::from_sat(1_000)),
+            ..SwapSummary::default()
+        };
+
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "btc_lock_amount=0.01 BTC tx_lock_id={} tx_lock_fee=0.00001 BTC",
+                TXID
+            )
+        );
+    }
+
+    #[test]
+    fn roundtrips_through_json() {
+        let summary = SwapSummary {
+            btc_lock_amount: Some(bitcoin::Amount::from_sat(1_000_000)),
+            tx_lock_id: Some(Txid::from_str(TXID).unwrap()),
+            xmr_amount: Some(monero::Amount::from_piconero(1_000_000_000_000)),
+            xmr_redeem_tx_hashes: vec![TxHash("abcd".to_string())],
+            tx_refund_fee: Some(bitcoin::Amount::from_sat(500)),
+            ..SwapSummary::default()
+        };
+
+        let json = serde_json::to_string(&summary).unwrap();
+        let deserialized: SwapSummary = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized, summary);
+    }
+}
@@ -1,11 +1,15 @@
 use crate::bitcoin::{ExpiredTimelocks, TxCancel, TxRefund};
 use crate::cli::EventLoopHandle;
+use crate::network::heartbeat::{ConnectionHealth, Heartbeat};
 use crate::network::swap_setup::bob::NewSwap;
 use crate::protocol::bob;
 use crate::protocol::bob::state::*;
+use crate::protocol::bob::SwapSummary;
+use crate::protocol::Database;
 use crate::{bitcoin, monero};
 use anyhow::{bail, Context, Result};
 use tokio::select;
+use tokio::sync::watch;
 use uuid::Uuid;
 
 pub fn is_complete(state: &BobState) -> bool {
@@ -23,26 +27,56 @@ pub async fn run(swap: bob::Swap) -> Result<BobState> {
     run_until(swap, is_complete).await
 }
 
+/// Like [`run`], but calls `on_transition` with every state the swap
+/// advances to.
+pub async fn run_and_observe(
+    swap: bob::Swap,
+    on_transition: impl FnMut(&BobState),
+) -> Result<BobState> {
+    run_until_and_observe(swap, is_complete, on_transition).await
+}
+
 pub async fn run_until(
+    swap: bob::Swap,
+    is_target_state: fn(&BobState) -> bool,
+) -> Result<BobState> {
+    run_until_and_observe(swap, is_target_state, |_| {}).await
+}
+
+async fn run_until_and_observe(
     mut swap: bob::Swap,
     is_target_state: fn(&BobState) -> bool,
+    mut on_transition: impl FnMut(&BobState),
 ) -> Result<BobState> {
     let mut current_state = swap.state;
+    let mut summary = swap.db.get_swap_summary(swap.id).await?.unwrap_or_default();
+
+    let broadcast_transactions = swap.db.get_broadcast_transactions(swap.id).await?;
+    swap.bitcoin_wallet
+        .rebroadcast_until_confirmed(broadcast_transactions)
+        .await;
 
     while !is_target_state(&current_state) {
         current_state = next_state(
             swap.id,
             current_state.clone(),
             &mut swap.event_loop_handle,
+            swap.db.as_ref(),
             swap.bitcoin_wallet.as_ref(),
             swap.monero_wallet.as_ref(),
             swap.monero_receive_address,
+            &mut summary,
         )
         .await?;
 
         swap.db
             .insert_latest_state(swap.id, current_state.clone().into())
             .await?;
+        swap.db
+            .insert_swap_summary(swap.id, summary.clone())
+            .await?;
+
+        on_transition(&current_state);
     }
 
     Ok(current_state)
@@ -52,9 +86,11 @@ async fn next_state(
     swap_id: Uuid,
     state: BobState,
     event_loop_handle: &mut EventLoopHandle,
+    db: &(dyn Database + Send + Sync),
     bitcoin_wallet: &bitcoin::Wallet,
     monero_wallet: &monero::Wallet,
     monero_receive_address: monero::Address,
+    summary: &mut SwapSummary,
 ) -> Result<BobState> {
     tracing::debug!(%state, "Advancing state");
 
@@ -70,7 +106,7 @@ async fn next_state(
                 .estimate_fee(TxCancel::weight(), btc_amount)
                 .await?;
 
-            let state2 = event_loop_handle
+            let setup = event_loop_handle
                 .setup_swap(NewSwap {
                     swap_id,
                     btc: btc_amount,
@@ -78,10 +114,24 @@ async fn next_state(
                     tx_cancel_fee,
                     bitcoin_refund_address: change_address,
                 })
-                .await?;
+                .await;
+
+            let state2 = match setup {
+                Ok(state2) => state2,
+                Err(error) => match error.downcast_ref::<TimelocksMismatch>() {
+                    // Nothing is locked yet, so aborting is safe
+                    Some(mismatch) => {
+                        tracing::error!(%swap_id, "{}, aborting the swap", mismatch);
+                        return Ok(BobState::SafelyAborted);
+                    }
+                    None => return Err(error),
+                },
+            };
 
             tracing::info!(%swap_id, "Starting new swap");
 
+            summary.swap_setup_completed(&state2);
+
             BobState::SwapSetupCompleted(state2)
         }
         BobState::SwapSetupCompleted(state2) => {
@@ -102,7 +152,10 @@ async fn next_state(
                 .sign_and_finalize(tx_lock.clone().into())
                 .await
                 .context("Failed to sign Bitcoin lock transaction")?;
-            let (..) = bitcoin_wallet.broadcast(signed_tx, "lock").await?;
+            let (..) = bitcoin_wallet.broadcast(signed_tx.clone(), "lock").await?;
+            db.insert_broadcast_transaction(swap_id, signed_tx).await?;
+
+            summary.tx_lock_fee = tx_lock.fee().ok();
 
             BobState::BtcLocked {
                 state3,
@@ -118,6 +171,7 @@ async fn next_state(
             let tx_lock_status = bitcoin_wallet.subscribe_to(state3.tx_lock.clone()).await;
 
             if let ExpiredTimelocks::None { .. } = state3.expired_timelock(bitcoin_wallet).await? {
+                let alice_unreachable = warn_when_alice_unreachable(event_loop_handle.heartbeat());
                 let transfer_proof_watcher = event_loop_handle.recv_transfer_proof();
                 let cancel_timelock_expires =
                     tx_lock_status.wait_until_confirmed_with(state3.cancel_timelock);
@@ -130,6 +184,8 @@ async fn next_state(
 
                         tracing::info!(txid = %transfer_proof.tx_hash(), "Alice locked Monero");
 
+                        summary.xmr_lock_tx_hash = Some(transfer_proof.tx_hash());
+
                         BobState::XmrLockProofReceived {
                             state: state3,
                             lock_transfer_proof: transfer_proof,
@@ -143,6 +199,7 @@ async fn next_state(
                         let state4 = state3.cancel();
                         BobState::CancelTimelockExpired(state4)
                     },
+                    _ = alice_unreachable => unreachable!("Watching the connection to Alice never completes"),
                 }
             } else {
                 let state4 = state3.cancel();
@@ -187,6 +244,7 @@ async fn next_state(
             // We need to check if she still published the Bitcoin redeem transaction
             // Otherwise we risk staying stuck in "XmrLocked"
             if let Ok(state5) = state.check_for_tx_redeem(bitcoin_wallet).await {
+                summary.tx_redeem_id = Some(state.tx_redeem_id());
                 return Ok(BobState::BtcRedeemed(state5));
             }
 
@@ -195,6 +253,7 @@ async fn next_state(
             if let ExpiredTimelocks::None { .. } = state.expired_timelock(bitcoin_wallet).await? {
                 // Alice has locked Xmr
                 // Bob sends Alice his key
+                let alice_unreachable = warn_when_alice_unreachable(event_loop_handle.heartbeat());
 
                 select! {
                     result = event_loop_handle.send_encrypted_signature(state.tx_redeem_encsig()) => {
@@ -208,6 +267,7 @@ async fn next_state(
                         result?;
                         BobState::CancelTimelockExpired(state.cancel())
                     }
+                    _ = alice_unreachable => unreachable!("Watching the connection to Alice never completes"),
                 }
             } else {
                 BobState::CancelTimelockExpired(state.cancel())
@@ -218,6 +278,7 @@ async fn next_state(
             // Even if the cancel timelock expired, if Alice published the redeem transaction while we were away we cannot miss it
             // If we do we cannot refund and will never be able to leave the "CancelTimelockExpired" state
             if let Ok(state5) = state.check_for_tx_redeem(bitcoin_wallet).await {
+                summary.tx_redeem_id = Some(state.tx_redeem_id());
                 return Ok(BobState::BtcRedeemed(state5));
             }
 
@@ -226,7 +287,9 @@ async fn next_state(
             if let ExpiredTimelocks::None { .. } = state.expired_timelock(bitcoin_wallet).await? {
                 select! {
                     state5 = state.watch_for_redeem_btc(bitcoin_wallet) => {
-                        BobState::BtcRedeemed(state5?)
+                        let state5 = state5?;
+                        summary.tx_redeem_id = Some(state.tx_redeem_id());
+                        BobState::BtcRedeemed(state5)
                     },
                     result = tx_lock_status.wait_until_confirmed_with(state.cancel_timelock) => {
                         result?;
@@ -237,49 +300,69 @@ async fn next_state(
                 BobState::CancelTimelockExpired(state.cancel())
             }
         }
+        BobState::BtcRedeemed(state) if !summary.xmr_redeem_tx_hashes.is_empty() => {
+            // The sweep went through but we stopped before recording the new state.
+            // Sweeping again would fail on the now empty wallet.
+            tracing::info!(
+                txids = ?summary.xmr_redeem_tx_hashes,
+                "Monero was already transferred to the receive address"
+            );
+
+            BobState::XmrRedeemed {
+                tx_lock_id: state.tx_lock_id(),
+            }
+        }
         BobState::BtcRedeemed(state) => {
             let (spend_key, view_key) = state.xmr_keys();
 
             let wallet_file_name = swap_id.to_string();
 
+            // Other swaps must not switch the loaded wallet until the Monero is swept
+            let _loaded_wallet = monero_wallet.claim_loaded_wallet().await;
+
             tracing::info!(%wallet_file_name, "Generating and opening Monero wallet from the extracted keys to redeem the Monero");
 
-            if let Err(e) = monero_wallet
-                .create_from_and_load(
-                    wallet_file_name.clone(),
+            // In case we failed to refresh/sweep, when resuming the wallet already exists
+            // and is opened instead
+            monero_wallet
+                .create_from_keys(
+                    wallet_file_name,
                     spend_key,
                     view_key,
                     state.monero_wallet_restore_blockheight,
                 )
-                .await
-            {
-                // In case we failed to refresh/sweep, when resuming the wallet might already
-                // exist! This is a very unlikely scenario, but if we don't take care of it we
-                // might not be able to ever transfer the Monero.
-                tracing::warn!("Failed to generate monero wallet from keys: {:#}", e);
-                tracing::info!(%wallet_file_name,
-                    "Falling back to trying to open the wallet if it already exists",
-                );
-                monero_wallet.open(wallet_file_name).await?;
-            }
+                .await?;
 
             // Ensure that the generated wallet is synced so we have a proper balance
             monero_wallet.refresh(20).await?;
             // Sweep (transfer all funds) to the given address
             let tx_hashes = monero_wallet.sweep_all(monero_receive_address).await?;
 
-            for tx_hash in tx_hashes {
+            for tx_hash in &tx_hashes {
                 tracing::info!(%monero_receive_address, txid=%tx_hash.0, "Successfully transferred XMR to wallet");
             }
 
+            summary.xmr_redeem_tx_hashes = tx_hashes;
+            db.insert_swap_summary(swap_id, summary.clone()).await?;
+
             BobState::XmrRedeemed {
                 tx_lock_id: state.tx_lock_id(),
             }
         }
         BobState::CancelTimelockExpired(state4) => {
-            if state4.check_for_tx_cancel(bitcoin_wallet).await.is_err() {
-                state4.submit_tx_cancel(bitcoin_wallet).await?;
-            }
+            let tx_cancel_id = match state4.check_for_tx_cancel(bitcoin_wallet).await {
+                Ok(tx_cancel) => tx_cancel.txid(),
+                Err(_) => {
+                    let (tx_cancel_id, _) = state4.submit_tx_cancel(bitcoin_wallet).await?;
+                    db.insert_broadcast_transaction(swap_id, state4.signed_cancel_transaction()?)
+                        .await?;
+
+                    tx_cancel_id
+                }
+            };
+
+            summary.tx_cancel_id = Some(tx_cancel_id);
+            summary.tx_cancel_fee = Some(state4.tx_cancel_fee);
 
             BobState::BtcCancelled(state4)
         }
@@ -292,7 +375,13 @@ async fn next_state(
                     );
                 }
                 ExpiredTimelocks::Cancel { .. } => {
-                    state.publish_refund_btc(bitcoin_wallet).await?;
+                    let tx_refund_id = state.publish_refund_btc(bitcoin_wallet).await?;
+                    db.insert_broadcast_transaction(swap_id, state.signed_refund_transaction()?)
+                        .await?;
+
+                    summary.tx_refund_id = Some(tx_refund_id);
+                    summary.tx_refund_fee = Some(state.tx_refund_fee);
+
                     BobState::BtcRefunded(state)
                 }
                 ExpiredTimelocks::Punish => {
@@ -309,3 +398,20 @@ async fn next_state(
         BobState::XmrRedeemed { tx_lock_id } => BobState::XmrRedeemed { tx_lock_id },
     })
 }
+
+/// Warns whenever the connection to Alice is lost while we wait for her
+/// before the cancel timelock expires. Never completes.
+async fn warn_when_alice_unreachable(mut heartbeat: watch::Receiver<Heartbeat>) {
+    let mut previous_health = heartbeat.borrow_and_update().health;
+
+    while heartbeat.changed().await.is_ok() {
+        let health = heartbeat.borrow_and_update().health;
+        if health == ConnectionHealth::Unreachable && previous_health != health {
+            tracing::warn!("Alice is unreachable, trying to reconnect. If she stays unreachable the swap is cancelled once the cancel timelock expires");
+        }
+        previous_health = health;
+    }
+
+    // The event loop stopped and reports why
+    futures::future::pending().await
+}
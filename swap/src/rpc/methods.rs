@@ -49,7 +49,14 @@ pub fn register_modules(context: Arc<Context>) -> Result<RpcModule<Arc<Context>>
     })?;
 
     module.register_async_method("get_history", |params, context| async move {
-        execute_request(params, Method::History, &context).await
+        execute_request(
+            params,
+            Method::History {
+                only_unfinished: false,
+            },
+            &context,
+        )
+        .await
     })?;
 
     module.register_async_method("get_raw_states", |params, context| async move {
@@ -163,13 +170,25 @@ pub fn register_modules(context: Arc<Context>) -> Result<RpcModule<Arc<Context>>
             })?)
             .map_err(|err| jsonrpsee_core::Error::Custom(err.to_string()))?;
 
+        let btc_amount = if let Some(amount_str) = params.get("btc_amount") {
+            Some(
+                ::bitcoin::Amount::from_str_in(amount_str, ::bitcoin::Denomination::Bitcoin)
+                    .map_err(|_| {
+                        jsonrpsee_core::Error::Custom("Unable to parse btc_amount".to_string())
+                    })?,
+            )
+        } else {
+            None
+        };
+
         execute_request(
             params_raw,
             Method::BuyXmr {
                 bitcoin_change_address,
                 monero_receive_address,
-                seller,
+                seller_addresses: vec![seller],
                 swap_id: Uuid::new_v4(),
+                btc_amount,
             },
             &context,
         )
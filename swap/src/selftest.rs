@@ -0,0 +1,740 @@
+//! Runs a complete swap against a local regtest stack to check that the CLI
+//! works end to end, without risking any funds.
+//!
+//! bitcoind, electrs, monerod and the monero wallet RPCs run in docker, Alice
+//! runs in-process. The integration tests in `swap/tests` are built on the
+//! same stack.
+
+pub mod bitcoind;
+pub mod electrs;
+
+use crate::api::request::{buy_xmr, BuyXmrArgs};
+use crate::asb::{FixedRate, RateConfig};
+use crate::bitcoin::CancelTimelock;
+use crate::database::SqliteDatabase;
+use crate::env::{Config, GetConfig, Regtest};
+use crate::fs::ensure_directory_exists;
+use crate::network::rendezvous::XmrBtcNamespace;
+use crate::network::swarm;
+use crate::protocol::bob::BobState;
+use crate::protocol::{alice, Database, State};
+use crate::seed::Seed;
+use crate::{api, asb, bitcoin, cli, monero};
+use anyhow::{bail, Context, Result};
+use bdk::electrum_client::{Client as ElectrumClient, ElectrumApi};
+use bitcoin_harness::{BitcoindRpcApi, Client};
+use futures::Future;
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::Multiaddr;
+use libp2p::PeerId;
+use monero_harness::{image, Monero};
+use std::fmt;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use testcontainers::clients::Cli;
+use testcontainers::{Container, RunnableImage};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+use url::Url;
+use uuid::Uuid;
+
+pub const MONERO_WALLET_NAME_BOB: &str = "bob";
+pub const MONERO_WALLET_NAME_ALICE: &str = "alice";
+pub const BITCOIN_TEST_WALLET_NAME: &str = "testwallet";
+
+/// Upper bound for the swap itself, the containers are ready before.
+const SWAP_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The stages of a swap from Bob's point of view, in the order he goes
+/// through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+pub enum Stage {
+    #[strum(serialize = "wallet init")]
+    WalletInit,
+    #[strum(serialize = "lock")]
+    Lock,
+    #[strum(serialize = "watch")]
+    Watch,
+    #[strum(serialize = "redeem")]
+    Redeem,
+    #[strum(serialize = "sweep")]
+    Sweep,
+}
+
+impl Stage {
+    const ALL: [Stage; 5] = [
+        Stage::WalletInit,
+        Stage::Lock,
+        Stage::Watch,
+        Stage::Redeem,
+        Stage::Sweep,
+    ];
+
+    /// The stage Bob completed by reaching the given state, if any.
+    fn completed_by(state: &BobState) -> Option<Stage> {
+        match state {
+            BobState::BtcLocked { .. } => Some(Stage::Lock),
+            BobState::XmrLocked(..) => Some(Stage::Watch),
+            BobState::BtcRedeemed(..) => Some(Stage::Redeem),
+            BobState::XmrRedeemed { .. } => Some(Stage::Sweep),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub stages: Vec<(Stage, Outcome)>,
+}
+
+impl Report {
+    /// Every stage up to `completed` passed, the next one failed with the
+    /// given error and the remaining ones were never reached.
+    fn new(completed: Option<Stage>, error: Option<anyhow::Error>) -> Self {
+        let mut error = Some(match error {
+            Some(error) => format!("{:#}", error),
+            None => "Swap ended before completing this stage".to_string(),
+        });
+
+        let stages = Stage::ALL
+            .iter()
+            .map(|stage| {
+                let outcome = if Some(*stage) <= completed {
+                    Outcome::Passed
+                } else {
+                    match error.take() {
+                        Some(error) => Outcome::Failed(error),
+                        None => Outcome::Skipped,
+                    }
+                };
+
+                (*stage, outcome)
+            })
+            .collect();
+
+        Self { stages }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, Outcome::Passed))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stage, outcome) in &self.stages {
+            match outcome {
+                Outcome::Passed => writeln!(f, "{:<12} PASS", stage)?,
+                Outcome::Failed(error) => writeln!(f, "{:<12} FAIL: {}", stage, error)?,
+                Outcome::Skipped => writeln!(f, "{:<12} SKIPPED", stage)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of log lines printed when the selftest fails.
+const FAILURE_LOG_LINES: usize = 100;
+
+/// Runs the selftest for `swap selftest` and prints the report, followed by
+/// the end of the log if any stage failed. Returns whether all stages passed.
+pub async fn run_and_print(data_dir: Option<PathBuf>, debug: bool, json: bool) -> Result<bool> {
+    let data_dir = data_dir
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("swap-selftest-{}", Uuid::new_v4())));
+    ensure_directory_exists(&data_dir)?;
+    let log_dir = data_dir.join("logs");
+    cli::tracing::init(debug, json, &log_dir)?;
+
+    let report = run(&data_dir).await;
+    println!("{}", report);
+
+    if report.passed() {
+        return Ok(true);
+    }
+
+    let log_file = log_dir.join("swap-all.log");
+    let logs = tokio::fs::read_to_string(&log_file)
+        .await
+        .with_context(|| format!("Failed to read {}", log_file.display()))?;
+    let lines: Vec<_> = logs.lines().collect();
+    for line in &lines[lines.len().saturating_sub(FAILURE_LOG_LINES)..] {
+        eprintln!("{}", line);
+    }
+    eprintln!(
+        "Selftest failed, the full log and the wallets are in {}",
+        data_dir.display()
+    );
+
+    Ok(false)
+}
+
+/// Swaps BTC for XMR between an in-process Alice and Bob on a fresh regtest
+/// stack, keeping both parties' wallets and databases in `data_dir`.
+pub async fn run(data_dir: &Path) -> Report {
+    let cli = Cli::default();
+    let mut completed = None;
+
+    let result = swap(&cli, data_dir, &mut completed).await;
+    if let Err(error) = &result {
+        tracing::error!("Selftest failed: {:#}", error);
+    }
+
+    Report::new(completed, result.err())
+}
+
+async fn swap(cli: &Cli, data_dir: &Path, completed: &mut Option<Stage>) -> Result<()> {
+    // Leaves enough room for slow machines, the harness mines a block per second
+    let env_config = Config {
+        bitcoin_cancel_timelock: CancelTimelock::new(180),
+        ..Regtest::get_config()
+    };
+
+    tracing::info!("Starting regtest containers, this can take a while");
+    let (monero, containers) = init_containers(cli).await?;
+    monero.init_miner().await?;
+
+    let electrs_rpc_port = containers.electrs.get_host_port_ipv4(electrs::RPC_PORT);
+    // electrs only serves requests once it has caught up with bitcoind
+    retry("electrs", || async {
+        let client = ElectrumClient::new(&format!("tcp://localhost:{}", electrs_rpc_port))?;
+        client.ping()
+    })
+    .await?;
+
+    let btc_amount = bitcoin::Amount::from_sat(1_000_000);
+    let xmr_amount = FixedRate::default().value().sell_quote(btc_amount)?;
+
+    let alice_seed = Seed::random()?;
+    let (alice_bitcoin_wallet, alice_monero_wallet) = init_wallets(
+        MONERO_WALLET_NAME_ALICE,
+        containers.bitcoind_url.clone(),
+        &monero,
+        StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10)),
+        &data_dir.join("alice").join("bitcoin"),
+        electrs_rpc_port,
+        &alice_seed,
+        env_config,
+    )
+    .await?;
+
+    let bob_seed = Seed::random()?;
+    let (bob_bitcoin_wallet, bob_monero_wallet) = init_wallets(
+        MONERO_WALLET_NAME_BOB,
+        containers.bitcoind_url.clone(),
+        &monero,
+        StartingBalances::new(btc_amount * 2, monero::Amount::ZERO, None),
+        &data_dir.join("bob").join("bitcoin"),
+        electrs_rpc_port,
+        &bob_seed,
+        env_config,
+    )
+    .await?;
+
+    monero.start_miner().await?;
+    *completed = Some(Stage::WalletInit);
+
+    let alice_address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", free_port()?).parse()?;
+    let (alice_handle, mut alice_swaps) = start_alice(
+        &alice_seed,
+        data_dir.join("alice").join("sqlite"),
+        alice_address.clone(),
+        env_config,
+        alice_bitcoin_wallet,
+        alice_monero_wallet,
+        btc_amount,
+    )
+    .await?;
+    let alice = tokio::spawn(async move {
+        let swap = alice_swaps
+            .recv()
+            .await
+            .context("Alice's event loop stopped")?;
+        alice::run(swap, FixedRate::default()).await
+    });
+
+    let context = Arc::new(
+        api::Context::for_harness(
+            bob_seed,
+            env_config,
+            data_dir.join("bob").join("sqlite"),
+            bob_bitcoin_wallet.clone(),
+            bob_monero_wallet.clone(),
+        )
+        .await,
+    );
+    let swap_id = Uuid::new_v4();
+    let seller = alice_address.with(Protocol::P2p(alice_handle.peer_id().into()));
+    let bob = buy_xmr(
+        BuyXmrArgs {
+            seller_addresses: vec![seller],
+            bitcoin_change_address: bob_bitcoin_wallet.new_address().await?,
+            monero_receive_address: bob_monero_wallet.get_main_address(),
+            swap_id,
+            btc_amount: None,
+        },
+        context.clone(),
+    )
+    .await?;
+
+    let result = tokio::time::timeout(SWAP_TIMEOUT, bob.finished()).await;
+    alice.abort();
+    alice_handle.abort();
+
+    *completed = context
+        .db
+        .get_states(swap_id)
+        .await?
+        .iter()
+        .filter_map(|state| match state {
+            State::Bob(state) => Stage::completed_by(state),
+            State::Alice(_) => None,
+        })
+        .max()
+        .max(*completed);
+
+    match result.context("Swap did not finish in time")?? {
+        BobState::XmrRedeemed { .. } => Ok(()),
+        state => bail!("Swap ended in state {} instead of redeeming the XMR", state),
+    }
+}
+
+/// Keeps the containers alive until dropped.
+pub struct Containers<'a> {
+    pub bitcoind_url: Url,
+    _bitcoind: Container<'a, bitcoind::Bitcoind>,
+    _monerod_container: Container<'a, image::Monerod>,
+    _monero_wallet_rpc_containers: Vec<Container<'a, image::MoneroWalletRpc>>,
+    pub electrs: Container<'a, electrs::Electrs>,
+}
+
+/// Starts bitcoind, electrs, monerod and a wallet RPC for Alice and Bob, on a
+/// docker network of their own.
+pub async fn init_containers(cli: &Cli) -> Result<(Monero, Containers<'_>)> {
+    let prefix = random_prefix();
+    let bitcoind_name = format!("{}_{}", prefix, "bitcoind");
+    let (_bitcoind, bitcoind_url, mapped_port) =
+        init_bitcoind_container(cli, prefix.clone(), bitcoind_name.clone(), prefix.clone())
+            .await
+            .context("Could not init bitcoind")?;
+    let electrs = init_electrs_container(cli, prefix.clone(), bitcoind_name, prefix, mapped_port)
+        .await
+        .context("Could not init electrs")?;
+    let (monero, _monerod_container, _monero_wallet_rpc_containers) =
+        Monero::new(cli, vec![MONERO_WALLET_NAME_ALICE, MONERO_WALLET_NAME_BOB]).await?;
+
+    Ok((
+        monero,
+        Containers {
+            bitcoind_url,
+            _bitcoind,
+            _monerod_container,
+            _monero_wallet_rpc_containers,
+            electrs,
+        },
+    ))
+}
+
+async fn init_bitcoind_container(
+    cli: &Cli,
+    volume: String,
+    name: String,
+    network: String,
+) -> Result<(Container<'_, bitcoind::Bitcoind>, Url, u16)> {
+    let image = bitcoind::Bitcoind::default().with_volume(volume);
+    let image = RunnableImage::from(image)
+        .with_container_name(name)
+        .with_network(network);
+
+    let docker = cli.run(image);
+    let port = docker.get_host_port_ipv4(bitcoind::RPC_PORT);
+
+    let bitcoind_url = Url::parse(&format!(
+        "http://{}:{}@localhost:{}",
+        bitcoind::RPC_USER,
+        bitcoind::RPC_PASSWORD,
+        port
+    ))?;
+
+    init_bitcoind(bitcoind_url.clone(), 5).await?;
+
+    Ok((docker, bitcoind_url, bitcoind::RPC_PORT))
+}
+
+pub async fn init_electrs_container(
+    cli: &Cli,
+    volume: String,
+    bitcoind_container_name: String,
+    network: String,
+    port: u16,
+) -> Result<Container<'_, electrs::Electrs>> {
+    let bitcoind_rpc_addr = format!("{}:{}", bitcoind_container_name, port);
+    let image = electrs::Electrs::default()
+        .with_volume(volume)
+        .with_daemon_rpc_addr(bitcoind_rpc_addr)
+        .with_tag("latest");
+    let image = RunnableImage::from(image.self_and_args())
+        .with_network(network.clone())
+        .with_container_name(format!("{}_electrs", network));
+
+    let docker = cli.run(image);
+
+    Ok(docker)
+}
+
+pub struct AliceApplicationHandle {
+    handle: JoinHandle<()>,
+    peer_id: PeerId,
+}
+
+impl AliceApplicationHandle {
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub fn abort(&self) {
+        self.handle.abort()
+    }
+}
+
+/// Runs Alice's event loop in the background, selling up to `max_buy` per
+/// swap at a [`FixedRate`].
+pub async fn start_alice(
+    seed: &Seed,
+    db_path: PathBuf,
+    listen_address: Multiaddr,
+    env_config: Config,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    max_buy: bitcoin::Amount,
+) -> Result<(AliceApplicationHandle, Receiver<alice::Swap>)> {
+    if let Some(parent_dir) = db_path.parent() {
+        ensure_directory_exists(parent_dir)?;
+    }
+    if !&db_path.exists() {
+        tokio::fs::File::create(&db_path).await?;
+    }
+    let db = Arc::new(SqliteDatabase::open(db_path.as_path()).await?);
+
+    let (_, rate_config) = watch::channel(RateConfig {
+        ask_spread: Default::default(),
+        min_buy: bitcoin::Amount::from_sat(u64::MIN),
+        max_buy,
+    });
+    let latest_rate = FixedRate::default();
+    let resume_only = false;
+
+    let mut swarm = swarm::asb(
+        seed.derive_libp2p_identity(XmrBtcNamespace::Testnet),
+        rate_config.clone(),
+        latest_rate,
+        resume_only,
+        env_config,
+        XmrBtcNamespace::Testnet,
+        &[],
+    )?;
+    swarm.listen_on(listen_address)?;
+
+    let (event_loop, swap_handle) = asb::EventLoop::new(
+        swarm,
+        env_config,
+        bitcoin_wallet,
+        monero_wallet,
+        db,
+        FixedRate::default(),
+        rate_config,
+        None,
+    )?;
+
+    let peer_id = event_loop.peer_id();
+    let handle = tokio::spawn(event_loop.run());
+
+    Ok((AliceApplicationHandle { handle, peer_id }, swap_handle))
+}
+
+/// Opens the named wallet pair and funds it with the given balances.
+#[allow(clippy::too_many_arguments)]
+pub async fn init_wallets(
+    name: &str,
+    bitcoind_url: Url,
+    monero: &Monero,
+    starting_balances: StartingBalances,
+    datadir: &Path,
+    electrum_rpc_port: u16,
+    seed: &Seed,
+    env_config: Config,
+) -> Result<(Arc<bitcoin::Wallet>, Arc<monero::Wallet>)> {
+    monero
+        .init_wallet(
+            name,
+            starting_balances
+                .xmr_outputs
+                .into_iter()
+                .map(|amount| amount.as_piconero())
+                .collect(),
+        )
+        .await?;
+
+    let xmr_wallet = monero::Wallet::connect(
+        monero.wallet(name)?.client().clone(),
+        name.to_string(),
+        env_config,
+    )
+    .await?;
+
+    let electrum_rpc_url = Url::parse(&format!("tcp://@localhost:{}", electrum_rpc_port))?;
+
+    let btc_wallet = bitcoin::Wallet::new(
+        electrum_rpc_url,
+        datadir,
+        seed.derive_extended_private_key(env_config.bitcoin_network)
+            .context("Could not create extended private key from seed")?,
+        env_config,
+        1,
+        None,
+    )
+    .await
+    .context("Could not init btc wallet")?;
+
+    if starting_balances.btc != bitcoin::Amount::ZERO {
+        mint(
+            bitcoind_url,
+            btc_wallet.new_address().await?,
+            starting_balances.btc,
+        )
+        .await
+        .context("Could not mint btc starting balance")?;
+
+        let mut interval = interval(Duration::from_secs(1u64));
+        let mut retries = 0u8;
+        let max_retries = 30u8;
+        loop {
+            retries += 1;
+            btc_wallet.sync().await?;
+
+            let btc_balance = btc_wallet.balance().await?;
+
+            if btc_balance == starting_balances.btc {
+                break;
+            } else if retries == max_retries {
+                bail!("Bitcoin wallet initialization failed, reached max retries upon balance sync")
+            }
+
+            interval.tick().await;
+        }
+    }
+
+    Ok((Arc::new(btc_wallet), Arc::new(xmr_wallet)))
+}
+
+#[derive(Debug, Clone)]
+pub struct StartingBalances {
+    pub xmr: monero::Amount,
+    pub xmr_outputs: Vec<monero::Amount>,
+    pub btc: bitcoin::Amount,
+}
+
+impl StartingBalances {
+    /// If monero_outputs is specified the monero balance will be:
+    /// monero_outputs * new_xmr = self_xmr
+    pub fn new(btc: bitcoin::Amount, xmr: monero::Amount, monero_outputs: Option<u64>) -> Self {
+        match monero_outputs {
+            None => {
+                if xmr == monero::Amount::ZERO {
+                    return Self {
+                        xmr,
+                        xmr_outputs: vec![],
+                        btc,
+                    };
+                }
+
+                Self {
+                    xmr,
+                    xmr_outputs: vec![xmr],
+                    btc,
+                }
+            }
+            Some(outputs) => {
+                let mut xmr_outputs = Vec::new();
+                let mut sum_xmr = monero::Amount::ZERO;
+
+                for _ in 0..outputs {
+                    xmr_outputs.push(xmr);
+                    sum_xmr = sum_xmr + xmr;
+                }
+
+                Self {
+                    xmr: sum_xmr,
+                    xmr_outputs,
+                    btc,
+                }
+            }
+        }
+    }
+}
+
+async fn mine(bitcoind_client: Client, reward_address: bitcoin::Address) -> Result<()> {
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        bitcoind_client
+            .generatetoaddress(1, reward_address.clone())
+            .await?;
+    }
+}
+
+async fn init_bitcoind(node_url: Url, spendable_quantity: u32) -> Result<Client> {
+    let bitcoind_client = Client::new(node_url.clone());
+
+    // bitcoind answers RPC calls with a warmup error until it is fully started
+    retry("bitcoind", || {
+        bitcoind_client.createwallet(BITCOIN_TEST_WALLET_NAME, None, None, None, None)
+    })
+    .await?;
+
+    let reward_address = bitcoind_client
+        .with_wallet(BITCOIN_TEST_WALLET_NAME)?
+        .getnewaddress(None, None)
+        .await?;
+
+    bitcoind_client
+        .generatetoaddress(101 + spendable_quantity, reward_address.clone())
+        .await?;
+    tokio::spawn(mine(bitcoind_client.clone(), reward_address));
+    Ok(bitcoind_client)
+}
+
+/// Send Bitcoin to the specified address, limited to the spendable bitcoin
+/// quantity.
+pub async fn mint(node_url: Url, address: bitcoin::Address, amount: bitcoin::Amount) -> Result<()> {
+    let bitcoind_client = Client::new(node_url.clone());
+
+    bitcoind_client
+        .send_to_address(BITCOIN_TEST_WALLET_NAME, address.clone(), amount)
+        .await?;
+
+    // Confirm the transaction
+    let reward_address = bitcoind_client
+        .with_wallet(BITCOIN_TEST_WALLET_NAME)?
+        .getnewaddress(None, None)
+        .await?;
+    bitcoind_client.generatetoaddress(1, reward_address).await?;
+
+    Ok(())
+}
+
+fn random_prefix() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::iter;
+    const LEN: usize = 8;
+    let mut rng = thread_rng();
+    let chars: String = iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(LEN)
+        .collect();
+    chars
+}
+
+/// Asks the OS for a free port on localhost.
+///
+/// The OS hands out ephemeral ports in a random or cycling order, so unlike
+/// scanning a fixed range this doesn't race with tests running concurrently.
+pub fn free_port() -> Result<u16> {
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .context("Failed to find a free port")?
+        .port();
+
+    Ok(port)
+}
+
+/// Retries the given startup step until it succeeds or the attempts are
+/// exhausted, for services that are running but not yet ready to serve
+/// requests.
+pub async fn retry<T, E, F, Fut>(service: &str, mut step: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<anyhow::Error>,
+{
+    const MAX_ATTEMPTS: u32 = 60;
+    const DELAY: Duration = Duration::from_millis(500);
+
+    let mut attempt = 1;
+    loop {
+        let e: anyhow::Error = match step().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e.into(),
+        };
+
+        if attempt == MAX_ATTEMPTS {
+            return Err(e).with_context(|| {
+                format!("{} was not ready after {} attempts", service, MAX_ATTEMPTS)
+            });
+        }
+
+        tracing::debug!(%attempt, "{} is not ready yet: {:#}", service, e);
+        attempt += 1;
+        tokio::time::sleep(DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_after_the_failed_one_are_skipped() {
+        let report = Report::new(Some(Stage::Lock), Some(anyhow::anyhow!("Alice went away")));
+
+        let outcomes: Vec<_> = report
+            .stages
+            .iter()
+            .map(|(stage, outcome)| (*stage, format!("{:?}", outcome)))
+            .collect();
+
+        assert_eq!(
+            outcomes,
+            vec![
+                (Stage::WalletInit, "Passed".to_string()),
+                (Stage::Lock, "Passed".to_string()),
+                (Stage::Watch, "Failed(\"Alice went away\")".to_string()),
+                (Stage::Redeem, "Skipped".to_string()),
+                (Stage::Sweep, "Skipped".to_string()),
+            ]
+        );
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn failing_container_startup_fails_wallet_init() {
+        let report = Report::new(None, Some(anyhow::anyhow!("docker is not running")));
+
+        assert!(matches!(
+            report.stages[0],
+            (Stage::WalletInit, Outcome::Failed(_))
+        ));
+        assert!(report.stages[1..]
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, Outcome::Skipped)));
+    }
+
+    #[test]
+    fn completing_all_stages_passes() {
+        assert!(Report::new(Some(Stage::Sweep), None).passed());
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use crate::harness::bitcoind;
+use crate::selftest::bitcoind;
 use bitcoin::Network;
 use testcontainers::{core::WaitFor, Image, ImageArgs};
 
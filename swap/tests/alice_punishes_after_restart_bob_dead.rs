@@ -4,12 +4,14 @@ use harness::alice_run_until::is_xmr_lock_transaction_sent;
 use harness::bob_run_until::is_btc_locked;
 use harness::FastPunishConfig;
 use swap::asb::FixedRate;
+use swap::cli;
 use swap::protocol::alice::AliceState;
 use swap::protocol::bob::BobState;
 use swap::protocol::{alice, bob};
 
 /// Bob locks Btc and Alice locks Xmr. Bob does not act; he fails to send Alice
-/// the encsig and fail to refund or redeem. Alice cancels and punishes.
+/// the encsig and fail to refund or redeem. Alice cancels and punishes, after
+/// which Bob can no longer refund.
 #[tokio::test]
 async fn alice_punishes_after_restart_if_bob_dead() {
     harness::setup_test(FastPunishConfig, |mut ctx| async move {
@@ -52,11 +54,21 @@ async fn alice_punishes_after_restart_if_bob_dead() {
 
         // Restart Bob after Alice punished to ensure Bob transitions to
         // punished and does not run indefinitely
-        let (bob_swap, _) = ctx
+        let (bob_swap, bob_join_handle) = ctx
             .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
             .await;
         assert!(matches!(bob_swap.state, BobState::BtcLocked { .. }));
 
+        bob_join_handle.abort();
+        let refund = cli::refund(bob_swap.id, bob_swap.bitcoin_wallet, bob_swap.db).await;
+        assert!(
+            refund.is_err(),
+            "Bob must not be able to refund after Alice punished"
+        );
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+            .await;
         let bob_state = bob::run(bob_swap).await?;
 
         ctx.assert_bob_punished(bob_state).await;
@@ -32,6 +32,11 @@ async fn alice_refunds_after_restart_if_bob_already_refunded() {
 
         ctx.restart_alice().await;
         let alice_swap = ctx.alice_next_swap().await;
+        assert!(matches!(
+            alice_swap.state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
         let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
 
         let alice_state = alice_swap.await??;
@@ -0,0 +1,49 @@
+//! Drives a swap through the library API that the CLI is built on.
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use std::sync::Arc;
+use swap::api::{self, BuyXmrArgs};
+use swap::asb::FixedRate;
+use swap::protocol::alice;
+use swap::protocol::bob::BobState;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn api_buy_xmr_and_history() {
+    harness::setup_test(SlowCancelConfig, |ctx| async move {
+        // Alice quotes all her Monero, the swap amount would depend on her balance
+        let mut maker = ctx.start_other_alice().await;
+        let context = Arc::new(ctx.get_bob_context().await);
+        let (bitcoin_change_address, monero_receive_address) =
+            ctx.bob_params.get_change_receive_addresses().await;
+        let swap_id = Uuid::new_v4();
+
+        let bob_swap = api::buy_xmr(
+            BuyXmrArgs {
+                seller_addresses: vec![maker.address()],
+                bitcoin_change_address,
+                monero_receive_address,
+                swap_id,
+                btc_amount: None,
+            },
+            context.clone(),
+        )
+        .await?;
+        assert_eq!(bob_swap.swap_id, swap_id);
+        assert!(bob_swap.quote.is_some());
+
+        let alice_swap = maker.next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let bob_state = bob_swap.finished().await?;
+        ctx.assert_alice_redeemed(alice_swap.await??).await;
+        ctx.assert_bob_redeemed(bob_state.clone()).await;
+
+        let history = api::history(&context).await?;
+        assert_eq!(history, vec![(swap_id, bob_state)]);
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,40 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::bitcoin::PunishTimelock;
+use swap::env::{Config, GetConfig};
+use swap::protocol::bob;
+use swap::protocol::bob::BobState;
+
+/// Alice proposes a punish timelock of a single block, which would leave Bob
+/// no time to refund after cancelling. Bob aborts the swap before locking his
+/// Btc.
+#[tokio::test]
+async fn bob_aborts_when_alice_proposes_short_timelocks() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        ctx.restart_alice_with_config(Config {
+            bitcoin_punish_timelock: PunishTimelock::new(1),
+            ..SlowCancelConfig::get_config()
+        })
+        .await;
+
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let bob_db = bob_swap.db.clone();
+        let bob_bitcoin_wallet = bob_swap.bitcoin_wallet.clone();
+        let bob_balance_before = bob_bitcoin_wallet.balance().await?;
+
+        let bob_state = bob::run(bob_swap).await?;
+        assert!(matches!(bob_state, BobState::SafelyAborted));
+        assert_eq!(
+            bob_db.get_state(bob_swap_id).await?,
+            BobState::SafelyAborted.into()
+        );
+
+        bob_bitcoin_wallet.sync().await?;
+        assert_eq!(bob_bitcoin_wallet.balance().await?, bob_balance_before);
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,16 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+
+/// Bob locks Btc but Alice disappears before locking Xmr. Once the cancel
+/// timelock expires Bob cancels and refunds his Btc.
+#[tokio::test]
+async fn bob_refunds_when_alice_does_not_lock_xmr() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let bob_state = ctx.run_swap_alice_never_locks_xmr().await?;
+        ctx.assert_bob_refunded(bob_state).await;
+
+        Ok(())
+    })
+    .await;
+}
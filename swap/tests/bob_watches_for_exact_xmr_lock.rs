@@ -0,0 +1,46 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::monero;
+
+/// Bob only counts Alice's XMR as locked once the exact amount has been
+/// sent and has enough confirmations to be spent.
+#[tokio::test]
+async fn bob_accepts_exact_xmr_lock() {
+    harness::setup_test(SlowCancelConfig, |ctx| async move {
+        let amount = monero::Amount::from_piconero(1_000_000_000);
+        let watch_request = ctx.send_xmr_to_fresh_address(amount).await?;
+        ctx.mine_monero_blocks(u32::try_from(monero::SPENDABLE_AGE)?)
+            .await?;
+
+        ctx.bob_monero_wallet()
+            .watch_for_transfer(watch_request)
+            .await?;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn bob_rejects_underpaid_xmr_lock() {
+    harness::setup_test(SlowCancelConfig, |ctx| async move {
+        let amount = monero::Amount::from_piconero(1_000_000_000);
+        let mut watch_request = ctx.send_xmr_to_fresh_address(amount).await?;
+        watch_request.expected = monero::Amount::from_piconero(1_000_000_001);
+        ctx.mine_monero_blocks(u32::try_from(monero::SPENDABLE_AGE)?)
+            .await?;
+
+        let error = ctx
+            .bob_monero_wallet()
+            .watch_for_transfer(watch_request)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.expected, monero::Amount::from_piconero(1_000_000_001));
+        assert_eq!(error.actual, amount);
+
+        Ok(())
+    })
+    .await;
+}
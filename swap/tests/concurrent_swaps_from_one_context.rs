@@ -0,0 +1,72 @@
+//! Runs two swaps with different makers at the same time from one Bob
+//! context, sharing its wallets and database.
+pub mod harness;
+
+use anyhow::Result;
+use harness::{SlowCancelConfig, TestContext};
+use libp2p::Multiaddr;
+use std::sync::Arc;
+use swap::api::{self, BuyXmrArgs, SwapHandle};
+use swap::asb::FixedRate;
+use swap::protocol::alice;
+use swap::protocol::alice::AliceState;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn concurrent_swaps_from_one_context() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        // Lock transactions of concurrent swaps can't share inputs
+        ctx.fund_bob_bitcoin().await;
+
+        let mut first_maker = ctx.start_other_alice().await;
+        let mut second_maker = ctx.start_other_alice().await;
+        let context = Arc::new(ctx.get_bob_context().await);
+
+        let first_swap = buy_xmr(&ctx, &context, first_maker.address()).await?;
+        let second_swap = buy_xmr(&ctx, &context, second_maker.address()).await?;
+        let (first_swap_id, second_swap_id) = (first_swap.swap_id, second_swap.swap_id);
+
+        let first_alice = first_maker.next_swap().await;
+        let first_alice = tokio::spawn(alice::run(first_alice, FixedRate::default()));
+        let second_alice = second_maker.next_swap().await;
+        let second_alice = tokio::spawn(alice::run(second_alice, FixedRate::default()));
+
+        let (first_state, second_state) =
+            tokio::try_join!(first_swap.finished(), second_swap.finished())?;
+        assert!(matches!(first_alice.await??, AliceState::BtcRedeemed));
+        assert!(matches!(second_alice.await??, AliceState::BtcRedeemed));
+
+        ctx.assert_bob_redeemed_all(vec![first_state.clone(), second_state.clone()])
+            .await;
+
+        let mut history = api::history(&context).await?;
+        history.sort_by_key(|(swap_id, _)| *swap_id);
+        let mut expected = vec![(first_swap_id, first_state), (second_swap_id, second_state)];
+        expected.sort_by_key(|(swap_id, _)| *swap_id);
+        assert_eq!(history, expected);
+
+        Ok(())
+    })
+    .await;
+}
+
+async fn buy_xmr(
+    ctx: &TestContext,
+    context: &Arc<api::Context>,
+    seller: Multiaddr,
+) -> Result<SwapHandle> {
+    let (bitcoin_change_address, monero_receive_address) =
+        ctx.bob_params.get_change_receive_addresses().await;
+
+    api::buy_xmr(
+        BuyXmrArgs {
+            seller_addresses: vec![seller],
+            bitcoin_change_address,
+            monero_receive_address,
+            swap_id: Uuid::new_v4(),
+            btc_amount: None,
+        },
+        context.clone(),
+    )
+    .await
+}
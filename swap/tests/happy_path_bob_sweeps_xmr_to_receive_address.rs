@@ -0,0 +1,35 @@
+pub mod harness;
+
+use anyhow::Context;
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::{alice, bob};
+use tokio::join;
+
+#[tokio::test]
+async fn given_bob_redeems_then_exactly_the_locked_xmr_minus_fee_reaches_his_wallet() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let bob_db = bob_swap.db.clone();
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        let summary = bob_db
+            .get_swap_summary(bob_swap_id)
+            .await?
+            .context("Bob did not record a swap summary")?;
+        ctx.assert_bob_received_swept_xmr(&summary.xmr_redeem_tx_hashes)
+            .await;
+
+        Ok(())
+    })
+    .await;
+}
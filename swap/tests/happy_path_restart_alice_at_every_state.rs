@@ -0,0 +1,63 @@
+pub mod harness;
+
+use harness::alice_run_until::{
+    is_btc_redeem_transaction_published, is_encsig_learned, is_xmr_lock_transaction_sent,
+    is_xmr_locked,
+};
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::{alice, bob};
+
+/// Runs the happy path but stops Alice right after she persisted the given
+/// state, restarts her from the database and asserts that both parties redeem.
+macro_rules! restart_alice_after {
+    ($name:ident, $run_until:ident, $state:pat) => {
+        #[tokio::test]
+        async fn $name() {
+            harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+                let (bob_swap, _) = ctx.bob_swap().await;
+                let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+                let alice_swap = ctx.alice_next_swap().await;
+                let alice_state =
+                    alice::run_until(alice_swap, $run_until, FixedRate::default()).await?;
+                assert!(matches!(alice_state, $state));
+
+                ctx.restart_alice().await;
+                let alice_swap = ctx.alice_next_swap().await;
+                assert!(matches!(alice_swap.state, $state));
+
+                let alice_state = alice::run(alice_swap, FixedRate::default()).await?;
+                ctx.assert_alice_redeemed(alice_state).await;
+
+                let bob_state = bob_swap.await??;
+                ctx.assert_bob_redeemed(bob_state).await;
+
+                Ok(())
+            })
+            .await;
+        }
+    };
+}
+
+restart_alice_after!(
+    given_alice_restarts_after_xmr_lock_transaction_sent_resume_swap,
+    is_xmr_lock_transaction_sent,
+    AliceState::XmrLockTransactionSent { .. }
+);
+restart_alice_after!(
+    given_alice_restarts_after_xmr_locked_resume_swap,
+    is_xmr_locked,
+    AliceState::XmrLocked { .. }
+);
+restart_alice_after!(
+    given_alice_restarts_after_encsig_learned_resume_swap,
+    is_encsig_learned,
+    AliceState::EncSigLearned { .. }
+);
+restart_alice_after!(
+    given_alice_restarts_after_redeem_published_resume_swap,
+    is_btc_redeem_transaction_published,
+    AliceState::BtcRedeemTransactionPublished { .. }
+);
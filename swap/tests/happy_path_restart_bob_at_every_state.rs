@@ -0,0 +1,76 @@
+pub mod harness;
+
+use harness::bob_run_until::{
+    is_btc_locked, is_btc_redeemed, is_encsig_sent, is_lock_proof_received,
+    is_swap_setup_completed, is_xmr_locked,
+};
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+
+/// Runs the happy path but stops Bob right after he persisted the given state,
+/// resumes him from the database and asserts that both parties redeem.
+macro_rules! restart_bob_after {
+    ($name:ident, $run_until:ident, $state:pat) => {
+        #[tokio::test]
+        async fn $name() {
+            harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+                let (bob_swap, bob_join_handle) = ctx.bob_swap().await;
+                let bob_swap_id = bob_swap.id;
+                let bob_swap = tokio::spawn(bob::run_until(bob_swap, $run_until));
+
+                let alice_swap = ctx.alice_next_swap().await;
+                let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+                let bob_state = bob_swap.await??;
+                assert!(matches!(bob_state, $state));
+
+                let (bob_swap, _) = ctx
+                    .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+                    .await;
+                assert!(matches!(bob_swap.state, $state));
+
+                let bob_state = bob::run(bob_swap).await?;
+                ctx.assert_bob_redeemed(bob_state).await;
+
+                let alice_state = alice_swap.await??;
+                ctx.assert_alice_redeemed(alice_state).await;
+
+                Ok(())
+            })
+            .await;
+        }
+    };
+}
+
+restart_bob_after!(
+    given_bob_restarts_after_swap_setup_completed_resume_swap,
+    is_swap_setup_completed,
+    BobState::SwapSetupCompleted(..)
+);
+restart_bob_after!(
+    given_bob_restarts_after_btc_locked_resume_swap,
+    is_btc_locked,
+    BobState::BtcLocked { .. }
+);
+restart_bob_after!(
+    given_bob_restarts_after_lock_proof_received_resume_swap,
+    is_lock_proof_received,
+    BobState::XmrLockProofReceived { .. }
+);
+restart_bob_after!(
+    given_bob_restarts_after_xmr_locked_resume_swap,
+    is_xmr_locked,
+    BobState::XmrLocked(..)
+);
+restart_bob_after!(
+    given_bob_restarts_after_encsig_sent_resume_swap,
+    is_encsig_sent,
+    BobState::EncSigSent(..)
+);
+restart_bob_after!(
+    given_bob_restarts_after_btc_redeemed_resume_swap,
+    is_btc_redeemed,
+    BobState::BtcRedeemed(..)
+);
@@ -0,0 +1,341 @@
+use anyhow::{bail, Result};
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::Multiaddr;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// A TCP proxy between Bob and Alice that injects network faults.
+///
+/// Bob dials the proxy instead of Alice and every connection is forwarded to
+/// Alice's listen address. The traffic is encrypted and multiplexed by libp2p,
+/// so faults are scheduled in terms of connections and forwarded chunks rather
+/// than individual protocol messages. Tests usually drive Alice and Bob into
+/// the state right before a message is sent and inject the fault from there.
+///
+/// Every intercepted event is logged and recorded, see [`ChaosProxy::events`].
+pub struct ChaosProxy {
+    address: Multiaddr,
+    shared: Arc<Shared>,
+    accept_task: JoinHandle<()>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    BobToAlice,
+    AliceToBob,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyEvent {
+    Accepted {
+        connection: usize,
+    },
+    Refused {
+        connection: usize,
+    },
+    Forwarded {
+        connection: usize,
+        direction: Direction,
+        bytes: usize,
+    },
+    Dropped {
+        connection: usize,
+        direction: Direction,
+        bytes: usize,
+    },
+    Closed {
+        connection: usize,
+    },
+    Severed,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    sever: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    latency: Duration,
+    refuse_connections: bool,
+    /// Connections with a lower number drop all traffic.
+    stalled_before: usize,
+    accepted_connections: usize,
+    sever_after_chunks: Option<usize>,
+    events: Vec<ProxyEvent>,
+}
+
+impl ChaosProxy {
+    pub async fn start(target: &Multiaddr) -> Result<Self> {
+        let target = socket_addr(target)?;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let port = listener.local_addr()?.port();
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            sever: Notify::new(),
+        });
+        let accept_task = tokio::spawn(accept(listener, target, shared.clone()));
+
+        Ok(Self {
+            address: format!("/ip4/127.0.0.1/tcp/{}", port)
+                .parse()
+                .expect("valid multiaddr"),
+            shared,
+            accept_task,
+        })
+    }
+
+    /// The address Bob dials instead of Alice's.
+    pub fn address(&self) -> Multiaddr {
+        self.address.clone()
+    }
+
+    /// Delays every forwarded chunk by the given duration.
+    pub fn set_latency(&self, latency: Duration) {
+        self.shared.state().latency = latency;
+    }
+
+    /// Severs all connections once the given number of further chunks have
+    /// been forwarded, in either direction.
+    pub fn sever_after(&self, chunks: usize) {
+        self.shared.state().sever_after_chunks = Some(chunks);
+    }
+
+    /// Severs all open connections.
+    pub fn sever(&self) {
+        self.shared.sever();
+    }
+
+    /// Silently drops all traffic of the open connections without closing
+    /// them, like a NAT that forgot about them. New connections are not
+    /// affected.
+    pub fn stall(&self) {
+        let mut state = self.shared.state();
+        state.stalled_before = state.accepted_connections;
+    }
+
+    /// Closes new connections right after accepting them, making Alice
+    /// unreachable for Bob.
+    pub fn refuse_connections(&self) {
+        self.shared.state().refuse_connections = true;
+    }
+
+    pub fn accept_connections(&self) {
+        self.shared.state().refuse_connections = false;
+    }
+
+    pub fn events(&self) -> Vec<ProxyEvent> {
+        self.shared.state().events.clone()
+    }
+
+    /// Waits until the proxy refused the given number of connections in total.
+    pub async fn wait_for_refused_connections(&self, count: usize) {
+        self.wait_for_events("refused", count, |event| {
+            matches!(event, ProxyEvent::Refused { .. })
+        })
+        .await
+    }
+
+    /// Waits until the proxy accepted the given number of connections in
+    /// total.
+    pub async fn wait_for_accepted_connections(&self, count: usize) {
+        self.wait_for_events("accepted", count, |event| {
+            matches!(event, ProxyEvent::Accepted { .. })
+        })
+        .await
+    }
+
+    async fn wait_for_events(
+        &self,
+        description: &str,
+        count: usize,
+        matches: impl Fn(&ProxyEvent) -> bool,
+    ) {
+        let matching = || self.events().iter().filter(|event| matches(event)).count();
+
+        tokio::time::timeout(Duration::from_secs(60), async {
+            while matching() < count {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| {
+            panic!(
+                "Proxy {} {} of {} expected connections within 60 seconds",
+                description,
+                matching(),
+                count
+            )
+        });
+    }
+}
+
+impl Drop for ChaosProxy {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        self.shared.sever.notify_waiters();
+    }
+}
+
+impl Shared {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("proxy lock is never poisoned")
+    }
+
+    fn record(&self, event: ProxyEvent) {
+        match &event {
+            ProxyEvent::Forwarded { .. } | ProxyEvent::Dropped { .. } => {
+                tracing::debug!(?event, "Chaos proxy")
+            }
+            _ => tracing::info!(?event, "Chaos proxy"),
+        }
+
+        self.state().events.push(event);
+    }
+
+    fn sever(&self) {
+        self.record(ProxyEvent::Severed);
+        self.sever.notify_waiters();
+    }
+}
+
+async fn accept(listener: TcpListener, target: SocketAddr, shared: Arc<Shared>) {
+    for connection in 0.. {
+        let inbound = match listener.accept().await {
+            Ok((inbound, _)) => inbound,
+            Err(error) => {
+                tracing::warn!(%error, "Chaos proxy failed to accept connection");
+                continue;
+            }
+        };
+
+        if shared.state().refuse_connections {
+            shared.record(ProxyEvent::Refused { connection });
+            continue;
+        }
+
+        shared.state().accepted_connections = connection + 1;
+        shared.record(ProxyEvent::Accepted { connection });
+        tokio::spawn(proxy_connection(
+            connection,
+            inbound,
+            target,
+            shared.clone(),
+        ));
+    }
+}
+
+async fn proxy_connection(
+    connection: usize,
+    inbound: TcpStream,
+    target: SocketAddr,
+    shared: Arc<Shared>,
+) {
+    // Created before forwarding starts so a sever can't slip through.
+    let severed = shared.sever.notified();
+
+    let outbound = match TcpStream::connect(target).await {
+        Ok(outbound) => outbound,
+        Err(error) => {
+            tracing::warn!(%error, "Chaos proxy failed to connect to Alice");
+            shared.record(ProxyEvent::Closed { connection });
+            return;
+        }
+    };
+
+    let (from_bob, to_bob) = inbound.into_split();
+    let (from_alice, to_alice) = outbound.into_split();
+
+    tokio::select! {
+        _ = forward(connection, Direction::BobToAlice, from_bob, to_alice, &shared) => {}
+        _ = forward(connection, Direction::AliceToBob, from_alice, to_bob, &shared) => {}
+        _ = severed => {}
+    }
+
+    shared.record(ProxyEvent::Closed { connection });
+}
+
+async fn forward(
+    connection: usize,
+    direction: Direction,
+    mut from: OwnedReadHalf,
+    mut to: OwnedWriteHalf,
+    shared: &Shared,
+) -> Result<()> {
+    let mut buffer = vec![0u8; 16 * 1024];
+
+    loop {
+        let bytes = from.read(&mut buffer).await?;
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        if connection < shared.state().stalled_before {
+            shared.record(ProxyEvent::Dropped {
+                connection,
+                direction,
+                bytes,
+            });
+            continue;
+        }
+
+        shared.record(ProxyEvent::Forwarded {
+            connection,
+            direction,
+            bytes,
+        });
+
+        let (latency, sever) = {
+            let mut state = shared.state();
+            let sever = match state.sever_after_chunks.as_mut() {
+                Some(0) | None => false,
+                Some(chunks) => {
+                    *chunks -= 1;
+                    *chunks == 0
+                }
+            };
+            if sever {
+                state.sever_after_chunks = None;
+            }
+
+            (state.latency, sever)
+        };
+
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        to.write_all(&buffer[..bytes]).await?;
+
+        if sever {
+            shared.sever();
+        }
+    }
+}
+
+fn socket_addr(address: &Multiaddr) -> Result<SocketAddr> {
+    let mut ip = None;
+    let mut port = None;
+
+    for protocol in address.iter() {
+        match protocol {
+            Protocol::Ip4(address) => ip = Some(address),
+            Protocol::Tcp(tcp_port) => port = Some(tcp_port),
+            _ => {}
+        }
+    }
+
+    match (ip, port) {
+        (Some(ip), Some(port)) => Ok(SocketAddr::from((ip, port))),
+        _ => bail!(
+            "Cannot proxy {}, expected an /ip4/../tcp/.. address",
+            address
+        ),
+    }
+}
@@ -1,17 +1,20 @@
-mod bitcoind;
-mod electrs;
+mod chaos_proxy;
+
+pub use chaos_proxy::{ChaosProxy, Direction, ProxyEvent};
 
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
+use bdk::electrum_client::{Client as ElectrumClient, ElectrumApi};
 use bitcoin_harness::{BitcoindRpcApi, Client};
 use futures::Future;
-use get_port::get_port;
+use libp2p::core::multiaddr::Protocol;
 use libp2p::core::Multiaddr;
 use libp2p::PeerId;
-use monero_harness::{image, Monero};
+use monero_harness::Monero;
+use rand::rngs::OsRng;
 use std::cmp::Ordering;
 use std::fmt;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use swap::asb::FixedRate;
@@ -19,20 +22,25 @@ use swap::bitcoin::{CancelTimelock, PunishTimelock, TxCancel, TxPunish, TxRedeem
 use swap::database::SqliteDatabase;
 use swap::env::{Config, GetConfig};
 use swap::fs::ensure_directory_exists;
+use swap::monero::wallet::{TransferRequest, WatchRequest};
 use swap::network::rendezvous::XmrBtcNamespace;
 use swap::network::swarm;
 use swap::protocol::alice::{AliceState, Swap};
 use swap::protocol::bob::BobState;
 use swap::protocol::{alice, bob, Database};
 use swap::seed::Seed;
-use swap::{api, asb, bitcoin, cli, env, monero};
-use tempfile::{tempdir, NamedTempFile};
+use swap::selftest::{
+    electrs, free_port, init_containers, init_wallets, mint, retry, start_alice,
+    AliceApplicationHandle, StartingBalances, BITCOIN_TEST_WALLET_NAME, MONERO_WALLET_NAME_ALICE,
+    MONERO_WALLET_NAME_BOB,
+};
+use swap::{api, bitcoin, cli, env, monero};
+use tempfile::{tempdir, TempDir};
 use testcontainers::clients::Cli;
-use testcontainers::{Container, RunnableImage};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
-use tokio::time::{interval, timeout};
+use tokio::time::timeout;
 use tracing_subscriber::util::SubscriberInitExt;
 use url::Url;
 use uuid::Uuid;
@@ -43,6 +51,24 @@ where
     F: Future<Output = Result<()>>,
     C: GetConfig,
 {
+    setup_test_with_config(C::get_config(), testfn).await
+}
+
+/// Like [`setup_test`] but with an explicit config, e.g. to use timelocks of
+/// only a few blocks that are crossed with [`TestContext::mine_bitcoin_blocks`].
+///
+/// Every test gets its own containers, docker network, host ports and data
+/// directory, so tests can run concurrently. The containers and the data
+/// directory are removed on drop, which also happens if the test panics.
+pub async fn setup_test_with_config<T, F>(env_config: Config, testfn: T)
+where
+    T: Fn(TestContext) -> F,
+    F: Future<Output = Result<()>>,
+{
+    env_config
+        .check_invariants()
+        .expect("test config must be possible in production");
+
     let cli = Cli::default();
 
     let _guard = tracing_subscriber::fmt()
@@ -50,38 +76,46 @@ where
         .with_test_writer()
         .set_default();
 
-    let env_config = C::get_config();
+    let data_dir = tempdir().expect("could not create test data directory");
 
-    let (monero, containers) = init_containers(&cli).await;
+    let (monero, containers) = init_containers(&cli).await.unwrap();
     monero.init_miner().await.unwrap();
 
     let btc_amount = bitcoin::Amount::from_sat(1_000_000);
-    let xmr_amount = monero::Amount::from_monero(btc_amount.to_btc() / FixedRate::RATE).unwrap();
+    let xmr_amount = FixedRate::default().value().sell_quote(btc_amount).unwrap();
 
     let alice_starting_balances =
         StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10));
 
     let electrs_rpc_port = containers.electrs.get_host_port_ipv4(electrs::RPC_PORT);
+    // electrs only serves requests once it has caught up with bitcoind
+    retry("electrs", || async {
+        let client = ElectrumClient::new(&format!("tcp://localhost:{}", electrs_rpc_port))?;
+        client.ping()
+    })
+    .await
+    .expect("electrs did not become ready");
 
     let alice_seed = Seed::random().unwrap();
-    let (alice_bitcoin_wallet, alice_monero_wallet) = init_test_wallets(
+    let (alice_bitcoin_wallet, alice_monero_wallet) = init_wallets(
         MONERO_WALLET_NAME_ALICE,
         containers.bitcoind_url.clone(),
         &monero,
         alice_starting_balances.clone(),
-        tempdir().unwrap().path(),
+        &data_dir.path().join("alice").join("bitcoin"),
         electrs_rpc_port,
         &alice_seed,
         env_config,
     )
-    .await;
+    .await
+    .unwrap();
 
-    let alice_listen_port = get_port().expect("Failed to find a free port");
+    let alice_listen_port = free_port().unwrap();
     let alice_listen_address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", alice_listen_port)
         .parse()
         .expect("failed to parse Alice's address");
 
-    let alice_db_path = NamedTempFile::new().unwrap().path().to_path_buf();
+    let alice_db_path = data_dir.path().join("alice").join("sqlite");
     let (alice_handle, alice_swap_handle) = start_alice(
         &alice_seed,
         alice_db_path.clone(),
@@ -89,31 +123,34 @@ where
         env_config,
         alice_bitcoin_wallet.clone(),
         alice_monero_wallet.clone(),
+        bitcoin::Amount::from_sat(u64::MAX),
     )
-    .await;
+    .await
+    .unwrap();
 
     let bob_seed = Seed::random().unwrap();
     let bob_starting_balances = StartingBalances::new(btc_amount * 10, monero::Amount::ZERO, None);
 
-    let (bob_bitcoin_wallet, bob_monero_wallet) = init_test_wallets(
+    let (bob_bitcoin_wallet, bob_monero_wallet) = init_wallets(
         MONERO_WALLET_NAME_BOB,
-        containers.bitcoind_url,
+        containers.bitcoind_url.clone(),
         &monero,
         bob_starting_balances.clone(),
-        tempdir().unwrap().path(),
+        &data_dir.path().join("bob").join("bitcoin"),
         electrs_rpc_port,
         &bob_seed,
         env_config,
     )
-    .await;
+    .await
+    .unwrap();
 
     let bob_params = BobParams {
         seed: Seed::random().unwrap(),
-        db_path: NamedTempFile::new().unwrap().path().to_path_buf(),
+        db_path: data_dir.path().join("bob").join("sqlite"),
         bitcoin_wallet: bob_bitcoin_wallet.clone(),
         monero_wallet: bob_monero_wallet.clone(),
         alice_address: alice_listen_address.clone(),
-        alice_peer_id: alice_handle.peer_id,
+        alice_peer_id: alice_handle.peer_id(),
         env_config,
     };
 
@@ -121,6 +158,8 @@ where
 
     let test = TestContext {
         env_config,
+        bitcoind_url: containers.bitcoind_url.clone(),
+        monero,
         btc_amount,
         xmr_amount,
         alice_seed,
@@ -135,271 +174,12 @@ where
         bob_starting_balances,
         bob_bitcoin_wallet,
         bob_monero_wallet,
+        _data_dir: data_dir,
     };
 
     testfn(test).await.unwrap()
 }
 
-async fn init_containers(cli: &Cli) -> (Monero, Containers<'_>) {
-    let prefix = random_prefix();
-    let bitcoind_name = format!("{}_{}", prefix, "bitcoind");
-    let (_bitcoind, bitcoind_url, mapped_port) =
-        init_bitcoind_container(cli, prefix.clone(), bitcoind_name.clone(), prefix.clone())
-            .await
-            .expect("could not init bitcoind");
-    let electrs = init_electrs_container(cli, prefix.clone(), bitcoind_name, prefix, mapped_port)
-        .await
-        .expect("could not init electrs");
-    let (monero, _monerod_container, _monero_wallet_rpc_containers) =
-        Monero::new(cli, vec![MONERO_WALLET_NAME_ALICE, MONERO_WALLET_NAME_BOB])
-            .await
-            .unwrap();
-
-    (
-        monero,
-        Containers {
-            bitcoind_url,
-            _bitcoind,
-            _monerod_container,
-            _monero_wallet_rpc_containers,
-            electrs,
-        },
-    )
-}
-
-async fn init_bitcoind_container(
-    cli: &Cli,
-    volume: String,
-    name: String,
-    network: String,
-) -> Result<(Container<'_, bitcoind::Bitcoind>, Url, u16)> {
-    let image = bitcoind::Bitcoind::default().with_volume(volume);
-    let image = RunnableImage::from(image)
-        .with_container_name(name)
-        .with_network(network);
-
-    let docker = cli.run(image);
-    let port = docker.get_host_port_ipv4(bitcoind::RPC_PORT);
-
-    let bitcoind_url = {
-        let input = format!(
-            "http://{}:{}@localhost:{}",
-            bitcoind::RPC_USER,
-            bitcoind::RPC_PASSWORD,
-            port
-        );
-        Url::parse(&input).unwrap()
-    };
-
-    init_bitcoind(bitcoind_url.clone(), 5).await?;
-
-    Ok((docker, bitcoind_url.clone(), bitcoind::RPC_PORT))
-}
-
-pub async fn init_electrs_container(
-    cli: &Cli,
-    volume: String,
-    bitcoind_container_name: String,
-    network: String,
-    port: u16,
-) -> Result<Container<'_, electrs::Electrs>> {
-    let bitcoind_rpc_addr = format!("{}:{}", bitcoind_container_name, port);
-    let image = electrs::Electrs::default()
-        .with_volume(volume)
-        .with_daemon_rpc_addr(bitcoind_rpc_addr)
-        .with_tag("latest");
-    let image = RunnableImage::from(image.self_and_args())
-        .with_network(network.clone())
-        .with_container_name(format!("{}_electrs", network));
-
-    let docker = cli.run(image);
-
-    Ok(docker)
-}
-
-async fn start_alice(
-    seed: &Seed,
-    db_path: PathBuf,
-    listen_address: Multiaddr,
-    env_config: Config,
-    bitcoin_wallet: Arc<bitcoin::Wallet>,
-    monero_wallet: Arc<monero::Wallet>,
-) -> (AliceApplicationHandle, Receiver<alice::Swap>) {
-    if let Some(parent_dir) = db_path.parent() {
-        ensure_directory_exists(parent_dir).unwrap();
-    }
-    if !&db_path.exists() {
-        tokio::fs::File::create(&db_path).await.unwrap();
-    }
-    let db = Arc::new(SqliteDatabase::open(db_path.as_path()).await.unwrap());
-
-    let min_buy = bitcoin::Amount::from_sat(u64::MIN);
-    let max_buy = bitcoin::Amount::from_sat(u64::MAX);
-    let latest_rate = FixedRate::default();
-    let resume_only = false;
-
-    let mut swarm = swarm::asb(
-        seed,
-        min_buy,
-        max_buy,
-        latest_rate,
-        resume_only,
-        env_config,
-        XmrBtcNamespace::Testnet,
-        &[],
-    )
-    .unwrap();
-    swarm.listen_on(listen_address).unwrap();
-
-    let (event_loop, swap_handle) = asb::EventLoop::new(
-        swarm,
-        env_config,
-        bitcoin_wallet,
-        monero_wallet,
-        db,
-        FixedRate::default(),
-        min_buy,
-        max_buy,
-        None,
-    )
-    .unwrap();
-
-    let peer_id = event_loop.peer_id();
-    let handle = tokio::spawn(event_loop.run());
-
-    (AliceApplicationHandle { handle, peer_id }, swap_handle)
-}
-
-#[allow(clippy::too_many_arguments)]
-async fn init_test_wallets(
-    name: &str,
-    bitcoind_url: Url,
-    monero: &Monero,
-    starting_balances: StartingBalances,
-    datadir: &Path,
-    electrum_rpc_port: u16,
-    seed: &Seed,
-    env_config: Config,
-) -> (Arc<bitcoin::Wallet>, Arc<monero::Wallet>) {
-    monero
-        .init_wallet(
-            name,
-            starting_balances
-                .xmr_outputs
-                .into_iter()
-                .map(|amount| amount.as_piconero())
-                .collect(),
-        )
-        .await
-        .unwrap();
-
-    let xmr_wallet = swap::monero::Wallet::connect(
-        monero.wallet(name).unwrap().client().clone(),
-        name.to_string(),
-        env_config,
-    )
-    .await
-    .unwrap();
-
-    let electrum_rpc_url = {
-        let input = format!("tcp://@localhost:{}", electrum_rpc_port);
-        Url::parse(&input).unwrap()
-    };
-
-    let btc_wallet = swap::bitcoin::Wallet::new(
-        electrum_rpc_url,
-        datadir,
-        seed.derive_extended_private_key(env_config.bitcoin_network)
-            .expect("Could not create extended private key from seed"),
-        env_config,
-        1,
-    )
-    .await
-    .expect("could not init btc wallet");
-
-    if starting_balances.btc != bitcoin::Amount::ZERO {
-        mint(
-            bitcoind_url,
-            btc_wallet.new_address().await.unwrap(),
-            starting_balances.btc,
-        )
-        .await
-        .expect("could not mint btc starting balance");
-
-        let mut interval = interval(Duration::from_secs(1u64));
-        let mut retries = 0u8;
-        let max_retries = 30u8;
-        loop {
-            retries += 1;
-            btc_wallet.sync().await.unwrap();
-
-            let btc_balance = btc_wallet.balance().await.unwrap();
-
-            if btc_balance == starting_balances.btc {
-                break;
-            } else if retries == max_retries {
-                panic!(
-                    "Bitcoin wallet initialization failed, reached max retries upon balance sync"
-                )
-            }
-
-            interval.tick().await;
-        }
-    }
-
-    (Arc::new(btc_wallet), Arc::new(xmr_wallet))
-}
-
-const MONERO_WALLET_NAME_BOB: &str = "bob";
-const MONERO_WALLET_NAME_ALICE: &str = "alice";
-const BITCOIN_TEST_WALLET_NAME: &str = "testwallet";
-
-#[derive(Debug, Clone)]
-pub struct StartingBalances {
-    pub xmr: monero::Amount,
-    pub xmr_outputs: Vec<monero::Amount>,
-    pub btc: bitcoin::Amount,
-}
-
-impl StartingBalances {
-    /// If monero_outputs is specified the monero balance will be:
-    /// monero_outputs * new_xmr = self_xmr
-    pub fn new(btc: bitcoin::Amount, xmr: monero::Amount, monero_outputs: Option<u64>) -> Self {
-        match monero_outputs {
-            None => {
-                if xmr == monero::Amount::ZERO {
-                    return Self {
-                        xmr,
-                        xmr_outputs: vec![],
-                        btc,
-                    };
-                }
-
-                Self {
-                    xmr,
-                    xmr_outputs: vec![xmr],
-                    btc,
-                }
-            }
-            Some(outputs) => {
-                let mut xmr_outputs = Vec::new();
-                let mut sum_xmr = monero::Amount::ZERO;
-
-                for _ in 0..outputs {
-                    xmr_outputs.push(xmr);
-                    sum_xmr = sum_xmr + xmr;
-                }
-
-                Self {
-                    xmr: sum_xmr,
-                    xmr_outputs,
-                    btc,
-                }
-            }
-        }
-    }
-}
-
 pub struct BobParams {
     seed: Seed,
     db_path: PathBuf,
@@ -427,15 +207,8 @@ impl BobParams {
     }
 
     pub async fn new_swap_from_db(&self, swap_id: Uuid) -> Result<(bob::Swap, cli::EventLoop)> {
-        let (event_loop, handle) = self.new_eventloop(swap_id).await?;
-
-        if let Some(parent_dir) = self.db_path.parent() {
-            ensure_directory_exists(parent_dir)?;
-        }
-        if !self.db_path.exists() {
-            tokio::fs::File::create(&self.db_path).await?;
-        }
-        let db = Arc::new(SqliteDatabase::open(&self.db_path).await?);
+        let db = self.open_db().await?;
+        let (event_loop, handle) = self.new_eventloop(swap_id, db.clone()).await?;
 
         let swap = bob::Swap::from_db(
             db,
@@ -457,15 +230,8 @@ impl BobParams {
     ) -> Result<(bob::Swap, cli::EventLoop)> {
         let swap_id = Uuid::new_v4();
 
-        let (event_loop, handle) = self.new_eventloop(swap_id).await?;
-
-        if let Some(parent_dir) = self.db_path.parent() {
-            ensure_directory_exists(parent_dir)?;
-        }
-        if !self.db_path.exists() {
-            tokio::fs::File::create(&self.db_path).await?;
-        }
-        let db = Arc::new(SqliteDatabase::open(&self.db_path).await?);
+        let db = self.open_db().await?;
+        let (event_loop, handle) = self.new_eventloop(swap_id, db.clone()).await?;
 
         db.insert_peer_id(swap_id, self.alice_peer_id).await?;
 
@@ -484,13 +250,26 @@ impl BobParams {
         Ok((swap, event_loop))
     }
 
+    async fn open_db(&self) -> Result<Arc<SqliteDatabase>> {
+        if let Some(parent_dir) = self.db_path.parent() {
+            ensure_directory_exists(parent_dir)?;
+        }
+        if !self.db_path.exists() {
+            tokio::fs::File::create(&self.db_path).await?;
+        }
+        let db = Arc::new(SqliteDatabase::open(&self.db_path).await?);
+
+        Ok(db)
+    }
+
     pub async fn new_eventloop(
         &self,
         swap_id: Uuid,
+        db: Arc<SqliteDatabase>,
     ) -> Result<(cli::EventLoop, cli::EventLoopHandle)> {
-        let tor_socks5_port = get_port()
-            .expect("We don't care about Tor in the tests so we get a free port to disable it.");
-        let identity = self.seed.derive_libp2p_identity();
+        // We don't care about Tor in the tests so we use a free port to disable it.
+        let tor_socks5_port = free_port().unwrap();
+        let identity = self.seed.derive_libp2p_identity(XmrBtcNamespace::Testnet);
 
         let behaviour = cli::Behaviour::new(
             self.alice_peer_id,
@@ -503,7 +282,7 @@ impl BobParams {
             .behaviour_mut()
             .add_address(self.alice_peer_id, self.alice_address.clone());
 
-        cli::EventLoop::new(swap_id, swarm, self.alice_peer_id)
+        cli::EventLoop::new(swap_id, swarm, self.alice_peer_id, db, self.env_config)
     }
 }
 
@@ -515,19 +294,37 @@ impl BobApplicationHandle {
     }
 }
 
-pub struct AliceApplicationHandle {
-    handle: JoinHandle<()>,
-    peer_id: PeerId,
+/// Another maker next to Alice, see [`TestContext::start_other_alice`].
+pub struct OtherAlice {
+    address: Multiaddr,
+    handle: AliceApplicationHandle,
+    swap_handle: Receiver<alice::Swap>,
 }
 
-impl AliceApplicationHandle {
-    pub fn abort(&self) {
-        self.handle.abort()
+impl OtherAlice {
+    /// The maker's listen address including its peer id.
+    pub fn address(&self) -> Multiaddr {
+        self.address.clone()
+    }
+
+    pub async fn next_swap(&mut self) -> alice::Swap {
+        timeout(Duration::from_secs(20), self.swap_handle.recv())
+            .await
+            .expect("No swap for the other Alice within 20 seconds")
+            .unwrap()
+    }
+}
+
+impl Drop for OtherAlice {
+    fn drop(&mut self) {
+        self.handle.abort();
     }
 }
 
 pub struct TestContext {
     env_config: Config,
+    bitcoind_url: Url,
+    monero: Monero,
 
     btc_amount: bitcoin::Amount,
     xmr_amount: monero::Amount,
@@ -546,37 +343,121 @@ pub struct TestContext {
     bob_starting_balances: StartingBalances,
     bob_bitcoin_wallet: Arc<bitcoin::Wallet>,
     bob_monero_wallet: Arc<monero::Wallet>,
+
+    _data_dir: TempDir,
 }
 
 impl TestContext {
-    pub async fn get_bob_context(self) -> api::Context {
+    pub async fn get_bob_context(&self) -> api::Context {
         api::Context::for_harness(
-            self.bob_params.seed,
+            self.bob_params.seed.clone(),
             self.env_config,
-            self.bob_params.db_path,
-            self.bob_bitcoin_wallet,
-            self.bob_monero_wallet,
+            self.bob_params.db_path.clone(),
+            self.bob_bitcoin_wallet.clone(),
+            self.bob_monero_wallet.clone(),
+        )
+        .await
+    }
+
+    /// Starts another maker that shares Alice's wallets but has its own
+    /// identity, database and listen address.
+    ///
+    /// Unlike Alice, it sells at most `btc_amount` per swap, so a Bob that
+    /// determines the swap amount from its balance swaps exactly that.
+    pub async fn start_other_alice(&self) -> OtherAlice {
+        let seed = Seed::random().unwrap();
+        let data_dir = self._data_dir.path().join(Uuid::new_v4().to_string());
+        let listen_address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", free_port().unwrap())
+            .parse()
+            .expect("failed to parse the other Alice's address");
+
+        let (handle, swap_handle) = start_alice(
+            &seed,
+            data_dir.join("sqlite"),
+            listen_address.clone(),
+            self.env_config,
+            self.alice_bitcoin_wallet.clone(),
+            self.alice_monero_wallet.clone(),
+            self.btc_amount,
+        )
+        .await
+        .unwrap();
+
+        OtherAlice {
+            address: listen_address.with(Protocol::P2p(handle.peer_id().into())),
+            handle,
+            swap_handle,
+        }
+    }
+
+    /// Sends Bob another `btc_amount * 10` in a separate output.
+    pub async fn fund_bob_bitcoin(&mut self) {
+        let amount = self.btc_amount * 10;
+        let expected_balance = self.bob_starting_balances.btc + amount;
+
+        mint(
+            self.bitcoind_url.clone(),
+            self.bob_bitcoin_wallet.new_address().await.unwrap(),
+            amount,
         )
         .await
+        .unwrap();
+
+        retry("Bob's Bitcoin funding", || async {
+            self.bob_bitcoin_wallet.sync().await?;
+            let balance = self.bob_bitcoin_wallet.balance().await?;
+            if balance != expected_balance {
+                bail!(
+                    "Bob's balance is {}, expected {}",
+                    balance,
+                    expected_balance
+                );
+            }
+
+            Ok::<_, anyhow::Error>(())
+        })
+        .await
+        .unwrap();
+
+        self.bob_starting_balances.btc = expected_balance;
     }
 
     pub async fn restart_alice(&mut self) {
+        self.restart_alice_with_config(self.env_config).await
+    }
+
+    /// Restarts Alice with a config that Bob does not know about, e.g. to
+    /// make her propose timelocks other than Bob's.
+    pub async fn restart_alice_with_config(&mut self, env_config: Config) {
         self.alice_handle.abort();
 
         let (alice_handle, alice_swap_handle) = start_alice(
             &self.alice_seed,
             self.alice_db_path.clone(),
             self.alice_listen_address.clone(),
-            self.env_config,
+            env_config,
             self.alice_bitcoin_wallet.clone(),
             self.alice_monero_wallet.clone(),
+            bitcoin::Amount::from_sat(u64::MAX),
         )
-        .await;
+        .await
+        .unwrap();
 
         self.alice_handle = alice_handle;
         self.alice_swap_handle = alice_swap_handle;
     }
 
+    /// Routes Bob's connections to Alice through a [`ChaosProxy`]. Only
+    /// affects event loops created after this call.
+    pub async fn route_bob_through_proxy(&mut self) -> ChaosProxy {
+        let proxy = ChaosProxy::start(&self.alice_listen_address)
+            .await
+            .expect("could not start chaos proxy");
+        self.bob_params.alice_address = proxy.address();
+
+        proxy
+    }
+
     pub async fn alice_next_swap(&mut self) -> alice::Swap {
         timeout(Duration::from_secs(20), self.alice_swap_handle.recv())
             .await
@@ -609,6 +490,80 @@ impl TestContext {
         (swap, BobApplicationHandle(join_handle))
     }
 
+    /// Runs a swap in which Alice stops once Bob locked his Btc and never
+    /// locks her Xmr. Returns Bob's final state after the cancel timelock
+    /// expired.
+    pub async fn run_swap_alice_never_locks_xmr(&mut self) -> Result<BobState> {
+        let (bob_swap, _) = self.bob_swap().await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = self.alice_next_swap().await;
+        let alice_state = alice::run_until(
+            alice_swap,
+            alice_run_until::is_btc_locked,
+            FixedRate::default(),
+        )
+        .await?;
+        assert!(matches!(alice_state, AliceState::BtcLocked { .. }));
+
+        let cancel_timelock = self.env_config.bitcoin_cancel_timelock;
+        self.mine_bitcoin_blocks(cancel_timelock.into()).await?;
+
+        bob_swap.await?
+    }
+
+    /// Mines the given number of Bitcoin blocks immediately, in addition to
+    /// the block mined every second in the background.
+    pub async fn mine_bitcoin_blocks(&self, blocks: u32) -> Result<()> {
+        let bitcoind_client = Client::new(self.bitcoind_url.clone());
+
+        let reward_address = bitcoind_client
+            .with_wallet(BITCOIN_TEST_WALLET_NAME)?
+            .getnewaddress(None, None)
+            .await?;
+        bitcoind_client
+            .generatetoaddress(blocks, reward_address)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Mines the given number of Monero blocks immediately, in addition to
+    /// the blocks mined in the background.
+    pub async fn mine_monero_blocks(&self, blocks: u32) -> Result<()> {
+        self.monero.generate_blocks(blocks).await
+    }
+
+    /// Sends `amount` from Alice's Monero wallet to a fresh address, the way
+    /// Alice locks her XMR, and returns the request Bob watches it with.
+    pub async fn send_xmr_to_fresh_address(&self, amount: monero::Amount) -> Result<WatchRequest> {
+        let public_spend_key = monero::PublicKey::from_private_key(
+            &monero::PrivateKey::from_scalar(monero::Scalar::random(&mut OsRng)),
+        );
+        let public_view_key = monero::PrivateViewKey::new_random(&mut OsRng).public();
+
+        let transfer_proof = self
+            .alice_monero_wallet
+            .transfer(TransferRequest {
+                public_spend_key,
+                public_view_key,
+                amount,
+            })
+            .await?;
+
+        Ok(WatchRequest {
+            public_spend_key,
+            public_view_key,
+            transfer_proof,
+            conf_target: monero::SPENDABLE_AGE,
+            expected: amount,
+        })
+    }
+
+    pub fn bob_monero_wallet(&self) -> &monero::Wallet {
+        &self.bob_monero_wallet
+    }
+
     pub async fn assert_alice_redeemed(&mut self, state: AliceState) {
         assert!(matches!(state, AliceState::BtcRedeemed));
 
@@ -671,10 +626,16 @@ impl TestContext {
     }
 
     pub async fn assert_bob_redeemed(&self, state: BobState) {
+        self.assert_bob_redeemed_all(vec![state]).await
+    }
+
+    /// Like [`TestContext::assert_bob_redeemed`] for several swaps over
+    /// `btc_amount` each that Bob ran concurrently.
+    pub async fn assert_bob_redeemed_all(&self, states: Vec<BobState>) {
         assert_eventual_balance(
             self.bob_bitcoin_wallet.as_ref(),
             Ordering::Equal,
-            self.bob_redeemed_btc_balance(state).await.unwrap(),
+            self.bob_redeemed_btc_balance(&states).await.unwrap(),
         )
         .await
         .unwrap();
@@ -691,6 +652,31 @@ impl TestContext {
         .unwrap();
     }
 
+    /// Asserts that the sweep transactions Bob recorded moved exactly the
+    /// Monero Alice locked, minus their fees, into Bob's wallet.
+    ///
+    /// Expects the swap to be the only Monero Bob received.
+    pub async fn assert_bob_received_swept_xmr(&self, tx_hashes: &[monero::TxHash]) {
+        assert!(!tx_hashes.is_empty(), "Bob did not record a sweep");
+
+        let mut received = monero::Amount::ZERO;
+        let mut fees = monero::Amount::ZERO;
+        for tx_hash in tx_hashes {
+            let transfer = self.bob_monero_wallet.get_transfer(tx_hash).await.unwrap();
+            received = received + monero::Amount::from_piconero(transfer.amount);
+            fees = fees + monero::Amount::from_piconero(transfer.fee);
+        }
+
+        assert_eq!(received, self.xmr_amount - fees);
+        assert_eventual_balance(
+            self.bob_monero_wallet.as_ref(),
+            Ordering::Equal,
+            self.bob_starting_balances.xmr + received,
+        )
+        .await
+        .unwrap();
+    }
+
     pub async fn assert_bob_refunded(&self, state: BobState) {
         self.bob_bitcoin_wallet.sync().await.unwrap();
 
@@ -767,18 +753,22 @@ impl TestContext {
         self.bob_starting_balances.xmr
     }
 
-    async fn bob_redeemed_btc_balance(&self, state: BobState) -> Result<bitcoin::Amount> {
+    async fn bob_redeemed_btc_balance(&self, states: &[BobState]) -> Result<bitcoin::Amount> {
         self.bob_bitcoin_wallet.sync().await?;
 
-        let lock_tx_id = if let BobState::XmrRedeemed { tx_lock_id } = state {
-            tx_lock_id
-        } else {
-            bail!("Bob in not in xmr redeemed state: {:?}", state);
-        };
+        let mut balance = self.bob_starting_balances.btc;
+        for state in states {
+            let lock_tx_id = if let BobState::XmrRedeemed { tx_lock_id } = state {
+                *tx_lock_id
+            } else {
+                bail!("Bob in not in xmr redeemed state: {:?}", state);
+            };
 
-        let lock_tx_bitcoin_fee = self.bob_bitcoin_wallet.transaction_fee(lock_tx_id).await?;
+            let lock_tx_bitcoin_fee = self.bob_bitcoin_wallet.transaction_fee(lock_tx_id).await?;
+            balance = balance - self.btc_amount - lock_tx_bitcoin_fee;
+        }
 
-        Ok(self.bob_starting_balances.btc - self.btc_amount - lock_tx_bitcoin_fee)
+        Ok(balance)
     }
 
     fn alice_refunded_xmr_balance(&self) -> monero::Amount {
@@ -916,87 +906,33 @@ impl Wallet for bitcoin::Wallet {
     }
 }
 
-fn random_prefix() -> String {
-    use rand::distributions::Alphanumeric;
-    use rand::{thread_rng, Rng};
-    use std::iter;
-    const LEN: usize = 8;
-    let mut rng = thread_rng();
-    let chars: String = iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(LEN)
-        .collect();
-    chars
-}
-
-async fn mine(bitcoind_client: Client, reward_address: bitcoin::Address) -> Result<()> {
-    loop {
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        bitcoind_client
-            .generatetoaddress(1, reward_address.clone())
-            .await?;
-    }
-}
-
-async fn init_bitcoind(node_url: Url, spendable_quantity: u32) -> Result<Client> {
-    let bitcoind_client = Client::new(node_url.clone());
-
-    bitcoind_client
-        .createwallet(BITCOIN_TEST_WALLET_NAME, None, None, None, None)
-        .await?;
-
-    let reward_address = bitcoind_client
-        .with_wallet(BITCOIN_TEST_WALLET_NAME)?
-        .getnewaddress(None, None)
-        .await?;
-
-    bitcoind_client
-        .generatetoaddress(101 + spendable_quantity, reward_address.clone())
-        .await?;
-    tokio::spawn(mine(bitcoind_client.clone(), reward_address));
-    Ok(bitcoind_client)
-}
-
-/// Send Bitcoin to the specified address, limited to the spendable bitcoin
-/// quantity.
-pub async fn mint(node_url: Url, address: bitcoin::Address, amount: bitcoin::Amount) -> Result<()> {
-    let bitcoind_client = Client::new(node_url.clone());
-
-    bitcoind_client
-        .send_to_address(BITCOIN_TEST_WALLET_NAME, address.clone(), amount)
-        .await?;
-
-    // Confirm the transaction
-    let reward_address = bitcoind_client
-        .with_wallet(BITCOIN_TEST_WALLET_NAME)?
-        .getnewaddress(None, None)
-        .await?;
-    bitcoind_client.generatetoaddress(1, reward_address).await?;
-
-    Ok(())
-}
-
-// This is just to keep the containers alive
-struct Containers<'a> {
-    bitcoind_url: Url,
-    _bitcoind: Container<'a, bitcoind::Bitcoind>,
-    _monerod_container: Container<'a, image::Monerod>,
-    _monero_wallet_rpc_containers: Vec<Container<'a, image::MoneroWalletRpc>>,
-    electrs: Container<'a, electrs::Electrs>,
-}
-
 pub mod alice_run_until {
     use swap::protocol::alice::AliceState;
 
+    pub fn is_btc_locked(state: &AliceState) -> bool {
+        matches!(state, AliceState::BtcLocked { .. })
+    }
+
     pub fn is_xmr_lock_transaction_sent(state: &AliceState) -> bool {
         matches!(state, AliceState::XmrLockTransactionSent { .. })
     }
 
+    pub fn is_xmr_locked(state: &AliceState) -> bool {
+        matches!(state, AliceState::XmrLocked { .. })
+    }
+
+    pub fn is_xmr_lock_transfer_proof_sent(state: &AliceState) -> bool {
+        matches!(state, AliceState::XmrLockTransferProofSent { .. })
+    }
+
     pub fn is_encsig_learned(state: &AliceState) -> bool {
         matches!(state, AliceState::EncSigLearned { .. })
     }
 
+    pub fn is_btc_redeem_transaction_published(state: &AliceState) -> bool {
+        matches!(state, AliceState::BtcRedeemTransactionPublished { .. })
+    }
+
     pub fn is_btc_redeemed(state: &AliceState) -> bool {
         matches!(state, AliceState::BtcRedeemed { .. })
     }
@@ -1005,6 +941,10 @@ pub mod alice_run_until {
 pub mod bob_run_until {
     use swap::protocol::bob::BobState;
 
+    pub fn is_swap_setup_completed(state: &BobState) -> bool {
+        matches!(state, BobState::SwapSetupCompleted(..))
+    }
+
     pub fn is_btc_locked(state: &BobState) -> bool {
         matches!(state, BobState::BtcLocked { .. })
     }
@@ -1020,6 +960,10 @@ pub mod bob_run_until {
     pub fn is_encsig_sent(state: &BobState) -> bool {
         matches!(state, BobState::EncSigSent(..))
     }
+
+    pub fn is_btc_redeemed(state: &BobState) -> bool {
+        matches!(state, BobState::BtcRedeemed(..))
+    }
 }
 
 pub struct SlowCancelConfig;
@@ -2,7 +2,9 @@ pub mod harness;
 
 use harness::bob_run_until::is_btc_locked;
 use harness::FastPunishConfig;
+use std::convert::TryInto;
 use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
 use swap::protocol::bob::BobState;
 use swap::protocol::{alice, bob};
 
@@ -39,3 +41,42 @@ async fn alice_punishes_if_bob_never_acts_after_fund() {
     })
     .await;
 }
+
+/// Bob locks Btc and goes offline right away, he never acknowledges the
+/// transfer proof. Alice keeps running without a restart, cancels once the
+/// cancel timelock expired and punishes once the punish timelock expired.
+#[tokio::test]
+async fn alice_punishes_while_bob_is_offline() {
+    harness::setup_test(FastPunishConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let bob_swap = tokio::spawn(bob::run_until(bob_swap, is_btc_locked));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap_id = alice_swap.swap_id;
+        let alice_db = alice_swap.db.clone();
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+        // Bob's event loop is gone, Alice cannot reach him anymore
+        bob_join_handle.abort();
+
+        let alice_state = alice_swap.await??;
+        ctx.assert_alice_punished(alice_state).await;
+
+        let stored_state: AliceState = alice_db.get_state(alice_swap_id).await?.try_into()?;
+        assert!(matches!(stored_state, AliceState::BtcPunished));
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        assert!(matches!(bob_swap.state, BobState::BtcLocked { .. }));
+
+        let bob_state = bob::run(bob_swap).await?;
+        ctx.assert_bob_punished(bob_state).await;
+
+        Ok(())
+    })
+    .await;
+}
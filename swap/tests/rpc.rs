@@ -403,10 +403,13 @@ mod test {
             tokio::spawn(async move {
                 // Immediately release lock when suspend signal is received. Mocks a running swap that is then cancelled.
                 ctx.swap_lock
-                    .listen_for_swap_force_suspension()
+                    .listen_for_swap_force_suspension(Uuid::parse_str(SWAP_ID).unwrap())
+                    .await
+                    .unwrap();
+                ctx.swap_lock
+                    .release_swap_lock(Uuid::parse_str(SWAP_ID).unwrap())
                     .await
                     .unwrap();
-                ctx.swap_lock.release_swap_lock().await.unwrap();
             });
 
             let response: HashMap<String, String> = client
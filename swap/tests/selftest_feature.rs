@@ -0,0 +1,8 @@
+//! All other integration tests require the `selftest` feature and are skipped
+//! without it. This target builds regardless, so a plain `cargo test` reports
+//! the skip instead of silently passing.
+
+#[cfg(not(feature = "selftest"))]
+#[test]
+#[ignore = "integration tests are skipped, run `cargo test-integration` or pass `--features selftest`"]
+fn integration_tests_require_the_selftest_feature() {}
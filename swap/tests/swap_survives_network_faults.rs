@@ -0,0 +1,284 @@
+pub mod harness;
+
+use harness::alice_run_until::{is_xmr_lock_transfer_proof_sent, is_xmr_locked};
+use harness::bob_run_until;
+use harness::{ProxyEvent, SlowCancelConfig};
+use std::time::Duration;
+use swap::asb::FixedRate;
+use swap::network::heartbeat::ConnectionHealth;
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+use tokio::join;
+use tokio::sync::watch;
+
+#[tokio::test]
+async fn alice_resends_transfer_proof_once_bob_reconnects() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run_until(alice_swap, is_xmr_locked, FixedRate::default()).await?;
+        assert!(matches!(alice_state, AliceState::XmrLocked { .. }));
+
+        // Bob becomes unreachable right before Alice sends the transfer proof
+        proxy.refuse_connections();
+        proxy.sever();
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        proxy.wait_for_refused_connections(2).await;
+        proxy.accept_connections();
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn alice_resends_transfer_proof_bob_did_not_acknowledge() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+
+        let (bob_swap, bob_join_handle) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let mut heartbeat = bob_swap.event_loop_handle.heartbeat();
+        let bob_swap = tokio::spawn(bob::run_until(bob_swap, bob_run_until::is_btc_locked));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run_until(alice_swap, is_xmr_locked, FixedRate::default()).await?;
+        assert!(matches!(alice_state, AliceState::XmrLocked { .. }));
+        let bob_state = bob_swap.await??;
+        assert!(matches!(bob_state, BobState::BtcLocked { .. }));
+
+        // Bob's swap stopped but his event loop redials Alice, so the first
+        // delivery of the transfer proof reaches an event loop that cannot pass
+        // it on and never acknowledges it
+        let accepted = proxy
+            .events()
+            .iter()
+            .filter(|event| matches!(event, ProxyEvent::Accepted { .. }))
+            .count();
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        proxy.wait_for_accepted_connections(accepted + 1).await;
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            heartbeat.wait_for(|heartbeat| heartbeat.health == ConnectionHealth::Healthy),
+        )
+        .await??;
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn bob_sends_encrypted_signature_once_alice_is_reachable() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+
+        let (bob_swap, bob_join_handle) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let bob_swap = tokio::spawn(bob::run_until(bob_swap, bob_run_until::is_xmr_locked));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(bob_state, BobState::XmrLocked { .. }));
+
+        // Alice becomes unreachable right before Bob sends the encrypted signature
+        proxy.refuse_connections();
+        proxy.sever();
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        proxy.wait_for_refused_connections(2).await;
+        proxy.accept_connections();
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn bob_resends_encrypted_signature_alice_did_not_acknowledge() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let (bob_state_sender, mut bob_state) = watch::channel(None);
+        let bob_swap = tokio::spawn(bob::run_and_observe(bob_swap, move |state| {
+            bob_state_sender.send_replace(Some(state.clone()));
+        }));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run_until(
+            alice_swap,
+            is_xmr_lock_transfer_proof_sent,
+            FixedRate::default(),
+        )
+        .await?;
+        assert!(matches!(
+            alice_state,
+            AliceState::XmrLockTransferProofSent { .. }
+        ));
+
+        // Alice's swap stopped, so the first delivery of the encrypted
+        // signature reaches an event loop that cannot pass it on and never
+        // acknowledges it
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            bob_state.wait_for(|state| matches!(state, Some(BobState::XmrLocked(..)))),
+        )
+        .await??;
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn bob_keeps_his_event_loop_while_alice_restarts() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run_until(alice_swap, is_xmr_locked, FixedRate::default()).await?;
+        assert!(matches!(alice_state, AliceState::XmrLocked { .. }));
+
+        // Alice's listener goes down while Bob waits for the Monero lock
+        // transaction to be confirmed, so Bob has to buffer the encrypted
+        // signature until he redialled her
+        proxy.refuse_connections();
+        proxy.sever();
+        ctx.restart_alice().await;
+
+        proxy.wait_for_refused_connections(3).await;
+        assert!(!bob_swap.is_finished());
+        proxy.accept_connections();
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn bob_redials_alice_once_heartbeats_stop() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let mut heartbeat = bob_swap.event_loop_handle.heartbeat();
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run_until(alice_swap, is_xmr_locked, FixedRate::default()).await?;
+        assert!(matches!(alice_state, AliceState::XmrLocked { .. }));
+
+        // The connection dies silently, like a NAT dropping it, so only the
+        // missed heartbeats can tell that it is gone
+        let accepted = proxy
+            .events()
+            .iter()
+            .filter(|event| matches!(event, ProxyEvent::Accepted { .. }))
+            .count();
+        proxy.stall();
+
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            heartbeat.wait_for(|heartbeat| heartbeat.health == ConnectionHealth::Unreachable),
+        )
+        .await??;
+        proxy.wait_for_accepted_connections(accepted + 1).await;
+        tokio::time::timeout(
+            Duration::from_secs(60),
+            heartbeat.wait_for(|heartbeat| heartbeat.health == ConnectionHealth::Healthy),
+        )
+        .await??;
+        assert!(heartbeat.borrow().latest_rtt.is_some());
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn happy_path_with_latency() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let proxy = ctx.route_bob_through_proxy().await;
+        proxy.set_latency(Duration::from_millis(100));
+
+        let (bob_swap, _) = ctx.bob_swap().await;
+        let bob_swap = tokio::spawn(bob::run(bob_swap));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+        ctx.assert_alice_redeemed(alice_state??).await;
+        ctx.assert_bob_redeemed(bob_state??).await;
+
+        assert!(proxy
+            .events()
+            .iter()
+            .any(|event| matches!(event, ProxyEvent::Forwarded { .. })));
+
+        Ok(())
+    })
+    .await;
+}
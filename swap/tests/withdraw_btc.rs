@@ -0,0 +1,51 @@
+//! Withdraws Bitcoin from Bob's internal wallet through the CLI request API.
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use std::sync::Arc;
+use swap::api::request::{Method, Request};
+use swap::bitcoin;
+
+#[tokio::test]
+async fn withdraw_btc_rejects_too_large_amounts_and_sweeps_the_wallet() {
+    harness::setup_test(SlowCancelConfig, |ctx| async move {
+        let context = Arc::new(ctx.get_bob_context().await);
+        let (address, _) = ctx.bob_params.get_change_receive_addresses().await;
+
+        let response = Request::new(Method::Balance {
+            force_refresh: true,
+        })
+        .call(context.clone())
+        .await?;
+        let balance = bitcoin::Amount::from_sat(response["balance"].as_u64().unwrap());
+
+        let error = Request::new(Method::WithdrawBtc {
+            amount: Some(balance),
+            address: address.clone(),
+        })
+        .call(context.clone())
+        .await
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("can be withdrawn after paying network fees"),
+            "unexpected error: {:#}",
+            error
+        );
+
+        let response = Request::new(Method::WithdrawBtc {
+            amount: None,
+            address,
+        })
+        .call(context.clone())
+        .await?;
+        let withdrawn = bitcoin::Amount::from_sat(response["amount"].as_u64().unwrap());
+
+        assert!(withdrawn > bitcoin::Amount::ZERO);
+        assert!(withdrawn < balance);
+
+        Ok(())
+    })
+    .await;
+}